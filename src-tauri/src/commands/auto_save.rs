@@ -0,0 +1,178 @@
+use super::history::{persist_undo_history, record_save};
+use super::{CommandResult, EditorMap, Editors, SuccessResponse};
+use crate::core::{Editor, EditorEventListener};
+use crate::ui::{NotificationLevel, UiEvent};
+use crate::utils::config::get_config_manager;
+use crate::utils::{AutoSaveMode, AutoSaver};
+use once_cell::sync::OnceCell;
+use std::path::Path;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tauri::{command, AppHandle, Emitter, State};
+use tokio::sync::RwLock;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+/// Event the frontend receives [`UiEvent`]s on
+const UI_EVENT: &str = "ui-event";
+
+/// How long an auto-save failure stays on screen, in milliseconds
+const FAILURE_NOTIFICATION_DURATION: u64 = 5000;
+
+struct AutoSaveContext {
+    app_handle: AppHandle,
+    editors: Weak<RwLock<Editors>>,
+    saver: AutoSaver,
+}
+
+static AUTO_SAVE: OnceCell<AutoSaveContext> = OnceCell::new();
+
+/// Start saving edited files as the auto-save settings ask
+///
+/// Must be called once during app setup, before editors are created.
+pub fn init_auto_save(app_handle: AppHandle, editors: &EditorMap) {
+    let context = AutoSaveContext {
+        app_handle,
+        editors: Arc::downgrade(editors),
+        saver: AutoSaver::new(),
+    };
+
+    if AUTO_SAVE.set(context).is_err() {
+        warn!("Auto-save already initialized");
+        return;
+    }
+    info!("Auto-save started");
+}
+
+/// Saves the file of an editor when its auto-save mode says so
+struct AutoSaveListener {
+    editor_id: Uuid,
+}
+
+impl EditorEventListener for AutoSaveListener {
+    fn on_text_changed(&self, _version: u64) {
+        let editor_id = self.editor_id;
+
+        tauri::async_runtime::spawn(async move {
+            let Some(context) = AUTO_SAVE.get() else {
+                return;
+            };
+            let Some((AutoSaveMode::AfterDelay, delay)) = auto_save_settings().await else {
+                return;
+            };
+
+            context
+                .saver
+                .schedule(editor_id, delay, move || auto_save(vec![editor_id]))
+                .await;
+        });
+    }
+
+    fn on_focus_changed(&self, has_focus: bool) {
+        if has_focus {
+            return;
+        }
+        let editor_id = self.editor_id;
+
+        tauri::async_runtime::spawn(async move {
+            if let Some((AutoSaveMode::OnFocusChange, _)) = auto_save_settings().await {
+                auto_save(vec![editor_id]).await;
+            }
+        });
+    }
+
+    fn on_file_saved(&self, _path: &Path) {
+        let editor_id = self.editor_id;
+
+        tauri::async_runtime::spawn(async move {
+            if let Some(context) = AUTO_SAVE.get() {
+                context.saver.cancel(editor_id).await;
+            }
+        });
+    }
+}
+
+/// Save the editor's file automatically as the auto-save settings ask
+pub(crate) fn enable_auto_save(editor: &mut Editor) {
+    editor.add_event_listener(Arc::new(AutoSaveListener {
+        editor_id: editor.id(),
+    }));
+}
+
+/// Auto-save mode and delay, or `None` when auto-save is off
+///
+/// Auto-save is off until turned on in the editor settings, which the UI
+/// preferences for it are carried over to.
+async fn auto_save_settings() -> Option<(AutoSaveMode, Duration)> {
+    let settings = get_config_manager().ok()?.get_editor_settings().await;
+    settings.auto_save.then(|| {
+        (
+            settings.auto_save_mode,
+            Duration::from_millis(settings.auto_save_delay),
+        )
+    })
+}
+
+/// Save editors with unsaved changes, reporting failures as notifications
+///
+/// Read-only and untitled editors are skipped.
+async fn auto_save(editor_ids: Vec<Uuid>) {
+    let Some(context) = AUTO_SAVE.get() else {
+        return;
+    };
+    let Some(editors) = context.editors.upgrade() else {
+        return;
+    };
+
+    let mut editors = editors.write().await;
+    for editor_id in editor_ids {
+        let Some(editor) = editors.editor_mut(&editor_id) else {
+            continue;
+        };
+        let Some(path) = editor.buffer().file_path().cloned() else {
+            continue;
+        };
+        if editor.state().is_readonly || !editor.buffer().is_dirty() {
+            continue;
+        }
+
+        match editor.save().await {
+            Ok(()) => {
+                record_save(editor);
+                persist_undo_history(editor);
+                debug!("Auto-saved {}", path.display());
+            }
+            Err(e) => {
+                warn!("Auto-save of {} failed: {}", path.display(), e);
+                let event = UiEvent::Notification {
+                    level: NotificationLevel::Error,
+                    message: format!("Auto-save of {} failed: {}", path.display(), e),
+                    duration: Some(FAILURE_NOTIFICATION_DURATION),
+                };
+                if let Err(e) = context.app_handle.emit(UI_EVENT, &event) {
+                    warn!("Failed to emit auto-save notification: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Tell the backend the application window gained or lost focus
+///
+/// Losing focus saves every edited file when auto-save runs on window changes.
+#[command]
+#[instrument(skip(editors))]
+pub async fn set_window_focus(
+    editors: State<'_, EditorMap>,
+    has_focus: bool,
+) -> CommandResult<SuccessResponse> {
+    if !has_focus {
+        if let Some((AutoSaveMode::OnWindowChange, _)) = auto_save_settings().await {
+            let editor_ids = editors.read().await.keys().copied().collect();
+            auto_save(editor_ids).await;
+        }
+    }
+
+    debug!("Set window focus: {}", has_focus);
+    Ok(SuccessResponse::new("Window focus updated successfully"))
+}
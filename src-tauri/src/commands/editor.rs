@@ -1,9 +1,13 @@
+use super::formatting::{format_pasted, lsp_client};
 use super::{CommandError, CommandResult, EditorMap, SuccessResponse};
 use crate::core::{
-    Direction, EditorConfig, EditorEvent, EditorMetrics, EditorState, MovementUnit, Position,
-    Range, ViewState,
+    BlockSelection, BufferSnapshot, Direction, Editor, EditorCommand, EditorConfig, EditorEvent,
+    EditorMetrics, EditorState, MovementUnit, Position, Range, SelectionMode, SortOptions,
+    TextTransform, UndoBranch, UndoNodeId, UndoNodeInfo, ViewState, VimEffect, VimKey,
 };
+use crate::lsp::utils::path_to_uri;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use tauri::{command, State};
 use tracing::{debug, instrument};
 use uuid::Uuid;
@@ -38,6 +42,14 @@ pub struct CursorInfo {
     pub primary_position: Position,
 }
 
+/// Undo tree overview for the frontend
+#[derive(Debug, Serialize)]
+pub struct UndoHistory {
+    pub nodes: Vec<UndoNodeInfo>,
+    pub branches: Vec<UndoBranch>,
+    pub current: UndoNodeId,
+}
+
 /// Editor content response
 #[derive(Debug, Serialize)]
 pub struct EditorContent {
@@ -58,9 +70,9 @@ pub async fn get_editor_state(
         parameter: "editor_id".to_string(),
     })?;
 
-    let editors_guard = editors.read().await;
+    let mut editors_guard = editors.write().await;
     let editor = editors_guard
-        .get(&id)
+        .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
     Ok(editor.state().clone())
@@ -77,17 +89,27 @@ pub async fn get_editor_content(
         parameter: "editor_id".to_string(),
     })?;
 
+    let snapshot = snapshot_buffer(&editors, id).await?;
+
+    Ok(EditorContent {
+        text: snapshot.text(),
+        version: snapshot.version(),
+        line_count: snapshot.len_lines(),
+        char_count: snapshot.len_chars(),
+    })
+}
+
+/// Snapshot an editor's buffer, holding the map lock only while cloning the rope
+pub(crate) async fn snapshot_buffer(
+    editors: &EditorMap,
+    id: Uuid,
+) -> CommandResult<BufferSnapshot> {
     let editors_guard = editors.read().await;
     let editor = editors_guard
         .get(&id)
-        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+        .ok_or_else(|| CommandError::EditorNotFound { id: id.to_string() })?;
 
-    Ok(EditorContent {
-        text: editor.buffer().text(),
-        version: editor.buffer().version(),
-        line_count: editor.state().line_count,
-        char_count: editor.state().char_count,
-    })
+    Ok(editor.buffer().snapshot())
 }
 
 /// Get text in a specific range
@@ -136,7 +158,9 @@ pub async fn insert_text(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.insert_text(&request.text)?;
+    editor.execute(EditorCommand::InsertText {
+        text: request.text.clone(),
+    })?;
 
     debug!("Inserted text in editor {}: {:?}", id, request.text);
     Ok(SuccessResponse::new("Text inserted successfully"))
@@ -166,7 +190,7 @@ pub async fn type_character(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.type_char(ch)?;
+    editor.execute(EditorCommand::TypeChar { ch })?;
 
     debug!("Typed character in editor {}: {}", id, ch);
     Ok(SuccessResponse::new("Character typed successfully"))
@@ -188,7 +212,7 @@ pub async fn delete_selection(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.delete_selection()?;
+    editor.execute(EditorCommand::DeleteSelection)?;
 
     debug!("Deleted selection in editor {}", id);
     Ok(SuccessResponse::new("Selection deleted successfully"))
@@ -210,7 +234,7 @@ pub async fn backspace(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.backspace()?;
+    editor.execute(EditorCommand::Backspace)?;
 
     debug!("Backspace in editor {}", id);
     Ok(SuccessResponse::new("Backspace completed successfully"))
@@ -233,7 +257,11 @@ pub async fn move_cursors(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.move_cursors(request.direction, request.unit, request.extend_selection)?;
+    editor.execute(EditorCommand::MoveCursors {
+        direction: request.direction,
+        unit: request.unit,
+        extend_selection: request.extend_selection,
+    })?;
 
     debug!("Moved cursors in editor {}: {:?}", id, request);
     Ok(SuccessResponse::new("Cursors moved successfully"))
@@ -250,9 +278,9 @@ pub async fn get_cursor_info(
         parameter: "editor_id".to_string(),
     })?;
 
-    let editors_guard = editors.read().await;
+    let mut editors_guard = editors.write().await;
     let editor = editors_guard
-        .get(&id)
+        .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
     let cursor_manager = editor.cursor_manager();
@@ -283,7 +311,7 @@ pub async fn goto_position(
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
     let position = Position::new(request.line, request.column);
-    editor.goto_position(position)?;
+    editor.execute(EditorCommand::GotoPosition { position })?;
 
     debug!("Moved to position in editor {}: {:?}", id, position);
     Ok(SuccessResponse::new("Moved to position successfully"))
@@ -306,7 +334,7 @@ pub async fn goto_line(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.goto_line(line_number)?;
+    editor.execute(EditorCommand::GotoLine { line_number })?;
 
     debug!("Moved to line {} in editor {}", line_number, id);
     Ok(SuccessResponse::new("Moved to line successfully"))
@@ -327,12 +355,161 @@ pub async fn select_all(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.select_all()?;
+    editor.execute(EditorCommand::SelectAll)?;
 
     debug!("Selected all text in editor {}", id);
     Ok(SuccessResponse::new("Selected all text successfully"))
 }
 
+/// Switch between normal, line and block selection
+#[command]
+#[instrument(skip(editors))]
+pub async fn set_selection_mode(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    mode: SelectionMode,
+) -> CommandResult<SuccessResponse> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    editor.execute(EditorCommand::SetSelectionMode { mode })?;
+
+    debug!("Set selection mode for editor {}: {:?}", id, mode);
+    Ok(SuccessResponse::new("Selection mode updated successfully"))
+}
+
+/// Select a rectangle between two corners given in visual columns, e.g. from a mouse drag
+#[command]
+#[instrument(skip(editors))]
+pub async fn select_block(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    block: BlockSelection,
+) -> CommandResult<SuccessResponse> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    editor.execute(EditorCommand::SelectBlock { block })?;
+
+    debug!("Selected block {:?} in editor {}", block, id);
+    Ok(SuccessResponse::new("Block selected successfully"))
+}
+
+/// Expand all selections to the enclosing syntax node
+#[command]
+#[instrument(skip(editors))]
+pub async fn expand_selection(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::ExpandSelection)?.changed();
+
+    debug!("Expand selection in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Shrink all selections along the syntax tree
+#[command]
+#[instrument(skip(editors))]
+pub async fn shrink_selection(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::ShrinkSelection)?.changed();
+
+    debug!("Shrink selection in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Turn Vim modal editing on or off
+#[command]
+#[instrument(skip(editors))]
+pub async fn set_vim_enabled(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    enabled: bool,
+) -> CommandResult<SuccessResponse> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    editor.set_vim_enabled(enabled)?;
+
+    debug!("Set vim mode for editor {}: {}", id, enabled);
+    Ok(SuccessResponse::new(if enabled {
+        "Vim mode enabled"
+    } else {
+        "Vim mode disabled"
+    }))
+}
+
+/// Feed keys in Vim notation, such as `d2w` or `ihello<Esc>`, to the Vim layer
+///
+/// Returns the effects the frontend has to carry out, such as saving the file
+/// with `save_file` for `:w` or closing the editor for `:q`.
+#[command]
+#[instrument(skip(editors))]
+pub async fn vim_keys(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    keys: String,
+) -> CommandResult<Vec<VimEffect>> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let mut effects = Vec::new();
+    for key in VimKey::parse_keys(&keys) {
+        let effect = editor.handle_vim_key(key)?;
+        if effect != VimEffect::None {
+            effects.push(effect);
+        }
+    }
+
+    debug!("Handled vim keys {:?} in editor {}", keys, id);
+    Ok(effects)
+}
+
 /// Copy selected text
 #[command]
 #[instrument(skip(editors))]
@@ -344,9 +521,9 @@ pub async fn copy_selection(
         parameter: "editor_id".to_string(),
     })?;
 
-    let editors_guard = editors.read().await;
+    let mut editors_guard = editors.write().await;
     let editor = editors_guard
-        .get(&id)
+        .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
     let copied_text = editor.copy()?;
@@ -371,7 +548,7 @@ pub async fn cut_selection(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    let cut_text = editor.cut()?;
+    let cut_text = editor.execute(EditorCommand::Cut)?.into_text();
 
     debug!("Cut {} characters from editor {}", cut_text.len(), id);
     Ok(cut_text)
@@ -394,7 +571,23 @@ pub async fn paste_text(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.paste(&text)?;
+    let first_line = editor
+        .cursor_manager()
+        .cursors()
+        .iter()
+        .map(|cursor| cursor.selection_range().start.line)
+        .min()
+        .unwrap_or(0);
+    editor.execute(EditorCommand::Paste { text: text.clone() })?;
+    let last_line = editor
+        .cursor_manager()
+        .cursors()
+        .iter()
+        .map(|cursor| cursor.position.line)
+        .max()
+        .unwrap_or(first_line);
+    drop(editors_guard);
+    format_pasted(&editors, id, first_line..=last_line).await;
 
     debug!("Pasted {} characters to editor {}", text.len(), id);
     Ok(SuccessResponse::new("Text pasted successfully"))
@@ -413,7 +606,7 @@ pub async fn undo(editors: State<'_, EditorMap>, editor_id: String) -> CommandRe
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    let undone = editor.undo()?;
+    let undone = editor.execute(EditorCommand::Undo)?.changed();
 
     debug!("Undo operation in editor {}: {}", id, undone);
     Ok(undone)
@@ -432,12 +625,111 @@ pub async fn redo(editors: State<'_, EditorMap>, editor_id: String) -> CommandRe
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    let redone = editor.redo()?;
+    let redone = editor.execute(EditorCommand::Redo)?.changed();
 
     debug!("Redo operation in editor {}: {}", id, redone);
     Ok(redone)
 }
 
+/// Get the undo tree with all of its branches
+#[command]
+#[instrument(skip(editors))]
+pub async fn get_undo_history(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<UndoHistory> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let editors_guard = editors.read().await;
+    let editor = editors_guard
+        .get(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let tree = editor.buffer().undo_tree();
+    Ok(UndoHistory {
+        nodes: tree.nodes_info(),
+        branches: tree.branches(),
+        current: tree.current(),
+    })
+}
+
+/// Jump to any state in the undo tree
+#[command]
+#[instrument(skip(editors))]
+pub async fn jump_to_undo_node(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    node_id: UndoNodeId,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    if editor.buffer().undo_tree().node(node_id).is_none() {
+        return Err(CommandError::InvalidParameter {
+            parameter: "node_id".to_string(),
+        });
+    }
+
+    let jumped = editor.jump_to_undo_node(node_id)?;
+
+    debug!("Jumped to undo node {} in editor {}", node_id, id);
+    Ok(jumped)
+}
+
+/// Go back to the state the editor had the given number of seconds earlier
+#[command]
+#[instrument(skip(editors))]
+pub async fn undo_earlier(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    seconds: u64,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let moved = editor.undo_earlier(Duration::from_secs(seconds))?;
+
+    debug!("Went {}s back in editor {}: {}", seconds, id, moved);
+    Ok(moved)
+}
+
+/// Go forward to the state the editor had the given number of seconds later
+#[command]
+#[instrument(skip(editors))]
+pub async fn undo_later(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    seconds: u64,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let moved = editor.undo_later(Duration::from_secs(seconds))?;
+
+    debug!("Went {}s forward in editor {}: {}", seconds, id, moved);
+    Ok(moved)
+}
+
 /// Indent selected lines
 #[command]
 #[instrument(skip(editors))]
@@ -454,7 +746,7 @@ pub async fn indent_lines(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.indent_lines()?;
+    editor.execute(EditorCommand::IndentLines)?;
 
     debug!("Indented lines in editor {}", id);
     Ok(SuccessResponse::new("Lines indented successfully"))
@@ -476,12 +768,309 @@ pub async fn unindent_lines(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.unindent_lines()?;
+    editor.execute(EditorCommand::UnindentLines)?;
 
     debug!("Unindented lines in editor {}", id);
     Ok(SuccessResponse::new("Lines unindented successfully"))
 }
 
+/// Reindent the selected lines as the language's syntax calls for
+///
+/// Returns whether any line changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn reindent_selection(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::ReindentSelection)?.changed();
+
+    debug!("Reindented selection in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Move the selected lines, or the cursor lines, up by one line
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn move_lines_up(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::MoveLinesUp)?.changed();
+
+    debug!("Moved lines up in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Move the selected lines, or the cursor lines, down by one line
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn move_lines_down(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::MoveLinesDown)?.changed();
+
+    debug!("Moved lines down in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Duplicate the selections, or the cursor lines when nothing is selected
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn duplicate_lines(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::DuplicateLines)?.changed();
+
+    debug!("Duplicated lines in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Snapshot and selections for rust-analyzer to join, when it runs for the buffer
+fn rust_analyzer_join_input(editor: &Editor) -> Option<(BufferSnapshot, Vec<Range>)> {
+    if editor.state().language.as_deref() != Some("rust") || editor.is_recording_macro() {
+        return None;
+    }
+    lsp_client()?;
+
+    let snapshot = editor.buffer().snapshot();
+    snapshot.file_path()?;
+    let ranges = editor
+        .cursor_manager()
+        .cursors()
+        .iter()
+        .map(|cursor| cursor.selection_range())
+        .collect();
+    Some((snapshot, ranges))
+}
+
+/// Text of a snapshot with the selections joined by rust-analyzer
+async fn rust_analyzer_join(snapshot: &BufferSnapshot, ranges: &[Range]) -> Option<String> {
+    let client = lsp_client()?;
+    let uri = path_to_uri(snapshot.file_path()?).ok()?;
+
+    match client.join_lines(uri, snapshot, ranges).await {
+        Ok(joined) => Some(joined),
+        Err(e) => {
+            debug!("Joining lines without rust-analyzer: {}", e);
+            None
+        }
+    }
+}
+
+/// Join the selected lines, or each cursor line with the next one
+///
+/// Rust buffers are joined by rust-analyzer when it is running, which knows
+/// where Rust wants no space, such as before `.method()`. The editors aren't
+/// locked while it works, and if the buffer changes in the meantime the
+/// editor's own joining is used instead. So is it while a macro is recorded,
+/// so the macro replays the same way. Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn join_lines(editors: State<'_, EditorMap>, editor_id: String) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let input = {
+        let mut editors_guard = editors.write().await;
+        let editor = editors_guard
+            .get_mut(&id)
+            .ok_or_else(|| CommandError::EditorNotFound {
+                id: editor_id.clone(),
+            })?;
+        rust_analyzer_join_input(editor)
+    };
+    let joined = match &input {
+        Some((snapshot, ranges)) => rust_analyzer_join(snapshot, ranges)
+            .await
+            .map(|joined| (snapshot.version(), joined)),
+        None => None,
+    };
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = match joined {
+        Some((version, joined)) if editor.buffer().version() == version => {
+            editor.apply_text(&joined)?
+        }
+        _ => editor.execute(EditorCommand::JoinLines)?.changed(),
+    };
+
+    debug!("Joined lines in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Sort the selected lines, or the whole buffer when nothing is selected
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn sort_lines(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    options: SortOptions,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor
+        .execute(EditorCommand::SortLines { options })?
+        .changed();
+
+    debug!("Sorted lines in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Reverse the selected lines, or the whole buffer when nothing is selected
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn reverse_lines(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::ReverseLines)?.changed();
+
+    debug!("Reversed lines in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Swap the selected lines, or the cursor lines, with the line above them
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn transpose_lines(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::TransposeLines)?.changed();
+
+    debug!("Transposed lines in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Delete the selected lines, or the cursor lines
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn delete_lines(editors: State<'_, EditorMap>, editor_id: String) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor.execute(EditorCommand::DeleteLines)?.changed();
+
+    debug!("Deleted lines in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
+/// Transform the text of each selection, or of the word at each cursor
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn transform_selections(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    transform: TextTransform,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let changed = editor
+        .execute(EditorCommand::TransformSelections { transform })?
+        .changed();
+
+    debug!("Transformed selections in editor {}: {}", id, changed);
+    Ok(changed)
+}
+
 /// Toggle line comments
 #[command]
 #[instrument(skip(editors))]
@@ -498,7 +1087,7 @@ pub async fn toggle_line_comment(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    editor.toggle_line_comment()?;
+    editor.execute(EditorCommand::ToggleLineComment)?;
 
     debug!("Toggled line comments in editor {}", id);
     Ok(SuccessResponse::new("Line comments toggled successfully"))
@@ -515,9 +1104,9 @@ pub async fn get_current_line(
         parameter: "editor_id".to_string(),
     })?;
 
-    let editors_guard = editors.read().await;
+    let mut editors_guard = editors.write().await;
     let editor = editors_guard
-        .get(&id)
+        .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
     let line_text = editor.current_line()?;
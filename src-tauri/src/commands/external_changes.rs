@@ -0,0 +1,259 @@
+use super::{CommandError, CommandResult, EditorMap};
+use crate::core::{Editor, EditorResult, ExternalChange, FileEncoding, FileStamp, TextBuffer};
+use crate::utils::file_watcher::{self, FileEvent, WatchConfig};
+use crate::utils::normalize_path;
+use once_cell::sync::Lazy;
+use ropey::Rope;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle, Emitter, State};
+use tokio::sync::Mutex;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+/// Event sent to the frontend when an open file changed on disk
+pub const EXTERNAL_CHANGE_EVENT: &str = "file-changed-externally";
+
+/// Payload of [`EXTERNAL_CHANGE_EVENT`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ExternalChangeEvent {
+    pub editor_id: String,
+    pub path: PathBuf,
+    pub change: ExternalChange,
+}
+
+/// Directories watched for open files, with their watcher and number of open files
+static WATCHED_DIRS: Lazy<Mutex<HashMap<PathBuf, (Uuid, usize)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Start routing file watcher events to the open editors
+///
+/// Must be called once during app setup, before files are opened.
+pub fn init_external_change_sync(app_handle: AppHandle, editors: EditorMap) {
+    let mut events = file_watcher::init_file_watcher();
+
+    tauri::async_runtime::spawn(async move {
+        while let Some((_, event)) = events.recv().await {
+            let path = match event {
+                FileEvent::Created { path } | FileEvent::Modified { path } => path,
+                FileEvent::Renamed { new_path, .. } => new_path,
+                FileEvent::Deleted { path } => path,
+                FileEvent::MetadataChanged { .. } => continue,
+            };
+
+            for change in sync_editors_with_disk(&editors, &path).await {
+                if let Err(e) = app_handle.emit(EXTERNAL_CHANGE_EVENT, &change) {
+                    warn!("Failed to emit external change event: {}", e);
+                }
+            }
+        }
+    });
+
+    info!("External change detection started");
+}
+
+/// Watch the directory of an open file for changes
+///
+/// Directories are watched rather than files so that files replaced by a
+/// rename, as most tools save them, keep being tracked.
+pub(crate) async fn watch_file(path: &Path) {
+    let Some(watcher) = file_watcher::try_get_file_watcher() else {
+        return;
+    };
+    let Some(dir) = normalize_path(path).parent().map(Path::to_path_buf) else {
+        return;
+    };
+
+    let mut watched = WATCHED_DIRS.lock().await;
+    if let Some((_, count)) = watched.get_mut(&dir) {
+        *count += 1;
+        return;
+    }
+
+    let config = WatchConfig {
+        recursive: false,
+        ..WatchConfig::default()
+    };
+
+    match watcher.watch_path(&dir, config).await {
+        Ok(watcher_id) => {
+            watched.insert(dir, (watcher_id, 1));
+        }
+        Err(e) => warn!("Failed to watch {}: {}", dir.display(), e),
+    }
+}
+
+/// Stop watching the directory of a file once no open file needs it
+pub(crate) async fn unwatch_file(path: &Path) {
+    let Some(watcher) = file_watcher::try_get_file_watcher() else {
+        return;
+    };
+    let Some(dir) = normalize_path(path).parent().map(Path::to_path_buf) else {
+        return;
+    };
+
+    let mut watched = WATCHED_DIRS.lock().await;
+    let Some((watcher_id, count)) = watched.get_mut(&dir) else {
+        return;
+    };
+
+    *count -= 1;
+    if *count == 0 {
+        let watcher_id = *watcher_id;
+        watched.remove(&dir);
+
+        if let Err(e) = watcher.stop_watching(watcher_id).await {
+            warn!("Failed to stop watching {}: {}", dir.display(), e);
+        }
+    }
+}
+
+/// Contents of a changed file, read in the encoding of the editor showing it
+enum DiskContents {
+    Text(String),
+    /// Large files are read in chunks straight into a rope
+    Large(Rope),
+}
+
+impl DiskContents {
+    async fn read(path: &Path, encoding: FileEncoding, large_file: bool) -> EditorResult<Self> {
+        if large_file {
+            Ok(Self::Large(TextBuffer::read_rope(path, encoding).await?))
+        } else {
+            let bytes = tokio::fs::read(path).await?;
+            Ok(Self::Text(encoding.decode(&bytes)?))
+        }
+    }
+
+    fn reconcile(self, editor: &mut Editor) -> EditorResult<ExternalChange> {
+        match self {
+            Self::Text(text) => editor.reconcile_with_disk(&text),
+            Self::Large(rope) => editor.reconcile_large_file(rope),
+        }
+    }
+}
+
+/// Reconcile every editor showing the given file with its contents on disk
+///
+/// Editors that last loaded or saved the file at its current size and
+/// modification time are skipped without reading it, which covers our own
+/// saves. The file is read without holding the editors lock.
+async fn sync_editors_with_disk(editors: &EditorMap, path: &Path) -> Vec<ExternalChangeEvent> {
+    let path = normalize_path(path);
+    let stamp = FileStamp::read(&path).await.ok();
+
+    let stale: Vec<(Uuid, FileEncoding, bool)> = editors
+        .read()
+        .await
+        .values()
+        .filter(|editor| {
+            let buffer = editor.buffer();
+            buffer.file_path().map(normalize_path).as_ref() == Some(&path)
+                && (stamp.is_none() || buffer.disk_stamp() != stamp)
+        })
+        .map(|editor| {
+            let buffer = editor.buffer();
+            (editor.id(), buffer.encoding(), buffer.is_large_file())
+        })
+        .collect();
+
+    let mut changes = Vec::new();
+    for (id, encoding, large_file) in stale {
+        let contents = match stamp {
+            Some(_) => match DiskContents::read(&path, encoding, large_file).await {
+                Ok(contents) => Some(contents),
+                Err(e) => {
+                    warn!("Failed to read changed file {}: {}", path.display(), e);
+                    continue;
+                }
+            },
+            None if !path.exists() => None,
+            None => continue,
+        };
+
+        let mut editors_guard = editors.write().await;
+        let Some(editor) = editors_guard.editor_mut(&id) else {
+            continue;
+        };
+
+        let change = match contents {
+            // Saved again while the file was being read
+            Some(_) if editor.buffer().disk_stamp() == stamp => continue,
+            Some(contents) => contents.reconcile(editor),
+            None => Ok(editor.handle_file_deleted()),
+        };
+        if change.is_ok() {
+            editor.buffer_mut().set_disk_stamp(stamp);
+        }
+
+        match change {
+            Ok(ExternalChange::Unchanged) => {}
+            Ok(change) => changes.push(ExternalChangeEvent {
+                editor_id: editor.id().to_string(),
+                path: path.clone(),
+                change,
+            }),
+            Err(e) => warn!("Failed to reload {}: {}", path.display(), e),
+        }
+    }
+
+    debug!(
+        "{} editors updated after {} changed",
+        changes.len(),
+        path.display()
+    );
+    changes
+}
+
+/// Check the file of an editor for changes on disk and reconcile them
+#[command]
+#[instrument(skip(editors))]
+pub async fn reload_from_disk(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<ExternalChange> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let (path, encoding, large_file) = {
+        let editors_guard = editors.read().await;
+        let editor = editors_guard
+            .get(&id)
+            .ok_or_else(|| CommandError::EditorNotFound {
+                id: editor_id.clone(),
+            })?;
+        let buffer = editor.buffer();
+
+        let path = buffer
+            .file_path()
+            .cloned()
+            .ok_or_else(|| CommandError::FileError {
+                message: "No file associated with editor".to_string(),
+            })?;
+        (path, buffer.encoding(), buffer.is_large_file())
+    };
+
+    // Read without holding the editors lock, large files can take a while
+    let stamp = FileStamp::read(&path).await.ok();
+    let contents = match DiskContents::read(&path, encoding, large_file).await {
+        Ok(contents) => Some(contents),
+        Err(_) if !path.exists() => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let change = match contents {
+        Some(contents) => contents.reconcile(editor)?,
+        None => editor.handle_file_deleted(),
+    };
+    editor.buffer_mut().set_disk_stamp(stamp);
+
+    debug!("Reloaded editor {} from disk: {:?}", id, change);
+    Ok(change)
+}
@@ -1,5 +1,10 @@
+use super::auto_save::enable_auto_save;
+use super::external_changes::{unwatch_file, watch_file};
+use super::formatting::format_before_save;
+use super::history::{persist_undo_history, record_save, restore_undo_history};
+use super::recovery::{remove_swap_file, track_unsaved_changes};
 use super::{CommandError, CommandResult, EditorMap, SuccessResponse};
-use crate::core::{Editor, EditorConfig};
+use crate::core::{Editor, EditorConfig, FileEncoding};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use tauri::{command, AppHandle, Manager, State};
@@ -41,11 +46,13 @@ pub async fn create_editor(
     editors: State<'_, EditorMap>,
     config: Option<EditorConfig>,
 ) -> CommandResult<String> {
-    let editor = if let Some(config) = config {
+    let mut editor = if let Some(config) = config {
         Editor::with_config(config)
     } else {
         Editor::new()
     };
+    track_unsaved_changes(&editors, &mut editor);
+    enable_auto_save(&mut editor);
 
     let editor_id = editor.id();
     let editor_id_str = editor_id.to_string();
@@ -56,6 +63,31 @@ pub async fn create_editor(
     Ok(editor_id_str)
 }
 
+/// Open another view onto the buffer of an editor, for showing it in a second pane
+///
+/// The view shares the buffer and undo history with the editor's other views
+/// and has its own cursors, scrolling and folds. Commands take its id in
+/// place of the editor's. Returns the id of the new view.
+#[command]
+#[instrument(skip(editors))]
+pub async fn split_editor(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<String> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let view_id = editors
+        .write()
+        .await
+        .split(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })??;
+
+    info!("Split view {} from editor {}", view_id, id);
+    Ok(view_id.to_string())
+}
+
 /// Open a file in a new or exisiting editor
 #[command]
 #[instrument(skip(editors))]
@@ -87,17 +119,21 @@ pub async fn open_file(
 
         let editor = editors_guard
             .get_mut(&id)
-            .ok_or_else(|| CommandError::EditorNotFound { id: id_str });
+            .ok_or_else(|| CommandError::EditorNotFound { id: id_str })?;
 
         (id, editor)
     } else {
-        let editor = Editor::new();
+        let mut editor = Editor::new();
+        track_unsaved_changes(&editors, &mut editor);
+        enable_auto_save(&mut editor);
         let id = editor.id();
         editors_guard.insert(id, editor);
         let editor = editors_guard.get_mut(&id).unwrap();
         (id, editor)
     };
 
+    let previous_path = editor.buffer().file_path().cloned();
+
     editor
         .load_file(&file_path)
         .await
@@ -105,6 +141,13 @@ pub async fn open_file(
             message: format!("Failed to load file: {}", e),
         })?;
 
+    restore_undo_history(editor);
+
+    if let Some(previous_path) = previous_path {
+        unwatch_file(&previous_path).await;
+    }
+    watch_file(&file_path).await;
+
     info!("Opened file {} in editor {}", path, editor_id);
     Ok(editor_id.to_string())
 }
@@ -120,15 +163,20 @@ pub async fn save_file(
         parameter: "editor_id".to_string(),
     })?;
 
+    format_before_save(&editors, id).await;
+
     let mut editors_guard = editors.write().await;
     let editor = editors_guard
-        .get_mut(&did)
+        .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
     editor.save().await.map_err(|e| CommandError::FileError {
         message: format!("Failed to save file: {}", e),
     })?;
 
+    record_save(editor);
+    persist_undo_history(editor);
+
     info!("Saved file for editor {}", id);
     Ok(SuccessResponse::new("File saved successfully"))
 }
@@ -147,11 +195,14 @@ pub async fn save_file_as(
 
     let file_path = PathBuf::from(&path);
 
+    format_before_save(&editors, id).await;
+
     let mut editors_guard = editors.write().await;
     let editor = editors_guard
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
+    let previous_path = editor.buffer().file_path().cloned();
     editor
         .save_as(&file_path)
         .await
@@ -159,11 +210,72 @@ pub async fn save_file_as(
             message: format!("Failed to save file: {}", e),
         })?;
 
+    record_save(editor);
+    persist_undo_history(editor);
+
+    if let Some(previous_path) = previous_path {
+        unwatch_file(&previous_path).await;
+    }
+    watch_file(&file_path).await;
+
     info!("Saved file as {} for editor {}", path, id);
     Ok(SuccessResponse::new("File saved successfully"))
 }
 
+/// Reload the file of an editor in a different encoding, discarding unsaved changes
+#[command]
+#[instrument(skip(editors))]
+pub async fn reopen_with_encoding(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    encoding: FileEncoding,
+) -> CommandResult<SuccessResponse> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    editor.reopen_with_encoding(encoding).await?;
+    restore_undo_history(editor);
+
+    info!("Reopened editor {} as {}", id, encoding);
+    Ok(SuccessResponse::new(format!("Reopened as {}", encoding)))
+}
+
+/// Save the file of an editor in a different encoding
+#[command]
+#[instrument(skip(editors))]
+pub async fn save_with_encoding(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    encoding: FileEncoding,
+) -> CommandResult<SuccessResponse> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    editor.save_with_encoding(encoding).await?;
+
+    record_save(editor);
+    persist_undo_history(editor);
+
+    info!("Saved editor {} as {}", id, encoding);
+    Ok(SuccessResponse::new(format!("Saved as {}", encoding)))
+}
+
 /// Close and editor instance
+///
+/// Closing one of several views of an editor only closes that view; the
+/// editor closes with its last view.
 #[command]
 #[instrument(skip(editors))]
 pub async fn close_editor(
@@ -175,10 +287,25 @@ pub async fn close_editor(
     })?;
 
     let mut editors_guard = editors.write().await;
-    editors_guard
+    if editors_guard.close_view(&id) {
+        info!("Closed view {}", id);
+        return Ok(SuccessResponse::new("View closed successfully"));
+    }
+
+    let editor = editors_guard
         .remove(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
+    drop(editors_guard);
+
+    // Left out when there are unsaved changes, keeping the last save's history
+    persist_undo_history(&editor);
+    remove_swap_file(editor.id()).await;
+
+    if let Some(path) = editor.buffer().file_path() {
+        unwatch_file(path).await;
+    }
+
     info!("Closed editor {}", id);
     Ok(SuccessResponse::new("Editor closed successfully"))
 }
@@ -0,0 +1,140 @@
+use super::{CommandError, CommandResult, EditorMap};
+use crate::core::{EditorCommand, FoldingRange};
+use tauri::{command, State};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+/// Run a folding command and return the folded ranges afterwards
+async fn run_fold_command(
+    editors: &EditorMap,
+    editor_id: String,
+    fold_command: EditorCommand,
+) -> CommandResult<Vec<FoldingRange>> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let output = editor.execute(fold_command)?;
+    let folded = editor.folded_ranges();
+
+    debug!(
+        "Folding changed {} ranges in editor {}, {} folded",
+        output.count(),
+        id,
+        folded.len()
+    );
+    Ok(folded)
+}
+
+/// Get the ranges that can be folded
+#[command]
+#[instrument(skip(editors))]
+pub async fn get_folding_ranges(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<Vec<FoldingRange>> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    Ok(editor.folding_ranges()?)
+}
+
+/// Get the folded ranges
+#[command]
+#[instrument(skip(editors))]
+pub async fn get_folded_ranges(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<Vec<FoldingRange>> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    Ok(editor.folded_ranges())
+}
+
+/// Fold the innermost unfolded range containing a line
+#[command]
+#[instrument(skip(editors))]
+pub async fn fold(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    line: usize,
+) -> CommandResult<Vec<FoldingRange>> {
+    run_fold_command(editors.inner(), editor_id, EditorCommand::Fold { line }).await
+}
+
+/// Unfold the innermost fold containing a line, or every fold around and inside it
+#[command]
+#[instrument(skip(editors))]
+pub async fn unfold(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    line: usize,
+    recursive: bool,
+) -> CommandResult<Vec<FoldingRange>> {
+    run_fold_command(
+        editors.inner(),
+        editor_id,
+        EditorCommand::Unfold { line, recursive },
+    )
+    .await
+}
+
+/// Fold every foldable range
+#[command]
+#[instrument(skip(editors))]
+pub async fn fold_all(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<Vec<FoldingRange>> {
+    run_fold_command(editors.inner(), editor_id, EditorCommand::FoldAll).await
+}
+
+/// Fold every range nested a given depth, where top-level ranges are level 1
+#[command]
+#[instrument(skip(editors))]
+pub async fn fold_level(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    level: usize,
+) -> CommandResult<Vec<FoldingRange>> {
+    if level == 0 {
+        return Err(CommandError::InvalidParameter {
+            parameter: "level".to_string(),
+        });
+    }
+
+    run_fold_command(
+        editors.inner(),
+        editor_id,
+        EditorCommand::FoldLevel { level },
+    )
+    .await
+}
+
+/// Unfold everything
+#[command]
+#[instrument(skip(editors))]
+pub async fn unfold_all(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<Vec<FoldingRange>> {
+    run_fold_command(editors.inner(), editor_id, EditorCommand::UnfoldAll).await
+}
@@ -0,0 +1,291 @@
+use super::{CommandError, CommandResult, EditorMap};
+use crate::core::traits::{DocumentFormatter, FormattingOptions};
+use crate::core::{BufferSnapshot, Editor, Position, Range, RustfmtFormatter};
+use crate::lsp::utils::{formatting_options, path_to_uri};
+use crate::lsp::LspClient;
+use crate::utils::config::{get_config_manager, EditorSettings};
+use crate::utils::FormatterKind;
+use once_cell::sync::OnceCell;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{command, State};
+use tokio::time::timeout;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+static LSP_CLIENT: OnceCell<Arc<LspClient>> = OnceCell::new();
+
+/// Longest a formatter may take before its result is given up on
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Let formatting and line joining use the language servers of a client
+///
+/// Must be called once during app setup. Without it only rustfmt and the
+/// editor's own line joining are used.
+pub fn init_formatting(lsp_client: Arc<LspClient>) {
+    if LSP_CLIENT.set(lsp_client).is_err() {
+        warn!("Formatting already initialized");
+        return;
+    }
+    info!("Formatting with language servers enabled");
+}
+
+/// Client whose language servers edits can be asked of, once initialized
+pub(crate) fn lsp_client() -> Option<&'static LspClient> {
+    LSP_CLIENT.get().map(Arc::as_ref)
+}
+
+/// Current editor settings, or the defaults before the config is loaded
+async fn editor_settings() -> EditorSettings {
+    match get_config_manager() {
+        Ok(manager) => manager.get_editor_settings().await,
+        Err(_) => EditorSettings::default(),
+    }
+}
+
+/// Formatted text of a buffer snapshot, limited to `range` when given
+///
+/// Rust goes through rustfmt unless the settings say otherwise, anything
+/// else through the language server. `None` means formatting is disabled
+/// for the language.
+async fn formatted_text(
+    snapshot: &BufferSnapshot,
+    language: Option<&str>,
+    range: Option<Range>,
+    settings: &EditorSettings,
+) -> Result<Option<String>, String> {
+    let formatter = settings.format_settings(language).formatter;
+    let use_rustfmt = match formatter {
+        FormatterKind::Disabled => return Ok(None),
+        FormatterKind::Rustfmt => true,
+        FormatterKind::LanguageServer => false,
+        FormatterKind::Auto => language == Some("rust"),
+    };
+
+    let options = FormattingOptions {
+        tab_size: settings.tab_size,
+        insert_spaces: !settings.use_tabs,
+        ..FormattingOptions::default()
+    };
+
+    let mut rustfmt_error = None;
+    if use_rustfmt {
+        let mut rustfmt = snapshot
+            .file_path()
+            .map_or_else(RustfmtFormatter::new, RustfmtFormatter::for_file);
+        rustfmt.set_options(options.clone());
+
+        // The text is only copied out of the snapshot on the blocking thread
+        let input = snapshot.clone();
+        let rustfmt_range = range.clone();
+        let result = tokio::task::spawn_blocking(move || match rustfmt_range {
+            Some(range) => rustfmt.format_range(&input.text(), range),
+            None => rustfmt.format_document(&input.text()),
+        })
+        .await
+        .map_err(|e| format!("rustfmt task failed: {}", e))?;
+
+        match result {
+            Ok(formatted) => return Ok(Some(formatted)),
+            Err(e) if formatter == FormatterKind::Rustfmt => return Err(e),
+            Err(e) => {
+                debug!("rustfmt failed, trying the language server: {}", e);
+                rustfmt_error = Some(e);
+            }
+        }
+    }
+
+    let unavailable = || {
+        rustfmt_error.clone().unwrap_or_else(|| {
+            format!(
+                "No formatter available for {}",
+                language.unwrap_or("plain text")
+            )
+        })
+    };
+    let Some(lsp_client) = lsp_client() else {
+        return Err(unavailable());
+    };
+    let Some(path) = snapshot.file_path() else {
+        return Err(unavailable());
+    };
+
+    let uri = path_to_uri(path).map_err(|e| e.to_string())?;
+    lsp_client
+        .format_document(uri, snapshot, formatting_options(&options), range)
+        .await
+        .map(Some)
+        .map_err(|e| rustfmt_error.unwrap_or_else(|| e.to_string()))
+}
+
+/// Format ranges of an editor's buffer, or all of it for `None`, as one undo step
+///
+/// The editors are only locked to take a snapshot and to apply the result, so
+/// a slow formatter doesn't hold up other commands. Each range is formatted
+/// separately. Returns whether the text changed.
+async fn format_editor(
+    editors: &EditorMap,
+    id: Uuid,
+    ranges: Option<Vec<Range>>,
+    settings: &EditorSettings,
+) -> Result<bool, String> {
+    let (snapshot, language) = {
+        let editors = editors.read().await;
+        let editor = editors.get(&id).ok_or("Editor was closed")?;
+        (editor.buffer().snapshot(), editor.state().language.clone())
+    };
+
+    let ranges = match ranges {
+        Some(ranges) => ranges.into_iter().map(Some).collect(),
+        None => vec![None],
+    };
+    let mut formatted = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        let result = timeout(
+            FORMAT_TIMEOUT,
+            formatted_text(&snapshot, language.as_deref(), range, settings),
+        )
+        .await
+        .map_err(|_| format!("Formatting took longer than {:?}", FORMAT_TIMEOUT))?;
+        match result? {
+            Some(text) => formatted.push(text),
+            None => return Ok(false),
+        }
+    }
+
+    let mut editors = editors.write().await;
+    let editor = editors.get_mut(&id).ok_or("Editor was closed")?;
+    if editor.buffer().version() != snapshot.version() {
+        return Err("Buffer changed while formatting".to_string());
+    }
+
+    let texts: Vec<&str> = formatted.iter().map(String::as_str).collect();
+    editor.apply_texts(&texts).map_err(|e| e.to_string())
+}
+
+/// Range covering whole lines of a buffer
+fn line_range(editor: &Editor, lines: RangeInclusive<usize>) -> Range {
+    let last = *lines.end();
+    let end_column = editor.buffer().line_len(last).unwrap_or(0);
+    Range::new(
+        Position::new(*lines.start(), 0),
+        Position::new(last, end_column),
+    )
+}
+
+/// Format an editor's buffer before it is saved, if its language asks for it
+///
+/// Must be called without the editors locked. Failing to format is logged and
+/// does not stop the save.
+pub(crate) async fn format_before_save(editors: &EditorMap, id: Uuid) {
+    let settings = editor_settings().await;
+    let language = match editors.read().await.get(&id) {
+        Some(editor) => editor.state().language.clone(),
+        None => return,
+    };
+    if !settings.format_settings(language.as_deref()).on_save {
+        return;
+    }
+
+    if let Err(e) = format_editor(editors, id, None, &settings).await {
+        warn!("Format on save failed for editor {}: {}", id, e);
+    }
+}
+
+/// Format the lines text was just pasted into, if its language asks for it
+///
+/// Must be called without the editors locked.
+pub(crate) async fn format_pasted(editors: &EditorMap, id: Uuid, lines: RangeInclusive<usize>) {
+    let settings = editor_settings().await;
+    let range = {
+        let editors = editors.read().await;
+        let Some(editor) = editors.get(&id) else {
+            return;
+        };
+        if !settings
+            .format_settings(editor.state().language.as_deref())
+            .on_paste
+        {
+            return;
+        }
+        line_range(editor, lines)
+    };
+
+    if let Err(e) = format_editor(editors, id, Some(vec![range]), &settings).await {
+        warn!("Format on paste failed for editor {}: {}", id, e);
+    }
+}
+
+/// Format the whole buffer of an editor
+///
+/// Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn format_document(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    if editors.read().await.get(&id).is_none() {
+        return Err(CommandError::EditorNotFound { id: editor_id });
+    }
+
+    let settings = editor_settings().await;
+    let changed = format_editor(&editors, id, None, &settings)
+        .await
+        .map_err(|message| CommandError::OperationFailed { message })?;
+
+    debug!("Formatted editor {} (changed: {})", id, changed);
+    Ok(changed)
+}
+
+/// Format the lines the selections of an editor cover
+///
+/// Each selection is formatted on its own, and without a selection the
+/// cursor's line is. Returns whether the text changed.
+#[command]
+#[instrument(skip(editors))]
+pub async fn format_selection(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let ranges: Vec<Range> = {
+        let mut editors_guard = editors.write().await;
+        let editor = editors_guard
+            .get_mut(&id)
+            .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+        editor
+            .cursor_manager()
+            .cursors()
+            .iter()
+            .map(|cursor| {
+                let range = cursor.selection_range();
+                if cursor.has_selection {
+                    Range::new(Position::new(range.start.line, 0), range.end)
+                } else {
+                    line_range(editor, range.start.line..=range.end.line)
+                }
+            })
+            .collect()
+    };
+
+    let settings = editor_settings().await;
+    let changed = format_editor(&editors, id, Some(ranges), &settings)
+        .await
+        .map_err(|message| CommandError::OperationFailed { message })?;
+
+    debug!(
+        "Formatted selection in editor {} (changed: {})",
+        id, changed
+    );
+    Ok(changed)
+}
@@ -0,0 +1,126 @@
+use super::{CommandError, CommandResult, EditorMap, SuccessResponse};
+use crate::core::Editor;
+use crate::utils::history_store::{get_history_store, SavedVersion};
+use crate::utils::UtilError;
+use std::path::PathBuf;
+use tauri::{command, State};
+use tracing::{debug, instrument, warn};
+use uuid::Uuid;
+
+/// Restore the undo history of a previous session for a freshly loaded editor
+pub(crate) fn restore_undo_history(editor: &mut Editor) {
+    let Some(path) = editor.buffer().file_path().cloned() else {
+        return;
+    };
+
+    let tree = get_history_store()
+        .and_then(|store| store.load_undo_history(&path, &editor.buffer().text()));
+
+    match tree {
+        Ok(Some(tree)) => {
+            editor.buffer_mut().set_undo_tree(tree);
+            debug!("Restored undo history for {}", path.display());
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Failed to load undo history for {}: {}", path.display(), e),
+    }
+}
+
+/// Persist the undo history of an editor so it survives a restart
+///
+/// The history is tied to the text on disk, since that is what the buffer
+/// holds when the file is opened again. Buffers with unsaved changes, or
+/// with text that saving cleans up, keep the history stored at their last
+/// save instead.
+pub(crate) fn persist_undo_history(editor: &Editor) {
+    let Some(path) = editor.buffer().file_path() else {
+        return;
+    };
+
+    let result =
+        get_history_store().and_then(|store| store.save_buffer_history(path, editor.buffer()));
+
+    if let Err(e) = result {
+        warn!("Failed to store undo history for {}: {}", path.display(), e);
+    }
+}
+
+/// Add the content just written to disk to the file's save timeline
+///
+/// Large files are left out, as every entry holds a full copy of the file.
+pub(crate) fn record_save(editor: &Editor) {
+    let Some(path) = editor.buffer().file_path() else {
+        return;
+    };
+    if editor.buffer().is_large_file() {
+        return;
+    }
+
+    let result = get_history_store()
+        .and_then(|store| store.record_save(path, &editor.buffer().content_for_save()));
+
+    if let Err(e) = result {
+        warn!("Failed to record save of {}: {}", path.display(), e);
+    }
+}
+
+/// List the saved versions of a file, newest first
+#[command]
+#[instrument]
+pub async fn list_saved_versions(path: String) -> CommandResult<Vec<SavedVersion>> {
+    let store = get_history_store().map_err(|e| CommandError::OperationFailed {
+        message: e.to_string(),
+    })?;
+
+    store
+        .list_saves(&PathBuf::from(path))
+        .map_err(|e| CommandError::OperationFailed {
+            message: format!("Failed to list saved versions: {}", e),
+        })
+}
+
+/// Get the content of a saved version
+#[command]
+#[instrument]
+pub async fn get_saved_version(version_id: i64) -> CommandResult<String> {
+    let store = get_history_store().map_err(|e| CommandError::OperationFailed {
+        message: e.to_string(),
+    })?;
+
+    store.saved_content(version_id).map_err(|e| match e {
+        UtilError::NotFound { .. } => CommandError::InvalidParameter {
+            parameter: "version_id".to_string(),
+        },
+        UtilError::Io(e) => CommandError::IoError {
+            message: e.to_string(),
+        },
+        e => CommandError::InternalError {
+            message: format!("Failed to read saved version: {}", e),
+        },
+    })
+}
+
+/// Replace the editor contents with a saved version, as an undoable edit
+#[command]
+#[instrument(skip(editors))]
+pub async fn restore_saved_version(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    version_id: i64,
+) -> CommandResult<SuccessResponse> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let content = get_saved_version(version_id).await?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    editor.replace_contents(&content)?;
+
+    debug!("Restored saved version {} in editor {}", version_id, id);
+    Ok(SuccessResponse::new("Saved version restored successfully"))
+}
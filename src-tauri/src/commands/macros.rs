@@ -0,0 +1,147 @@
+use super::{CommandError, CommandResult, EditorMap, SuccessResponse};
+use crate::core::Macro;
+use crate::utils::macro_store::{get_macro_store, MacroStore};
+use serde::Deserialize;
+use tauri::{command, State};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+/// Request to replay a saved macro
+#[derive(Debug, Deserialize)]
+pub struct PlayMacroRequest {
+    pub name: String,
+    /// Number of times to replay the macro, once if not given
+    pub times: Option<usize>,
+    /// Replay once from each cursor instead of with all cursors at once
+    #[serde(default)]
+    pub on_every_cursor: bool,
+}
+
+fn macro_store() -> CommandResult<&'static MacroStore> {
+    get_macro_store().map_err(|e| CommandError::OperationFailed {
+        message: e.to_string(),
+    })
+}
+
+/// Start recording the editor's commands into a named macro
+#[command]
+#[instrument(skip(editors))]
+pub async fn start_macro_recording(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    name: String,
+) -> CommandResult<SuccessResponse> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    if name.trim().is_empty() {
+        return Err(CommandError::InvalidParameter {
+            parameter: "name".to_string(),
+        });
+    }
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    editor.start_macro_recording(name.clone());
+
+    debug!("Recording macro {} in editor {}", name, id);
+    Ok(SuccessResponse::new("Macro recording started"))
+}
+
+/// Stop recording and save the macro, returning it
+///
+/// Returns `None` when nothing was being recorded. Empty recordings aren't saved.
+#[command]
+#[instrument(skip(editors))]
+pub async fn stop_macro_recording(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<Option<Macro>> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let Some(recorded) = editor.stop_macro_recording() else {
+        return Ok(None);
+    };
+
+    if !recorded.is_empty() {
+        macro_store()?
+            .save(recorded.clone())
+            .map_err(|e| CommandError::FileError {
+                message: format!("Failed to save macro: {}", e),
+            })?;
+    }
+
+    debug!(
+        "Recorded macro {} with {} commands in editor {}",
+        recorded.name,
+        recorded.commands.len(),
+        id
+    );
+    Ok(Some(recorded))
+}
+
+/// Replay a saved macro
+#[command]
+#[instrument(skip(editors, request))]
+pub async fn play_macro(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    request: PlayMacroRequest,
+) -> CommandResult<SuccessResponse> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let recorded =
+        macro_store()?
+            .get(&request.name)
+            .ok_or_else(|| CommandError::InvalidParameter {
+                parameter: format!("name: no macro called {}", request.name),
+            })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    if request.on_every_cursor {
+        editor.play_macro_on_cursors(&recorded)?;
+    } else {
+        editor.play_macro(&recorded, request.times.unwrap_or(1))?;
+    }
+
+    debug!("Played macro {} in editor {}", request.name, id);
+    Ok(SuccessResponse::new("Macro played successfully"))
+}
+
+/// List saved macros
+#[command]
+#[instrument]
+pub async fn list_macros() -> CommandResult<Vec<Macro>> {
+    Ok(macro_store()?.list())
+}
+
+/// Delete a saved macro, returning whether it existed
+#[command]
+#[instrument]
+pub async fn delete_macro(name: String) -> CommandResult<bool> {
+    let removed = macro_store()?
+        .remove(&name)
+        .map_err(|e| CommandError::FileError {
+            message: format!("Failed to delete macro: {}", e),
+        })?;
+
+    debug!("Deleted macro {}: {}", name, removed);
+    Ok(removed)
+}
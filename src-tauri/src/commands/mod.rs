@@ -5,7 +5,7 @@
 //! to interact with our core text editing system. Commands are organized by
 //! functionality and provide a clean API for the TypeScript frontend.
 
-use crate::core::{Editor, EditorConfig, Position, Range, SearchOptions};
+use crate::core::{Editor, EditorConfig, EditorResult, Position, Range, SearchOptions};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -15,15 +15,148 @@ use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+pub mod auto_save;
 pub mod editor;
+pub mod external_changes;
 pub mod file_system;
+pub mod folding;
+pub mod formatting;
+pub mod history;
+pub mod macros;
+pub mod recovery;
 pub mod search;
 pub mod settings;
+pub mod snippets;
 pub mod syntax;
 pub mod project;
 
 /// Global state containing all open editors
-pub type EditorMap = Arc<RwLock<HashMap<Uuid, Editor>>>;
+pub type EditorMap = Arc<RwLock<Editors>>;
+
+/// Open editors, reachable through the id of any of their views
+///
+/// Commands take the id of the view they act on. Taking an editor mutably
+/// makes that view the active one, so its cursors, viewport and folds are
+/// the ones the editor works with.
+#[derive(Default)]
+pub struct Editors {
+    editors: HashMap<Uuid, Editor>,
+    /// Editor each open view belongs to, an editor's first view included
+    views: HashMap<Uuid, Uuid>,
+}
+
+impl Editors {
+    /// Id of the editor an open view belongs to
+    fn editor_id(&self, id: &Uuid) -> Option<Uuid> {
+        self.views.get(id).copied()
+    }
+
+    /// Editor of a view, as it was last used
+    pub fn get(&self, id: &Uuid) -> Option<&Editor> {
+        self.editors.get(&self.editor_id(id)?)
+    }
+
+    /// Editor of a view, with that view made the active one
+    pub fn get_mut(&mut self, id: &Uuid) -> Option<&mut Editor> {
+        let editor_id = self.editor_id(id)?;
+        let editor = self.editors.get_mut(&editor_id)?;
+        if editor.activate_view(*id) {
+            return Some(editor);
+        }
+
+        warn!("View {} is no longer open in editor {}", id, editor_id);
+        self.views.remove(id);
+        None
+    }
+
+    /// Editor by its own id, for work that isn't done in a view, such as saving
+    pub fn editor(&self, editor_id: &Uuid) -> Option<&Editor> {
+        self.editors.get(editor_id)
+    }
+
+    /// Editor by its own id, mutably, whichever of its views is active
+    pub fn editor_mut(&mut self, editor_id: &Uuid) -> Option<&mut Editor> {
+        self.editors.get_mut(editor_id)
+    }
+
+    /// Add an editor, reachable through its own id
+    pub fn insert(&mut self, id: Uuid, editor: Editor) {
+        self.views.insert(id, id);
+        self.editors.insert(id, editor);
+    }
+
+    /// Remove an editor along with all of its views
+    pub fn remove(&mut self, id: &Uuid) -> Option<Editor> {
+        let editor_id = self.editor_id(id)?;
+        let editor = self.editors.remove(&editor_id)?;
+        self.views.retain(|_, owner| *owner != editor_id);
+        Some(editor)
+    }
+
+    /// Open another view onto the buffer of a view's editor, returning its id
+    pub fn split(&mut self, id: &Uuid) -> Option<EditorResult<Uuid>> {
+        let editor = self.get_mut(id)?;
+        let editor_id = editor.id();
+        let view = editor.split_view();
+        if let Ok(view_id) = &view {
+            self.views.insert(*view_id, editor_id);
+        }
+        Some(view)
+    }
+
+    /// Close a view of an editor that has others, returning whether it was closed
+    pub fn close_view(&mut self, id: &Uuid) -> bool {
+        let Some(editor_id) = self.editor_id(id) else {
+            return false;
+        };
+        let Some(editor) = self.editors.get_mut(&editor_id) else {
+            return false;
+        };
+        if !editor.close_view(*id) {
+            return false;
+        }
+
+        self.views.remove(id);
+        true
+    }
+
+    /// Ids of the open editors
+    ///
+    /// An editor keeps its id after its first view is closed, so these are
+    /// for [`Editors::editor`] rather than [`Editors::get`].
+    pub fn keys(&self) -> impl Iterator<Item = &Uuid> {
+        self.editors.keys()
+    }
+
+    /// Every open editor, whichever of its views is active
+    pub fn values(&self) -> impl Iterator<Item = &Editor> {
+        self.editors.values()
+    }
+
+    /// Every open editor, mutably, whichever of its views is active
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut Editor> {
+        self.editors.values_mut()
+    }
+
+    /// Number of open editors, not counting split views
+    pub fn len(&self) -> usize {
+        self.editors.len()
+    }
+
+    /// Whether no editors are open
+    pub fn is_empty(&self) -> bool {
+        self.editors.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Editors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Editors")
+            .field("editors", &self.editors.keys().collect::<Vec<_>>())
+            .field("views", &self.views)
+            .finish()
+    }
+}
 
 /// Result type for Tauri commands
 pub type CommandResult<T> = Result<T, CommandError>;
@@ -96,13 +229,25 @@ impl From<crate::core::EditorError> for CommandError {
             crate::core::EditorError::SearchError(msg) => {
                 CommandError::OperationFailed { message: msg }
             }
+            crate::core::EditorError::VimError(msg) => {
+                CommandError::OperationFailed { message: msg }
+            }
+            err @ crate::core::EditorError::TransformError(_) => CommandError::OperationFailed {
+                message: err.to_string(),
+            },
+            crate::core::EditorError::EncodingError(msg) => {
+                CommandError::FileError { message: msg }
+            }
+            err @ crate::core::EditorError::SaveFailed { .. } => CommandError::FileError {
+                message: err.to_string(),
+            },
         }
     }
 }
 
 /// Initialize the editor state for the Tauri application
 pub fn init_editor_state() -> EditorMap {
-    Arc::new(RwLock::new(HashMap::new()))
+    Arc::new(RwLock::new(Editors::default()))
 }
 
 /// Common response structure for successful operations
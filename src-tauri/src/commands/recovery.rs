@@ -0,0 +1,269 @@
+use super::auto_save::enable_auto_save;
+use super::external_changes::watch_file;
+use super::history::{persist_undo_history, restore_undo_history};
+use super::{CommandError, CommandResult, EditorMap, Editors, SuccessResponse};
+use crate::core::{Editor, EditorEventListener};
+use crate::utils::swap::{get_swap_manager, SwapFile, SwapManager, SwapRecovery};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Arc, Weak};
+use tauri::{command, State};
+use tokio::sync::RwLock;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+/// An editor reopened from a swap file
+#[derive(Debug, Serialize)]
+pub struct RecoveredEditor {
+    /// Id of the editor that wrote the swap file
+    pub swap_id: String,
+    /// Id of the new editor holding the recovered contents
+    pub editor_id: String,
+}
+
+/// Keeps the swap file of an editor in sync with its unsaved changes
+struct SwapListener {
+    editor_id: Uuid,
+    editors: Weak<RwLock<Editors>>,
+}
+
+impl EditorEventListener for SwapListener {
+    fn on_text_changed(&self, _version: u64) {
+        let editor_id = self.editor_id;
+        let editors = self.editors.clone();
+
+        tauri::async_runtime::spawn(async move {
+            let Ok(manager) = get_swap_manager() else {
+                return;
+            };
+
+            manager
+                .schedule_write(editor_id, move || async move {
+                    let editors = editors.upgrade()?;
+                    let editors = editors.read().await;
+                    let editor = editors.editor(&editor_id)?;
+
+                    // Large files would rewrite their whole contents after every pause in typing
+                    let buffer = editor.buffer();
+                    (buffer.is_dirty() && !buffer.is_large_file())
+                        .then(|| swap_snapshot(editor, false))
+                })
+                .await;
+        });
+    }
+
+    fn on_file_saved(&self, _path: &Path) {
+        tauri::async_runtime::spawn(remove_swap_file(self.editor_id));
+    }
+
+    fn on_file_loaded(&self, _path: &Path) {
+        tauri::async_runtime::spawn(remove_swap_file(self.editor_id));
+    }
+}
+
+/// Write a swap file for the editor whenever it has unsaved changes
+pub(crate) fn track_unsaved_changes(editors: &EditorMap, editor: &mut Editor) {
+    editor.add_event_listener(Arc::new(SwapListener {
+        editor_id: editor.id(),
+        editors: Arc::downgrade(editors),
+    }));
+}
+
+/// Remove the swap file of an editor that no longer needs one
+pub(crate) async fn remove_swap_file(editor_id: Uuid) {
+    let result = match get_swap_manager() {
+        Ok(manager) => manager.remove(editor_id).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = result {
+        warn!("Failed to remove swap file of editor {}: {}", editor_id, e);
+    }
+}
+
+/// Capture the state of an editor for its swap file
+fn swap_snapshot(editor: &Editor, hot_exit: bool) -> SwapFile {
+    SwapFile {
+        editor_id: editor.id(),
+        file_path: editor.buffer().file_path().cloned(),
+        content: editor.buffer().is_dirty().then(|| editor.buffer().text()),
+        encoding: Some(editor.buffer().encoding()),
+        cursors: editor.cursor_manager().cursors().to_vec(),
+        view_state: editor.view_state().clone(),
+        pid: std::process::id(),
+        session: None,
+        saved_at: chrono::Utc::now().timestamp_millis(),
+        hot_exit,
+    }
+}
+
+fn swap_manager() -> CommandResult<&'static SwapManager> {
+    get_swap_manager().map_err(|e| CommandError::OperationFailed {
+        message: e.to_string(),
+    })
+}
+
+/// Find the orphaned swap file written by the given editor
+///
+/// Swap files of sessions that are still running are not found, so another
+/// instance's editors are never recovered or discarded from under it.
+async fn find_swap(swap_id: &str) -> CommandResult<SwapFile> {
+    let invalid = || CommandError::InvalidParameter {
+        parameter: "swap_id".to_string(),
+    };
+    let id = Uuid::parse_str(swap_id).map_err(|_| invalid())?;
+
+    let manager = swap_manager()?;
+    let swap = manager.read(id).await.map_err(|_| invalid())?;
+    if !manager.is_orphan(&swap) {
+        return Err(invalid());
+    }
+    Ok(swap)
+}
+
+/// Open a new editor with the state saved in a swap file
+async fn recover_swap(editors: &EditorMap, swap: &SwapFile) -> CommandResult<Uuid> {
+    let mut editor = Editor::new();
+
+    if let Some(path) = &swap.file_path {
+        if path.exists() {
+            editor
+                .load_file(path)
+                .await
+                .map_err(|e| CommandError::FileError {
+                    message: format!("Failed to load file: {}", e),
+                })?;
+            // The file was open in an encoding that detection may not pick
+            if let Some(encoding) = swap.encoding {
+                if editor.buffer().encoding() != encoding {
+                    editor.reopen_with_encoding(encoding).await?;
+                }
+            }
+            restore_undo_history(&mut editor);
+            watch_file(path).await;
+        } else {
+            editor.buffer_mut().set_file_path(Some(path.clone()));
+        }
+    }
+
+    // Recovered changes are a single undoable edit on top of the file on disk
+    track_unsaved_changes(editors, &mut editor);
+    if let Some(content) = &swap.content {
+        editor.replace_contents(content)?;
+    }
+
+    // Recovered changes wait for the user to save them
+    enable_auto_save(&mut editor);
+
+    editor.restore_cursors(swap.cursors.clone());
+    editor.update_view_state(swap.view_state.clone());
+
+    let id = editor.id();
+    editors.write().await.insert(id, editor);
+
+    // The new editor writes its own swap file from now on
+    swap_manager()?
+        .remove(swap.editor_id)
+        .await
+        .map_err(|e| CommandError::OperationFailed {
+            message: format!("Failed to remove swap file: {}", e),
+        })?;
+
+    Ok(id)
+}
+
+/// List files left with unsaved changes by a session that crashed
+#[command]
+#[instrument]
+pub async fn list_recoverable_files() -> CommandResult<Vec<SwapRecovery>> {
+    let orphans =
+        swap_manager()?
+            .find_orphans()
+            .await
+            .map_err(|e| CommandError::OperationFailed {
+                message: format!("Failed to read swap files: {}", e),
+            })?;
+
+    let mut recoveries = Vec::new();
+    for swap in orphans.iter().filter(|swap| !swap.hot_exit) {
+        recoveries.push(SwapManager::recovery(swap).await);
+    }
+
+    debug!("Found {} recoverable files", recoveries.len());
+    Ok(recoveries)
+}
+
+/// Reopen a file from its swap file in a new editor
+#[command]
+#[instrument(skip(editors))]
+pub async fn recover_file(editors: State<'_, EditorMap>, swap_id: String) -> CommandResult<String> {
+    let swap = find_swap(&swap_id).await?;
+    let editor_id = recover_swap(editors.inner(), &swap).await?;
+
+    info!("Recovered swap file {} into editor {}", swap_id, editor_id);
+    Ok(editor_id.to_string())
+}
+
+/// Delete a swap file without recovering it
+#[command]
+#[instrument]
+pub async fn discard_recovery(swap_id: String) -> CommandResult<SuccessResponse> {
+    let swap = find_swap(&swap_id).await?;
+    remove_swap_file(swap.editor_id).await;
+
+    info!("Discarded swap file {}", swap_id);
+    Ok(SuccessResponse::new("Recovery discarded successfully"))
+}
+
+/// Save the state of every open editor so the next launch reopens them as they were
+///
+/// Unsaved changes are kept in swap files instead of prompting to save them,
+/// and the undo history is stored for buffers that match their file.
+#[command]
+#[instrument(skip(editors))]
+pub async fn hot_exit(editors: State<'_, EditorMap>) -> CommandResult<usize> {
+    let manager = swap_manager()?;
+    let editors_guard = editors.read().await;
+
+    for editor in editors_guard.values() {
+        persist_undo_history(editor);
+        manager
+            .write_now(&swap_snapshot(editor, true))
+            .await
+            .map_err(|e| CommandError::OperationFailed {
+                message: format!("Failed to write swap file: {}", e),
+            })?;
+    }
+
+    info!("Hot exit saved {} editors", editors_guard.len());
+    Ok(editors_guard.len())
+}
+
+/// Reopen the editors saved by the last hot exit
+#[command]
+#[instrument(skip(editors))]
+pub async fn restore_hot_exit_session(
+    editors: State<'_, EditorMap>,
+) -> CommandResult<Vec<RecoveredEditor>> {
+    let orphans =
+        swap_manager()?
+            .find_orphans()
+            .await
+            .map_err(|e| CommandError::OperationFailed {
+                message: format!("Failed to read swap files: {}", e),
+            })?;
+
+    let mut recovered = Vec::new();
+    for swap in orphans.iter().filter(|swap| swap.hot_exit) {
+        match recover_swap(editors.inner(), swap).await {
+            Ok(editor_id) => recovered.push(RecoveredEditor {
+                swap_id: swap.editor_id.to_string(),
+                editor_id: editor_id.to_string(),
+            }),
+            Err(e) => warn!("Failed to restore editor {}: {}", swap.editor_id, e),
+        }
+    }
+
+    info!("Restored {} editors from hot exit", recovered.len());
+    Ok(recovered)
+}
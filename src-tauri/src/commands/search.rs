@@ -1,5 +1,5 @@
 use super::{CommandError, CommandResult, EditorMap, SuccessResponse};
-use crate::core::{SearchOptions, SearchResult};
+use crate::core::{EditorCommand, SearchOptions, SearchResult};
 use serde::{Deserialize, Serialize};
 use tauri::{command, State};
 use tracing::{debug, instrument};
@@ -29,6 +29,26 @@ impl From<SearchRequest> for SearchOptions {
     }
 }
 
+/// Matching options for occurrence commands, which search for the selected text
+#[derive(Debug, Deserialize)]
+pub struct OccurrenceRequest {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+}
+
+impl From<OccurrenceRequest> for SearchOptions {
+    fn from(request: OccurrenceRequest) -> Self {
+        SearchOptions {
+            query: String::new(),
+            case_sensitive: request.case_sensitive,
+            whole_word: request.whole_word,
+            use_regex: false,
+            forward: true,
+            wrap_around: true,
+        }
+    }
+}
+
 /// Search for text in the editor
 #[command]
 #[instrument(skip(editors, request))]
@@ -47,7 +67,11 @@ pub async fn search_text(
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
     let search_options: SearchOptions = request.into();
-    let results = editor.search(search_options)?;
+    let results = editor
+        .execute(EditorCommand::Search {
+            options: search_options,
+        })?
+        .into_search_results();
 
     debug!(
         "Search completed in editor {}: found {} results",
@@ -73,7 +97,9 @@ pub async fn find_next(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    let result = editor.find_next()?;
+    let result = editor
+        .execute(EditorCommand::FindNext)?
+        .into_search_result();
 
     debug!("Find next in editor {}: {:?}", id, result.is_some());
     Ok(result)
@@ -98,7 +124,9 @@ pub async fn find_previous(
     // Create a reverse search by temporarily modifying search direction
     // Note: This is a simplified implementation - in practice, you might want
     // to store the search state more comprehensively
-    let result = editor.find_next()?; // This would need proper previous implementation
+    let result = editor
+        .execute(EditorCommand::FindNext)?
+        .into_search_result(); // This would need proper previous implementation
 
     debug!("Find previous in editor {}: {:?}", id, result.is_some());
     Ok(result)
@@ -121,7 +149,9 @@ pub async fn replace_selection(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    let replaced = editor.replace(&replacement)?;
+    let replaced = editor
+        .execute(EditorCommand::Replace { replacement })?
+        .changed();
 
     debug!("Replace in editor {}: {}", id, replaced);
     Ok(replaced)
@@ -144,8 +174,113 @@ pub async fn replace_all(
         .get_mut(&id)
         .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
 
-    let count = editor.replace_all(&replacement)?;
+    let count = editor
+        .execute(EditorCommand::ReplaceAll { replacement })?
+        .count();
 
     debug!("Replace all in editor {}: {} replacements", id, count);
     Ok(count)
 }
+
+/// Add a cursor at the next occurrence of the newest selection
+#[command]
+#[instrument(skip(editors, request))]
+pub async fn add_next_occurrence(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    request: OccurrenceRequest,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let added = editor
+        .execute(EditorCommand::AddNextOccurrence {
+            options: request.into(),
+        })?
+        .changed();
+
+    debug!("Add next occurrence in editor {}: {}", id, added);
+    Ok(added)
+}
+
+/// Move the newest selection to the next occurrence
+#[command]
+#[instrument(skip(editors, request))]
+pub async fn skip_occurrence(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    request: OccurrenceRequest,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let moved = editor
+        .execute(EditorCommand::SkipOccurrence {
+            options: request.into(),
+        })?
+        .changed();
+
+    debug!("Skip occurrence in editor {}: {}", id, moved);
+    Ok(moved)
+}
+
+/// Remove the most recently added cursor
+#[command]
+#[instrument(skip(editors))]
+pub async fn undo_last_cursor_add(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<bool> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let removed = editor.execute(EditorCommand::UndoLastCursorAdd)?.changed();
+
+    debug!("Undo last cursor add in editor {}: {}", id, removed);
+    Ok(removed)
+}
+
+/// Put a cursor on every occurrence of the newest selection
+#[command]
+#[instrument(skip(editors, request))]
+pub async fn select_all_occurrences(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    request: OccurrenceRequest,
+) -> CommandResult<usize> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let count = editor
+        .execute(EditorCommand::SelectAllOccurrences {
+            options: request.into(),
+        })?
+        .count();
+
+    debug!("Select all occurrences in editor {}: {}", id, count);
+    Ok(count)
+}
@@ -39,7 +39,7 @@ impl Default for AppSettings {
                 maximized: false,
             },
             theme: "dark".to_string(),
-            auto_save: true,
+            auto_save: false,
             auto_save_delay: 2000,
         }
     }
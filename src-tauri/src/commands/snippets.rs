@@ -0,0 +1,129 @@
+use super::{CommandError, CommandResult, EditorMap};
+use crate::core::{EditorCommand, SnippetStatus};
+use crate::lsp::utils::completion_edit;
+use crate::lsp::PositionEncoding;
+use crate::utils::snippet_store::get_snippet_store;
+use crate::utils::{UserSnippet, UtilError};
+use tauri::{command, State};
+use tower_lsp::lsp_types::CompletionItem;
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+/// Run a snippet command and return where the snippet session is afterwards
+async fn run_snippet_command(
+    editors: &EditorMap,
+    editor_id: String,
+    snippet_command: EditorCommand,
+) -> CommandResult<Option<SnippetStatus>> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    editor.execute(snippet_command)?;
+    let status = editor.snippet_status();
+
+    debug!("Snippet in editor {} at {:?}", id, status);
+    Ok(status)
+}
+
+/// Insert a snippet at every cursor and select its first tabstop
+#[command]
+#[instrument(skip(editors, body))]
+pub async fn insert_snippet(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    body: String,
+) -> CommandResult<Option<SnippetStatus>> {
+    run_snippet_command(
+        editors.inner(),
+        editor_id,
+        EditorCommand::InsertSnippet { body },
+    )
+    .await
+}
+
+/// Move to the next tabstop of the active snippet
+#[command]
+#[instrument(skip(editors))]
+pub async fn next_tabstop(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<Option<SnippetStatus>> {
+    run_snippet_command(editors.inner(), editor_id, EditorCommand::NextTabstop).await
+}
+
+/// Move back to the previous tabstop of the active snippet
+#[command]
+#[instrument(skip(editors))]
+pub async fn previous_tabstop(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<Option<SnippetStatus>> {
+    run_snippet_command(editors.inner(), editor_id, EditorCommand::PreviousTabstop).await
+}
+
+/// Stop filling in the active snippet
+#[command]
+#[instrument(skip(editors))]
+pub async fn exit_snippet(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+) -> CommandResult<Option<SnippetStatus>> {
+    run_snippet_command(editors.inner(), editor_id, EditorCommand::ExitSnippet).await
+}
+
+/// Accept an LSP completion item, expanding it when it is a snippet
+///
+/// Item positions are counted in `encoding`, UTF-16 unless another encoding
+/// was negotiated with the server.
+#[command]
+#[instrument(skip(editors, item))]
+pub async fn apply_completion(
+    editors: State<'_, EditorMap>,
+    editor_id: String,
+    item: CompletionItem,
+    encoding: Option<PositionEncoding>,
+) -> CommandResult<Option<SnippetStatus>> {
+    let id = Uuid::parse_str(&editor_id).map_err(|_| CommandError::InvalidParameter {
+        parameter: "editor_id".to_string(),
+    })?;
+
+    let mut editors_guard = editors.write().await;
+    let editor = editors_guard
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::EditorNotFound { id: editor_id })?;
+
+    let edit = completion_edit(editor.buffer().rope(), &item, encoding.unwrap_or_default());
+    editor.execute(EditorCommand::ApplyCompletion {
+        range: edit.range,
+        text: edit.text,
+        is_snippet: edit.is_snippet,
+        additional_edits: edit.additional_edits,
+    })?;
+
+    debug!("Applied completion {} in editor {}", item.label, id);
+    Ok(editor.snippet_status())
+}
+
+/// List the user snippets for a language
+#[command]
+#[instrument]
+pub async fn list_snippets(language: String) -> CommandResult<Vec<UserSnippet>> {
+    let store = get_snippet_store().map_err(|e| CommandError::OperationFailed {
+        message: e.to_string(),
+    })?;
+
+    store.snippets(&language).map_err(|e| match e {
+        UtilError::InvalidArgument { .. } => CommandError::InvalidParameter {
+            parameter: "language".to_string(),
+        },
+        e => CommandError::OperationFailed {
+            message: e.to_string(),
+        },
+    })
+}
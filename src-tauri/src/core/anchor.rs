@@ -0,0 +1,219 @@
+// src-tauri/src/core/anchor.rs
+use crate::core::text_buffer::{Position, Range};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Which side of an edit a position sticks to when the edit touches it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Gravity {
+    /// Stay before text inserted at the position
+    Left,
+    /// Move after text inserted at the position
+    Right,
+}
+
+/// Handle to a position in a buffer that follows edits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AnchorId(u64);
+
+/// Handle to a range in a buffer that follows edits
+///
+/// Text typed at either edge is not included in the range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RangeAnchor {
+    start: AnchorId,
+    end: AnchorId,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    position: Position,
+    gravity: Gravity,
+}
+
+/// Positions kept up to date as the text around them changes
+#[derive(Debug, Default)]
+pub struct AnchorSet {
+    anchors: HashMap<AnchorId, Anchor>,
+    next_id: u64,
+}
+
+impl AnchorSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an anchor at a position
+    pub fn insert(&mut self, position: Position, gravity: Gravity) -> AnchorId {
+        let id = AnchorId(self.next_id);
+        self.next_id += 1;
+        self.anchors.insert(id, Anchor { position, gravity });
+        id
+    }
+
+    /// Add a pair of anchors that keep tracking a range
+    pub fn insert_range(&mut self, range: Range) -> RangeAnchor {
+        RangeAnchor {
+            start: self.insert(range.start, Gravity::Right),
+            end: self.insert(range.end, Gravity::Left),
+        }
+    }
+
+    /// Get the current position of an anchor
+    pub fn get(&self, id: AnchorId) -> Option<Position> {
+        self.anchors.get(&id).map(|anchor| anchor.position)
+    }
+
+    /// Get the current range of a range anchor
+    ///
+    /// A range whose text was deleted collapses to an empty range.
+    pub fn get_range(&self, range: RangeAnchor) -> Option<Range> {
+        let start = self.get(range.start)?;
+        let end = self.get(range.end)?;
+        Some(Range::new(start, end.max(start)))
+    }
+
+    /// Remove an anchor, returning whether it existed
+    pub fn remove(&mut self, id: AnchorId) -> bool {
+        self.anchors.remove(&id).is_some()
+    }
+
+    /// Remove both anchors of a range anchor
+    pub fn remove_range(&mut self, range: RangeAnchor) -> bool {
+        self.remove(range.start) & self.remove(range.end)
+    }
+
+    pub fn len(&self) -> usize {
+        self.anchors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchors.is_empty()
+    }
+
+    /// Move every anchor to account for `range` being replaced by `new_text`
+    pub fn adjust(&mut self, range: &Range, new_text: &str) {
+        for anchor in self.anchors.values_mut() {
+            anchor.position = adjust_position(anchor.position, range, new_text, anchor.gravity);
+        }
+    }
+}
+
+/// Position just past `text` when it is inserted at `start`
+pub fn inserted_end(start: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        Some(last_newline) => Position::new(
+            start.line + text.matches('\n').count(),
+            text[last_newline + 1..].chars().count(),
+        ),
+        None => Position::new(start.line, start.column + text.chars().count()),
+    }
+}
+
+/// Move a position to where it ends up once `range` is replaced by `new_text`
+///
+/// Positions before the edit are unchanged and positions after it shift with
+/// the text. Positions touching or inside the replaced range go to the start
+/// of the edit with left gravity and past the inserted text with right gravity.
+pub fn adjust_position(
+    position: Position,
+    range: &Range,
+    new_text: &str,
+    gravity: Gravity,
+) -> Position {
+    if position < range.start {
+        return position;
+    }
+
+    let inserted_end = inserted_end(range.start, new_text);
+    if position <= range.end {
+        return match gravity {
+            Gravity::Left => range.start,
+            Gravity::Right => inserted_end,
+        };
+    }
+
+    // Position is after the edit, shift it by the size difference
+    if position.line == range.end.line {
+        Position::new(
+            inserted_end.line,
+            inserted_end.column + (position.column - range.end.column),
+        )
+    } else {
+        Position::new(
+            inserted_end.line + (position.line - range.end.line),
+            position.column,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gravity_at_insertion_point() {
+        let at = Position::new(1, 4);
+        let insert = Range::single_point(at);
+
+        assert_eq!(adjust_position(at, &insert, "ab", Gravity::Left), at);
+        assert_eq!(
+            adjust_position(at, &insert, "ab", Gravity::Right),
+            Position::new(1, 6)
+        );
+        assert_eq!(
+            adjust_position(at, &insert, "x\nyz", Gravity::Right),
+            Position::new(2, 2)
+        );
+    }
+
+    #[test]
+    fn test_positions_around_replacement() {
+        let range = Range::new(Position::new(0, 2), Position::new(1, 3));
+
+        // Before, inside and after a two-line range replaced by "abc"
+        assert_eq!(
+            adjust_position(Position::new(0, 1), &range, "abc", Gravity::Right),
+            Position::new(0, 1)
+        );
+        assert_eq!(
+            adjust_position(Position::new(1, 0), &range, "abc", Gravity::Left),
+            Position::new(0, 2)
+        );
+        assert_eq!(
+            adjust_position(Position::new(1, 0), &range, "abc", Gravity::Right),
+            Position::new(0, 5)
+        );
+        assert_eq!(
+            adjust_position(Position::new(1, 7), &range, "abc", Gravity::Left),
+            Position::new(0, 9)
+        );
+        assert_eq!(
+            adjust_position(Position::new(4, 7), &range, "abc", Gravity::Left),
+            Position::new(3, 7)
+        );
+    }
+
+    #[test]
+    fn test_range_anchor_does_not_grow_at_edges() {
+        let mut anchors = AnchorSet::new();
+        let range = anchors.insert_range(Range::new(Position::new(0, 4), Position::new(0, 8)));
+
+        anchors.adjust(&Range::single_point(Position::new(0, 8)), "!!");
+        anchors.adjust(&Range::single_point(Position::new(0, 4)), "--");
+        assert_eq!(
+            anchors.get_range(range),
+            Some(Range::new(Position::new(0, 6), Position::new(0, 10)))
+        );
+
+        // Deleting the whole range collapses it
+        anchors.adjust(&Range::new(Position::new(0, 5), Position::new(0, 11)), "");
+        assert_eq!(
+            anchors.get_range(range),
+            Some(Range::single_point(Position::new(0, 5)))
+        );
+
+        assert!(anchors.remove_range(range));
+        assert!(anchors.is_empty());
+    }
+}
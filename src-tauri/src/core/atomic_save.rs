@@ -0,0 +1,319 @@
+// src-tauri/src/core/atomic_save.rs
+use crate::core::{EditorError, EditorResult};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{debug, instrument, warn};
+
+/// Backups kept of the previous contents of a file when it is saved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackupMode {
+    /// Don't keep backups
+    #[default]
+    None,
+    /// Keep the previous version as `<file>.bak`
+    Single,
+    /// Keep up to `keep` previous versions as `<file>.~N~`
+    Versioned { keep: usize },
+}
+
+/// Step of a save that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveStage {
+    CreateTemp,
+    Write,
+    Metadata,
+    Sync,
+    Backup,
+    Rename,
+}
+
+impl fmt::Display for SaveStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self {
+            SaveStage::CreateTemp => "creating temporary file",
+            SaveStage::Write => "writing contents",
+            SaveStage::Metadata => "copying permissions",
+            SaveStage::Sync => "flushing to disk",
+            SaveStage::Backup => "creating backup",
+            SaveStage::Rename => "replacing original file",
+        };
+        write!(f, "{}", stage)
+    }
+}
+
+/// Write a file so that it either keeps its old contents or has the new ones
+///
+/// The contents go to a temporary file next to the target, which is synced and
+/// renamed over it. Symlinks are followed so the link itself survives, and the
+/// mode and owner of an existing file are carried over.
+pub async fn write_atomic(path: &Path, contents: Vec<u8>, backup: BackupMode) -> EditorResult<()> {
+    let path = path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || write_atomic_blocking(&path, &contents, backup))
+        .await
+        .map_err(|e| EditorError::BufferError(anyhow::anyhow!("Save task failed: {}", e)))?
+}
+
+#[instrument(skip(contents))]
+fn write_atomic_blocking(path: &Path, contents: &[u8], backup: BackupMode) -> EditorResult<()> {
+    let target = resolve_target(path);
+    let error = |stage| {
+        let target = target.clone();
+        move |source| EditorError::SaveFailed {
+            path: target,
+            stage,
+            source,
+        }
+    };
+
+    let existing = fs::metadata(&target).ok();
+
+    let dir = parent_dir(&target);
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_path = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name,
+        uuid::Uuid::new_v4().simple()
+    ));
+
+    let mut temp = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .map_err(error(SaveStage::CreateTemp))?;
+
+    let result = (|| {
+        temp.write_all(contents).map_err(error(SaveStage::Write))?;
+
+        if let Some(metadata) = &existing {
+            preserve_metadata(&temp, metadata).map_err(error(SaveStage::Metadata))?;
+        }
+
+        temp.sync_all().map_err(error(SaveStage::Sync))?;
+        drop(temp);
+
+        // Only back up once the new contents are safely on disk
+        if existing.is_some() {
+            write_backup(&target, backup).map_err(error(SaveStage::Backup))?;
+        }
+
+        fs::rename(&temp_path, &target).map_err(error(SaveStage::Rename))?;
+        sync_dir(dir).map_err(error(SaveStage::Sync))
+    })();
+
+    if result.is_err() {
+        // Never leave a half-written temporary file behind
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+
+    debug!(
+        "Atomically wrote {} bytes to {}",
+        contents.len(),
+        target.display()
+    );
+    Ok(())
+}
+
+/// Follow symlinks to the file that should actually be replaced
+fn resolve_target(path: &Path) -> PathBuf {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Copy the mode and owner of the original file onto the temporary one
+fn preserve_metadata(temp: &File, original: &fs::Metadata) -> std::io::Result<()> {
+    temp.set_permissions(original.permissions())?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{fchown, MetadataExt};
+
+        let current = temp.metadata()?;
+        if current.uid() != original.uid() || current.gid() != original.gid() {
+            // Only root can give files away, so a failure here is expected for other users
+            if let Err(e) = fchown(temp, Some(original.uid()), Some(original.gid())) {
+                warn!("Could not preserve file owner: {}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Make a completed rename durable
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+    }
+    Ok(())
+}
+
+/// Copy the current contents of a file aside before it is replaced
+fn write_backup(target: &Path, backup: BackupMode) -> std::io::Result<()> {
+    match backup {
+        BackupMode::None => Ok(()),
+        BackupMode::Single => {
+            fs::copy(target, append_extension(target, "bak"))?;
+            Ok(())
+        }
+        BackupMode::Versioned { keep } => {
+            let mut versions = backup_versions(target)?;
+            let next = versions.last().map_or(1, |(version, _)| version + 1);
+            let backup_path = append_extension(target, &format!("~{}~", next));
+
+            fs::copy(target, &backup_path)?;
+            versions.push((next, backup_path));
+
+            let excess = versions.len().saturating_sub(keep.max(1));
+            for (_, path) in versions.drain(..excess) {
+                fs::remove_file(path)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Existing versioned backups of a file, oldest first
+fn backup_versions(target: &Path) -> std::io::Result<Vec<(u64, PathBuf)>> {
+    let prefix = format!(
+        "{}.~",
+        target
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default()
+    );
+
+    let mut versions = Vec::new();
+    for entry in fs::read_dir(parent_dir(target))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        let version = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix('~'))
+            .and_then(|number| number.parse::<u64>().ok());
+
+        if let Some(version) = version {
+            versions.push((version, entry.path()));
+        }
+    }
+
+    versions.sort_by_key(|(version, _)| *version);
+    Ok(versions)
+}
+
+/// Directory containing a file, `.` for bare file names
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+}
+
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_write_atomic_replaces_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("main.rs");
+
+        write_atomic(&path, b"fn main() {}\n".to_vec(), BackupMode::None)
+            .await
+            .unwrap();
+        write_atomic(
+            &path,
+            b"fn main() { run(); }\n".to_vec(),
+            BackupMode::Single,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() { run(); }\n");
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("main.rs.bak")).unwrap(),
+            "fn main() {}\n"
+        );
+
+        // Only the file and its backup remain, no temporary files
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_versioned_backups_are_pruned() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("lib.rs");
+
+        for version in 0..5 {
+            write_atomic(
+                &path,
+                format!("// version {}\n", version).into_bytes(),
+                BackupMode::Versioned { keep: 2 },
+            )
+            .await
+            .unwrap();
+        }
+
+        let versions = backup_versions(&path).unwrap();
+        assert_eq!(
+            versions.iter().map(|(v, _)| *v).collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert_eq!(
+            fs::read_to_string(&versions[1].1).unwrap(),
+            "// version 3\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_write_atomic_preserves_mode_and_symlinks() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("script.sh");
+        let link = temp_dir.path().join("link.sh");
+
+        fs::write(&target, "echo old\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o750)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"echo new\n".to_vec(), BackupMode::None)
+            .await
+            .unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "echo new\n");
+        assert_eq!(
+            fs::metadata(&target).unwrap().permissions().mode() & 0o777,
+            0o750
+        );
+    }
+}
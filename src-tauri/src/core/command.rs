@@ -0,0 +1,180 @@
+// src-tauri/src/core/command.rs
+//! Serializable editor commands
+//!
+//! Tauri commands that change an editor build an [`EditorCommand`] and run it
+//! through [`Editor::execute`](crate::core::Editor::execute), so every change
+//! made from the UI can be recorded into a macro and replayed later.
+
+use crate::core::cursor::{BlockSelection, Direction, MovementUnit, SelectionMode};
+use crate::core::editor::{SearchOptions, SearchResult, SortOptions};
+use crate::core::text_buffer::{Position, Range};
+use crate::core::text_transform::TextTransform;
+use serde::{Deserialize, Serialize};
+
+/// An editor operation that can be recorded and replayed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum EditorCommand {
+    InsertText {
+        text: String,
+    },
+    TypeChar {
+        ch: char,
+    },
+    DeleteSelection,
+    Backspace,
+    MoveCursors {
+        direction: Direction,
+        unit: MovementUnit,
+        extend_selection: bool,
+    },
+    GotoPosition {
+        position: Position,
+    },
+    /// Go to a 1-indexed line
+    GotoLine {
+        line_number: usize,
+    },
+    SelectAll,
+    SetSelectionMode {
+        mode: SelectionMode,
+    },
+    SelectBlock {
+        block: BlockSelection,
+    },
+    ExpandSelection,
+    ShrinkSelection,
+    Cut,
+    Paste {
+        text: String,
+    },
+    Undo,
+    Redo,
+    IndentLines,
+    UnindentLines,
+    ReindentSelection,
+    MoveLinesUp,
+    MoveLinesDown,
+    DuplicateLines,
+    JoinLines,
+    SortLines {
+        options: SortOptions,
+    },
+    ReverseLines,
+    TransposeLines,
+    DeleteLines,
+    TransformSelections {
+        transform: TextTransform,
+    },
+    ToggleLineComment,
+    Search {
+        options: SearchOptions,
+    },
+    FindNext,
+    Replace {
+        replacement: String,
+    },
+    ReplaceAll {
+        replacement: String,
+    },
+    AddNextOccurrence {
+        options: SearchOptions,
+    },
+    SkipOccurrence {
+        options: SearchOptions,
+    },
+    UndoLastCursorAdd,
+    SelectAllOccurrences {
+        options: SearchOptions,
+    },
+    Fold {
+        line: usize,
+    },
+    Unfold {
+        line: usize,
+        recursive: bool,
+    },
+    FoldAll,
+    /// Fold ranges nested this deep, where top-level ranges are level 1
+    FoldLevel {
+        level: usize,
+    },
+    UnfoldAll,
+    InsertSnippet {
+        body: String,
+    },
+    NextTabstop,
+    PreviousTabstop,
+    ExitSnippet,
+    /// Replace `range` around the primary cursor, and as much around the others
+    ApplyCompletion {
+        range: Option<Range>,
+        text: String,
+        is_snippet: bool,
+        /// Edits elsewhere in the document that come with the completion
+        #[serde(default)]
+        additional_edits: Vec<RangeReplacement>,
+    },
+}
+
+/// Text to put in place of a range
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeReplacement {
+    pub range: Range,
+    pub text: String,
+}
+
+/// Value returned by an [`EditorCommand`]
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum CommandOutput {
+    None,
+    /// Whether the command changed anything
+    Changed(bool),
+    Count(usize),
+    Text(String),
+    SearchResults(Vec<SearchResult>),
+    SearchResult(Option<SearchResult>),
+}
+
+impl CommandOutput {
+    /// Whether the command changed anything; commands without a flag count as changed
+    pub fn changed(&self) -> bool {
+        match self {
+            Self::Changed(changed) => *changed,
+            Self::Count(count) => *count > 0,
+            _ => true,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        match self {
+            Self::Count(count) => *count,
+            Self::SearchResults(results) => results.len(),
+            _ => 0,
+        }
+    }
+
+    pub fn into_text(self) -> String {
+        match self {
+            Self::Text(text) => text,
+            _ => String::new(),
+        }
+    }
+
+    pub fn into_search_results(self) -> Vec<SearchResult> {
+        match self {
+            Self::SearchResults(results) => results,
+            Self::SearchResult(result) => result.into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn into_search_result(self) -> Option<SearchResult> {
+        match self {
+            Self::SearchResult(result) => result,
+            Self::SearchResults(results) => results.into_iter().next(),
+            _ => None,
+        }
+    }
+}
@@ -1,10 +1,16 @@
 // src-tauri/src/core/cursor.rs
+use crate::core::anchor::{adjust_position, Gravity};
+use crate::core::syntax_selection;
 use crate::core::text_buffer::{Position, Range, TextBuffer, TextEdit};
+use crate::core::utils::{column_to_visual_column, visual_column_to_column, visual_width};
+use crate::core::wrap::WrapLayout;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::fmt;
 use tracing::{debug, instrument};
+use tree_sitter::Tree;
+use unicode_segmentation::UnicodeSegmentation;
 
 /// Represents the direction of cursor movement
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -23,6 +29,12 @@ pub enum MovementUnit {
     Line,
     Page,
     Document,
+    /// Parts of an identifier, such as `parse`, `Http` and `request` in `parseHttp_request`
+    SubWord,
+    /// User-perceived characters, keeping combining marks and emoji sequences whole
+    Grapheme,
+    /// Rows as drawn on screen, which differ from lines when word wrap is on
+    VisualLine,
 }
 
 /// Represents the type of selection operation
@@ -36,6 +48,18 @@ pub enum SelectionMode {
     Block,
 }
 
+/// A rectangular selection between two corners
+///
+/// Columns are visual columns, so rows with tabs or wide characters line up.
+/// The corners may lie past the end of a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockSelection {
+    /// Corner where the selection started
+    pub anchor: Position,
+    /// Corner that moves as the selection is extended
+    pub head: Position,
+}
+
 /// A single cursor with position and selection
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cursor {
@@ -45,6 +69,9 @@ pub struct Cursor {
     pub anchor: Position,
     /// Preferred column for vertical movement
     pub preferred_column: Option<usize>,
+    /// Preferred visual offset within a wrapped row for visual line movement
+    #[serde(default)]
+    pub preferred_row_offset: Option<usize>,
     /// Whether this cursor has an active selection
     pub has_selection: bool,
     /// Unique identifier for this cursor
@@ -58,6 +85,7 @@ impl Cursor {
             position,
             anchor: position,
             preferred_column: Some(position.column),
+            preferred_row_offset: None,
             has_selection: false,
             id,
         }
@@ -69,6 +97,7 @@ impl Cursor {
             position,
             anchor,
             preferred_column: Some(position.column),
+            preferred_row_offset: None,
             has_selection: anchor != position,
             id,
         }
@@ -102,6 +131,7 @@ impl Cursor {
         self.anchor = position;
         self.has_selection = false;
         self.preferred_column = Some(position.column);
+        self.preferred_row_offset = None;
     }
 
     /// Move cursor to a new position, extending selection
@@ -109,6 +139,7 @@ impl Cursor {
         self.position = position;
         self.has_selection = self.anchor != position;
         self.preferred_column = Some(position.column);
+        self.preferred_row_offset = None;
     }
 
     /// Start a new selection from current position
@@ -129,6 +160,7 @@ impl Cursor {
         self.position = range.end;
         self.has_selection = !range.is_empty();
         self.preferred_column = Some(self.position.column);
+        self.preferred_row_offset = None;
     }
 
     /// Check if this cursor overlaps with another cursor's selection
@@ -145,6 +177,13 @@ impl Cursor {
     }
 }
 
+/// Cursors before and after one syntax expansion
+#[derive(Debug, Clone)]
+struct SyntaxSelectionStep {
+    before: Vec<Cursor>,
+    after: Vec<Cursor>,
+}
+
 /// Manages multiple cursors and their operations
 pub struct CursorManager {
     /// All cursors (first one is primary)
@@ -159,6 +198,16 @@ pub struct CursorManager {
     max_history: usize,
     /// Page size for page up/down operations
     page_size: usize,
+    /// Tab width used to line up block selections and wrapped rows
+    tab_size: usize,
+    /// Row width in visual columns when word wrap is on
+    wrap_width: Option<usize>,
+    /// Inclusive line ranges hidden by folds, sorted and not touching
+    hidden_lines: Vec<(usize, usize)>,
+    /// Active block selection the cursors were built from
+    block_selection: Option<BlockSelection>,
+    /// Syntax expansions that shrinking can step back through
+    syntax_history: Vec<SyntaxSelectionStep>,
 }
 
 impl CursorManager {
@@ -172,6 +221,11 @@ impl CursorManager {
             history: VecDeque::new(),
             max_history: 100,
             page_size: 25,
+            tab_size: 4,
+            wrap_width: None,
+            hidden_lines: Vec::new(),
+            block_selection: None,
+            syntax_history: Vec::new(),
         }
     }
 
@@ -185,6 +239,11 @@ impl CursorManager {
             history: VecDeque::new(),
             max_history: 100,
             page_size: 25,
+            tab_size: 4,
+            wrap_width: None,
+            hidden_lines: Vec::new(),
+            block_selection: None,
+            syntax_history: Vec::new(),
         }
     }
 
@@ -216,6 +275,9 @@ impl CursorManager {
     /// Set selection mode
     pub fn set_selection_mode(&mut self, mode: SelectionMode) {
         self.selection_mode = mode;
+        if mode != SelectionMode::Block {
+            self.block_selection = None;
+        }
     }
 
     /// Set page size for page up/down operations
@@ -223,6 +285,110 @@ impl CursorManager {
         self.page_size = size;
     }
 
+    /// Set the tab width used to line up block selections
+    pub fn set_tab_size(&mut self, size: usize) {
+        self.tab_size = size.max(1);
+    }
+
+    /// Set the row width used for visual line movement, or `None` without word wrap
+    pub fn set_wrap_width(&mut self, width: Option<usize>) {
+        self.wrap_width = width;
+    }
+
+    /// Layout of visual rows, with one row per line when word wrap is off
+    pub fn wrap_layout(&self) -> WrapLayout {
+        match self.wrap_width {
+            Some(width) => WrapLayout::new(width, self.tab_size),
+            None => WrapLayout::unwrapped(self.tab_size),
+        }
+    }
+
+    /// Set the line ranges hidden by folds, which cursor movement skips over
+    ///
+    /// Cursors on a hidden line move to the end of the visible line above it.
+    pub fn set_hidden_lines(
+        &mut self,
+        buffer: &TextBuffer,
+        mut ranges: Vec<(usize, usize)>,
+    ) -> Result<()> {
+        ranges.retain(|(start, end)| start <= end);
+        ranges.sort_unstable();
+
+        self.hidden_lines.clear();
+        for (start, end) in ranges {
+            match self.hidden_lines.last_mut() {
+                Some((_, last_end)) if start <= *last_end + 1 => *last_end = (*last_end).max(end),
+                _ => self.hidden_lines.push((start, end)),
+            }
+        }
+
+        for index in 0..self.cursors.len() {
+            let line = self.cursors[index].position.line;
+            if let Some((start, _)) = self.hidden_range(line) {
+                let header = start.saturating_sub(1);
+                let position = Position::new(header, buffer.line_len(header)?);
+
+                // A selection keeps its anchor and only its hidden end moves
+                let cursor = &mut self.cursors[index];
+                if cursor.has_selection {
+                    cursor.move_to_with_selection(position);
+                } else {
+                    cursor.move_to(position);
+                }
+            }
+        }
+        self.merge_overlapping_cursors();
+        Ok(())
+    }
+
+    /// Hidden line range containing `line`
+    fn hidden_range(&self, line: usize) -> Option<(usize, usize)> {
+        self.hidden_lines
+            .iter()
+            .copied()
+            .find(|&(start, end)| start <= line && line <= end)
+    }
+
+    /// Move a position that landed on a hidden line past the hidden lines
+    ///
+    /// Moving down or right continues below them, moving up or left stops on
+    /// the line above them.
+    fn skip_hidden_lines(
+        &self,
+        buffer: &TextBuffer,
+        cursor: &Cursor,
+        position: Position,
+        direction: Direction,
+    ) -> Result<Position> {
+        let Some((start, end)) = self.hidden_range(position.line) else {
+            return Ok(position);
+        };
+
+        let header = start.saturating_sub(1);
+        let forward = matches!(direction, Direction::Down | Direction::Right);
+        let line = if forward && end + 1 < buffer.len_lines() {
+            end + 1
+        } else {
+            header
+        };
+
+        let line_len = buffer.line_len(line)?;
+        let column = match direction {
+            Direction::Up | Direction::Down if line != header || !forward => cursor
+                .preferred_column
+                .unwrap_or(cursor.position.column)
+                .min(line_len),
+            Direction::Right if line != header => 0,
+            _ => line_len,
+        };
+        Ok(Position::new(line, column))
+    }
+
+    /// Get the active block selection, if the cursors came from one
+    pub fn block_selection(&self) -> Option<BlockSelection> {
+        self.block_selection
+    }
+
     /// Save current cursor state to history
     fn save_state(&mut self) {
         self.history.push_back(self.cursors.clone());
@@ -235,6 +401,7 @@ impl CursorManager {
     pub fn restore_previous_state(&mut self) -> bool {
         if let Some(previous_state) = self.history.pop_back() {
             self.cursors = previous_state;
+            self.block_selection = None;
             true
         } else {
             false
@@ -246,6 +413,7 @@ impl CursorManager {
         let cursor = Cursor::new(self.next_id, position);
         self.next_id += 1;
         self.cursors.push(cursor);
+        self.block_selection = None;
         self.merge_overlapping_cursors();
         debug!(
             "Added cursor at {}, total cursors: {}",
@@ -259,6 +427,7 @@ impl CursorManager {
         let cursor = Cursor::with_selection(self.next_id, anchor, position);
         self.next_id += 1;
         self.cursors.push(cursor);
+        self.block_selection = None;
         self.merge_overlapping_cursors();
         debug!(
             "Added cursor with selection from {} to {}",
@@ -269,6 +438,7 @@ impl CursorManager {
     /// Remove all cursors except the primary one
     pub fn clear_secondary_cursors(&mut self) {
         self.cursors.truncate(1);
+        self.block_selection = None;
         debug!("Cleared secondary cursors, keeping only primary");
     }
 
@@ -294,14 +464,54 @@ impl CursorManager {
     ) -> Result<()> {
         self.save_state();
 
-        for cursor in &mut self.cursors {
-            let new_position = self.calculate_movement(buffer, cursor, direction, unit)?;
+        // Extending a block grows the rectangle; a lone cursor starts a new one
+        if extend_selection
+            && self.selection_mode == SelectionMode::Block
+            && (self.block_selection.is_some() || self.cursors.len() == 1)
+        {
+            return self.extend_block_selection(buffer, direction, unit);
+        }
+        self.block_selection = None;
+
+        let new_positions = self
+            .cursors
+            .iter()
+            .map(|cursor| {
+                let position = self.calculate_movement(buffer, cursor, direction, unit)?;
+                self.skip_hidden_lines(buffer, cursor, position, direction)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let vertical = matches!(direction, Direction::Up | Direction::Down)
+            && matches!(
+                unit,
+                MovementUnit::Line | MovementUnit::Page | MovementUnit::VisualLine
+            );
+        let row_offsets = if vertical && unit == MovementUnit::VisualLine {
+            self.cursors
+                .iter()
+                .map(|cursor| self.preferred_row_offset(buffer, cursor).map(Some))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            vec![None; self.cursors.len()]
+        };
+
+        for ((cursor, new_position), row_offset) in
+            self.cursors.iter_mut().zip(new_positions).zip(row_offsets)
+        {
+            let preferred_column = cursor.preferred_column;
 
             if extend_selection {
                 cursor.move_to_with_selection(new_position);
             } else {
                 cursor.move_to(new_position);
             }
+
+            // Vertical movement keeps the column the cursor was aiming for
+            if vertical {
+                cursor.preferred_column = preferred_column.or(Some(cursor.position.column));
+                cursor.preferred_row_offset = row_offset;
+            }
         }
 
         self.merge_overlapping_cursors();
@@ -341,6 +551,22 @@ impl CursorManager {
                 let last_column = buffer.line_len(last_line).unwrap_or(0);
                 Ok(Position::new(last_line, last_column))
             }
+            (Direction::Left, MovementUnit::SubWord) => {
+                self.move_sub_word_left(buffer, cursor.position)
+            }
+            (Direction::Right, MovementUnit::SubWord) => {
+                self.move_sub_word_right(buffer, cursor.position)
+            }
+            (Direction::Left, MovementUnit::Grapheme) => {
+                self.move_grapheme_left(buffer, cursor.position)
+            }
+            (Direction::Right, MovementUnit::Grapheme) => {
+                self.move_grapheme_right(buffer, cursor.position)
+            }
+            (Direction::Up, MovementUnit::VisualLine) => self.move_visual_line_up(buffer, cursor),
+            (Direction::Down, MovementUnit::VisualLine) => {
+                self.move_visual_line_down(buffer, cursor)
+            }
             _ => Err(anyhow::anyhow!(
                 "Invalid movement combination: {:?} {:?}",
                 direction,
@@ -467,6 +693,119 @@ impl CursorManager {
         Ok(current_pos)
     }
 
+    /// Move cursor to the start of the previous sub-word
+    fn move_sub_word_left(&self, buffer: &TextBuffer, position: Position) -> Result<Position> {
+        let text = line_content(buffer, position.line)?;
+        let start = sub_word_spans(&text)
+            .into_iter()
+            .map(|(start, _)| start)
+            .take_while(|&start| start < position.column)
+            .last();
+
+        match start {
+            Some(start) => Ok(Position::new(position.line, start)),
+            None if position.column > 0 => Ok(Position::new(position.line, 0)),
+            None => self.move_character_left(buffer, position),
+        }
+    }
+
+    /// Move cursor to the end of the next sub-word
+    fn move_sub_word_right(&self, buffer: &TextBuffer, position: Position) -> Result<Position> {
+        let text = line_content(buffer, position.line)?;
+        let line_len = text.chars().count();
+        let end = sub_word_spans(&text)
+            .into_iter()
+            .map(|(_, end)| end)
+            .find(|&end| end > position.column);
+
+        match end {
+            Some(end) => Ok(Position::new(position.line, end)),
+            None if position.column < line_len => Ok(Position::new(position.line, line_len)),
+            None => self.move_character_right(buffer, position),
+        }
+    }
+
+    /// Move cursor to the previous grapheme cluster boundary
+    fn move_grapheme_left(&self, buffer: &TextBuffer, position: Position) -> Result<Position> {
+        if position.column == 0 {
+            return self.move_character_left(buffer, position);
+        }
+
+        let text = line_content(buffer, position.line)?;
+        let column = grapheme_starts(&text)
+            .take_while(|&start| start < position.column)
+            .last()
+            .unwrap_or(0);
+        Ok(Position::new(position.line, column))
+    }
+
+    /// Move cursor to the next grapheme cluster boundary
+    fn move_grapheme_right(&self, buffer: &TextBuffer, position: Position) -> Result<Position> {
+        let text = line_content(buffer, position.line)?;
+        let line_len = text.chars().count();
+        if position.column >= line_len {
+            return self.move_character_right(buffer, position);
+        }
+
+        let column = grapheme_starts(&text)
+            .find(|&start| start > position.column)
+            .unwrap_or(line_len);
+        Ok(Position::new(position.line, column))
+    }
+
+    /// Visual offset within its row that a cursor aims for when moving by rows
+    fn preferred_row_offset(&self, buffer: &TextBuffer, cursor: &Cursor) -> Result<usize> {
+        if let Some(offset) = cursor.preferred_row_offset {
+            return Ok(offset);
+        }
+
+        let text = line_content(buffer, cursor.position.line)?;
+        let (_, offset) = self.wrap_layout().locate(&text, cursor.position.column);
+        Ok(offset)
+    }
+
+    /// Move cursor up one visual row, keeping its offset within the row
+    fn move_visual_line_up(&self, buffer: &TextBuffer, cursor: &Cursor) -> Result<Position> {
+        let layout = self.wrap_layout();
+        let offset = self.preferred_row_offset(buffer, cursor)?;
+        let line = cursor.position.line;
+        let text = line_content(buffer, line)?;
+        let (row, _) = layout.locate(&text, cursor.position.column);
+
+        if row > 0 {
+            let column = layout.column_at(&text, row - 1, offset).unwrap_or(0);
+            return Ok(Position::new(line, column));
+        }
+        if line == 0 {
+            return Ok(Position::zero());
+        }
+
+        let text = line_content(buffer, line - 1)?;
+        let last_row = layout.row_count(&text) - 1;
+        let column = layout.column_at(&text, last_row, offset).unwrap_or(0);
+        Ok(Position::new(line - 1, column))
+    }
+
+    /// Move cursor down one visual row, keeping its offset within the row
+    fn move_visual_line_down(&self, buffer: &TextBuffer, cursor: &Cursor) -> Result<Position> {
+        let layout = self.wrap_layout();
+        let offset = self.preferred_row_offset(buffer, cursor)?;
+        let line = cursor.position.line;
+        let text = line_content(buffer, line)?;
+        let (row, _) = layout.locate(&text, cursor.position.column);
+
+        if let Some(column) = layout.column_at(&text, row + 1, offset) {
+            return Ok(Position::new(line, column));
+        }
+        if line + 1 >= buffer.len_lines() {
+            return Ok(Position::new(line, text.chars().count()));
+        }
+
+        let text = line_content(buffer, line + 1)?;
+        let column = layout.column_at(&text, 0, offset).unwrap_or(0);
+        Ok(Position::new(line + 1, column))
+    }
+
     /// Move cursor up by one page
     fn move_page_up(&self, buffer: &TextBuffer, cursor: &Cursor) -> Result<Position> {
         let target_line = cursor.position.line.saturating_sub(self.page_size);
@@ -487,6 +826,145 @@ impl CursorManager {
         Ok(Position::new(target_line, new_column))
     }
 
+    /// Select a rectangle, placing one cursor on each row
+    pub fn select_block(&mut self, buffer: &TextBuffer, block: BlockSelection) -> Result<()> {
+        self.save_state();
+        self.selection_mode = SelectionMode::Block;
+        self.apply_block_selection(buffer, block)
+    }
+
+    /// Move the head corner of the block selection
+    fn extend_block_selection(
+        &mut self,
+        buffer: &TextBuffer,
+        direction: Direction,
+        unit: MovementUnit,
+    ) -> Result<()> {
+        let block = match self.block_selection {
+            Some(block) => block,
+            None => {
+                let cursor = self.primary_cursor();
+                BlockSelection {
+                    anchor: self.visual_position(buffer, cursor.anchor)?,
+                    head: self.visual_position(buffer, cursor.position)?,
+                }
+            }
+        };
+
+        let head = self.move_block_head(buffer, block.head, direction, unit)?;
+        self.apply_block_selection(buffer, BlockSelection { head, ..block })
+    }
+
+    /// Calculate where the head of a block selection moves to
+    fn move_block_head(
+        &self,
+        buffer: &TextBuffer,
+        head: Position,
+        direction: Direction,
+        unit: MovementUnit,
+    ) -> Result<Position> {
+        let last_line = buffer.len_lines().saturating_sub(1);
+        let line = head.line.min(last_line);
+
+        let moved = match (direction, unit) {
+            (Direction::Up, MovementUnit::Line) => {
+                Position::new(line.saturating_sub(1), head.column)
+            }
+            (Direction::Down, MovementUnit::Line) => {
+                Position::new((line + 1).min(last_line), head.column)
+            }
+            (Direction::Up, MovementUnit::Page) => {
+                Position::new(line.saturating_sub(self.page_size), head.column)
+            }
+            (Direction::Down, MovementUnit::Page) => {
+                Position::new((line + self.page_size).min(last_line), head.column)
+            }
+            (Direction::Up, MovementUnit::Document) => Position::new(0, head.column),
+            (Direction::Down, MovementUnit::Document) => Position::new(last_line, head.column),
+            (Direction::Left | Direction::Right, MovementUnit::Character) => {
+                let text = line_content(buffer, line)?;
+                let width = visual_width(&text, self.tab_size);
+
+                // Past the end of the line the head moves through virtual space
+                let column = match direction {
+                    Direction::Right if head.column >= width => head.column + 1,
+                    Direction::Right => {
+                        let column = self.column_at_or_before(&text, head.column) + 1;
+                        column_to_visual_column(&text, column, self.tab_size)
+                    }
+                    _ if head.column > width => head.column - 1,
+                    _ if head.column == 0 => 0,
+                    _ => {
+                        let column = self.column_at_or_before(&text, head.column - 1);
+                        column_to_visual_column(&text, column, self.tab_size)
+                    }
+                };
+                Position::new(line, column)
+            }
+            _ => {
+                let text = line_content(buffer, line)?;
+                let cursor = Cursor::new(
+                    0,
+                    Position::new(line, self.column_at_or_before(&text, head.column)),
+                );
+                let position = self.calculate_movement(buffer, &cursor, direction, unit)?;
+                self.visual_position(buffer, position)?
+            }
+        };
+
+        Ok(moved)
+    }
+
+    /// Replace the cursors with one per row of the block
+    fn apply_block_selection(&mut self, buffer: &TextBuffer, block: BlockSelection) -> Result<()> {
+        let last_line = buffer.len_lines().saturating_sub(1);
+        let anchor_line = block.anchor.line.min(last_line);
+        let head_line = block.head.line.min(last_line);
+
+        let mut cursors = Vec::new();
+        for line in anchor_line.min(head_line)..=anchor_line.max(head_line) {
+            let text = line_content(buffer, line)?;
+            let anchor = visual_column_to_column(&text, block.anchor.column, self.tab_size);
+            let head = visual_column_to_column(&text, block.head.column, self.tab_size);
+
+            cursors.push(Cursor::with_selection(
+                self.next_id,
+                Position::new(line, anchor),
+                Position::new(line, head),
+            ));
+            self.next_id += 1;
+        }
+
+        self.cursors = cursors;
+        self.block_selection = Some(block);
+        debug!(
+            "Block selection from {} to {} covers {} rows",
+            block.anchor,
+            block.head,
+            self.cursors.len()
+        );
+        Ok(())
+    }
+
+    /// Convert a buffer position to one with a visual column
+    fn visual_position(&self, buffer: &TextBuffer, position: Position) -> Result<Position> {
+        let text = line_content(buffer, position.line)?;
+        Ok(Position::new(
+            position.line,
+            column_to_visual_column(&text, position.column, self.tab_size),
+        ))
+    }
+
+    /// Column of the character drawn at a visual column
+    fn column_at_or_before(&self, text: &str, visual_column: usize) -> usize {
+        let column = visual_column_to_column(text, visual_column, self.tab_size);
+        if column > 0 && column_to_visual_column(text, column, self.tab_size) > visual_column {
+            column - 1
+        } else {
+            column
+        }
+    }
+
     /// Select all text in the buffer
     pub fn select_all(&mut self, buffer: &TextBuffer) -> Result<()> {
         self.clear_secondary_cursors();
@@ -504,6 +982,7 @@ impl CursorManager {
 
     /// Select the current line for all cursors
     pub fn select_lines(&mut self, buffer: &TextBuffer) -> Result<()> {
+        self.block_selection = None;
         for cursor in &mut self.cursors {
             let start = Position::new(cursor.position.line, 0);
             let end = if cursor.position.line < buffer.len_lines() - 1 {
@@ -523,6 +1002,7 @@ impl CursorManager {
 
     /// Expand selection to word boundaries for all cursors
     pub fn expand_selection_to_words(&mut self, buffer: &TextBuffer) -> Result<()> {
+        self.block_selection = None;
         for cursor in &mut self.cursors {
             let current_range = if cursor.has_selection {
                 cursor.selection_range()
@@ -544,6 +1024,93 @@ impl CursorManager {
         Ok(())
     }
 
+    /// Expand every selection to the enclosing syntax node
+    ///
+    /// Empty selections grow to the node under the cursor. Returns whether any
+    /// selection grew.
+    pub fn expand_selection_to_syntax(&mut self, buffer: &TextBuffer, tree: &Tree) -> Result<bool> {
+        let before = self.cursors.clone();
+        let mut expanded = false;
+        for cursor in &mut self.cursors {
+            let range = if cursor.has_selection {
+                cursor.selection_range()
+            } else {
+                Range::single_point(cursor.position)
+            };
+
+            if let Some(range) = syntax_selection::expand_range(tree, buffer.rope(), &range)? {
+                cursor.select_range(range);
+                expanded = true;
+            }
+        }
+
+        if !expanded {
+            return Ok(false);
+        }
+
+        self.block_selection = None;
+        self.merge_overlapping_cursors();
+
+        // Only a chain of expansions can be stepped back through
+        if self
+            .syntax_history
+            .last()
+            .is_some_and(|step| step.after != before)
+        {
+            self.syntax_history.clear();
+        }
+        self.syntax_history.push(SyntaxSelectionStep {
+            before,
+            after: self.cursors.clone(),
+        });
+
+        debug!(
+            "Expanded selection to syntax nodes for {} cursors",
+            self.cursors.len()
+        );
+        Ok(true)
+    }
+
+    /// Shrink every selection to a syntax node inside it
+    ///
+    /// Right after an expansion this restores the cursors from before it, so
+    /// expanding and shrinking retrace the same steps. Returns whether any
+    /// selection shrank.
+    pub fn shrink_selection_to_syntax(&mut self, buffer: &TextBuffer, tree: &Tree) -> Result<bool> {
+        if let Some(step) = self.syntax_history.pop() {
+            if step.after == self.cursors {
+                self.cursors = step.before;
+                self.block_selection = None;
+                return Ok(true);
+            }
+            self.syntax_history.clear();
+        }
+
+        let mut shrunk = false;
+        for cursor in &mut self.cursors {
+            if !cursor.has_selection {
+                continue;
+            }
+
+            let range = cursor.selection_range();
+            if let Some(range) =
+                syntax_selection::shrink_range(tree, buffer.rope(), &range, cursor.position)?
+            {
+                cursor.select_range(range);
+                shrunk = true;
+            }
+        }
+
+        if shrunk {
+            self.block_selection = None;
+        }
+        debug!(
+            "Shrank selection to syntax nodes for {} cursors",
+            self.cursors.len()
+        );
+        Ok(shrunk)
+    }
+
     /// Move cursors to start of their selections
     pub fn move_to_selection_start(&mut self) {
         for cursor in &mut self.cursors {
@@ -568,6 +1135,7 @@ impl CursorManager {
 
     /// Clear all selections but keep cursor positions
     pub fn clear_selections(&mut self) {
+        self.block_selection = None;
         for cursor in &mut self.cursors {
             cursor.clear_selection();
         }
@@ -715,11 +1283,49 @@ impl CursorManager {
         Cursor::with_selection(id, merged_start, merged_end)
     }
 
+    /// Replace all cursors with a previously captured set
+    pub fn restore_cursors(&mut self, cursors: Vec<Cursor>) {
+        if cursors.is_empty() {
+            return;
+        }
+
+        let max_id = cursors.iter().map(|cursor| cursor.id).max().unwrap_or(0);
+        self.next_id = self.next_id.max(max_id + 1);
+        self.cursors = cursors;
+        self.block_selection = None;
+        debug!("Restored {} cursors", self.cursors.len());
+    }
+
+    /// Pull cursors and selections that lie past the end of the buffer back inside it
+    pub fn clamp_to_buffer(&mut self, buffer: &TextBuffer) {
+        let clamp = |position: Position| {
+            let line = position.line.min(buffer.len_lines().saturating_sub(1));
+            let column = position.column.min(buffer.line_len(line).unwrap_or(0));
+            Position::new(line, column)
+        };
+
+        for cursor in &mut self.cursors {
+            cursor.anchor = clamp(cursor.anchor);
+            cursor.position = clamp(cursor.position);
+            cursor.has_selection = cursor.anchor != cursor.position;
+        }
+        self.block_selection = None;
+        self.syntax_history.clear();
+        self.merge_overlapping_cursors();
+    }
+
     /// Update cursor positions after text edits
     pub fn update_after_edits(&mut self, edits: &[TextEdit]) -> Result<()> {
+        // Edits are applied from the end of the document backwards, so adjusting
+        // in the same order keeps each edit's range valid for the next step
+        let mut sorted_edits: Vec<&TextEdit> = edits.iter().collect();
+        sorted_edits.sort_by(|a, b| b.range.start.cmp(&a.range.start));
+        self.block_selection = None;
+        self.syntax_history.clear();
+
         for cursor in &mut self.cursors {
-            cursor.position = self.adjust_position_after_edits(cursor.position, edits)?;
-            cursor.anchor = self.adjust_position_after_edits(cursor.anchor, edits)?;
+            cursor.position = Self::adjust_position_after_edits(cursor.position, &sorted_edits);
+            cursor.anchor = Self::adjust_position_after_edits(cursor.anchor, &sorted_edits);
 
             // Update selection state
             cursor.has_selection = cursor.position != cursor.anchor;
@@ -733,63 +1339,99 @@ impl CursorManager {
         Ok(())
     }
 
-    /// Adjust a single position based on text edits
-    fn adjust_position_after_edits(
-        &self,
-        mut position: Position,
-        edits: &[TextEdit],
-    ) -> Result<Position> {
-        // Apply edits in reverse order (they should already be sorted)
-        for edit in edits.iter().rev() {
-            position = self.adjust_position_after_edit(position, edit)?;
+    /// Adjust a single position based on text edits sorted from last to first
+    ///
+    /// Cursors touching an edit move past the inserted text.
+    fn adjust_position_after_edits(mut position: Position, edits: &[&TextEdit]) -> Position {
+        for edit in edits {
+            position = adjust_position(position, &edit.range, &edit.new_text, Gravity::Right);
         }
-        Ok(position)
+        position
     }
+}
 
-    /// Adjust a position after a single edit
-    fn adjust_position_after_edit(&self, position: Position, edit: &TextEdit) -> Result<Position> {
-        let edit_start = edit.range.start;
-        let edit_end = edit.range.end;
-
-        // If position is before the edit, no change needed
-        if position < edit_start {
-            return Ok(position);
-        }
+/// Text of a line without its line ending
+fn line_content(buffer: &TextBuffer, line: usize) -> Result<String> {
+    let len = buffer.line_len(line)?;
+    Ok(buffer.line_text(line)?.chars().take(len).collect())
+}
 
-        // If position is within the edited range, move to start of edit
-        if position >= edit_start && position <= edit_end {
-            return Ok(edit_start);
-        }
+/// Columns where each grapheme cluster of a line starts
+fn grapheme_starts(text: &str) -> impl Iterator<Item = usize> + '_ {
+    text.graphemes(true).scan(0, |column, grapheme| {
+        let start = *column;
+        *column += grapheme.chars().count();
+        Some(start)
+    })
+}
 
-        // Position is after the edit, need to adjust
-        let deleted_text_lines = edit_end.line - edit_start.line;
-        let inserted_text_lines = edit.new_text.lines().count().saturating_sub(1);
+/// How a character splits identifiers into sub-words
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SubWordClass {
+    Lower,
+    Upper,
+    Digit,
+    Punctuation,
+    Separator,
+}
 
-        if deleted_text_lines == 0 && inserted_text_lines == 0 {
-            // Single-line edit
-            if position.line == edit_start.line {
-                let chars_deleted = edit_end.column - edit_start.column;
-                let chars_inserted = edit.new_text.len();
+impl SubWordClass {
+    fn of(ch: char) -> Self {
+        if ch.is_whitespace() || ch == '_' {
+            Self::Separator
+        } else if ch.is_uppercase() {
+            Self::Upper
+        } else if ch.is_numeric() {
+            Self::Digit
+        } else if ch.is_alphabetic() {
+            Self::Lower
+        } else {
+            Self::Punctuation
+        }
+    }
+}
 
-                if chars_inserted >= chars_deleted {
-                    let chars_added = chars_inserted - chars_deleted;
-                    return Ok(Position::new(position.line, position.column + chars_added));
-                } else {
-                    let chars_removed = chars_deleted - chars_inserted;
-                    return Ok(Position::new(
-                        position.line,
-                        position.column.saturating_sub(chars_removed),
-                    ));
+/// Start and end columns of the sub-words in a line
+///
+/// `parseHTTPRequest_v2` splits into `parse`, `HTTP`, `Request`, `v` and `2`.
+fn sub_word_spans(text: &str) -> Vec<(usize, usize)> {
+    let classes: Vec<SubWordClass> = text.chars().map(SubWordClass::of).collect();
+    let class_at = |i: usize| classes.get(i).copied();
+    let mut spans = Vec::new();
+    let mut i = 0;
+
+    while i < classes.len() {
+        let start = i;
+        let class = classes[i];
+        i += 1;
+
+        match class {
+            SubWordClass::Separator => continue,
+            SubWordClass::Upper => {
+                while class_at(i) == Some(SubWordClass::Upper) {
+                    i += 1;
+                }
+                if i - start == 1 {
+                    // A capital followed by lowercase letters, like `Request`
+                    while class_at(i) == Some(SubWordClass::Lower) {
+                        i += 1;
+                    }
+                } else if class_at(i) == Some(SubWordClass::Lower) {
+                    // The last capital of `HTTPRequest` starts the next sub-word
+                    i -= 1;
+                }
+            }
+            _ => {
+                while class_at(i) == Some(class) {
+                    i += 1;
                 }
             }
         }
 
-        // Multi-line edit adjustment (simplified)
-        let line_delta = inserted_text_lines as i32 - deleted_text_lines as i32;
-        let new_line = (position.line as i32 + line_delta).max(0) as usize;
-
-        Ok(Position::new(new_line, position.column))
+        spans.push((start, i));
     }
+
+    spans
 }
 
 impl Default for CursorManager {
@@ -1041,4 +1683,202 @@ mod tests {
             .unwrap();
         assert_eq!(manager.primary_cursor().position, Position::new(2, 10));
     }
+
+    #[test]
+    fn test_movement_skips_hidden_lines() {
+        let buffer = TextBuffer::from_content("a {\n  b\n  c\n}\nlast", None).unwrap();
+        let mut manager = CursorManager::with_position(Position::new(2, 1));
+
+        // Cursors on hidden lines move up to the visible line above
+        manager
+            .set_hidden_lines(&buffer, vec![(2, 2), (1, 1)])
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(0, 3));
+
+        manager
+            .move_cursors(&buffer, Direction::Right, MovementUnit::Character, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(3, 0));
+        manager
+            .move_cursors(&buffer, Direction::Left, MovementUnit::Character, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(0, 3));
+
+        manager
+            .move_cursors(&buffer, Direction::Down, MovementUnit::Line, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(3, 1));
+        manager
+            .move_cursors(&buffer, Direction::Up, MovementUnit::Line, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(0, 3));
+
+        // A fold reaching the end of the document leaves nowhere below to go
+        manager.set_hidden_lines(&buffer, vec![(1, 4)]).unwrap();
+        manager
+            .move_cursors(&buffer, Direction::Down, MovementUnit::Line, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(0, 3));
+
+        // A selection keeps its anchor when its end is hidden
+        manager.restore_cursors(vec![Cursor::with_selection(
+            0,
+            Position::new(4, 2),
+            Position::new(2, 1),
+        )]);
+        manager.set_hidden_lines(&buffer, vec![(1, 2)]).unwrap();
+        let cursor = manager.primary_cursor();
+        assert_eq!(cursor.anchor, Position::new(4, 2));
+        assert_eq!(cursor.position, Position::new(0, 3));
+        assert!(cursor.has_selection);
+    }
+
+    #[test]
+    fn test_sub_word_movement() {
+        let buffer = TextBuffer::from_content(
+            "let parseHTTPRequest_v2 = x;
+next",
+            None,
+        )
+        .unwrap();
+        let mut manager = CursorManager::with_position(Position::new(0, 4));
+        let mut stops = Vec::new();
+
+        for _ in 0..8 {
+            manager
+                .move_cursors(&buffer, Direction::Right, MovementUnit::SubWord, false)
+                .unwrap();
+            stops.push(manager.primary_cursor().position.column);
+        }
+        assert_eq!(stops, [9, 13, 20, 22, 23, 25, 27, 28]);
+
+        manager
+            .move_cursors(&buffer, Direction::Right, MovementUnit::SubWord, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(1, 0));
+
+        manager.primary_cursor_mut().move_to(Position::new(0, 23));
+        let mut stops = Vec::new();
+        for _ in 0..4 {
+            manager
+                .move_cursors(&buffer, Direction::Left, MovementUnit::SubWord, false)
+                .unwrap();
+            stops.push(manager.primary_cursor().position.column);
+        }
+        assert_eq!(stops, [22, 21, 13, 9]);
+    }
+
+    #[test]
+    fn test_grapheme_movement() {
+        // "e" with a combining acute accent, then a family emoji made of joined code points
+        let buffer =
+            TextBuffer::from_content("ae\u{301}\u{1F468}\u{200D}\u{1F469}b\nx", None).unwrap();
+        let mut manager = CursorManager::new();
+        let mut stops = Vec::new();
+
+        for _ in 0..5 {
+            manager
+                .move_cursors(&buffer, Direction::Right, MovementUnit::Grapheme, false)
+                .unwrap();
+            stops.push(manager.primary_cursor().position);
+        }
+        assert_eq!(
+            stops,
+            [
+                Position::new(0, 1),
+                Position::new(0, 3),
+                Position::new(0, 6),
+                Position::new(0, 7),
+                Position::new(1, 0),
+            ]
+        );
+
+        manager.primary_cursor_mut().move_to(Position::new(0, 6));
+        manager
+            .move_cursors(&buffer, Direction::Left, MovementUnit::Grapheme, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(0, 3));
+    }
+
+    #[test]
+    fn test_visual_line_movement_follows_wrapped_rows() {
+        let buffer = TextBuffer::from_content("hello world foo\nab\nlast", None).unwrap();
+        let mut manager = CursorManager::with_position(Position::new(0, 2));
+
+        // Without word wrap each line is a single row
+        manager
+            .move_cursors(&buffer, Direction::Down, MovementUnit::VisualLine, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(1, 2));
+
+        // Rows are "hello ", "world " and "foo"
+        manager.set_wrap_width(Some(8));
+        manager.primary_cursor_mut().move_to(Position::new(0, 4));
+        let mut stops = Vec::new();
+        for _ in 0..4 {
+            manager
+                .move_cursors(&buffer, Direction::Down, MovementUnit::VisualLine, false)
+                .unwrap();
+            stops.push(manager.primary_cursor().position);
+        }
+        assert_eq!(
+            stops,
+            [
+                Position::new(0, 10),
+                Position::new(0, 15),
+                Position::new(1, 2),
+                Position::new(2, 4),
+            ]
+        );
+
+        // The row offset survives passing through short rows
+        for _ in 0..2 {
+            manager
+                .move_cursors(&buffer, Direction::Up, MovementUnit::VisualLine, false)
+                .unwrap();
+        }
+        assert_eq!(manager.primary_cursor().position, Position::new(0, 15));
+        manager
+            .move_cursors(&buffer, Direction::Up, MovementUnit::VisualLine, false)
+            .unwrap();
+        assert_eq!(manager.primary_cursor().position, Position::new(0, 10));
+    }
+
+    #[test]
+    fn test_block_selection_lines_up_visual_columns() {
+        let buffer = TextBuffer::from_content("x\tabc\n中文abc\nab\nabcdefgh", None).unwrap();
+        let mut manager = CursorManager::new();
+        let selected = |manager: &CursorManager| {
+            manager
+                .cursors()
+                .iter()
+                .map(|cursor| cursor.selected_text(&buffer).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let block = BlockSelection {
+            anchor: Position::new(0, 4),
+            head: Position::new(3, 6),
+        };
+        manager.select_block(&buffer, block).unwrap();
+        assert_eq!(manager.selection_mode(), SelectionMode::Block);
+        assert_eq!(selected(&manager), vec!["ab", "ab", "", "ef"]);
+
+        // Keyboard extension moves the head corner
+        manager
+            .move_cursors(&buffer, Direction::Right, MovementUnit::Character, true)
+            .unwrap();
+        manager
+            .move_cursors(&buffer, Direction::Up, MovementUnit::Line, true)
+            .unwrap();
+        assert_eq!(selected(&manager), vec!["abc", "abc", ""]);
+        assert_eq!(manager.block_selection().unwrap().head, Position::new(2, 7));
+
+        // Moving without extending leaves block mode's rectangle behind
+        manager
+            .move_cursors(&buffer, Direction::Left, MovementUnit::Character, false)
+            .unwrap();
+        assert!(manager.block_selection().is_none());
+        assert_eq!(manager.cursor_count(), 3);
+    }
 }
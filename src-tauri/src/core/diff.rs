@@ -0,0 +1,420 @@
+// src-tauri/src/core/diff.rs
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A single step of an edit script between two sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    /// Element present in both sequences
+    Equal { old: usize, new: usize },
+    /// Element only present in the old sequence
+    Delete { old: usize },
+    /// Element only present in the new sequence
+    Insert { new: usize },
+}
+
+/// Kind of a line in a line diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineDiffKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A line of a line diff, for display
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineDiff {
+    pub kind: LineDiffKind,
+    /// Line text without its line ending
+    pub text: String,
+    /// 0-indexed line in the old text
+    pub old_line: Option<usize>,
+    /// 0-indexed line in the new text
+    pub new_line: Option<usize>,
+}
+
+/// Compute a shortest edit script between two sequences (Myers' algorithm)
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    // The middle snake search runs in linear space: O(N + M) however far
+    // apart the sequences are
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut forward = vec![0usize; 2 * max_d + 1];
+    let mut backward = vec![0usize; 2 * max_d + 1];
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+
+    conquer(
+        old,
+        0..old.len(),
+        new,
+        0..new.len(),
+        &mut forward,
+        &mut backward,
+        &mut ops,
+    );
+
+    // Within each changed region, deletions come before insertions
+    for region in ops.split_mut(|op| matches!(op, DiffOp::Equal { .. })) {
+        region.sort_by_key(|op| matches!(op, DiffOp::Insert { .. }));
+    }
+    ops
+}
+
+/// Emit the edit script between two subranges, splitting at the middle snake
+fn conquer<T: PartialEq>(
+    old: &[T],
+    mut old_range: Range<usize>,
+    new: &[T],
+    mut new_range: Range<usize>,
+    forward: &mut [usize],
+    backward: &mut [usize],
+    ops: &mut Vec<DiffOp>,
+) {
+    let prefix = common_prefix(&old[old_range.clone()], &new[new_range.clone()]);
+    ops.extend((0..prefix).map(|i| DiffOp::Equal {
+        old: old_range.start + i,
+        new: new_range.start + i,
+    }));
+    old_range.start += prefix;
+    new_range.start += prefix;
+
+    let suffix = common_suffix(&old[old_range.clone()], &new[new_range.clone()]);
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+    let (old_suffix_start, new_suffix_start) = (old_range.end, new_range.end);
+
+    if old_range.is_empty() {
+        ops.extend(new_range.map(|new| DiffOp::Insert { new }));
+    } else if new_range.is_empty() {
+        ops.extend(old_range.map(|old| DiffOp::Delete { old }));
+    } else {
+        let (x, y) = middle_snake(
+            &old[old_range.clone()],
+            &new[new_range.clone()],
+            forward,
+            backward,
+        );
+        let (old_split, new_split) = (old_range.start + x, new_range.start + y);
+
+        conquer(
+            old,
+            old_range.start..old_split,
+            new,
+            new_range.start..new_split,
+            forward,
+            backward,
+            ops,
+        );
+        conquer(
+            old,
+            old_split..old_range.end,
+            new,
+            new_split..new_range.end,
+            forward,
+            backward,
+            ops,
+        );
+    }
+
+    ops.extend((0..suffix).map(|i| DiffOp::Equal {
+        old: old_suffix_start + i,
+        new: new_suffix_start + i,
+    }));
+}
+
+/// Find a point on an optimal path through the edit graph of two non-empty
+/// sequences that differ at both ends, searching from both corners at once
+///
+/// The returned point splits the edit script into two strictly smaller ones.
+fn middle_snake<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    forward: &mut [usize],
+    backward: &mut [usize],
+) -> (usize, usize) {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let delta = n - m;
+    let odd = delta & 1 == 1;
+    let max_d = (n + m + 1) / 2 + 1;
+    let index = |k: isize| (k + max_d) as usize;
+
+    forward[index(1)] = 0;
+    backward[index(1)] = 0;
+
+    for d in 0..max_d {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)]) {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            } as isize;
+            let y = x - k;
+            let snake_start = (x, y);
+
+            if x < n && y >= 0 && y < m {
+                x += common_prefix(&old[x as usize..], &new[y as usize..]) as isize;
+            }
+            forward[index(k)] = x as usize;
+
+            if odd && (k - delta).abs() < d && x + backward[index(delta - k)] as isize >= n {
+                return (snake_start.0 as usize, snake_start.1 as usize);
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            } as isize;
+            let mut y = x - k;
+
+            if x < n && y >= 0 && y < m {
+                let advance =
+                    common_suffix(&old[..(n - x) as usize], &new[..(m - y) as usize]) as isize;
+                x += advance;
+                y += advance;
+            }
+            backward[index(k)] = x as usize;
+
+            if !odd && (k - delta).abs() <= d && x + forward[index(delta - k)] as isize >= n {
+                return ((n - x) as usize, (m - y) as usize);
+            }
+        }
+    }
+
+    unreachable!("the forward and backward searches always meet")
+}
+
+/// Number of leading elements two sequences share
+fn common_prefix<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Number of trailing elements two sequences share
+fn common_suffix<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// A replaced region of text, in char offsets into the old text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChange {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Split text into lines, keeping line endings attached
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Compute a line-by-line diff between two texts
+pub fn diff_lines(old: &str, new: &str) -> Vec<LineDiff> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+
+    let strip = |line: &str| line.trim_end_matches(['\n', '\r']).to_string();
+
+    diff(&old_lines, &new_lines)
+        .into_iter()
+        .map(|op| match op {
+            DiffOp::Equal { old, new } => LineDiff {
+                kind: LineDiffKind::Equal,
+                text: strip(old_lines[old]),
+                old_line: Some(old),
+                new_line: Some(new),
+            },
+            DiffOp::Delete { old } => LineDiff {
+                kind: LineDiffKind::Delete,
+                text: strip(old_lines[old]),
+                old_line: Some(old),
+                new_line: None,
+            },
+            DiffOp::Insert { new } => LineDiff {
+                kind: LineDiffKind::Insert,
+                text: strip(new_lines[new]),
+                old_line: None,
+                new_line: Some(new),
+            },
+        })
+        .collect()
+}
+
+/// Compute the changed line regions that turn `old` into `new`
+pub fn line_changes(old: &str, new: &str) -> Vec<TextChange> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+
+    let mut line_offsets = Vec::with_capacity(old_lines.len() + 1);
+    let mut offset = 0;
+    for line in &old_lines {
+        line_offsets.push(offset);
+        offset += line.chars().count();
+    }
+    line_offsets.push(offset);
+
+    let mut changes = Vec::new();
+    // Pending change as (first old line, end old line, replacement)
+    let mut pending: Option<(usize, usize, String)> = None;
+    let mut old_line = 0;
+
+    for op in diff(&old_lines, &new_lines) {
+        match op {
+            DiffOp::Equal { old, .. } => {
+                if let Some((start, end, text)) = pending.take() {
+                    changes.push(TextChange {
+                        start: line_offsets[start],
+                        end: line_offsets[end],
+                        text,
+                    });
+                }
+                old_line = old + 1;
+            }
+            DiffOp::Delete { old } => {
+                pending.get_or_insert((old, old, String::new())).1 = old + 1;
+                old_line = old + 1;
+            }
+            DiffOp::Insert { new } => {
+                pending
+                    .get_or_insert((old_line, old_line, String::new()))
+                    .2
+                    .push_str(new_lines[new]);
+            }
+        }
+    }
+
+    if let Some((start, end, text)) = pending {
+        changes.push(TextChange {
+            start: line_offsets[start],
+            end: line_offsets[end],
+            text,
+        });
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rebuild the new sequence from the old one and an edit script
+    fn apply(old: &[char], new: &[char], ops: &[DiffOp]) -> Vec<char> {
+        ops.iter()
+            .filter_map(|op| match *op {
+                DiffOp::Equal { old: i, .. } => Some(old[i]),
+                DiffOp::Insert { new: i } => Some(new[i]),
+                DiffOp::Delete { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_is_minimal() {
+        let old: Vec<char> = "ABCABBA".chars().collect();
+        let new: Vec<char> = "CBABAC".chars().collect();
+        let ops = diff(&old, &new);
+
+        let edits = ops
+            .iter()
+            .filter(|op| !matches!(op, DiffOp::Equal { .. }))
+            .count();
+        assert_eq!(edits, 5);
+        assert_eq!(apply(&old, &new, &ops), new);
+    }
+
+    #[test]
+    fn test_diff_edge_cases() {
+        let empty: Vec<char> = Vec::new();
+        let text: Vec<char> = "abc".chars().collect();
+
+        assert!(diff(&empty, &empty).is_empty());
+        assert_eq!(apply(&empty, &text, &diff(&empty, &text)), text);
+        assert_eq!(apply(&text, &empty, &diff(&text, &empty)), empty);
+        assert!(diff(&text, &text)
+            .iter()
+            .all(|op| matches!(op, DiffOp::Equal { .. })));
+    }
+
+    #[test]
+    fn test_diff_is_minimal_on_scrambled_input() {
+        // Length of the longest common subsequence, by dynamic programming
+        fn lcs(a: &[char], b: &[char]) -> usize {
+            let mut row = vec![0; b.len() + 1];
+            for x in a {
+                let mut diagonal = 0;
+                for (j, y) in b.iter().enumerate() {
+                    let above = row[j + 1];
+                    row[j + 1] = if x == y {
+                        diagonal + 1
+                    } else {
+                        above.max(row[j])
+                    };
+                    diagonal = above;
+                }
+            }
+            row[b.len()]
+        }
+
+        let mut seed = 7u32;
+        let mut scrambled = |len: usize| -> Vec<char> {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (b'a' + (seed >> 16) as u8 % 4) as char
+                })
+                .collect()
+        };
+
+        for len in [1, 2, 5, 17, 60, 200] {
+            let old = scrambled(len);
+            let new = scrambled(len + len / 3);
+            let ops = diff(&old, &new);
+
+            let equal = ops
+                .iter()
+                .filter(|op| matches!(op, DiffOp::Equal { .. }))
+                .count();
+            assert_eq!(equal, lcs(&old, &new));
+            assert_eq!(apply(&old, &new, &ops), new);
+        }
+    }
+
+    #[test]
+    fn test_line_changes() {
+        let old = "one\ntwo\nthree\nfour\n";
+        let new = "zero\none\n2\nthree\n";
+        let changes = line_changes(old, new);
+
+        // Applying the changes back to front rebuilds the new text
+        let mut chars: Vec<char> = old.chars().collect();
+        for change in changes.iter().rev() {
+            chars.splice(change.start..change.end, change.text.chars());
+        }
+        assert_eq!(chars.into_iter().collect::<String>(), new);
+        assert_eq!(changes.len(), 3);
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let lines = diff_lines("one\ntwo\nthree\n", "one\n2\nthree\n");
+
+        let kinds: Vec<LineDiffKind> = lines.iter().map(|line| line.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                LineDiffKind::Equal,
+                LineDiffKind::Delete,
+                LineDiffKind::Insert,
+                LineDiffKind::Equal
+            ]
+        );
+        assert_eq!(lines[1].text, "two");
+        assert_eq!(lines[2].new_line, Some(1));
+    }
+}
@@ -1,21 +1,44 @@
 use crate::core::{
-    cursor::{CursorManager, Direction, MovementUnit, SelectionMode},
+    anchor::{adjust_position, AnchorId, Gravity, RangeAnchor},
+    atomic_save::BackupMode,
+    command::{CommandOutput, EditorCommand, RangeReplacement},
+    cursor::{BlockSelection, Cursor, CursorManager, Direction, MovementUnit, SelectionMode},
+    diff::{line_changes, TextChange},
+    encoding::FileEncoding,
+    folding::{self, FoldKind, FoldingRange},
+    macros::Macro,
+    merge::{merge3, MergeConflict},
+    snippet::{Snippet, SnippetSession, SnippetStatus, SnippetVariables},
     syntax::{SyntaxTheme, ThemedSyntaxHighlighter, ThemedToken},
-    text_buffer::{BufferChangeEvent, BufferConfig, Position, Range, TextBuffer, TextEdit},
+    text_buffer::{
+        BufferChangeEvent, BufferConfig, LargeFileConfig, Position, Range, TextBuffer, TextEdit,
+        UndoGroupKind,
+    },
+    text_transform::TextTransform,
     traits::EditorEventListener,
-    utils, EditorError, EditorResult,
+    undo_tree::UndoNodeId,
+    utils,
+    vim::{VimEffect, VimKey, VimState, VimStatus},
+    EditorError, EditorResult,
 };
 
 use anyhow::{Context, Result};
+use parking_lot::Mutex;
 use regex::Regex;
+use ropey::Rope;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::{debug, info, instrument, warn};
+use tree_sitter::Tree;
 use uuid::Uuid;
 
+/// Bytes of text searched at a time, rounded up to whole lines
+const SEARCH_WINDOW_BYTES: usize = 1 << 20;
+
 /// Configuration for the editor
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EditorConfig {
@@ -51,6 +74,19 @@ pub struct EditorConfig {
     pub line_height: f32,
     /// Maximum number of undo operations
     pub max_undo_operations: usize,
+    /// Backups of the previous file contents kept on save
+    #[serde(default)]
+    pub backup_mode: BackupMode,
+    /// Thresholds for large-file mode
+    #[serde(default)]
+    pub large_file: LargeFileConfig,
+    /// Whether code can be folded
+    #[serde(default = "default_folding")]
+    pub folding: bool,
+}
+
+fn default_folding() -> bool {
+    true
 }
 
 impl Default for EditorConfig {
@@ -72,6 +108,9 @@ impl Default for EditorConfig {
             font_size: 14.0,
             line_height: 1.4,
             max_undo_operations: 1000,
+            backup_mode: BackupMode::None,
+            large_file: LargeFileConfig::default(),
+            folding: true,
         }
     }
 }
@@ -97,6 +136,15 @@ pub struct EditorState {
     pub encoding: String,
     /// Line ending style
     pub line_ending: String,
+    /// Whether the file is open in large-file mode
+    #[serde(default)]
+    pub large_file: bool,
+    /// Vim mode and pending keys, when Vim editing is enabled
+    #[serde(default)]
+    pub vim: Option<VimStatus>,
+    /// Active tabstop of an inserted snippet
+    #[serde(default)]
+    pub snippet: Option<SnippetStatus>,
 }
 
 /// Viewport/scroll information for the editor
@@ -110,6 +158,9 @@ pub struct ViewState {
     pub visible_lines: usize,
     /// Width of the editor viewport in pixels
     pub viewport_width: f32,
+    /// Number of character columns that fit in the viewport, where word wrap breaks rows
+    #[serde(default = "default_visible_columns")]
+    pub visible_columns: usize,
     /// Height of the editor viewport in pixels
     pub viewport_height: f32,
 }
@@ -121,11 +172,16 @@ impl Default for ViewState {
             scroll_left: 0.0,
             visible_lines: 25,
             viewport_width: 800.0,
+            visible_columns: default_visible_columns(),
             viewport_height: 600.0,
         }
     }
 }
 
+fn default_visible_columns() -> usize {
+    80
+}
+
 /// Scroll position information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScrollInfo {
@@ -159,7 +215,7 @@ pub struct EditorMetrics {
 }
 
 /// Search configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SearchOptions {
     /// Search query
     pub query: String,
@@ -188,6 +244,27 @@ pub struct SearchResult {
     pub total_matches: usize,
 }
 
+/// How lines are ordered by [`Editor::sort_lines`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortOptions {
+    /// Compare runs of digits by value, so `item2` comes before `item10`
+    pub natural: bool,
+    /// Ignore case when comparing lines
+    pub case_insensitive: bool,
+    /// Keep only the first of lines that compare equal
+    pub unique: bool,
+}
+
+/// New contents for a run of whole lines, as the line commands produce
+struct LineBlockEdit {
+    /// Lines being replaced
+    lines: RangeInclusive<usize>,
+    /// Replacement lines, empty to remove the lines altogether
+    text: Vec<String>,
+    /// Lines to move the cursors starting inside the block by
+    cursor_shift: isize,
+}
+
 /// Events emitted by the editor
 #[derive(Debug, Clone, Serialize)]
 pub enum EditorEvent {
@@ -223,6 +300,30 @@ pub enum EditorEvent {
         results: Vec<SearchResult>,
         current_index: Option<usize>,
     },
+    /// The editor gained or lost focus
+    FocusChanged { has_focus: bool },
+    /// A view other than the active one moved along with an edit
+    ViewMoved {
+        view_id: Uuid,
+        positions: Vec<Position>,
+        scroll_top: usize,
+    },
+}
+
+/// Outcome of reconciling an editor with its file changing on disk
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
+pub enum ExternalChange {
+    /// The disk contents match what the buffer last loaded or saved
+    Unchanged,
+    /// The buffer had no unsaved changes and now shows the disk contents
+    Reloaded,
+    /// Unsaved changes were merged with the disk contents
+    Merged { conflicts: Vec<MergeConflict> },
+    /// Unsaved changes in a large file were kept as they are, without merging
+    Diverged,
+    /// The file was removed from disk
+    Deleted,
 }
 
 /// The main editor implementation
@@ -251,6 +352,59 @@ pub struct Editor {
     metrics: EditorMetrics,
     /// Operation history for metrics
     operation_times: VecDeque<Duration>,
+    /// Modal editing state, when Vim editing is enabled
+    vim: Option<VimState>,
+    /// Macro being recorded from executed commands
+    macro_recording: Option<Macro>,
+    /// Folded regions
+    folds: Vec<Fold>,
+    /// Tabstops of the snippet being filled in
+    snippet_session: Option<SnippetSession>,
+    /// View the cursors, viewport, folds and search above belong to
+    view_id: Uuid,
+    /// Other views onto the same buffer, each with its own cursors and viewport
+    ///
+    /// Shared with the buffer's change listener, which moves them along with
+    /// every edit. Never locked while the buffer is being changed.
+    split_views: Arc<Mutex<Vec<SplitView>>>,
+}
+
+/// A folded region, tracked from the end of its first line to the end of its last hidden line
+#[derive(Debug, Clone, Copy)]
+struct Fold {
+    range: RangeAnchor,
+    kind: FoldKind,
+}
+
+/// State of a view that isn't the active one, swapped in when it becomes active
+struct SplitView {
+    id: Uuid,
+    cursor_manager: CursorManager,
+    view_state: ViewState,
+    folds: Vec<Fold>,
+    current_search: Option<SearchOptions>,
+    search_results: Vec<SearchResult>,
+    snippet_session: Option<SnippetSession>,
+}
+
+impl SplitView {
+    /// Move the cursors and viewport along with edits made in another view
+    fn follow_edits(&mut self, edits: &[TextEdit]) {
+        let mut scroll_top = Position::new(self.view_state.scroll_top, 0);
+        for edit in edits {
+            if let Err(e) = self
+                .cursor_manager
+                .update_after_edits(std::slice::from_ref(edit))
+            {
+                warn!("Failed to update cursors of view {}: {}", self.id, e);
+            }
+            scroll_top = adjust_position(scroll_top, &edit.range, &edit.new_text, Gravity::Left);
+        }
+        self.view_state.scroll_top = scroll_top.line;
+        if !edits.is_empty() {
+            self.search_results.clear();
+        }
+    }
 }
 
 impl Editor {
@@ -263,12 +417,14 @@ impl Editor {
     pub fn with_config(config: EditorConfig) -> Self {
         let buffer_config = BufferConfig {
             max_undo_entries: config.max_undo_operations,
+            backup_mode: config.backup_mode,
+            large_file: config.large_file,
             ..BufferConfig::default()
         };
 
         let buffer = TextBuffer::with_config(buffer_config);
         let mut cursor_manager = CursorManager::new();
-        cursor_manager.set_page_size(config.page_scroll_lines);
+        configure_cursors(&mut cursor_manager, &config, &ViewState::default());
 
         let syntax_highlighter = ThemedSyntaxHighlighter::with_dark_theme();
 
@@ -282,10 +438,14 @@ impl Editor {
             char_count: 0,
             encoding: "UTF-8".to_string(),
             line_ending: "LF".to_string(),
+            large_file: false,
+            vim: None,
+            snippet: None,
         };
 
-        Self {
-            id: Uuid::new_v4(),
+        let id = Uuid::new_v4();
+        let mut editor = Self {
+            id,
             buffer,
             cursor_manager,
             syntax_highlighter,
@@ -303,7 +463,15 @@ impl Editor {
                 syntax_cache_hit_rate: 0.0,
             },
             operation_times: VecDeque::new(),
-        }
+            vim: None,
+            macro_recording: None,
+            folds: Vec::new(),
+            snippet_session: None,
+            view_id: id,
+            split_views: Arc::new(Mutex::new(Vec::new())),
+        };
+        editor.track_view_changes();
+        editor
     }
 
     /// Get the editor's unique ID
@@ -368,13 +536,32 @@ impl Editor {
         self.config = config;
 
         // Update dependent components
-        self.cursor_manager
-            .set_page_size(self.config.page_scroll_lines);
+        configure_cursors(&mut self.cursor_manager, &self.config, &self.view_state);
+        for view in self.split_views.lock().iter_mut() {
+            configure_cursors(&mut view.cursor_manager, &self.config, &view.view_state);
+        }
+        if !self.config.folding {
+            self.unfold_all()?;
+            let folds: Vec<Fold> = self
+                .split_views
+                .lock()
+                .iter_mut()
+                .flat_map(|view| view.folds.drain(..))
+                .collect();
+            for fold in folds {
+                self.buffer.remove_range_anchor(fold.range);
+            }
+        }
 
         // Update buffer config if needed
-        if old_config.max_undo_operations != self.config.max_undo_operations {
+        if old_config.max_undo_operations != self.config.max_undo_operations
+            || old_config.backup_mode != self.config.backup_mode
+            || old_config.large_file != self.config.large_file
+        {
             let mut buffer_config = self.buffer.config().clone();
             buffer_config.max_undo_entries = self.config.max_undo_operations;
+            buffer_config.backup_mode = self.config.backup_mode;
+            buffer_config.large_file = self.config.large_file;
             self.buffer.set_config(buffer_config);
         }
 
@@ -395,13 +582,35 @@ impl Editor {
     /// Load file into the editor
     #[instrument(skip(self))]
     pub async fn load_file<P: AsRef<Path>>(&mut self, path: P) -> EditorResult<()> {
-        let path = path.as_ref();
+        self.load(path.as_ref(), None).await
+    }
+
+    /// Reload the current file, decoding it in a different encoding
+    ///
+    /// Unsaved changes are discarded.
+    #[instrument(skip(self))]
+    pub async fn reopen_with_encoding(&mut self, encoding: FileEncoding) -> EditorResult<()> {
+        let path = self
+            .buffer
+            .file_path()
+            .cloned()
+            .ok_or(EditorError::NoFile)?;
+        self.load(&path, Some(encoding)).await
+    }
+
+    async fn load(&mut self, path: &Path, encoding: Option<FileEncoding>) -> EditorResult<()> {
         let start_time = Instant::now();
 
-        // Load the file
-        self.buffer = TextBuffer::from_file(path.to_path_buf())
-            .await
-            .context("Failed to load file")?;
+        // Load the file, keeping this editor's buffer settings
+        let buffer_config = self.buffer.config().clone();
+        self.buffer =
+            TextBuffer::from_file_with_config(path.to_path_buf(), encoding, buffer_config)
+                .await
+                .context("Failed to load file")?;
+        self.track_view_changes();
+
+        // Trees and tokens of the old buffer are keyed by versions the new one reuses
+        self.syntax_highlighter.highlighter_mut().clear_cache();
 
         // Detect and set language
         if let Some(language) = self
@@ -421,13 +630,30 @@ impl Editor {
         self.state.is_dirty = false;
         self.update_state_from_buffer();
 
+        // Folds and snippet fields were anchored in the old buffer
+        self.folds.clear();
+        self.snippet_session = None;
+        self.state.snippet = None;
+
         // Reset cursor to start
         self.cursor_manager = CursorManager::new();
+        configure_cursors(&mut self.cursor_manager, &self.config, &self.view_state);
 
         // Clear search state
         self.current_search = None;
         self.search_results.clear();
 
+        // Split views start over at the top of the new contents
+        for view in self.split_views.lock().iter_mut() {
+            view.cursor_manager = CursorManager::new();
+            configure_cursors(&mut view.cursor_manager, &self.config, &view.view_state);
+            view.view_state.scroll_top = 0;
+            view.folds.clear();
+            view.current_search = None;
+            view.search_results.clear();
+            view.snippet_session = None;
+        }
+
         self.record_operation_time(start_time.elapsed());
 
         self.emit_event(EditorEvent::FileLoaded {
@@ -444,7 +670,7 @@ impl Editor {
     pub async fn save(&mut self) -> EditorResult<()> {
         let start_time = Instant::now();
 
-        self.buffer.save().await.context("Failed to save file")?;
+        self.buffer.save().await?;
 
         self.state.is_dirty = false;
 
@@ -458,16 +684,28 @@ impl Editor {
         Ok(())
     }
 
+    /// Save the current file in a different encoding
+    #[instrument(skip(self))]
+    pub async fn save_with_encoding(&mut self, encoding: FileEncoding) -> EditorResult<()> {
+        let (previous, was_dirty) = (self.buffer.encoding(), self.buffer.is_dirty());
+        self.buffer.set_encoding(encoding);
+
+        let result = self.save().await;
+        if result.is_err() {
+            // Keep saving in the old encoding if the text doesn't fit the new one
+            self.buffer.set_encoding(previous);
+            self.buffer.set_dirty(was_dirty);
+        }
+        result
+    }
+
     /// Save to a specific file
     #[instrument(skip(self))]
     pub async fn save_as<P: AsRef<Path>>(&mut self, path: P) -> EditorResult<()> {
         let path = path.as_ref();
         let start_time = Instant::now();
 
-        self.buffer
-            .save_to_file(path.to_path_buf())
-            .await
-            .context("Failed to save file")?;
+        self.buffer.save_to_file(path.to_path_buf()).await?;
 
         self.state.file_path = Some(path.to_path_buf());
         self.state.is_dirty = false;
@@ -485,6 +723,15 @@ impl Editor {
     /// Insert text at current cursor positions
     #[instrument(skip(self, text))]
     pub fn insert_text(&mut self, text: &str) -> EditorResult<()> {
+        let texts = vec![text; self.cursor_manager.cursor_count()];
+        self.insert_at_cursors(&texts)?;
+
+        debug!("Inserted text: {:?}", text);
+        Ok(())
+    }
+
+    /// Insert one piece of text at each cursor, in cursor order
+    fn insert_at_cursors(&mut self, texts: &[&str]) -> EditorResult<()> {
         let start_time = Instant::now();
 
         if self.state.is_readonly {
@@ -492,24 +739,23 @@ impl Editor {
         }
 
         let mut edits = Vec::new();
-        let cursor_positions = self.cursor_manager.cursor_positions();
 
-        // Create text edits for each cursor
-        for position in cursor_positions.iter().rev() {
+        // Create text edits for each cursor, replacing any selection
+        for (cursor, text) in self.cursor_manager.cursors().iter().zip(texts).rev() {
             // Insert in reverse order to maintain position accuracy
-            edits.push(TextEdit::insert(*position, text.to_string()));
+            if cursor.has_selection {
+                edits.push(TextEdit::replace(
+                    cursor.selection_range(),
+                    text.to_string(),
+                ));
+            } else {
+                edits.push(TextEdit::insert(cursor.position, text.to_string()));
+            }
         }
 
-        // Apply edits
-        self.buffer
-            .apply_edits(edits.clone())
+        self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
             .context("Failed to apply text edits")?;
 
-        // Update cursor positions
-        self.cursor_manager
-            .update_after_edits(&edits)
-            .context("Failed to update cursor positions")?;
-
         self.update_state_from_buffer();
         self.record_operation_time(start_time.elapsed());
 
@@ -520,7 +766,6 @@ impl Editor {
 
         self.emit_cursor_event();
 
-        debug!("Inserted text: {:?}", text);
         Ok(())
     }
 
@@ -532,10 +777,22 @@ impl Editor {
         }
 
         let mut text = ch.to_string();
+        // Characters to step back over so the cursor lands inside a bracket pair
+        let mut step_back = 0;
 
         // Handle auto-indentation
         if ch == '\n' && self.config.auto_indent {
-            if let Some(indent) = self.calculate_auto_indent()? {
+            let position = self.cursor_manager.primary_cursor().position;
+            let indent = self.indentation_at(position)?;
+            if self.is_between_brackets(position)? {
+                // The closing bracket moves down and the cursor goes in between
+                let unit = utils::create_indentation(1, self.config.use_tabs, self.config.tab_size);
+                text.push_str(&indent);
+                text.push_str(&unit);
+                text.push('\n');
+                text.push_str(&indent);
+                step_back = indent.chars().count() + 1;
+            } else {
                 text.push_str(&indent);
             }
         }
@@ -548,14 +805,31 @@ impl Editor {
             }
         }
 
-        self.insert_text(&text)?;
+        // Consecutive keystrokes are merged into word-sized undo steps
+        self.begin_undo_group(UndoGroupKind::Typing);
+        let result = self.insert_text(&text).and_then(|_| {
+            // Move cursor back if we auto-closed a bracket
+            if self.config.auto_close_brackets && self.get_auto_close_char(ch).is_some() {
+                step_back += 1;
+            }
+            for _ in 0..step_back {
+                self.move_cursors(Direction::Left, MovementUnit::Character, false)?;
+            }
 
-        // Move cursor back if we auto-closed a bracket
-        if self.config.auto_close_brackets && self.get_auto_close_char(ch).is_some() {
-            self.move_cursors(Direction::Left, MovementUnit::Character, false)?;
-        }
+            // A closing bracket typed at the start of a line moves back a level
+            if self.config.auto_indent && matches!(ch, '}' | ']' | ')') {
+                let position = self.cursor_manager.primary_cursor().position;
+                let line = self.buffer.line_text(position.line)?;
+                let before: String = line.chars().take(position.column).collect();
+                if before.trim_start() == ch.to_string() {
+                    self.reindent_lines(&[position.line])?;
+                }
+            }
+            Ok(())
+        });
+        self.end_undo_group();
 
-        Ok(())
+        result
     }
 
     /// Delete text at current selections or at cursor positions
@@ -587,949 +861,4435 @@ impl Editor {
         }
 
         if !edits.is_empty() {
-            self.buffer
-                .apply_edits(edits.clone())
+            self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
                 .context("Failed to apply delete edits")?;
 
-            self.cursor_manager
-                .update_after_edits(&edits)
-                .context("Failed to update cursor positions")?;
-
             self.update_state_from_buffer();
             self.record_operation_time(start_time.elapsed());
 
             self.emit_event(EditorEvent::TextChanged {
                 version: self.buffer.version(),
-                changes: vec![], // We don't track the inverse changes for now
+                changes: edits,
             });
 
             self.emit_cursor_event();
-
-            debug!("Redo operation completed");
-            Ok(true)
-        } else {
-            Ok(false)
         }
+
+        debug!("Deleted selection");
+        Ok(())
     }
 
-    /// Search for text in the buffer
+    /// Delete the selection, or the character before each cursor
     #[instrument(skip(self))]
-    pub fn search(&mut self, options: SearchOptions) -> EditorResult<Vec<SearchResult>> {
+    pub fn backspace(&mut self) -> EditorResult<()> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        if self.cursor_manager.has_selection() {
+            return self.delete_selection();
+        }
+
         let start_time = Instant::now();
+        let mut edits = Vec::new();
 
-        self.current_search = Some(options.clone());
-        let text = self.buffer.text();
+        for cursor in self.cursor_manager.cursors().iter().rev() {
+            let start_pos = self.move_position_left(cursor.position)?;
+            if start_pos != cursor.position {
+                edits.push(TextEdit::delete(Range::new(start_pos, cursor.position)));
+            }
+        }
 
-        let results = if options.use_regex {
-            self.search_regex(&text, &options)?
-        } else {
-            self.search_literal(&text, &options)?
-        };
+        if !edits.is_empty() {
+            self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+                .context("Failed to apply backspace edits")?;
 
-        self.search_results = results.clone();
-        self.record_operation_time(start_time.elapsed());
+            self.update_state_from_buffer();
+            self.record_operation_time(start_time.elapsed());
 
-        self.emit_event(EditorEvent::SearchResults {
-            results: results.clone(),
-            current_index: None,
-        });
+            self.emit_event(EditorEvent::TextChanged {
+                version: self.buffer.version(),
+                changes: edits,
+            });
 
-        debug!("Search completed: found {} matches", results.len());
-        Ok(results)
+            self.emit_cursor_event();
+        }
+
+        debug!("Backspace completed");
+        Ok(())
     }
 
-    /// Find next search result
-    pub fn find_next(&mut self) -> EditorResult<Option<SearchResult>> {
-        if let Some(options) = &self.current_search.clone() {
-            let current_pos = self.cursor_manager.primary_cursor().position;
+    /// Move all cursors in the given direction
+    #[instrument(skip(self))]
+    pub fn move_cursors(
+        &mut self,
+        direction: Direction,
+        unit: MovementUnit,
+        extend_selection: bool,
+    ) -> EditorResult<()> {
+        self.sync_folds()?;
+        self.cursor_manager
+            .move_cursors(&self.buffer, direction, unit, extend_selection)
+            .context("Failed to move cursors")?;
 
-            for (index, result) in self.search_results.iter().enumerate() {
-                if (options.forward && result.range.start > current_pos)
-                    || (!options.forward && result.range.start < current_pos)
-                {
-                    // Move cursor to this result
-                    self.cursor_manager
-                        .primary_cursor_mut()
-                        .select_range(result.range.clone());
+        let position = self.cursor_manager.primary_cursor().position;
+        self.scroll_to_position(position);
+        self.emit_cursor_event();
 
-                    self.emit_event(EditorEvent::SearchResults {
-                        results: self.search_results.clone(),
-                        current_index: Some(index),
-                    });
+        Ok(())
+    }
 
-                    return Ok(Some(result.clone()));
-                }
-            }
+    /// Select the entire buffer
+    pub fn select_all(&mut self) -> EditorResult<()> {
+        self.cursor_manager
+            .select_all(&self.buffer)
+            .context("Failed to select all")?;
 
-            // Handle wrap around
-            if options.wrap_around && !self.search_results.is_empty() {
-                let index = if options.forward {
-                    0
-                } else {
-                    self.search_results.len() - 1
-                };
-                let result = &self.search_results[index];
+        self.emit_cursor_event();
+        Ok(())
+    }
 
-                self.cursor_manager
-                    .primary_cursor_mut()
-                    .select_range(result.range.clone());
+    /// Replace all cursors, clamping them to the current buffer contents
+    pub fn restore_cursors(&mut self, mut cursors: Vec<Cursor>) {
+        let clamp = |buffer: &TextBuffer, position: Position| {
+            let line = position.line.min(buffer.len_lines().saturating_sub(1));
+            let column = position.column.min(buffer.line_len(line).unwrap_or(0));
+            Position::new(line, column)
+        };
 
-                self.emit_event(EditorEvent::SearchResults {
-                    results: self.search_results.clone(),
-                    current_index: Some(index),
-                });
+        for cursor in &mut cursors {
+            cursor.position = clamp(&self.buffer, cursor.position);
+            cursor.anchor = clamp(&self.buffer, cursor.anchor);
+            cursor.has_selection = cursor.position != cursor.anchor;
+        }
 
-                return Ok(Some(result.clone()));
+        self.cursor_manager.restore_cursors(cursors);
+        self.emit_cursor_event();
+    }
+
+    /// Get the selected text of all cursors, one selection per line
+    ///
+    /// Block selections copy every row, including rows too short to reach the block.
+    pub fn copy(&self) -> EditorResult<String> {
+        let block = self.cursor_manager.block_selection().is_some();
+        let mut selections = Vec::new();
+
+        for cursor in self.cursor_manager.cursors() {
+            if cursor.has_selection || block {
+                selections.push(cursor.selected_text(&self.buffer)?);
             }
         }
 
-        Ok(None)
+        Ok(selections.join(self.buffer.line_ending().as_str()))
     }
 
-    /// Replace text at current selection
-    pub fn replace(&mut self, replacement: &str) -> EditorResult<bool> {
-        if self.cursor_manager.has_selection() {
-            self.insert_text(replacement)?;
-            Ok(true)
-        } else {
-            Ok(false)
+    /// Copy the selected text and delete it
+    #[instrument(skip(self))]
+    pub fn cut(&mut self) -> EditorResult<String> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
         }
-    }
 
-    /// Replace all occurrences
-    pub fn replace_all(&mut self, replacement: &str) -> EditorResult<usize> {
-        if self.search_results.is_empty() {
-            return Ok(0);
+        let text = self.copy()?;
+        if self.cursor_manager.has_selection() {
+            self.delete_selection()?;
         }
 
-        let mut edits = Vec::new();
+        Ok(text)
+    }
 
-        // Create replacement edits in reverse order
-        for result in self.search_results.iter().rev() {
-            edits.push(TextEdit::replace(
-                result.range.clone(),
-                replacement.to_string(),
-            ));
+    /// Paste text at the current cursor positions, replacing selections
+    ///
+    /// When the text has one line per cursor, each cursor gets its own line.
+    #[instrument(skip(self, text))]
+    pub fn paste(&mut self, text: &str) -> EditorResult<()> {
+        let lines: Vec<&str> = text.lines().collect();
+        let cursor_count = self.cursor_manager.cursor_count();
+        if cursor_count > 1 && lines.len() == cursor_count {
+            return self.insert_at_cursors(&lines);
         }
 
-        let count = edits.len();
+        self.insert_text(text)
+    }
 
-        self.buffer
-            .apply_edits(edits.clone())
-            .context("Failed to apply replacement edits")?;
+    /// Replace the buffer text with a rewritten version, such as a formatter's
+    ///
+    /// Only the lines that differ are edited, as one undo step, so cursors on
+    /// untouched lines stay where they are. Returns whether anything changed.
+    pub fn apply_text(&mut self, text: &str) -> EditorResult<bool> {
+        self.apply_texts(&[text])
+    }
 
-        self.cursor_manager
-            .update_after_edits(&edits)
-            .context("Failed to update cursor positions")?;
+    /// Replace the buffer text with several rewrites of parts of it
+    ///
+    /// Each rewrite is compared with the current text on its own and all the
+    /// changed lines are edited as one undo step. A change overlapping one
+    /// taken from an earlier rewrite is left out. Returns whether anything
+    /// changed.
+    #[instrument(skip(self, texts))]
+    pub fn apply_texts(&mut self, texts: &[&str]) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
 
-        // Clear search results since positions have changed
-        self.search_results.clear();
-        self.current_search = None;
+        let version = self.buffer.version();
+        self.apply_text_changes(texts)?;
+        if self.buffer.version() == version {
+            return Ok(false);
+        }
 
         self.update_state_from_buffer();
-
-        self.emit_event(EditorEvent::TextChanged {
-            version: self.buffer.version(),
-            changes: edits,
-        });
-
-        debug!("Replaced {} occurrences", count);
-        Ok(count)
-    }
-
-    /// Get highlighted tokens for the visible area
-    pub fn get_visible_tokens(&mut self) -> EditorResult<Vec<ThemedToken>> {
-        let visible_range = self.get_visible_range();
-        self.syntax_highlighter
-            .get_themed_tokens_for_range(&self.buffer, &visible_range)
-            .map_err(|e| EditorError::SyntaxError(e.to_string()))
+        debug!("Applied rewritten text");
+        Ok(true)
     }
 
-    /// Get all highlighted tokens
-    pub fn get_all_tokens(&mut self) -> EditorResult<Vec<ThemedToken>> {
-        self.syntax_highlighter
-            .get_themed_tokens(&self.buffer)
-            .map_err(|e| EditorError::SyntaxError(e.to_string()))
+    /// Switch between normal, line and block selection
+    pub fn set_selection_mode(&mut self, mode: SelectionMode) {
+        self.cursor_manager.set_selection_mode(mode);
     }
 
-    /// Update view state (called by UI layer)
-    pub fn update_view_state(&mut self, view_state: ViewState) {
-        self.view_state = view_state;
+    /// Select a rectangle of text, with one cursor per row
+    pub fn select_block(&mut self, block: BlockSelection) -> EditorResult<()> {
+        self.cursor_manager
+            .select_block(&self.buffer, block)
+            .context("Failed to select block")?;
+
+        let head = self
+            .cursor_manager
+            .cursors()
+            .iter()
+            .find(|cursor| cursor.position.line == block.head.line)
+            .map_or(block.head, |cursor| cursor.position);
+        self.scroll_to_position(head);
+        self.emit_cursor_event();
+        Ok(())
     }
 
-    /// Scroll to ensure position is visible
-    pub fn scroll_to_position(&mut self, position: Position) {
-        let line = position.line;
-
-        // Adjust vertical scroll if needed
-        if line < self.view_state.scroll_top {
-            self.view_state.scroll_top = line;
-        } else if line >= self.view_state.scroll_top + self.view_state.visible_lines {
-            self.view_state.scroll_top = line.saturating_sub(self.view_state.visible_lines - 1);
+    /// Undo the last operation, restoring the cursors from before it
+    #[instrument(skip(self))]
+    pub fn undo(&mut self) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
         }
 
-        debug!("Scrolled to position {}", position);
+        let start_time = Instant::now();
+        let cursors = self.buffer.undo().context("Failed to undo")?;
+
+        debug!("Undo operation completed");
+        Ok(self.finish_history_step(cursors, start_time))
     }
 
-    /// Get scroll information
-    pub fn get_scroll_info(&self) -> ScrollInfo {
-        let total_lines = self.buffer.len_lines();
-        let visible_start = self.view_state.scroll_top;
-        let visible_end = (visible_start + self.view_state.visible_lines).min(total_lines);
-
-        ScrollInfo {
-            total_lines,
-            visible_range: Range::new(
-                Position::new(visible_start, 0),
-                Position::new(visible_end, 0),
-            ),
-            needs_vertical_scroll: total_lines > self.view_state.visible_lines,
-            needs_horizontal_scroll: false, // We'll implement this later
-            max_scroll_top: total_lines.saturating_sub(self.view_state.visible_lines),
-            max_scroll_left: 0.0, // We'll implement this later
+    /// Redo the last undone operation, restoring the cursors from after it
+    #[instrument(skip(self))]
+    pub fn redo(&mut self) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
         }
-    }
-
-    /// Add an event listener
-    pub fn add_event_listener(&mut self, listener: Arc<dyn EditorEventListener>) {
-        self.event_listeners.push(listener);
-    }
 
-    /// Set read-only mode
-    pub fn set_readonly(&mut self, readonly: bool) {
-        self.state.is_readonly = readonly;
-        debug!("Set read-only mode: {}", readonly);
-    }
-
-    /// Set focus state
-    pub fn set_focus(&mut self, has_focus: bool) {
-        self.state.has_focus = has_focus;
-        debug!("Set focus: {}", has_focus);
-    }
+        let start_time = Instant::now();
+        let cursors = self.buffer.redo().context("Failed to redo")?;
 
-    /// Get current line content
-    pub fn current_line(&self) -> EditorResult<String> {
-        let line_num = self.cursor_manager.primary_cursor().position.line;
-        self.buffer
-            .line_text(line_num)
-            .map_err(|e| EditorError::BufferError(e))
+        debug!("Redo operation completed");
+        Ok(self.finish_history_step(cursors, start_time))
     }
 
-    /// Go to specific line and column
-    pub fn goto_position(&mut self, position: Position) -> EditorResult<()> {
-        // Validate position
-        if position.line >= self.buffer.len_lines() {
-            return Err(EditorError::InvalidPosition { position });
+    /// Jump to any state in the undo tree
+    #[instrument(skip(self))]
+    pub fn jump_to_undo_node(&mut self, node: UndoNodeId) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
         }
 
-        let line_len = self
+        let start_time = Instant::now();
+        let cursors = self
             .buffer
-            .line_len(position.line)
-            .map_err(|e| EditorError::BufferError(e))?;
+            .jump_to_undo_node(node)
+            .context("Failed to jump to undo node")?;
 
-        if position.column > line_len {
-            return Err(EditorError::InvalidPosition { position });
-        }
+        Ok(self.finish_history_step(cursors, start_time))
+    }
 
-        // Move cursor
-        self.cursor_manager.clear_secondary_cursors();
-        self.cursor_manager.primary_cursor_mut().move_to(position);
+    /// Go back to the state the buffer had the given time earlier
+    #[instrument(skip(self))]
+    pub fn undo_earlier(&mut self, duration: Duration) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
 
-        // Ensure position is visible
-        self.scroll_to_position(position);
+        let start_time = Instant::now();
+        let cursors = self
+            .buffer
+            .undo_earlier(duration)
+            .context("Failed to travel back in undo history")?;
 
-        self.emit_cursor_event();
-        debug!("Moved to position {}", position);
-        Ok(())
+        Ok(self.finish_history_step(cursors, start_time))
     }
 
-    /// Go to specific line number (1-indexed for user interface)
-    pub fn goto_line(&mut self, line_number: usize) -> EditorResult<()> {
-        if line_number == 0 {
-            return Err(EditorError::InvalidPosition {
-                position: Position::new(0, 0),
-            });
+    /// Go forward to the state the buffer had the given time later
+    #[instrument(skip(self))]
+    pub fn undo_later(&mut self, duration: Duration) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
         }
 
-        let position = Position::new(line_number - 1, 0);
-        self.goto_position(position)
+        let start_time = Instant::now();
+        let cursors = self
+            .buffer
+            .undo_later(duration)
+            .context("Failed to travel forward in undo history")?;
+
+        Ok(self.finish_history_step(cursors, start_time))
     }
 
-    /// Indent selected lines or current line
-    pub fn indent_lines(&mut self) -> EditorResult<()> {
+    /// Replace the whole text as a single undo step, keeping the cursor near its old position
+    #[instrument(skip(self, text))]
+    pub fn replace_contents(&mut self, text: &str) -> EditorResult<()> {
+        let start_time = Instant::now();
+
         if self.state.is_readonly {
             return Err(EditorError::SearchError("Editor is read-only".to_string()));
         }
 
-        let indent_text = utils::create_indentation(1, self.config.use_tabs, self.config.tab_size);
-        let mut edits = Vec::new();
-
-        if self.cursor_manager.has_selection() {
-            // Indent all lines that have selections
-            let ranges = self.cursor_manager.selected_ranges();
-            for range in &ranges {
-                for line in range.start.line..=range.end.line {
-                    edits.push(TextEdit::insert(
-                        Position::new(line, 0),
-                        indent_text.clone(),
-                    ));
-                }
-            }
-        } else {
-            // Indent current lines for all cursors
-            for cursor in self.cursor_manager.cursors() {
-                edits.push(TextEdit::insert(
-                    Position::new(cursor.position.line, 0),
-                    indent_text.clone(),
-                ));
-            }
+        let end = self
+            .buffer
+            .char_index_to_position(self.buffer.len_chars())?;
+        let edits = vec![TextEdit::replace(
+            Range::new(Position::zero(), end),
+            text.to_string(),
+        )];
+        let position = self.cursor_manager.primary_cursor().position;
+
+        self.begin_undo_group(UndoGroupKind::Discrete);
+        let result = self.buffer.apply_edits(edits.clone());
+        if result.is_ok() {
+            let line = position.line.min(self.buffer.len_lines().saturating_sub(1));
+            let column = position.column.min(self.buffer.line_len(line).unwrap_or(0));
+
+            self.cursor_manager.clear_secondary_cursors();
+            self.cursor_manager
+                .primary_cursor_mut()
+                .move_to(Position::new(line, column));
         }
+        self.end_undo_group();
+        result.context("Failed to replace contents")?;
 
-        self.buffer
-            .apply_edits(edits.clone())
-            .context("Failed to apply indent edits")?;
-
-        self.cursor_manager
-            .update_after_edits(&edits)
-            .context("Failed to update cursor positions")?;
-
+        self.search_results.clear();
         self.update_state_from_buffer();
+        self.record_operation_time(start_time.elapsed());
 
         self.emit_event(EditorEvent::TextChanged {
             version: self.buffer.version(),
             changes: edits,
         });
 
-        debug!("Indented lines");
+        self.emit_cursor_event();
+
+        debug!("Replaced editor contents");
         Ok(())
     }
 
-    /// Unindent selected lines or current line
-    pub fn unindent_lines(&mut self) -> EditorResult<()> {
-        if self.state.is_readonly {
-            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+    /// Reconcile the buffer with new contents of its file on disk
+    ///
+    /// A buffer without unsaved changes takes the disk contents. Otherwise the
+    /// disk contents are merged with the buffer, using the last loaded or saved
+    /// contents as the common base. Either way the change is one undo step and
+    /// cursors stay on the text they were on.
+    #[instrument(skip(self, disk_text))]
+    pub fn reconcile_with_disk(&mut self, disk_text: &str) -> EditorResult<ExternalChange> {
+        if self.buffer.is_large_file() {
+            return self.reconcile_large_file(Rope::from_str(disk_text));
         }
 
-        let mut edits = Vec::new();
-        let tab_size = self.config.tab_size;
+        // The buffer is merged as it is: save-time cleanups such as trimming
+        // trailing whitespace only happen when it is written
+        let base = self.buffer.saved_text().unwrap_or_default().to_string();
+        if disk_text == base {
+            // A file restored after being deleted matches the buffer again
+            if self.buffer.is_dirty() && self.buffer.text() == disk_text {
+                self.buffer.set_dirty(false);
+                self.update_state_from_buffer();
+            }
+            return Ok(ExternalChange::Unchanged);
+        }
 
-        let lines_to_unindent: Vec<usize> = if self.cursor_manager.has_selection() {
-            let ranges = self.cursor_manager.selected_ranges();
-            ranges
-                .iter()
-                .flat_map(|range| range.start.line..=range.end.line)
-                .collect()
+        let start_time = Instant::now();
+
+        let change = if self.buffer.is_dirty() {
+            let merge = merge3(&base, &self.buffer.text(), disk_text);
+            self.apply_text_changes(&[&merge.text])?;
+            self.buffer.set_dirty(merge.text != disk_text);
+
+            ExternalChange::Merged {
+                conflicts: merge.conflicts,
+            }
         } else {
-            self.cursor_manager
-                .cursors()
-                .iter()
-                .map(|cursor| cursor.position.line)
-                .collect()
+            self.apply_text_changes(&[disk_text])?;
+            self.buffer.set_dirty(false);
+
+            ExternalChange::Reloaded
         };
 
-        for line_num in lines_to_unindent.iter().rev() {
-            let line_text = self
-                .buffer
-                .line_text(*line_num)
-                .map_err(|e| EditorError::BufferError(e))?;
+        self.buffer.set_saved_text(Some(disk_text.to_string()));
+        self.update_state_from_buffer();
+        self.record_operation_time(start_time.elapsed());
 
-            let mut chars_to_remove = 0;
-            let chars: Vec<char> = line_text.chars().collect();
+        info!("Reconciled buffer with changes on disk: {:?}", change);
+        Ok(change)
+    }
 
-            if !chars.is_empty() && chars[0] == '\t' {
-                chars_to_remove = 1;
-            } else {
-                let spaces = chars
-                    .iter()
-                    .take(tab_size)
-                    .take_while(|&&c| c == ' ')
-                    .count();
-                chars_to_remove = spaces.min(tab_size);
-            }
+    /// Reconcile a large file with its contents on disk, read into a rope
+    ///
+    /// Large files have no saved base to merge against or diff cheaply, so a
+    /// clean buffer takes the new contents as if freshly opened, keeping its
+    /// cursors where they still fit, and a dirty one is left alone.
+    #[instrument(skip(self, disk))]
+    pub fn reconcile_large_file(&mut self, disk: Rope) -> EditorResult<ExternalChange> {
+        let change = if self.buffer.is_dirty() {
+            ExternalChange::Diverged
+        } else if disk == *self.buffer.rope() {
+            ExternalChange::Unchanged
+        } else {
+            self.reload_buffer(disk)?;
+            ExternalChange::Reloaded
+        };
 
-            if chars_to_remove > 0 {
-                let range = Range::new(
-                    Position::new(*line_num, 0),
-                    Position::new(*line_num, chars_to_remove),
-                );
-                edits.push(TextEdit::delete(range));
-            }
-        }
+        info!("Reconciled large file with changes on disk: {:?}", change);
+        Ok(change)
+    }
 
-        if !edits.is_empty() {
-            self.buffer
-                .apply_edits(edits.clone())
-                .context("Failed to apply unindent edits")?;
+    /// Swap in a buffer with new contents of the file, without an undo step
+    fn reload_buffer(&mut self, contents: Rope) -> EditorResult<()> {
+        self.buffer = self.buffer.reloaded(contents);
+        self.track_view_changes();
 
-            self.cursor_manager
-                .update_after_edits(&edits)
-                .context("Failed to update cursor positions")?;
+        // Folds and snippet fields were anchored in the old buffer
+        self.folds.clear();
+        self.snippet_session = None;
+        self.state.snippet = None;
+        self.search_results.clear();
+        self.cursor_manager.clamp_to_buffer(&self.buffer);
 
-            self.update_state_from_buffer();
+        for view in self.split_views.lock().iter_mut() {
+            view.cursor_manager.clamp_to_buffer(&self.buffer);
+            view.folds.clear();
+            view.search_results.clear();
+            view.snippet_session = None;
+        }
 
-            self.emit_event(EditorEvent::TextChanged {
-                version: self.buffer.version(),
-                changes: edits,
+        self.sync_folds()?;
+        self.update_state_from_buffer();
+
+        if let Some(path) = self.buffer.file_path().cloned() {
+            self.emit_event(EditorEvent::FileLoaded {
+                path,
+                language: self.state.language.clone(),
             });
         }
-
-        debug!("Unindented lines");
+        self.emit_cursor_event();
         Ok(())
     }
 
-    /// Comment/uncomment selected lines
-    pub fn toggle_line_comment(&mut self) -> EditorResult<()> {
-        if self.state.is_readonly {
-            return Err(EditorError::SearchError("Editor is read-only".to_string()));
-        }
+    /// Handle the file of this editor being removed from disk
+    ///
+    /// The buffer keeps its contents and is marked as having unsaved changes.
+    pub fn handle_file_deleted(&mut self) -> ExternalChange {
+        self.buffer.set_dirty(true);
+        self.update_state_from_buffer();
 
-        // Get comment prefix for current language
-        let comment_prefix = self.get_comment_prefix();
-        if comment_prefix.is_empty() {
-            return Ok(()); // No comment syntax for this language
-        }
+        info!("File of editor {} was deleted on disk", self.id);
+        ExternalChange::Deleted
+    }
 
-        let lines_to_toggle: Vec<usize> = if self.cursor_manager.has_selection() {
-            let ranges = self.cursor_manager.selected_ranges();
-            ranges
-                .iter()
-                .flat_map(|range| range.start.line..=range.end.line)
-                .collect()
-        } else {
-            self.cursor_manager
-                .cursors()
-                .iter()
-                .map(|cursor| cursor.position.line)
-                .collect()
-        };
+    /// Search for text in the buffer
+    #[instrument(skip(self))]
+    pub fn search(&mut self, options: SearchOptions) -> EditorResult<Vec<SearchResult>> {
+        let start_time = Instant::now();
 
-        // Check if all lines are already commented
-        let all_commented = lines_to_toggle.iter().all(|&line_num| {
-            if let Ok(line_text) = self.buffer.line_text(line_num) {
-                line_text.trim_start().starts_with(&comment_prefix)
-            } else {
-                false
-            }
+        self.current_search = Some(options.clone());
+        let results = self.search_buffer(&options)?;
+
+        self.search_results = results.clone();
+        self.record_operation_time(start_time.elapsed());
+
+        self.emit_event(EditorEvent::SearchResults {
+            results: results.clone(),
+            current_index: None,
         });
 
-        let mut edits = Vec::new();
+        debug!("Search completed: found {} matches", results.len());
+        Ok(results)
+    }
 
-        for line_num in lines_to_toggle.iter().rev() {
-            let line_text = self
-                .buffer
-                .line_text(*line_num)
-                .map_err(|e| EditorError::BufferError(e))?;
+    /// Find next search result
+    pub fn find_next(&mut self) -> EditorResult<Option<SearchResult>> {
+        if let Some(options) = &self.current_search.clone() {
+            let current_pos = self.cursor_manager.primary_cursor().position;
 
-            if all_commented {
-                // Uncomment: remove comment prefix
-                if let Some(pos) = line_text.find(&comment_prefix) {
-                    let range = Range::new(
-                        Position::new(*line_num, pos),
-                        Position::new(*line_num, pos + comment_prefix.len()),
-                    );
-                    edits.push(TextEdit::delete(range));
+            for (index, result) in self.search_results.iter().enumerate() {
+                if (options.forward && result.range.start > current_pos)
+                    || (!options.forward && result.range.start < current_pos)
+                {
+                    // Move cursor to this result
+                    self.cursor_manager
+                        .primary_cursor_mut()
+                        .select_range(result.range.clone());
+
+                    self.emit_event(EditorEvent::SearchResults {
+                        results: self.search_results.clone(),
+                        current_index: Some(index),
+                    });
+
+                    return Ok(Some(result.clone()));
                 }
-            } else {
-                // Comment: add comment prefix at start of line (after indentation)
-                let indent_len = utils::get_line_indentation(&line_text);
-                edits.push(TextEdit::insert(
-                    Position::new(*line_num, indent_len),
-                    format!("{} ", comment_prefix),
-                ));
             }
-        }
 
-        if !edits.is_empty() {
-            self.buffer
-                .apply_edits(edits.clone())
-                .context("Failed to apply comment edits")?;
+            // Handle wrap around
+            if options.wrap_around && !self.search_results.is_empty() {
+                let index = if options.forward {
+                    0
+                } else {
+                    self.search_results.len() - 1
+                };
+                let result = &self.search_results[index];
 
-            self.cursor_manager
-                .update_after_edits(&edits)
-                .context("Failed to update cursor positions")?;
+                self.cursor_manager
+                    .primary_cursor_mut()
+                    .select_range(result.range.clone());
 
-            self.update_state_from_buffer();
+                self.emit_event(EditorEvent::SearchResults {
+                    results: self.search_results.clone(),
+                    current_index: Some(index),
+                });
 
-            self.emit_event(EditorEvent::TextChanged {
-                version: self.buffer.version(),
-                changes: edits,
-            });
+                return Ok(Some(result.clone()));
+            }
         }
 
-        debug!("Toggled line comments");
-        Ok(())
+        Ok(None)
     }
 
-    // Helper methods
-
-    /// Update editor state from buffer
-    fn update_state_from_buffer(&mut self) {
-        self.state.line_count = self.buffer.len_lines();
-        self.state.char_count = self.buffer.len_chars();
-        self.state.is_dirty = self.buffer.is_dirty();
-        self.state.line_ending = match self.buffer.line_ending() {
-            crate::core::text_buffer::LineEnding::Unix => "LF".to_string(),
-            crate::core::text_buffer::LineEnding::Windows => "CRLF".to_string(),
-            crate::core::text_buffer::LineEnding::Mac => "CR".to_string(),
-        };
+    /// Replace text at current selection
+    pub fn replace(&mut self, replacement: &str) -> EditorResult<bool> {
+        if self.cursor_manager.has_selection() {
+            self.insert_text(replacement)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
     }
 
-    /// Emit cursor position event
-    fn emit_cursor_event(&self) {
-        let positions = self.cursor_manager.cursor_positions();
-        let selections = self.cursor_manager.selected_ranges();
+    /// Replace all occurrences
+    pub fn replace_all(&mut self, replacement: &str) -> EditorResult<usize> {
+        if self.search_results.is_empty() {
+            return Ok(0);
+        }
 
-        self.emit_event(EditorEvent::CursorMoved {
-            positions,
-            selections: selections.clone(),
-        });
+        let mut edits = Vec::new();
 
-        // Also emit selection changed event
-        let has_selection = self.cursor_manager.has_selection();
-        let selected_text = if has_selection {
-            self.cursor_manager
-                .cursors()
-                .iter()
-                .filter_map(|cursor| cursor.selected_text(&self.buffer).ok())
-                .collect::<Vec<_>>()
-                .join("\n")
-        } else {
-            String::new()
-        };
+        // Create replacement edits in reverse order
+        for result in self.search_results.iter().rev() {
+            edits.push(TextEdit::replace(
+                result.range.clone(),
+                replacement.to_string(),
+            ));
+        }
 
-        let char_count = selected_text.len();
+        let count = edits.len();
 
-        self.emit_event(EditorEvent::SelectionChanged {
-            has_selection,
-            selected_text,
-            char_count,
+        self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply replacement edits")?;
+
+        // Clear search results since positions have changed
+        self.search_results.clear();
+        self.current_search = None;
+
+        self.update_state_from_buffer();
+
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
         });
+
+        debug!("Replaced {} occurrences", count);
+        Ok(count)
     }
 
-    /// Emit an event to all listeners
-    fn emit_event(&self, event: EditorEvent) {
-        for listener in &self.event_listeners {
-            match &event {
-                EditorEvent::TextChanged { .. } => {
-                    // We'll need to convert this to BufferChangeEvent format
-                    // For now, we'll skip this to avoid complex conversions
-                }
-                EditorEvent::CursorMoved { positions, .. } => {
-                    listener.on_cursor_moved(positions);
-                }
-                EditorEvent::SelectionChanged { has_selection, .. } => {
-                    listener.on_selection_changed(*has_selection);
-                }
-                EditorEvent::FileSaved { path } => {
-                    listener.on_file_saved(path);
-                }
-                _ => {} // Other events don't have corresponding trait methods
-            }
-        }
+    /// Add a cursor selecting the next match of the newest selection
+    ///
+    /// With no selection, the word under the newest cursor is selected instead.
+    /// Only the case and whole-word flags of `options` are used, the query is
+    /// the selected text. Returns whether the cursors changed.
+    #[instrument(skip(self))]
+    pub fn add_next_occurrence(&mut self, options: &SearchOptions) -> EditorResult<bool> {
+        self.select_next_occurrence(options, false)
     }
 
-    /// Record operation time for metrics
-    fn record_operation_time(&mut self, duration: Duration) {
-        self.metrics.last_operation_time = duration;
-        self.metrics.operation_count += 1;
+    /// Move the newest selection on to the next match, skipping the current one
+    #[instrument(skip(self))]
+    pub fn skip_occurrence(&mut self, options: &SearchOptions) -> EditorResult<bool> {
+        self.select_next_occurrence(options, true)
+    }
 
-        self.operation_times.push_back(duration);
-        if self.operation_times.len() > 100 {
-            self.operation_times.pop_front();
+    /// Remove the most recently added cursor
+    pub fn undo_last_cursor_add(&mut self) -> bool {
+        let newest = self.newest_cursor().id;
+        if !self.cursor_manager.remove_cursor(newest) {
+            return false;
         }
 
-        // Calculate average
-        if !self.operation_times.is_empty() {
-            let total: Duration = self.operation_times.iter().sum();
-            self.metrics.average_operation_time = total / self.operation_times.len() as u32;
-        }
+        let position = self.newest_cursor().position;
+        self.scroll_to_position(position);
+        self.emit_cursor_event();
+        true
     }
 
-    /// Get visible range based on current view state
-    fn get_visible_range(&self) -> Range {
-        let start_line = self.view_state.scroll_top;
-        let end_line = (start_line + self.view_state.visible_lines).min(self.buffer.len_lines());
+    /// Put a cursor on every match of the newest selection
+    ///
+    /// Returns the number of cursors placed.
+    #[instrument(skip(self))]
+    pub fn select_all_occurrences(&mut self, options: &SearchOptions) -> EditorResult<usize> {
+        let newest = self.newest_cursor().clone();
+        if !newest.has_selection && !self.select_word_at_newest_cursor()? {
+            return Ok(0);
+        }
 
-        Range::new(Position::new(start_line, 0), Position::new(end_line, 0))
+        let selection = self.newest_cursor().selection_range();
+        let mut ranges: Vec<Range> = Vec::new();
+        for range in self.find_occurrences(&selection, options)? {
+            if ranges.last().is_none_or(|last| last.end <= range.start) {
+                ranges.push(range);
+            }
+        }
+
+        let cursors = ranges
+            .iter()
+            .enumerate()
+            .map(|(id, range)| Cursor::with_selection(id, range.start, range.end))
+            .collect::<Vec<_>>();
+        let count = cursors.len();
+
+        self.cursor_manager.restore_cursors(cursors);
+        self.emit_cursor_event();
+
+        debug!("Selected {} occurrences", count);
+        Ok(count)
     }
 
-    /// Move position one character to the right
-    fn move_position_right(&self, position: Position) -> EditorResult<Position> {
-        let line_len = self
-            .buffer
-            .line_len(position.line)
-            .map_err(|e| EditorError::BufferError(e))?;
+    /// Expand every selection to the enclosing syntax node
+    ///
+    /// Buffers without a syntax tree, such as plain text or large files, expand
+    /// to word boundaries instead. Returns whether the selections changed.
+    #[instrument(skip(self))]
+    pub fn expand_selection(&mut self) -> EditorResult<bool> {
+        let expanded = match self.syntax_tree()? {
+            Some(tree) => self
+                .cursor_manager
+                .expand_selection_to_syntax(&self.buffer, &tree)?,
+            None => {
+                let before = self.cursor_manager.cursors().to_vec();
+                self.cursor_manager
+                    .expand_selection_to_words(&self.buffer)?;
+                self.cursor_manager.cursors() != before.as_slice()
+            }
+        };
 
-        if position.column < line_len {
-            Ok(Position::new(position.line, position.column + 1))
-        } else if position.line < self.buffer.len_lines() - 1 {
-            Ok(Position::new(position.line + 1, 0))
-        } else {
-            Ok(position) // At end of document
+        if expanded {
+            self.emit_cursor_event();
         }
+        Ok(expanded)
     }
 
-    /// Move position one character to the left
-    fn move_position_left(&self, position: Position) -> EditorResult<Position> {
-        if position.column > 0 {
-            Ok(Position::new(position.line, position.column - 1))
-        } else if position.line > 0 {
-            let prev_line = position.line - 1;
-            let line_len = self
-                .buffer
-                .line_len(prev_line)
-                .map_err(|e| EditorError::BufferError(e))?;
-            Ok(Position::new(prev_line, line_len))
-        } else {
-            Ok(position) // At start of document
+    /// Shrink every selection back towards the cursor along the syntax tree
+    ///
+    /// Returns whether the selections changed.
+    #[instrument(skip(self))]
+    pub fn shrink_selection(&mut self) -> EditorResult<bool> {
+        let Some(tree) = self.syntax_tree()? else {
+            return Ok(false);
+        };
+
+        let shrunk = self
+            .cursor_manager
+            .shrink_selection_to_syntax(&self.buffer, &tree)?;
+        if shrunk {
+            self.emit_cursor_event();
         }
+        Ok(shrunk)
     }
 
-    /// Calculate auto-indent for new line
-    fn calculate_auto_indent(&self) -> EditorResult<Option<String>> {
-        let cursor_pos = self.cursor_manager.primary_cursor().position;
-        if cursor_pos.line == 0 {
-            return Ok(None);
+    /// Ranges that can be folded, sorted by start line
+    ///
+    /// Empty when folding is turned off. Without a syntax tree only region
+    /// markers fold.
+    pub fn folding_ranges(&mut self) -> EditorResult<Vec<FoldingRange>> {
+        if !self.config.folding {
+            return Ok(Vec::new());
         }
 
-        let current_line = self
-            .buffer
-            .line_text(cursor_pos.line)
-            .map_err(|e| EditorError::BufferError(e))?;
-
-        let indent_level = utils::get_line_indentation(&current_line);
-        let indent =
-            utils::create_indentation(indent_level, self.config.use_tabs, self.config.tab_size);
-
-        Ok(Some(indent))
+        let tree = self.syntax_tree()?;
+        Ok(folding::folding_ranges(tree.as_ref(), &self.buffer.text()))
     }
 
-    /// Get auto-closing character for the given opening character
-    fn get_auto_close_char(&self, ch: char) -> Option<char> {
-        match ch {
-            '(' => Some(')'),
-            '[' => Some(']'),
-            '{' => Some('}'),
-            '"' => Some('"'),
-            '\'' => Some('\''),
-            _ => None,
-        }
+    /// Folded ranges where they are now, sorted by start line
+    pub fn folded_ranges(&self) -> Vec<FoldingRange> {
+        let mut ranges: Vec<FoldingRange> = self
+            .folds
+            .iter()
+            .filter_map(|fold| self.fold_range(fold))
+            .collect();
+        ranges.sort_by_key(|range| (range.start_line, range.end_line));
+        ranges
     }
 
-    /// Get comment prefix for current language
-    fn get_comment_prefix(&self) -> String {
-        // This could be expanded to use language-specific comment syntax
-        match self.state.language.as_deref() {
-            Some("rust") => "//".to_string(),
-            Some("toml") => "#".to_string(),
-            Some("json") => "//".to_string(), // JSON doesn't have comments, but we'll use this
-            _ => "//".to_string(),            // Default
+    /// Fold the innermost unfolded range containing `line`
+    ///
+    /// Returns whether anything was folded.
+    #[instrument(skip(self))]
+    pub fn fold(&mut self, line: usize) -> EditorResult<bool> {
+        let folded = self.folded_ranges();
+        let range = self
+            .folding_ranges()?
+            .into_iter()
+            .filter(|range| range.contains_line(line) && !is_folded(&folded, range))
+            .min_by_key(|range| range.end_line - range.start_line);
+
+        match range {
+            Some(range) => Ok(self.add_folds(&[range])? > 0),
+            None => Ok(false),
         }
     }
 
-    /// Search for literal text
-    fn search_literal(
-        &self,
-        text: &str,
-        options: &SearchOptions,
-    ) -> EditorResult<Vec<SearchResult>> {
-        let query = if options.case_sensitive {
-            options.query.clone()
+    /// Unfold the innermost fold containing `line`
+    ///
+    /// With `recursive`, every fold containing the line is unfolded along with
+    /// all folds inside them. Returns the number of folds removed.
+    #[instrument(skip(self))]
+    pub fn unfold(&mut self, line: usize, recursive: bool) -> EditorResult<usize> {
+        let folded = self.folded_ranges();
+        let containing: Vec<FoldingRange> = folded
+            .iter()
+            .filter(|range| range.contains_line(line))
+            .copied()
+            .collect();
+
+        let targets: Vec<FoldingRange> = if recursive {
+            folded
+                .into_iter()
+                .filter(|range| containing.iter().any(|outer| outer.contains(range)))
+                .collect()
         } else {
-            options.query.to_lowercase()
+            containing
+                .into_iter()
+                .min_by_key(|range| range.end_line - range.start_line)
+                .into_iter()
+                .collect()
         };
 
-        let search_text = if options.case_sensitive {
-            text.to_string()
-        } else {
-            text.to_lowercase()
-        };
+        self.remove_folds(|range| is_folded(&targets, range))
+    }
+
+    /// Fold every foldable range, returning how many were folded
+    #[instrument(skip(self))]
+    pub fn fold_all(&mut self) -> EditorResult<usize> {
+        let ranges = self.folding_ranges()?;
+        self.add_folds(&ranges)
+    }
+
+    /// Fold every range nested `level` deep, where top-level ranges are level 1
+    ///
+    /// Returns how many ranges were folded.
+    #[instrument(skip(self))]
+    pub fn fold_level(&mut self, level: usize) -> EditorResult<usize> {
+        let ranges = self.folding_ranges()?;
+        let levels = folding::fold_levels(&ranges);
+        let ranges: Vec<FoldingRange> = ranges
+            .into_iter()
+            .zip(levels)
+            .filter(|&(_, range_level)| range_level == level)
+            .map(|(range, _)| range)
+            .collect();
+        self.add_folds(&ranges)
+    }
+
+    /// Unfold everything, returning how many folds were removed
+    #[instrument(skip(self))]
+    pub fn unfold_all(&mut self) -> EditorResult<usize> {
+        self.remove_folds(|_| true)
+    }
+
+    /// Insert a snippet at every cursor and select its first tabstop
+    ///
+    /// Selections are replaced and become `$TM_SELECTED_TEXT`. Lines after the
+    /// first line of the snippet are indented like the line it is inserted on,
+    /// and every copy of a tabstop gets a cursor.
+    #[instrument(skip(self, body))]
+    pub fn insert_snippet(&mut self, body: &str) -> EditorResult<()> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+        self.exit_snippet();
+
+        let snippet = Snippet::parse(body);
+        let tab = utils::create_indentation(1, self.config.use_tabs, self.config.tab_size);
+        let variables = self.snippet_variables();
+
+        let mut cursors = self.cursor_manager.cursors().to_vec();
+        cursors.sort_by_key(|cursor| cursor.selection_range().start);
+        cursors.dedup_by_key(|cursor| cursor.selection_range());
+
+        let mut expansions = Vec::with_capacity(cursors.len());
+        for cursor in &cursors {
+            let range = cursor.selection_range();
+            let line = self.buffer.line_text(range.start.line)?;
+            let indent: String = line
+                .chars()
+                .take_while(|&ch| ch == ' ' || ch == '\t')
+                .collect();
+            let word = match self.word_range_at(cursor.position)? {
+                Some(word) => self.buffer.text_in_range(&word)?,
+                None => String::new(),
+            };
+
+            let variables = variables
+                .clone()
+                .with("TM_SELECTED_TEXT", cursor.selected_text(&self.buffer)?)
+                .with("TM_CURRENT_LINE", line.trim_end_matches(['\r', '\n']))
+                .with("TM_CURRENT_WORD", word)
+                .with("TM_LINE_INDEX", range.start.line.to_string())
+                .with("TM_LINE_NUMBER", (range.start.line + 1).to_string());
+            let expanded = snippet.expand(&variables).reindent(&indent, &tab);
+            expansions.push((range, expanded));
+        }
+
+        // Char index each snippet starts at once all of them are inserted
+        let mut shift = 0isize;
+        let mut edits = Vec::with_capacity(expansions.len());
+        let mut insertions = Vec::with_capacity(expansions.len());
+        for (range, expanded) in expansions {
+            let start = self.buffer.position_to_char_index(range.start)?;
+            let end = self.buffer.position_to_char_index(range.end)?;
+            let len = expanded.text.chars().count();
+
+            insertions.push((start.saturating_add_signed(shift), expanded.clone()));
+            shift += len as isize - (end - start) as isize;
+            edits.push(TextEdit::replace(range, expanded.text));
+        }
+
+        self.apply_edits(edits)?;
+
+        let session = SnippetSession::start(&mut self.buffer, insertions)?;
+        self.snippet_session = Some(session);
+        self.select_tabstop(0)?;
+
+        debug!("Inserted snippet at {} cursors", cursors.len());
+        Ok(())
+    }
+
+    /// Move to the next tabstop of the active snippet
+    ///
+    /// Reaching the final tabstop ends the snippet. Returns whether a snippet
+    /// was active.
+    pub fn next_tabstop(&mut self) -> EditorResult<bool> {
+        let Some(session) = &self.snippet_session else {
+            return Ok(false);
+        };
+
+        self.select_tabstop(session.current() + 1)?;
+        Ok(true)
+    }
+
+    /// Move back to the previous tabstop of the active snippet
+    ///
+    /// Returns whether the selection moved.
+    pub fn previous_tabstop(&mut self) -> EditorResult<bool> {
+        let Some(current) = self.snippet_session.as_ref().map(SnippetSession::current) else {
+            return Ok(false);
+        };
+        if current == 0 {
+            return Ok(false);
+        }
+
+        self.select_tabstop(current - 1)?;
+        Ok(true)
+    }
+
+    /// Stop filling in the active snippet, leaving the cursors where they are
+    ///
+    /// Returns whether a snippet was active.
+    pub fn exit_snippet(&mut self) -> bool {
+        let Some(session) = self.snippet_session.take() else {
+            return false;
+        };
+
+        session.end(&mut self.buffer);
+        self.state.snippet = None;
+        true
+    }
+
+    /// Active tabstop of the snippet being filled in
+    pub fn snippet_status(&self) -> Option<SnippetStatus> {
+        self.snippet_session
+            .as_ref()
+            .and_then(SnippetSession::status)
+    }
+
+    /// Apply a completion, replacing `range` around the primary cursor
+    ///
+    /// Other cursors replace as many characters before and after themselves.
+    /// Snippet completions expand as [`Editor::insert_snippet`] does. The
+    /// `additional_edits` elsewhere in the document, such as an import the
+    /// completion needs, are made in the same undo step.
+    #[instrument(skip(self, text, additional_edits))]
+    pub fn apply_completion(
+        &mut self,
+        range: Option<Range>,
+        text: &str,
+        is_snippet: bool,
+        additional_edits: &[RangeReplacement],
+    ) -> EditorResult<()> {
+        // Measured from the primary cursor, which the other edits move along
+        let around = match range {
+            Some(range) => {
+                let primary = self.cursor_manager.primary_cursor().position;
+                let index = self.buffer.position_to_char_index(primary)?;
+                let before = index.saturating_sub(self.buffer.position_to_char_index(range.start)?);
+                let after = self
+                    .buffer
+                    .position_to_char_index(range.end)?
+                    .saturating_sub(index);
+                Some((before, after))
+            }
+            None => None,
+        };
+
+        self.begin_undo_group(UndoGroupKind::Discrete);
+        let result = self.complete_at_cursors(around, text, is_snippet, additional_edits);
+        self.end_undo_group();
+        result
+    }
+
+    fn complete_at_cursors(
+        &mut self,
+        around: Option<(usize, usize)>,
+        text: &str,
+        is_snippet: bool,
+        additional_edits: &[RangeReplacement],
+    ) -> EditorResult<()> {
+        let edits = additional_edits
+            .iter()
+            .map(|edit| TextEdit::replace(edit.range.clone(), edit.text.clone()))
+            .collect();
+        self.apply_edits(edits)?;
+
+        if let Some((before, after)) = around {
+            let len = self.buffer.len_chars();
+            let cursors = self
+                .cursor_manager
+                .cursors()
+                .iter()
+                .map(|cursor| {
+                    let index = self.buffer.position_to_char_index(cursor.position)?;
+                    let start = self
+                        .buffer
+                        .char_index_to_position(index.saturating_sub(before))?;
+                    let end = self
+                        .buffer
+                        .char_index_to_position((index + after).min(len))?;
+                    Ok(Cursor::with_selection(cursor.id, start, end))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            self.restore_cursors(cursors);
+        }
+
+        if is_snippet {
+            self.insert_snippet(text)
+        } else {
+            self.insert_text(text)
+        }
+    }
+
+    /// Run an editor command, recording it if a macro is being recorded
+    #[instrument(skip(self))]
+    pub fn execute(&mut self, command: EditorCommand) -> EditorResult<CommandOutput> {
+        let output = match &command {
+            EditorCommand::InsertText { text } => {
+                self.insert_text(text)?;
+                CommandOutput::None
+            }
+            EditorCommand::TypeChar { ch } => {
+                self.type_char(*ch)?;
+                CommandOutput::None
+            }
+            EditorCommand::DeleteSelection => {
+                self.delete_selection()?;
+                CommandOutput::None
+            }
+            EditorCommand::Backspace => {
+                self.backspace()?;
+                CommandOutput::None
+            }
+            EditorCommand::MoveCursors {
+                direction,
+                unit,
+                extend_selection,
+            } => {
+                self.move_cursors(*direction, *unit, *extend_selection)?;
+                CommandOutput::None
+            }
+            EditorCommand::GotoPosition { position } => {
+                self.goto_position(*position)?;
+                CommandOutput::None
+            }
+            EditorCommand::GotoLine { line_number } => {
+                self.goto_line(*line_number)?;
+                CommandOutput::None
+            }
+            EditorCommand::SelectAll => {
+                self.select_all()?;
+                CommandOutput::None
+            }
+            EditorCommand::SetSelectionMode { mode } => {
+                self.set_selection_mode(*mode);
+                CommandOutput::None
+            }
+            EditorCommand::SelectBlock { block } => {
+                self.select_block(*block)?;
+                CommandOutput::None
+            }
+            EditorCommand::ExpandSelection => CommandOutput::Changed(self.expand_selection()?),
+            EditorCommand::ShrinkSelection => CommandOutput::Changed(self.shrink_selection()?),
+            EditorCommand::Cut => CommandOutput::Text(self.cut()?),
+            EditorCommand::Paste { text } => {
+                self.paste(text)?;
+                CommandOutput::None
+            }
+            EditorCommand::Undo => CommandOutput::Changed(self.undo()?),
+            EditorCommand::Redo => CommandOutput::Changed(self.redo()?),
+            EditorCommand::IndentLines => {
+                self.indent_lines()?;
+                CommandOutput::None
+            }
+            EditorCommand::UnindentLines => {
+                self.unindent_lines()?;
+                CommandOutput::None
+            }
+            EditorCommand::ReindentSelection => CommandOutput::Changed(self.reindent_selection()?),
+            EditorCommand::MoveLinesUp => CommandOutput::Changed(self.move_lines_up()?),
+            EditorCommand::MoveLinesDown => CommandOutput::Changed(self.move_lines_down()?),
+            EditorCommand::DuplicateLines => CommandOutput::Changed(self.duplicate_lines()?),
+            EditorCommand::JoinLines => CommandOutput::Changed(self.join_lines()?),
+            EditorCommand::SortLines { options } => {
+                CommandOutput::Changed(self.sort_lines(*options)?)
+            }
+            EditorCommand::ReverseLines => CommandOutput::Changed(self.reverse_lines()?),
+            EditorCommand::TransposeLines => CommandOutput::Changed(self.transpose_lines()?),
+            EditorCommand::DeleteLines => CommandOutput::Changed(self.delete_lines()?),
+            EditorCommand::TransformSelections { transform } => {
+                CommandOutput::Changed(self.transform_selections(*transform)?)
+            }
+            EditorCommand::ToggleLineComment => {
+                self.toggle_line_comment()?;
+                CommandOutput::None
+            }
+            EditorCommand::Search { options } => {
+                CommandOutput::SearchResults(self.search(options.clone())?)
+            }
+            EditorCommand::FindNext => CommandOutput::SearchResult(self.find_next()?),
+            EditorCommand::Replace { replacement } => {
+                CommandOutput::Changed(self.replace(replacement)?)
+            }
+            EditorCommand::ReplaceAll { replacement } => {
+                CommandOutput::Count(self.replace_all(replacement)?)
+            }
+            EditorCommand::AddNextOccurrence { options } => {
+                CommandOutput::Changed(self.add_next_occurrence(options)?)
+            }
+            EditorCommand::SkipOccurrence { options } => {
+                CommandOutput::Changed(self.skip_occurrence(options)?)
+            }
+            EditorCommand::UndoLastCursorAdd => CommandOutput::Changed(self.undo_last_cursor_add()),
+            EditorCommand::SelectAllOccurrences { options } => {
+                CommandOutput::Count(self.select_all_occurrences(options)?)
+            }
+            EditorCommand::Fold { line } => CommandOutput::Changed(self.fold(*line)?),
+            EditorCommand::Unfold { line, recursive } => {
+                CommandOutput::Count(self.unfold(*line, *recursive)?)
+            }
+            EditorCommand::FoldAll => CommandOutput::Count(self.fold_all()?),
+            EditorCommand::FoldLevel { level } => CommandOutput::Count(self.fold_level(*level)?),
+            EditorCommand::UnfoldAll => CommandOutput::Count(self.unfold_all()?),
+            EditorCommand::InsertSnippet { body } => {
+                self.insert_snippet(body)?;
+                CommandOutput::None
+            }
+            EditorCommand::NextTabstop => CommandOutput::Changed(self.next_tabstop()?),
+            EditorCommand::PreviousTabstop => CommandOutput::Changed(self.previous_tabstop()?),
+            EditorCommand::ExitSnippet => CommandOutput::Changed(self.exit_snippet()),
+            EditorCommand::ApplyCompletion {
+                range,
+                text,
+                is_snippet,
+                additional_edits,
+            } => {
+                self.apply_completion(range.clone(), text, *is_snippet, additional_edits)?;
+                CommandOutput::None
+            }
+        };
+
+        if let Some(recording) = &mut self.macro_recording {
+            if !is_history_command(&command) {
+                recording.commands.push(command);
+            }
+        }
+        Ok(output)
+    }
+
+    /// Start recording executed commands into a macro, dropping any unfinished recording
+    pub fn start_macro_recording(&mut self, name: impl Into<String>) {
+        self.macro_recording = Some(Macro::new(name));
+    }
+
+    /// Stop recording and return the recorded macro
+    pub fn stop_macro_recording(&mut self) -> Option<Macro> {
+        self.macro_recording.take()
+    }
+
+    /// Check if a macro is being recorded
+    pub fn is_recording_macro(&self) -> bool {
+        self.macro_recording.is_some()
+    }
+
+    /// Replay a macro `times` times as a single undo step
+    #[instrument(skip(self, recorded), fields(name = %recorded.name))]
+    pub fn play_macro(&mut self, recorded: &Macro, times: usize) -> EditorResult<()> {
+        self.begin_undo_group(UndoGroupKind::Discrete);
+        let result = (0..times).try_for_each(|_| self.run_macro_commands(recorded));
+        self.end_undo_group();
+
+        debug!("Played macro {} {} times", recorded.name, times);
+        result
+    }
+
+    /// Replay a macro once from each cursor as a single undo step
+    ///
+    /// The macro runs with only that cursor active, starting from the first
+    /// cursor in the document. The cursors it leaves behind become the new cursors.
+    #[instrument(skip(self, recorded), fields(name = %recorded.name))]
+    pub fn play_macro_on_cursors(&mut self, recorded: &Macro) -> EditorResult<()> {
+        let mut starts = self.cursor_manager.cursors().to_vec();
+        starts.sort_by_key(|cursor| cursor.selection_range().start);
+
+        // Anchors keep the remaining cursors in place while earlier runs edit the text
+        let mut pending = Vec::with_capacity(starts.len());
+        for cursor in &starts {
+            pending.push(self.anchor_cursor(cursor)?);
+        }
+
+        self.begin_undo_group(UndoGroupKind::Discrete);
+        let mut finished = Vec::new();
+        let mut result = Ok(());
+        for &(anchor, position) in &pending {
+            let (Some(anchor), Some(position)) = (
+                self.buffer.anchor_position(anchor),
+                self.buffer.anchor_position(position),
+            ) else {
+                continue;
+            };
+
+            self.cursor_manager
+                .restore_cursors(vec![Cursor::with_selection(0, anchor, position)]);
+            result = self.run_macro_commands(recorded).and_then(|_| {
+                for cursor in self.cursor_manager.cursors().to_vec() {
+                    finished.push(self.anchor_cursor(&cursor)?);
+                }
+                Ok(())
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        self.end_undo_group();
+
+        let mut cursors = Vec::with_capacity(finished.len());
+        for (id, &(anchor, position)) in finished.iter().enumerate() {
+            if let (Some(anchor), Some(position)) = (
+                self.buffer.anchor_position(anchor),
+                self.buffer.anchor_position(position),
+            ) {
+                cursors.push(Cursor::with_selection(id, anchor, position));
+            }
+        }
+        for (anchor, position) in pending.into_iter().chain(finished) {
+            self.buffer.remove_anchor(anchor);
+            self.buffer.remove_anchor(position);
+        }
+
+        if result.is_ok() && !cursors.is_empty() {
+            self.restore_cursors(cursors);
+        }
+        debug!("Played macro {} on {} cursors", recorded.name, starts.len());
+        result
+    }
+
+    fn run_macro_commands(&mut self, recorded: &Macro) -> EditorResult<()> {
+        for command in &recorded.commands {
+            if is_history_command(command) {
+                continue;
+            }
+            self.execute(command.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Track both ends of a cursor with anchors
+    fn anchor_cursor(&mut self, cursor: &Cursor) -> EditorResult<(AnchorId, AnchorId)> {
+        Ok((
+            self.buffer.create_anchor(cursor.anchor, Gravity::Right)?,
+            self.buffer.create_anchor(cursor.position, Gravity::Right)?,
+        ))
+    }
+
+    /// Turn Vim editing on or off
+    ///
+    /// Enabling starts in normal mode; disabling closes any open insert session.
+    pub fn set_vim_enabled(&mut self, enabled: bool) -> EditorResult<()> {
+        match (enabled, self.vim.take()) {
+            (true, Some(vim)) => self.vim = Some(vim),
+            (true, None) => {
+                let mut vim = VimState::new();
+                vim.enter(self)?;
+                self.vim = Some(vim);
+            }
+            (false, Some(mut vim)) => vim.leave(self),
+            (false, None) => {}
+        }
+
+        self.state.vim = self.vim.as_ref().map(VimState::status);
+        Ok(())
+    }
+
+    /// Get the Vim state, when Vim editing is enabled
+    pub fn vim(&self) -> Option<&VimState> {
+        self.vim.as_ref()
+    }
+
+    /// Feed a key press to the Vim layer
+    ///
+    /// Returns what the caller still has to do, such as saving for `:w`.
+    #[instrument(skip(self))]
+    pub fn handle_vim_key(&mut self, key: VimKey) -> EditorResult<VimEffect> {
+        let Some(mut vim) = self.vim.take() else {
+            return Err(EditorError::VimError("Vim mode is not enabled".to_string()));
+        };
+
+        let result = vim.handle_key(self, key);
+        self.state.vim = Some(vim.status());
+        self.vim = Some(vim);
+        result
+    }
+
+    /// Apply edits to the buffer as a single undo step
+    pub fn apply_edits(&mut self, edits: Vec<TextEdit>) -> EditorResult<()> {
+        let start_time = Instant::now();
+
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+        if edits.is_empty() {
+            return Ok(());
+        }
+
+        self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply edits")?;
+
+        self.search_results.clear();
+        self.update_state_from_buffer();
+        self.record_operation_time(start_time.elapsed());
+
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
+        });
+        self.emit_cursor_event();
+
+        Ok(())
+    }
+
+    /// Check if the buffer is small enough to be syntax highlighted
+    pub fn highlighting_enabled(&self) -> bool {
+        self.buffer.rope().len_bytes() as u64 <= self.config.large_file.max_highlight_bytes()
+    }
+
+    /// Get highlighted tokens for the visible area
+    pub fn get_visible_tokens(&mut self) -> EditorResult<Vec<ThemedToken>> {
+        if !self.highlighting_enabled() {
+            return Ok(Vec::new());
+        }
+
+        let visible_range = self.get_visible_range();
+        self.syntax_highlighter
+            .get_themed_tokens_for_range(&self.buffer, &visible_range)
+            .map_err(|e| EditorError::SyntaxError(e.to_string()))
+    }
+
+    /// Get all highlighted tokens
+    pub fn get_all_tokens(&mut self) -> EditorResult<Vec<ThemedToken>> {
+        if !self.highlighting_enabled() {
+            return Ok(Vec::new());
+        }
+
+        self.syntax_highlighter
+            .get_themed_tokens(&self.buffer)
+            .map_err(|e| EditorError::SyntaxError(e.to_string()))
+    }
+
+    /// Update view state (called by UI layer)
+    pub fn update_view_state(&mut self, view_state: ViewState) {
+        self.view_state = view_state;
+        self.update_wrap_width();
+    }
+
+    /// Wrap visual line movement at the viewport width when word wrap is on
+    fn update_wrap_width(&mut self) {
+        self.cursor_manager.set_wrap_width(
+            self.config
+                .word_wrap
+                .then_some(self.view_state.visible_columns),
+        );
+    }
+
+    /// Scroll to ensure position is visible
+    pub fn scroll_to_position(&mut self, position: Position) {
+        let line = position.line;
+
+        // Adjust vertical scroll if needed
+        if line < self.view_state.scroll_top {
+            self.view_state.scroll_top = line;
+        } else if line >= self.view_state.scroll_top + self.view_state.visible_lines {
+            self.view_state.scroll_top = line.saturating_sub(self.view_state.visible_lines - 1);
+        }
+
+        debug!("Scrolled to position {}", position);
+    }
+
+    /// Get scroll information
+    pub fn get_scroll_info(&self) -> ScrollInfo {
+        let total_lines = self.buffer.len_lines();
+        let visible_start = self.view_state.scroll_top;
+        let visible_end = (visible_start + self.view_state.visible_lines).min(total_lines);
+
+        ScrollInfo {
+            total_lines,
+            visible_range: Range::new(
+                Position::new(visible_start, 0),
+                Position::new(visible_end, 0),
+            ),
+            needs_vertical_scroll: total_lines > self.view_state.visible_lines,
+            needs_horizontal_scroll: false, // We'll implement this later
+            max_scroll_top: total_lines.saturating_sub(self.view_state.visible_lines),
+            max_scroll_left: 0.0, // We'll implement this later
+        }
+    }
+
+    /// Id of the view the cursors, viewport and folds belong to
+    ///
+    /// An editor's first view has the editor's own id.
+    pub fn active_view(&self) -> Uuid {
+        self.view_id
+    }
+
+    /// Ids of every view onto the buffer, starting with the active one
+    pub fn view_ids(&self) -> Vec<Uuid> {
+        std::iter::once(self.view_id)
+            .chain(self.split_views.lock().iter().map(|view| view.id))
+            .collect()
+    }
+
+    /// Open another view onto the buffer, returning its id
+    ///
+    /// The new view starts with the active view's cursors, viewport and folds
+    /// and moves on its own from then on. Edits and undo in any view show up
+    /// in all of them.
+    pub fn split_view(&mut self) -> EditorResult<Uuid> {
+        let mut cursor_manager = CursorManager::new();
+        configure_cursors(&mut cursor_manager, &self.config, &self.view_state);
+        cursor_manager.restore_cursors(self.cursor_manager.cursors().to_vec());
+
+        let mut folds = Vec::with_capacity(self.folds.len());
+        for fold in &self.folds {
+            if let Some(range) = self.buffer.anchor_range(fold.range) {
+                folds.push(Fold {
+                    range: self.buffer.create_range_anchor(range)?,
+                    kind: fold.kind,
+                });
+            }
+        }
+
+        let id = Uuid::new_v4();
+        self.split_views.lock().push(SplitView {
+            id,
+            cursor_manager,
+            view_state: self.view_state.clone(),
+            folds,
+            current_search: None,
+            search_results: Vec::new(),
+            snippet_session: None,
+        });
+
+        debug!("Split view {} from editor {}", id, self.id);
+        Ok(id)
+    }
+
+    /// Make a view the active one
+    ///
+    /// Returns false when the editor has no such view.
+    pub fn activate_view(&mut self, view_id: Uuid) -> bool {
+        if view_id == self.view_id {
+            return true;
+        }
+
+        {
+            let mut split_views = self.split_views.lock();
+            let Some(view) = split_views.iter_mut().find(|view| view.id == view_id) else {
+                return false;
+            };
+            std::mem::swap(&mut self.view_id, &mut view.id);
+            std::mem::swap(&mut self.cursor_manager, &mut view.cursor_manager);
+            std::mem::swap(&mut self.view_state, &mut view.view_state);
+            std::mem::swap(&mut self.folds, &mut view.folds);
+            std::mem::swap(&mut self.current_search, &mut view.current_search);
+            std::mem::swap(&mut self.search_results, &mut view.search_results);
+            std::mem::swap(&mut self.snippet_session, &mut view.snippet_session);
+        }
+
+        self.state.snippet = self.snippet_status();
+        if let Err(e) = self.sync_folds() {
+            warn!("Failed to restore folds of view {}: {}", view_id, e);
+        }
+
+        debug!("Activated view {} of editor {}", view_id, self.id);
+        true
+    }
+
+    /// Close a view along with its folds and snippet
+    ///
+    /// Returns false when the editor has no such view or it is the last one.
+    pub fn close_view(&mut self, view_id: Uuid) -> bool {
+        if view_id == self.view_id {
+            let next = self.split_views.lock().first().map(|view| view.id);
+            let Some(next) = next else {
+                return false;
+            };
+            self.activate_view(next);
+        }
+        let view = {
+            let mut split_views = self.split_views.lock();
+            let Some(index) = split_views.iter().position(|view| view.id == view_id) else {
+                return false;
+            };
+            split_views.remove(index)
+        };
+
+        for fold in view.folds {
+            self.buffer.remove_range_anchor(fold.range);
+        }
+        if let Some(session) = view.snippet_session {
+            session.end(&mut self.buffer);
+        }
+
+        debug!("Closed view {} of editor {}", view_id, self.id);
+        true
+    }
+
+    /// Add an event listener
+    pub fn add_event_listener(&mut self, listener: Arc<dyn EditorEventListener>) {
+        self.event_listeners.push(listener);
+    }
+
+    /// Set read-only mode
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.state.is_readonly = readonly;
+        debug!("Set read-only mode: {}", readonly);
+    }
+
+    /// Set focus state
+    pub fn set_focus(&mut self, has_focus: bool) {
+        if self.state.has_focus == has_focus {
+            return;
+        }
+
+        self.state.has_focus = has_focus;
+        self.emit_event(EditorEvent::FocusChanged { has_focus });
+        debug!("Set focus: {}", has_focus);
+    }
+
+    /// Get current line content
+    pub fn current_line(&self) -> EditorResult<String> {
+        let line_num = self.cursor_manager.primary_cursor().position.line;
+        self.buffer
+            .line_text(line_num)
+            .map_err(|e| EditorError::BufferError(e))
+    }
+
+    /// Go to specific line and column
+    ///
+    /// Only the target line is looked at, so jumps cost the same in large files.
+    pub fn goto_position(&mut self, position: Position) -> EditorResult<()> {
+        // Validate position
+        if position.line >= self.buffer.len_lines() {
+            return Err(EditorError::InvalidPosition { position });
+        }
+
+        let line_len = self
+            .buffer
+            .line_len(position.line)
+            .map_err(|e| EditorError::BufferError(e))?;
+
+        if position.column > line_len {
+            return Err(EditorError::InvalidPosition { position });
+        }
+
+        // Reveal the position if it is folded away
+        if !self.folds.is_empty() {
+            self.remove_folds(|range| {
+                range.start_line < position.line && position.line <= range.end_line
+            })?;
+        }
+
+        // Move cursor
+        self.cursor_manager.clear_secondary_cursors();
+        self.cursor_manager.primary_cursor_mut().move_to(position);
+
+        // Ensure position is visible
+        self.scroll_to_position(position);
+
+        self.emit_cursor_event();
+        debug!("Moved to position {}", position);
+        Ok(())
+    }
+
+    /// Go to specific line number (1-indexed for user interface)
+    pub fn goto_line(&mut self, line_number: usize) -> EditorResult<()> {
+        if line_number == 0 {
+            return Err(EditorError::InvalidPosition {
+                position: Position::new(0, 0),
+            });
+        }
+
+        let position = Position::new(line_number - 1, 0);
+        self.goto_position(position)
+    }
+
+    /// Indent selected lines or current line
+    pub fn indent_lines(&mut self) -> EditorResult<()> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        let indent_text = utils::create_indentation(1, self.config.use_tabs, self.config.tab_size);
+        let mut edits = Vec::new();
+
+        if self.cursor_manager.has_selection() {
+            // Indent all lines that have selections
+            let ranges = self.cursor_manager.selected_ranges();
+            for range in &ranges {
+                for line in range.start.line..=range.end.line {
+                    edits.push(TextEdit::insert(
+                        Position::new(line, 0),
+                        indent_text.clone(),
+                    ));
+                }
+            }
+        } else {
+            // Indent current lines for all cursors
+            for cursor in self.cursor_manager.cursors() {
+                edits.push(TextEdit::insert(
+                    Position::new(cursor.position.line, 0),
+                    indent_text.clone(),
+                ));
+            }
+        }
+
+        self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply indent edits")?;
+
+        self.update_state_from_buffer();
+
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
+        });
+
+        debug!("Indented lines");
+        Ok(())
+    }
+
+    /// Unindent selected lines or current line
+    pub fn unindent_lines(&mut self) -> EditorResult<()> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        let mut edits = Vec::new();
+        let tab_size = self.config.tab_size;
+
+        let lines_to_unindent: Vec<usize> = if self.cursor_manager.has_selection() {
+            let ranges = self.cursor_manager.selected_ranges();
+            ranges
+                .iter()
+                .flat_map(|range| range.start.line..=range.end.line)
+                .collect()
+        } else {
+            self.cursor_manager
+                .cursors()
+                .iter()
+                .map(|cursor| cursor.position.line)
+                .collect()
+        };
+
+        for line_num in lines_to_unindent.iter().rev() {
+            let line_text = self
+                .buffer
+                .line_text(*line_num)
+                .map_err(|e| EditorError::BufferError(e))?;
+
+            let mut chars_to_remove = 0;
+            let chars: Vec<char> = line_text.chars().collect();
+
+            if !chars.is_empty() && chars[0] == '\t' {
+                chars_to_remove = 1;
+            } else {
+                let spaces = chars
+                    .iter()
+                    .take(tab_size)
+                    .take_while(|&&c| c == ' ')
+                    .count();
+                chars_to_remove = spaces.min(tab_size);
+            }
+
+            if chars_to_remove > 0 {
+                let range = Range::new(
+                    Position::new(*line_num, 0),
+                    Position::new(*line_num, chars_to_remove),
+                );
+                edits.push(TextEdit::delete(range));
+            }
+        }
+
+        if !edits.is_empty() {
+            self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+                .context("Failed to apply unindent edits")?;
+
+            self.update_state_from_buffer();
+
+            self.emit_event(EditorEvent::TextChanged {
+                version: self.buffer.version(),
+                changes: edits,
+            });
+        }
+
+        debug!("Unindented lines");
+        Ok(())
+    }
+
+    /// Reindent the lines the selections cover, or the cursor lines
+    ///
+    /// Returns whether any line changed.
+    pub fn reindent_selection(&mut self) -> EditorResult<bool> {
+        let lines: Vec<usize> = self.selected_line_blocks().into_iter().flatten().collect();
+        self.reindent_lines(&lines)
+    }
+
+    /// Give lines the indentation the syntax calls for, as one undo step
+    ///
+    /// Blank lines and lines the syntax has no say over, like those inside a
+    /// multi-line string, are left alone. Does nothing for languages without
+    /// an indent query. Returns whether any line changed.
+    #[instrument(skip(self, lines))]
+    pub fn reindent_lines(&mut self, lines: &[usize]) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        let Some(tree) = self.syntax_tree()? else {
+            return Ok(false);
+        };
+        let Some(query) = self.syntax_highlighter.highlighter().indent_query() else {
+            return Ok(false);
+        };
+
+        let text = self.buffer.text();
+        let rope = self.buffer.rope();
+        let mut lines = lines.to_vec();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let mut edits = Vec::new();
+        for line in lines.into_iter().filter(|&line| line < rope.len_lines()) {
+            let line_text = rope.line(line).to_string();
+            let current: String = line_text
+                .chars()
+                .take_while(|ch| *ch == ' ' || *ch == '\t')
+                .collect();
+            if line_text[current.len()..]
+                .trim_end_matches(['\n', '\r'])
+                .is_empty()
+            {
+                continue;
+            }
+
+            let offset = rope.line_to_byte(line) + current.len();
+            let Some(level) = query.indent_level(&tree, &text, offset) else {
+                continue;
+            };
+            let indent =
+                utils::create_indentation(level, self.config.use_tabs, self.config.tab_size);
+            if indent != current {
+                edits.push(TextEdit::replace(
+                    Range::new(Position::new(line, 0), Position::new(line, current.len())),
+                    indent,
+                ));
+            }
+        }
+
+        if edits.is_empty() {
+            return Ok(false);
+        }
+
+        self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply reindent edits")?;
+
+        self.update_state_from_buffer();
+        self.search_results.clear();
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
+        });
+        self.emit_cursor_event();
+
+        debug!("Reindented lines");
+        Ok(true)
+    }
+
+    /// Move the selected lines, or the cursor lines, up by one line
+    ///
+    /// Nothing moves when a block of lines is already at the top. Returns
+    /// whether the text changed.
+    #[instrument(skip(self))]
+    pub fn move_lines_up(&mut self) -> EditorResult<bool> {
+        let blocks = self.selected_line_blocks();
+        if blocks.first().is_some_and(|block| *block.start() == 0) {
+            return Ok(false);
+        }
+
+        let edits = blocks
+            .into_iter()
+            .map(|block| {
+                let above = *block.start() - 1;
+                let mut text = self.line_contents(block.clone())?;
+                text.extend(self.line_contents(above..=above)?);
+                Ok(LineBlockEdit {
+                    lines: above..=*block.end(),
+                    text,
+                    cursor_shift: -1,
+                })
+            })
+            .collect::<EditorResult<Vec<_>>>()?;
+
+        self.replace_line_blocks(edits)
+    }
+
+    /// Move the selected lines, or the cursor lines, down by one line
+    ///
+    /// Nothing moves when a block of lines is already at the bottom. Returns
+    /// whether the text changed.
+    #[instrument(skip(self))]
+    pub fn move_lines_down(&mut self) -> EditorResult<bool> {
+        let blocks = self.selected_line_blocks();
+        let last_line = self.buffer.len_lines().saturating_sub(1);
+        if blocks.last().is_some_and(|block| *block.end() >= last_line) {
+            return Ok(false);
+        }
+
+        let edits = blocks
+            .into_iter()
+            .map(|block| {
+                let below = *block.end() + 1;
+                let mut text = self.line_contents(below..=below)?;
+                text.extend(self.line_contents(block.clone())?);
+                Ok(LineBlockEdit {
+                    lines: *block.start()..=below,
+                    text,
+                    cursor_shift: 1,
+                })
+            })
+            .collect::<EditorResult<Vec<_>>>()?;
+
+        self.replace_line_blocks(edits)
+    }
+
+    /// Duplicate the selections, or the cursor lines when nothing is selected
+    ///
+    /// A duplicated selection is inserted right after itself and selected.
+    /// Duplicated lines go below the originals with the cursors on the copy.
+    /// When selecting by line whole lines are always duplicated. Returns
+    /// whether the text changed.
+    #[instrument(skip(self))]
+    pub fn duplicate_lines(&mut self) -> EditorResult<bool> {
+        if self.cursor_manager.has_selection()
+            && self.cursor_manager.selection_mode() != SelectionMode::Line
+        {
+            return self.duplicate_selections();
+        }
+
+        let edits = self
+            .selected_line_blocks()
+            .into_iter()
+            .map(|block| {
+                let mut text = self.line_contents(block.clone())?;
+                text.extend_from_within(..);
+                Ok(LineBlockEdit {
+                    cursor_shift: (text.len() / 2) as isize,
+                    lines: block,
+                    text,
+                })
+            })
+            .collect::<EditorResult<Vec<_>>>()?;
+
+        self.replace_line_blocks(edits)
+    }
+
+    /// Join the selected lines, or each cursor line with the next one
+    ///
+    /// Indentation of the joined lines is dropped and a single space put
+    /// between them, unless either side is blank. Returns whether the text
+    /// changed.
+    #[instrument(skip(self))]
+    pub fn join_lines(&mut self) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        let last_line = self.buffer.len_lines().saturating_sub(1);
+        let mut edits = Vec::new();
+        for block in self.selected_line_blocks() {
+            let end = (*block.end()).max(*block.start() + 1).min(last_line);
+            let lines = self.line_contents(*block.start()..=end)?;
+
+            // Columns of a line already taken by the previous join
+            let mut joined_columns = 0;
+            for (line, pair) in (*block.start()..).zip(lines.windows(2)) {
+                let (current, next) = (&pair[0], &pair[1]);
+                let kept = current.trim_end().chars().count().max(joined_columns);
+                let skipped = next.chars().count() - next.trim_start().chars().count();
+                let separator = if current.trim().is_empty() || next.trim().is_empty() {
+                    ""
+                } else {
+                    " "
+                };
+
+                edits.push(TextEdit::replace(
+                    Range::new(Position::new(line, kept), Position::new(line + 1, skipped)),
+                    separator.to_string(),
+                ));
+                joined_columns = skipped;
+            }
+        }
+
+        if edits.is_empty() {
+            return Ok(false);
+        }
+
+        self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply join edits")?;
+
+        self.update_state_from_buffer();
+        self.search_results.clear();
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
+        });
+        self.emit_cursor_event();
+
+        debug!("Joined lines");
+        Ok(true)
+    }
+
+    /// Sort the selected lines, or the whole buffer when nothing is selected
+    ///
+    /// Returns whether the text changed.
+    #[instrument(skip(self))]
+    pub fn sort_lines(&mut self, options: SortOptions) -> EditorResult<bool> {
+        let key = |line: &String| {
+            if options.case_insensitive {
+                line.to_lowercase()
+            } else {
+                line.clone()
+            }
+        };
+        let compare = |a: &String, b: &String| {
+            if options.natural {
+                utils::natural_cmp(&key(a), &key(b))
+            } else {
+                key(a).cmp(&key(b))
+            }
+        };
+
+        let edits = self
+            .selected_or_all_line_blocks()
+            .into_iter()
+            .map(|block| {
+                let mut text = self.line_contents(block.clone())?;
+                text.sort_by(compare);
+                if options.unique {
+                    text.dedup_by(|a, b| compare(a, b).is_eq());
+                }
+                Ok(LineBlockEdit {
+                    lines: block,
+                    text,
+                    cursor_shift: 0,
+                })
+            })
+            .collect::<EditorResult<Vec<_>>>()?;
+
+        self.replace_line_blocks(edits)
+    }
+
+    /// Reverse the selected lines, or the whole buffer when nothing is selected
+    ///
+    /// Returns whether the text changed.
+    #[instrument(skip(self))]
+    pub fn reverse_lines(&mut self) -> EditorResult<bool> {
+        let edits = self
+            .selected_or_all_line_blocks()
+            .into_iter()
+            .map(|block| {
+                let mut text = self.line_contents(block.clone())?;
+                text.reverse();
+                Ok(LineBlockEdit {
+                    lines: block,
+                    text,
+                    cursor_shift: 0,
+                })
+            })
+            .collect::<EditorResult<Vec<_>>>()?;
+
+        self.replace_line_blocks(edits)
+    }
+
+    /// Swap the selected lines, or the cursor lines, with the line above them
+    ///
+    /// The cursors end up below both, so repeating the command drags the
+    /// line above further down. Lines at the top are left alone. Returns
+    /// whether the text changed.
+    #[instrument(skip(self))]
+    pub fn transpose_lines(&mut self) -> EditorResult<bool> {
+        let edits = self
+            .selected_line_blocks()
+            .into_iter()
+            .filter(|block| *block.start() > 0)
+            .map(|block| {
+                let above = *block.start() - 1;
+                let mut text = self.line_contents(block.clone())?;
+                text.extend(self.line_contents(above..=above)?);
+                Ok(LineBlockEdit {
+                    cursor_shift: (text.len() - 1) as isize,
+                    lines: above..=*block.end(),
+                    text,
+                })
+            })
+            .collect::<EditorResult<Vec<_>>>()?;
+
+        self.replace_line_blocks(edits)
+    }
+
+    /// Delete the selected lines, or the cursor lines, with their line endings
+    ///
+    /// Returns whether the text changed.
+    #[instrument(skip(self))]
+    pub fn delete_lines(&mut self) -> EditorResult<bool> {
+        let edits = self
+            .selected_line_blocks()
+            .into_iter()
+            .map(|block| LineBlockEdit {
+                lines: block,
+                text: Vec::new(),
+                cursor_shift: 0,
+            })
+            .collect();
+
+        self.replace_line_blocks(edits)
+    }
+
+    /// Transform the text of each selection, or of the word at each cursor
+    ///
+    /// Transformed selections stay selected. Nothing changes when any of the
+    /// texts can't be transformed. Returns whether the text changed.
+    #[instrument(skip(self))]
+    pub fn transform_selections(&mut self, transform: TextTransform) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        let mut cursors = self.cursor_manager.cursors().to_vec();
+        cursors.sort_by_key(|cursor| cursor.selection_range().start);
+
+        let mut edits = Vec::new();
+        // Character offsets of the new cursors, counted in the edited text
+        let mut offsets = Vec::new();
+        let mut shift = 0isize;
+        // Cursors in the same word share its transformed text
+        let mut last: Option<(Range, isize, usize)> = None;
+        for cursor in &cursors {
+            let index = |position| -> EditorResult<isize> {
+                Ok(self.buffer.position_to_char_index(position)? as isize)
+            };
+            let range = if cursor.has_selection {
+                Some(cursor.selection_range())
+            } else {
+                self.word_range_at(cursor.position)?
+            };
+            let Some(range) = range else {
+                let offset = index(cursor.position)? + shift;
+                offsets.push((offset, offset));
+                continue;
+            };
+
+            let (start, len) = match &last {
+                Some((last_range, start, len)) if *last_range == range => (*start, *len),
+                _ => {
+                    let text = self.buffer.text_in_range(&range)?;
+                    let transformed = transform.apply(&text)?;
+                    let start = index(range.start)? + shift;
+                    let len = transformed.chars().count();
+                    shift += len as isize - text.chars().count() as isize;
+                    if transformed != text {
+                        edits.push(TextEdit::replace(range.clone(), transformed));
+                    }
+                    last = Some((range.clone(), start, len));
+                    (start, len)
+                }
+            };
+
+            let end = start + len as isize;
+            offsets.push(if !cursor.has_selection {
+                let offset = (start + index(cursor.position)? - index(range.start)?).min(end);
+                (offset, offset)
+            } else if cursor.anchor <= cursor.position {
+                (start, end)
+            } else {
+                (end, start)
+            });
+        }
+
+        if edits.is_empty() {
+            return Ok(false);
+        }
+
+        self.begin_undo_group(UndoGroupKind::Discrete);
+        let result = self
+            .apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply transform edits")
+            .and_then(|_| {
+                cursors
+                    .iter()
+                    .zip(offsets)
+                    .map(|(cursor, (anchor, position))| {
+                        Ok(Cursor::with_selection(
+                            cursor.id,
+                            self.buffer.char_index_to_position(anchor as usize)?,
+                            self.buffer.char_index_to_position(position as usize)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            });
+        if let Ok(cursors) = &result {
+            self.restore_cursors(cursors.clone());
+        }
+        self.end_undo_group();
+        result?;
+
+        self.update_state_from_buffer();
+        self.search_results.clear();
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
+        });
+
+        debug!("Transformed selections with {:?}", transform);
+        Ok(true)
+    }
+
+    /// Comment/uncomment selected lines
+    pub fn toggle_line_comment(&mut self) -> EditorResult<()> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        // Get comment prefix for current language
+        let comment_prefix = self.get_comment_prefix();
+        if comment_prefix.is_empty() {
+            return Ok(()); // No comment syntax for this language
+        }
+
+        let lines_to_toggle: Vec<usize> = if self.cursor_manager.has_selection() {
+            let ranges = self.cursor_manager.selected_ranges();
+            ranges
+                .iter()
+                .flat_map(|range| range.start.line..=range.end.line)
+                .collect()
+        } else {
+            self.cursor_manager
+                .cursors()
+                .iter()
+                .map(|cursor| cursor.position.line)
+                .collect()
+        };
+
+        // Check if all lines are already commented
+        let all_commented = lines_to_toggle.iter().all(|&line_num| {
+            if let Ok(line_text) = self.buffer.line_text(line_num) {
+                line_text.trim_start().starts_with(&comment_prefix)
+            } else {
+                false
+            }
+        });
+
+        let mut edits = Vec::new();
+
+        for line_num in lines_to_toggle.iter().rev() {
+            let line_text = self
+                .buffer
+                .line_text(*line_num)
+                .map_err(|e| EditorError::BufferError(e))?;
+
+            if all_commented {
+                // Uncomment: remove comment prefix
+                if let Some(pos) = line_text.find(&comment_prefix) {
+                    let range = Range::new(
+                        Position::new(*line_num, pos),
+                        Position::new(*line_num, pos + comment_prefix.len()),
+                    );
+                    edits.push(TextEdit::delete(range));
+                }
+            } else {
+                // Comment: add comment prefix at start of line (after indentation)
+                let indent_len = utils::get_line_indentation(&line_text);
+                edits.push(TextEdit::insert(
+                    Position::new(*line_num, indent_len),
+                    format!("{} ", comment_prefix),
+                ));
+            }
+        }
+
+        if !edits.is_empty() {
+            self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+                .context("Failed to apply comment edits")?;
+
+            self.update_state_from_buffer();
+
+            self.emit_event(EditorEvent::TextChanged {
+                version: self.buffer.version(),
+                changes: edits,
+            });
+        }
+
+        debug!("Toggled line comments");
+        Ok(())
+    }
+
+    // Helper methods
+
+    /// Open an undo group capturing the current cursor state
+    pub(crate) fn begin_undo_group(&mut self, kind: UndoGroupKind) {
+        let cursors = self.cursor_manager.cursors().to_vec();
+        self.buffer.begin_undo_group(cursors, kind);
+    }
+
+    /// Close the current undo group capturing the resulting cursor state
+    pub(crate) fn end_undo_group(&mut self) {
+        let cursors = self.cursor_manager.cursors().to_vec();
+        self.buffer.end_undo_group(cursors);
+    }
+
+    /// Apply edits and update cursors as a single undo step
+    fn apply_edits_as_undo_step(&mut self, edits: &[TextEdit], kind: UndoGroupKind) -> Result<()> {
+        self.begin_undo_group(kind);
+        let result = self
+            .buffer
+            .apply_edits(edits.to_vec())
+            .and_then(|_| self.cursor_manager.update_after_edits(edits));
+        self.end_undo_group();
+
+        result
+    }
+
+    /// Turn the buffer text into `texts` with minimal line edits as one undo step
+    fn apply_text_changes(&mut self, texts: &[&str]) -> EditorResult<()> {
+        let current = self.buffer.text();
+        let mut changes: Vec<TextChange> = Vec::new();
+        for text in texts {
+            for change in line_changes(&current, text) {
+                if !changes.iter().any(|kept| changes_overlap(kept, &change)) {
+                    changes.push(change);
+                }
+            }
+        }
+        changes.sort_by_key(|change| change.start);
+
+        let edits = changes
+            .into_iter()
+            .map(|change| {
+                Ok(TextEdit::replace(
+                    Range::new(
+                        self.buffer.char_index_to_position(change.start)?,
+                        self.buffer.char_index_to_position(change.end)?,
+                    ),
+                    change.text,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if edits.is_empty() {
+            return Ok(());
+        }
+
+        self.apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply changes")?;
+
+        self.search_results.clear();
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
+        });
+        self.emit_cursor_event();
+
+        Ok(())
+    }
+
+    /// Restore cursors captured by an undo tree step and notify listeners
+    fn finish_history_step(&mut self, cursors: Option<Vec<Cursor>>, start_time: Instant) -> bool {
+        let Some(cursors) = cursors else {
+            return false;
+        };
+
+        self.cursor_manager.restore_cursors(cursors);
+        self.record_operation_time(start_time.elapsed());
+
+        self.search_results.clear();
+        self.update_state_from_buffer();
+
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: vec![], // Listeners re-read the buffer after history steps
+        });
+
+        let position = self.cursor_manager.primary_cursor().position;
+        self.scroll_to_position(position);
+        self.emit_cursor_event();
+
+        true
+    }
+
+    /// Move the split views along with every change to the buffer
+    fn track_view_changes(&mut self) {
+        let split_views = Arc::clone(&self.split_views);
+        self.buffer.add_change_listener(move |event| {
+            for view in split_views.lock().iter_mut() {
+                view.follow_edits(&event.edits);
+            }
+        });
+    }
+
+    /// Tell listeners where the split views' cursors went after an edit
+    fn emit_view_events(&self) {
+        let events: Vec<EditorEvent> = self
+            .split_views
+            .lock()
+            .iter()
+            .map(|view| EditorEvent::ViewMoved {
+                view_id: view.id,
+                positions: view.cursor_manager.cursor_positions(),
+                scroll_top: view.view_state.scroll_top,
+            })
+            .collect();
+
+        for event in events {
+            self.emit_event(event);
+        }
+    }
+
+    /// Update editor state from buffer
+    fn update_state_from_buffer(&mut self) {
+        self.state.line_count = self.buffer.len_lines();
+        self.state.char_count = self.buffer.len_chars();
+        self.state.is_dirty = self.buffer.is_dirty();
+        self.state.line_ending = match self.buffer.line_ending() {
+            crate::core::text_buffer::LineEnding::Unix => "LF".to_string(),
+            crate::core::text_buffer::LineEnding::Windows => "CRLF".to_string(),
+            crate::core::text_buffer::LineEnding::Mac => "CR".to_string(),
+        };
+        self.state.encoding = self.buffer.encoding().to_string();
+        self.state.large_file = self.buffer.is_large_file();
+    }
+
+    /// Emit cursor position event
+    fn emit_cursor_event(&self) {
+        let positions = self.cursor_manager.cursor_positions();
+        let selections = self.cursor_manager.selected_ranges();
+
+        self.emit_event(EditorEvent::CursorMoved {
+            positions,
+            selections: selections.clone(),
+        });
+
+        // Also emit selection changed event
+        let has_selection = self.cursor_manager.has_selection();
+        let selected_text = if has_selection {
+            self.cursor_manager
+                .cursors()
+                .iter()
+                .filter_map(|cursor| cursor.selected_text(&self.buffer).ok())
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            String::new()
+        };
+
+        let char_count = selected_text.len();
+
+        self.emit_event(EditorEvent::SelectionChanged {
+            has_selection,
+            selected_text,
+            char_count,
+        });
+    }
+
+    /// Emit an event to all listeners
+    fn emit_event(&self, event: EditorEvent) {
+        for listener in &self.event_listeners {
+            match &event {
+                EditorEvent::TextChanged { version, .. } => {
+                    listener.on_text_changed(*version);
+                }
+                EditorEvent::CursorMoved { positions, .. } => {
+                    listener.on_cursor_moved(positions);
+                }
+                EditorEvent::SelectionChanged { has_selection, .. } => {
+                    listener.on_selection_changed(*has_selection);
+                }
+                EditorEvent::FileSaved { path } => {
+                    listener.on_file_saved(path);
+                }
+                EditorEvent::FileLoaded { path, .. } => {
+                    listener.on_file_loaded(path);
+                }
+                EditorEvent::FocusChanged { has_focus } => {
+                    listener.on_focus_changed(*has_focus);
+                }
+                EditorEvent::ViewMoved {
+                    view_id, positions, ..
+                } => {
+                    listener.on_view_moved(*view_id, positions);
+                }
+                _ => {} // Other events don't have corresponding trait methods
+            }
+        }
+
+        // Split views were moved by the buffer's change listener
+        if let EditorEvent::TextChanged { .. } = event {
+            self.emit_view_events();
+        }
+    }
+
+    /// Record operation time for metrics
+    fn record_operation_time(&mut self, duration: Duration) {
+        self.metrics.last_operation_time = duration;
+        self.metrics.operation_count += 1;
+
+        self.operation_times.push_back(duration);
+        if self.operation_times.len() > 100 {
+            self.operation_times.pop_front();
+        }
+
+        // Calculate average
+        if !self.operation_times.is_empty() {
+            let total: Duration = self.operation_times.iter().sum();
+            self.metrics.average_operation_time = total / self.operation_times.len() as u32;
+        }
+    }
+
+    /// Get visible range based on current view state
+    fn get_visible_range(&self) -> Range {
+        let start_line = self.view_state.scroll_top;
+        let end_line = (start_line + self.view_state.visible_lines).min(self.buffer.len_lines());
+
+        Range::new(Position::new(start_line, 0), Position::new(end_line, 0))
+    }
+
+    /// Move position one character to the right
+    fn move_position_right(&self, position: Position) -> EditorResult<Position> {
+        let line_len = self
+            .buffer
+            .line_len(position.line)
+            .map_err(|e| EditorError::BufferError(e))?;
+
+        if position.column < line_len {
+            Ok(Position::new(position.line, position.column + 1))
+        } else if position.line < self.buffer.len_lines() - 1 {
+            Ok(Position::new(position.line + 1, 0))
+        } else {
+            Ok(position) // At end of document
+        }
+    }
+
+    /// Move position one character to the left
+    fn move_position_left(&self, position: Position) -> EditorResult<Position> {
+        if position.column > 0 {
+            Ok(Position::new(position.line, position.column - 1))
+        } else if position.line > 0 {
+            let prev_line = position.line - 1;
+            let line_len = self
+                .buffer
+                .line_len(prev_line)
+                .map_err(|e| EditorError::BufferError(e))?;
+            Ok(Position::new(prev_line, line_len))
+        } else {
+            Ok(position) // At start of document
+        }
+    }
+
+    /// Indentation for a line break inserted at `position`
+    ///
+    /// Uses the language's indent query when there is one, otherwise the
+    /// new line keeps the indentation of the line it was split from.
+    fn indentation_at(&mut self, position: Position) -> EditorResult<String> {
+        if let Some(tree) = self.syntax_tree()? {
+            if let Some(query) = self.syntax_highlighter.highlighter().indent_query() {
+                let char_index = self.buffer.position_to_char_index(position)?;
+                let offset = self.buffer.rope().char_to_byte(char_index);
+                if let Some(level) = query.indent_level(&tree, &self.buffer.text(), offset) {
+                    return Ok(utils::create_indentation(
+                        level,
+                        self.config.use_tabs,
+                        self.config.tab_size,
+                    ));
+                }
+            }
+        }
+
+        let line = self.buffer.line_text(position.line)?;
+        Ok(line
+            .chars()
+            .take(position.column)
+            .take_while(|ch| *ch == ' ' || *ch == '\t')
+            .collect())
+    }
+
+    /// Whether `position` sits right between an opening and closing bracket
+    fn is_between_brackets(&self, position: Position) -> EditorResult<bool> {
+        let line: Vec<char> = self.buffer.line_text(position.line)?.chars().collect();
+        let before = position.column.checked_sub(1).and_then(|i| line.get(i));
+        Ok(matches!(
+            (before, line.get(position.column)),
+            (Some('{'), Some('}')) | (Some('['), Some(']')) | (Some('('), Some(')'))
+        ))
+    }
+
+    /// Get auto-closing character for the given opening character
+    fn get_auto_close_char(&self, ch: char) -> Option<char> {
+        match ch {
+            '(' => Some(')'),
+            '[' => Some(']'),
+            '{' => Some('}'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            _ => None,
+        }
+    }
+
+    /// Get comment prefix for current language
+    fn get_comment_prefix(&self) -> String {
+        // This could be expanded to use language-specific comment syntax
+        match self.state.language.as_deref() {
+            Some("rust") => "//".to_string(),
+            Some("toml") => "#".to_string(),
+            Some("json") => "//".to_string(), // JSON doesn't have comments, but we'll use this
+            _ => "//".to_string(),            // Default
+        }
+    }
+
+    /// Select the tabstop at `position` of the active snippet
+    ///
+    /// Transformed copies of the tabstop being left are updated first. The
+    /// final tabstop ends the snippet.
+    fn select_tabstop(&mut self, position: usize) -> EditorResult<()> {
+        let Some(mut session) = self.snippet_session.take() else {
+            return Ok(());
+        };
+
+        let ranges = session
+            .transform_edits(&self.buffer)
+            .map_err(EditorError::from)
+            .and_then(|edits| self.apply_edits(edits))
+            .and_then(|_| Ok(session.select(&mut self.buffer, position)?));
+        let ranges = match ranges {
+            Ok(ranges) => ranges,
+            Err(error) => {
+                session.end(&mut self.buffer);
+                self.state.snippet = None;
+                return Err(error);
+            }
+        };
+
+        if session.is_last(session.current()) {
+            session.end(&mut self.buffer);
+            self.state.snippet = None;
+        } else {
+            self.state.snippet = session.status();
+            self.snippet_session = Some(session);
+        }
+
+        let cursors: Vec<Cursor> = ranges
+            .iter()
+            .enumerate()
+            .map(|(id, range)| Cursor::with_selection(id, range.start, range.end))
+            .collect();
+        if let Some(cursor) = cursors.first() {
+            self.scroll_to_position(cursor.position);
+        }
+        self.restore_cursors(cursors);
+        Ok(())
+    }
+
+    /// Snippet variables that are the same at every cursor
+    fn snippet_variables(&self) -> SnippetVariables {
+        let mut variables = SnippetVariables::new()
+            .with_time(&chrono::Local::now())
+            .with_random()
+            .with("LINE_COMMENT", self.get_comment_prefix());
+
+        if !matches!(self.state.language.as_deref(), Some("toml")) {
+            variables.set("BLOCK_COMMENT_START", "/*");
+            variables.set("BLOCK_COMMENT_END", "*/");
+        }
+
+        if let Some(path) = &self.state.file_path {
+            let name = |part: Option<&std::ffi::OsStr>| {
+                part.map(|part| part.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            };
+            variables.set("TM_FILENAME", name(path.file_name()));
+            variables.set("TM_FILENAME_BASE", name(path.file_stem()));
+            variables.set("TM_FILEPATH", path.to_string_lossy());
+            variables.set("TM_DIRECTORY", name(path.parent().map(Path::as_os_str)));
+        }
+
+        variables
+    }
+
+    /// Current lines of a fold, or `None` once its hidden lines were deleted
+    fn fold_range(&self, fold: &Fold) -> Option<FoldingRange> {
+        let range = self.buffer.anchor_range(fold.range)?;
+        (range.end.line > range.start.line)
+            .then(|| FoldingRange::new(range.start.line, range.end.line, fold.kind))
+    }
+
+    /// Fold ranges that aren't folded yet, returning how many were folded
+    fn add_folds(&mut self, ranges: &[FoldingRange]) -> EditorResult<usize> {
+        let folded = self.folded_ranges();
+        let mut added = 0;
+
+        for range in ranges.iter().filter(|range| !is_folded(&folded, range)) {
+            let start = Position::new(range.start_line, self.buffer.line_len(range.start_line)?);
+            let end = Position::new(range.end_line, self.buffer.line_len(range.end_line)?);
+            let anchor = self.buffer.create_range_anchor(Range::new(start, end))?;
+            self.folds.push(Fold {
+                range: anchor,
+                kind: range.kind,
+            });
+            added += 1;
+        }
+
+        if added > 0 {
+            self.sync_folds()?;
+            self.emit_cursor_event();
+        }
+        debug!("Folded {} ranges", added);
+        Ok(added)
+    }
+
+    /// Unfold the folds matching `predicate`, returning how many were removed
+    fn remove_folds(&mut self, predicate: impl Fn(&FoldingRange) -> bool) -> EditorResult<usize> {
+        let mut removed = 0;
+        for fold in std::mem::take(&mut self.folds) {
+            if self
+                .fold_range(&fold)
+                .is_some_and(|range| predicate(&range))
+            {
+                self.buffer.remove_range_anchor(fold.range);
+                removed += 1;
+            } else {
+                self.folds.push(fold);
+            }
+        }
+
+        self.sync_folds()?;
+        debug!("Unfolded {} ranges", removed);
+        Ok(removed)
+    }
+
+    /// Drop folds whose hidden lines were deleted and hide folded lines from the cursors
+    fn sync_folds(&mut self) -> EditorResult<()> {
+        let mut hidden = Vec::new();
+        for fold in std::mem::take(&mut self.folds) {
+            match self.fold_range(&fold) {
+                Some(range) => {
+                    hidden.push((range.start_line + 1, range.end_line));
+                    self.folds.push(fold);
+                }
+                None => {
+                    self.buffer.remove_range_anchor(fold.range);
+                }
+            }
+        }
+
+        self.cursor_manager
+            .set_hidden_lines(&self.buffer, hidden)
+            .context("Failed to hide folded lines")?;
+        Ok(())
+    }
+
+    /// Syntax tree for the current buffer, if it is highlighted at all
+    fn syntax_tree(&mut self) -> EditorResult<Option<Tree>> {
+        if !self.highlighting_enabled() {
+            return Ok(None);
+        }
+
+        self.syntax_highlighter
+            .highlighter_mut()
+            .syntax_tree(&self.buffer)
+            .map_err(|e| EditorError::SyntaxError(e.to_string()))
+    }
+
+    /// Cursor added most recently, which occurrence commands work from
+    fn newest_cursor(&self) -> &Cursor {
+        self.cursor_manager
+            .cursors()
+            .iter()
+            .max_by_key(|cursor| cursor.id)
+            .unwrap_or_else(|| self.cursor_manager.primary_cursor())
+    }
+
+    /// Runs of whole lines the cursors cover, sorted with touching runs merged
+    ///
+    /// A selection ending at the start of a line leaves that line out, unless
+    /// selecting by line.
+    fn selected_line_blocks(&self) -> Vec<RangeInclusive<usize>> {
+        let by_line = self.cursor_manager.selection_mode() == SelectionMode::Line;
+        let mut spans: Vec<(usize, usize)> = self
+            .cursor_manager
+            .cursors()
+            .iter()
+            .map(|cursor| {
+                let range = cursor.selection_range();
+                if !by_line && range.end.column == 0 && range.end.line > range.start.line {
+                    (range.start.line, range.end.line - 1)
+                } else {
+                    (range.start.line, range.end.line)
+                }
+            })
+            .collect();
+        spans.sort_unstable();
+
+        let mut blocks: Vec<RangeInclusive<usize>> = Vec::new();
+        for (start, end) in spans {
+            match blocks.last_mut() {
+                Some(last) if start <= *last.end() + 1 => {
+                    *last = *last.start()..=end.max(*last.end());
+                }
+                _ => blocks.push(start..=end),
+            }
+        }
+        blocks
+    }
+
+    /// Selected lines, or every line but a trailing empty one without a selection
+    fn selected_or_all_line_blocks(&self) -> Vec<RangeInclusive<usize>> {
+        if self.cursor_manager.has_selection() {
+            return self.selected_line_blocks();
+        }
+
+        let mut last_line = self.buffer.len_lines().saturating_sub(1);
+        if last_line > 0 && self.buffer.line_len(last_line).unwrap_or(0) == 0 {
+            last_line -= 1;
+        }
+        vec![0..=last_line]
+    }
+
+    /// Text of lines without their line endings
+    fn line_contents(&self, lines: RangeInclusive<usize>) -> EditorResult<Vec<String>> {
+        lines
+            .map(|line| {
+                let len = self.buffer.line_len(line)?;
+                Ok(self.buffer.line_text(line)?.chars().take(len).collect())
+            })
+            .collect()
+    }
+
+    /// Replace runs of whole lines as one undo step and move the cursors along
+    ///
+    /// `blocks` must be sorted and must not overlap. Cursors after a block
+    /// follow its change in line count, those starting inside one move by
+    /// its `cursor_shift`, or to the line after it when it is removed.
+    /// Returns whether the text changed.
+    fn replace_line_blocks(&mut self, blocks: Vec<LineBlockEdit>) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        let line_ending = self.buffer.line_ending().as_str();
+        let last_line = self.buffer.len_lines().saturating_sub(1);
+        let mut edits = Vec::new();
+        for block in &blocks {
+            let (start, end) = (*block.lines.start(), *block.lines.end());
+            let end_position = Position::new(end, self.buffer.line_len(end)?);
+            let range = if !block.text.is_empty() {
+                Range::new(Position::new(start, 0), end_position)
+            } else if end < last_line {
+                Range::new(Position::new(start, 0), Position::new(end + 1, 0))
+            } else if start > 0 {
+                let previous_end = Position::new(start - 1, self.buffer.line_len(start - 1)?);
+                Range::new(previous_end, end_position)
+            } else {
+                Range::new(Position::new(0, 0), end_position)
+            };
+
+            let text = block.text.join(line_ending);
+            if self.buffer.text_in_range(&range)? != text {
+                edits.push(TextEdit::replace(range, text));
+            }
+        }
+
+        if edits.is_empty() {
+            return Ok(false);
+        }
+
+        let shift = |position: Position, lines: isize| {
+            Position::new(position.line.saturating_add_signed(lines), position.column)
+        };
+        let cursors: Vec<Cursor> = self
+            .cursor_manager
+            .cursors()
+            .iter()
+            .map(|cursor| {
+                let line = cursor.selection_range().start.line;
+                let mut lines = 0;
+                for block in &blocks {
+                    let old_len = block.lines.end() - block.lines.start() + 1;
+                    if *block.lines.end() < line {
+                        lines += block.text.len() as isize - old_len as isize;
+                    } else if block.lines.contains(&line) {
+                        if block.text.is_empty() {
+                            let position = Position::new(
+                                block.lines.start().saturating_add_signed(lines),
+                                cursor.position.column,
+                            );
+                            return Cursor::new(cursor.id, position);
+                        }
+                        lines += block.cursor_shift;
+                        break;
+                    } else {
+                        break;
+                    }
+                }
+
+                let mut moved = cursor.clone();
+                moved.position = shift(cursor.position, lines);
+                moved.anchor = shift(cursor.anchor, lines);
+                moved
+            })
+            .collect();
+
+        // Cursors are placed inside the step so undo and redo restore them
+        self.begin_undo_group(UndoGroupKind::Discrete);
+        let result = self
+            .apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply line edits");
+        if result.is_ok() {
+            self.restore_cursors(cursors);
+        }
+        self.end_undo_group();
+        result?;
+
+        self.update_state_from_buffer();
+        self.search_results.clear();
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
+        });
+
+        debug!("Replaced {} blocks of lines", blocks.len());
+        Ok(true)
+    }
+
+    /// Insert a copy of each selection right after it and select the copies
+    fn duplicate_selections(&mut self) -> EditorResult<bool> {
+        if self.state.is_readonly {
+            return Err(EditorError::SearchError("Editor is read-only".to_string()));
+        }
+
+        let mut cursors = self.cursor_manager.cursors().to_vec();
+        cursors.sort_by_key(|cursor| cursor.selection_range().start);
+
+        // Character offsets of the new cursors, counted in the edited text
+        let mut edits = Vec::new();
+        let mut offsets = Vec::new();
+        let mut inserted = 0;
+        for cursor in &cursors {
+            let range = cursor.selection_range();
+            let start = self.buffer.position_to_char_index(range.start)? + inserted;
+            let end = self.buffer.position_to_char_index(range.end)? + inserted;
+            if !cursor.has_selection {
+                offsets.push((start, start));
+                continue;
+            }
+
+            let text = self.buffer.text_in_range(&range)?;
+            let len = text.chars().count();
+            edits.push(TextEdit::insert(range.end, text));
+            offsets.push((end, end + len));
+            inserted += len;
+        }
+
+        self.begin_undo_group(UndoGroupKind::Discrete);
+        let result = self
+            .apply_edits_as_undo_step(&edits, UndoGroupKind::Discrete)
+            .context("Failed to apply duplicate edits")
+            .and_then(|_| {
+                cursors
+                    .iter()
+                    .zip(offsets)
+                    .map(|(cursor, (anchor, position))| {
+                        Ok(Cursor::with_selection(
+                            cursor.id,
+                            self.buffer.char_index_to_position(anchor)?,
+                            self.buffer.char_index_to_position(position)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            });
+        if let Ok(cursors) = &result {
+            self.restore_cursors(cursors.clone());
+        }
+        self.end_undo_group();
+        result?;
+
+        self.update_state_from_buffer();
+        self.search_results.clear();
+        self.emit_event(EditorEvent::TextChanged {
+            version: self.buffer.version(),
+            changes: edits,
+        });
+
+        debug!("Duplicated selections");
+        Ok(true)
+    }
+
+    /// Shared implementation of adding and skipping occurrences
+    fn select_next_occurrence(
+        &mut self,
+        options: &SearchOptions,
+        skip: bool,
+    ) -> EditorResult<bool> {
+        let newest = self.newest_cursor().clone();
+        if !newest.has_selection {
+            let selected = self.select_word_at_newest_cursor()?;
+            if selected {
+                self.emit_cursor_event();
+            }
+            return Ok(selected);
+        }
+
+        let selection = newest.selection_range();
+        let occurrences = self.find_occurrences(&selection, options)?;
+        let is_free = |range: &&Range| {
+            !self
+                .cursor_manager
+                .cursors()
+                .iter()
+                .any(|cursor| cursor.has_selection && cursor.selection_range() == **range)
+        };
+
+        // Search onwards from the newest selection, wrapping around at the end
+        let next = occurrences
+            .iter()
+            .filter(|range| range.start >= selection.end)
+            .chain(
+                occurrences
+                    .iter()
+                    .filter(|range| range.start < selection.end),
+            )
+            .find(is_free)
+            .cloned();
+        let Some(next) = next else {
+            return Ok(false);
+        };
+
+        if skip && !self.cursor_manager.remove_cursor(newest.id) {
+            self.cursor_manager
+                .primary_cursor_mut()
+                .select_range(next.clone());
+        } else {
+            self.cursor_manager
+                .add_cursor_with_selection(next.start, next.end);
+        }
+
+        self.scroll_to_position(next.end);
+        self.emit_cursor_event();
+        Ok(true)
+    }
+
+    /// Select the word under the newest cursor, returning whether there was one
+    fn select_word_at_newest_cursor(&mut self) -> EditorResult<bool> {
+        let newest = self.newest_cursor().clone();
+        let Some(word) = self.word_range_at(newest.position)? else {
+            return Ok(false);
+        };
+
+        let cursors = self
+            .cursor_manager
+            .cursors()
+            .iter()
+            .map(|cursor| {
+                if cursor.id == newest.id {
+                    Cursor::with_selection(cursor.id, word.start, word.end)
+                } else {
+                    cursor.clone()
+                }
+            })
+            .collect();
+        self.cursor_manager.restore_cursors(cursors);
+        Ok(true)
+    }
+
+    /// Range of the word touching a position, if any
+    fn word_range_at(&self, position: Position) -> EditorResult<Option<Range>> {
+        let line_len = self.buffer.line_len(position.line)?;
+        let chars: Vec<char> = self
+            .buffer
+            .line_text(position.line)?
+            .chars()
+            .take(line_len)
+            .collect();
+
+        let column = position.column.min(chars.len());
+        let start = column
+            - chars[..column]
+                .iter()
+                .rev()
+                .take_while(|&&ch| utils::is_word_char(ch))
+                .count();
+        let end = column
+            + chars[column..]
+                .iter()
+                .take_while(|&&ch| utils::is_word_char(ch))
+                .count();
+
+        Ok((start < end).then(|| {
+            Range::new(
+                Position::new(position.line, start),
+                Position::new(position.line, end),
+            )
+        }))
+    }
+
+    /// Ranges of all literal matches of the text in `selection`
+    fn find_occurrences(
+        &self,
+        selection: &Range,
+        options: &SearchOptions,
+    ) -> EditorResult<Vec<Range>> {
+        let options = SearchOptions {
+            query: self.buffer.text_in_range(selection)?,
+            use_regex: false,
+            forward: true,
+            wrap_around: true,
+            ..options.clone()
+        };
+
+        Ok(self
+            .search_buffer(&options)?
+            .into_iter()
+            .map(|result| result.range)
+            .collect())
+    }
+
+    /// Maximum number of matches a search collects
+    fn search_limit(&self) -> usize {
+        if self.buffer.is_large_file() {
+            self.config.large_file.max_search_results
+        } else {
+            usize::MAX
+        }
+    }
+
+    /// Search the buffer a window of whole lines at a time
+    ///
+    /// Only one window of text is copied out of the rope at once, so huge
+    /// buffers are searched without a copy of their whole contents. Literal
+    /// matches may overlap and span as many lines as the query does; regex
+    /// matches don't cross window boundaries.
+    fn search_buffer(&self, options: &SearchOptions) -> EditorResult<Vec<SearchResult>> {
+        let pattern = if options.use_regex {
+            options.query.clone()
+        } else {
+            regex::escape(&options.query)
+        };
+        let regex = if options.case_sensitive {
+            Regex::new(&pattern)
+        } else {
+            Regex::new(&format!("(?i){}", pattern))
+        }
+        .map_err(|e| EditorError::SearchError(format!("Invalid regex: {}", e)))?;
+
+        let rope = self.buffer.rope();
+        let limit = self.search_limit();
+        let overlap_lines = if options.use_regex {
+            0
+        } else {
+            options.query.matches('\n').count()
+        };
+
+        let mut results = Vec::new();
+        let mut start_line = 0;
+        // End of the previous window, whose matches were already collected
+        let mut searched_to = None;
+
+        while results.len() < limit {
+            let start_byte = rope.line_to_byte(start_line);
+            let end_line =
+                rope.byte_to_line((start_byte + SEARCH_WINDOW_BYTES).min(rope.len_bytes())) + 1;
+            let end_byte = rope.line_to_byte(end_line.min(rope.len_lines()));
+            let window = rope.byte_slice(start_byte..end_byte).to_string();
+
+            let mut pos = 0;
+            while pos <= window.len() && results.len() < limit {
+                let Some(mat) = regex.find_at(&window, pos) else {
+                    break;
+                };
+
+                // Regex matches don't overlap, literal ones step over their first character
+                pos = if options.use_regex && !mat.is_empty() {
+                    mat.end()
+                } else {
+                    mat.start()
+                        + window[mat.start()..]
+                            .chars()
+                            .next()
+                            .map_or(1, char::len_utf8)
+                };
+
+                let (start, end) = (start_byte + mat.start(), start_byte + mat.end());
+                if searched_to.is_some_and(|searched_to| end <= searched_to) {
+                    continue;
+                }
+                if !options.use_regex
+                    && options.whole_word
+                    && !self.is_whole_word_match(&window, mat.start(), mat.end())
+                {
+                    continue;
+                }
+
+                results.push(SearchResult {
+                    range: Range::new(self.byte_to_position(start)?, self.byte_to_position(end)?),
+                    text: mat.as_str().to_string(),
+                    match_index: 0,
+                    total_matches: 0, // Will be updated after all matches are found
+                });
+            }
+
+            if end_line >= rope.len_lines() {
+                break;
+            }
+            searched_to = Some(end_byte);
+            start_line = end_line.saturating_sub(overlap_lines).max(start_line + 1);
+        }
+
+        // Matches spanning a window boundary are found after later ones
+        results.sort_by_key(|result| result.range.start);
+        let total_matches = results.len();
+        for (index, result) in results.iter_mut().enumerate() {
+            result.match_index = index;
+            result.total_matches = total_matches;
+        }
+
+        Ok(results)
+    }
+
+    /// Convert a byte offset into the buffer text to a line/column position
+    fn byte_to_position(&self, byte_pos: usize) -> EditorResult<Position> {
+        let rope = self.buffer.rope();
+        let char_index = rope.byte_to_char(byte_pos.min(rope.len_bytes()));
+        Ok(self.buffer.char_index_to_position(char_index)?)
+    }
+
+    /// Check if a match is a whole word
+    fn is_whole_word_match(&self, text: &str, start: usize, end: usize) -> bool {
+        let before = text.get(..start).and_then(|text| text.chars().next_back());
+        let after = text.get(end..).and_then(|text| text.chars().next());
+
+        !before.is_some_and(utils::is_word_char) && !after.is_some_and(utils::is_word_char)
+    }
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Set up a view's cursor movement for the editor's configuration
+fn configure_cursors(
+    cursor_manager: &mut CursorManager,
+    config: &EditorConfig,
+    view_state: &ViewState,
+) {
+    cursor_manager.set_page_size(config.page_scroll_lines);
+    cursor_manager.set_tab_size(config.tab_size);
+    cursor_manager.set_wrap_width(config.word_wrap.then_some(view_state.visible_columns));
+}
+
+/// Whether `ranges` has a range covering the same lines as `range`
+fn is_folded(ranges: &[FoldingRange], range: &FoldingRange) -> bool {
+    ranges
+        .iter()
+        .any(|folded| folded.start_line == range.start_line && folded.end_line == range.end_line)
+}
+
+/// Whether two changes replace some of the same text or insert at the same place
+fn changes_overlap(a: &TextChange, b: &TextChange) -> bool {
+    (a.start < b.end && b.start < a.end) || a.start == b.start
+}
+
+/// Whether a command moves through the undo history
+///
+/// Macros leave these out: a replay runs as one undo group of its own, so an
+/// undo inside it would act on history outside the replay.
+fn is_history_command(command: &EditorCommand) -> bool {
+    matches!(command, EditorCommand::Undo | EditorCommand::Redo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_creation() {
+        let editor = Editor::new();
+        assert_eq!(editor.state().line_count, 1);
+        assert_eq!(editor.state().char_count, 0);
+        assert!(!editor.state().is_dirty);
+        assert!(!editor.state().is_readonly);
+    }
+
+    #[test]
+    fn test_text_insertion() {
+        let mut editor = Editor::new();
+        editor.insert_text("Hello, World!").unwrap();
+
+        assert_eq!(editor.buffer().text(), "Hello, World!");
+        assert!(editor.state().is_dirty);
+        assert_eq!(editor.state().char_count, 13);
+    }
+
+    #[test]
+    fn test_cursor_movement() {
+        let mut editor = Editor::new();
+        editor.insert_text("Line 1\nLine 2\nLine 3").unwrap();
+        editor.goto_position(Position::new(1, 0)).unwrap();
+
+        // Move down
+        editor
+            .move_cursors(Direction::Down, MovementUnit::Line, false)
+            .unwrap();
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(2, 0)
+        );
+
+        // Test invalid line number
+        assert!(editor.goto_line(0).is_err());
+        assert!(editor.goto_line(100).is_err());
+    }
+
+    #[test]
+    fn test_replace_operations() {
+        let mut editor = Editor::new();
+        editor.insert_text("Hello World\nHello Rust").unwrap();
+
+        let options = SearchOptions {
+            query: "Hello".to_string(),
+            case_sensitive: true,
+            whole_word: false,
+            use_regex: false,
+            forward: true,
+            wrap_around: true,
+        };
+
+        editor.search(options).unwrap();
+        let replaced = editor.replace_all("Hi").unwrap();
+
+        assert_eq!(replaced, 2);
+        assert_eq!(editor.buffer().text(), "Hi World\nHi Rust");
+    }
+
+    #[test]
+    fn test_readonly_mode() {
+        let mut editor = Editor::new();
+        editor.set_readonly(true);
+
+        let result = editor.insert_text("Hello");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_performance_metrics() {
+        let mut editor = Editor::new();
+
+        // Perform some operations
+        editor.insert_text("Hello").unwrap();
+        editor.insert_text(" World").unwrap();
+
+        let metrics = editor.metrics();
+        assert!(metrics.operation_count > 0);
+        assert!(metrics.last_operation_time > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_view_state_management() {
+        let mut editor = Editor::new();
+
+        let view_state = ViewState {
+            scroll_top: 10,
+            scroll_left: 50.0,
+            visible_lines: 30,
+            viewport_width: 1000.0,
+            visible_columns: 120,
+            viewport_height: 800.0,
+        };
+
+        editor.update_view_state(view_state.clone());
+        assert_eq!(editor.view_state().scroll_top, 10);
+        assert_eq!(editor.view_state().visible_lines, 30);
+    }
+
+    #[test]
+    fn test_focus_changes_notify_listeners() {
+        struct FocusListener(std::sync::Mutex<Vec<bool>>);
+        impl EditorEventListener for FocusListener {
+            fn on_focus_changed(&self, has_focus: bool) {
+                self.0.lock().unwrap().push(has_focus);
+            }
+        }
+
+        let listener = Arc::new(FocusListener(std::sync::Mutex::new(Vec::new())));
+        let mut editor = Editor::new();
+        editor.add_event_listener(listener.clone());
+
+        editor.set_focus(true);
+        editor.set_focus(true);
+        editor.set_focus(false);
+        assert_eq!(*listener.0.lock().unwrap(), [true, false]);
+    }
+
+    #[test]
+    fn test_snippet_tabstops() {
+        let mut editor = Editor::new();
+        editor.insert_text("    ").unwrap();
+        editor
+            .insert_snippet("fn ${1:name}($2) {\n\t$0\n}\n// ${1/(.*)/${1:/upcase}/}")
+            .unwrap();
+        assert_eq!(
+            editor.buffer().text(),
+            "    fn name() {\n        \n    }\n    // NAME"
+        );
+        assert_eq!(editor.state().snippet.as_ref().map(|s| s.index), Some(1));
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().selection_range(),
+            Range::new(Position::new(0, 7), Position::new(0, 11))
+        );
+
+        // Typing replaces the placeholder, and leaving it updates the transform
+        editor.insert_text("parse").unwrap();
+        assert!(editor.next_tabstop().unwrap());
+        assert_eq!(
+            editor.buffer().text(),
+            "    fn parse() {\n        \n    }\n    // PARSE"
+        );
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(0, 13)
+        );
+
+        assert!(editor.previous_tabstop().unwrap());
+        assert_eq!(editor.copy().unwrap(), "parse");
+
+        // The final tabstop ends the snippet
+        assert!(editor.next_tabstop().unwrap());
+        assert!(editor.next_tabstop().unwrap());
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(1, 8)
+        );
+        assert!(editor.state().snippet.is_none());
+        assert!(!editor.next_tabstop().unwrap());
+    }
+
+    #[test]
+    fn test_snippet_mirrors_and_completions() {
+        let mut editor = Editor::new();
+        editor.insert_snippet("<${1:div}>$0</$1>").unwrap();
+        assert_eq!(editor.cursor_manager().cursor_count(), 2);
+        editor.insert_text("span").unwrap();
+        assert_eq!(editor.buffer().text(), "<span></span>");
+        editor.next_tabstop().unwrap();
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(0, 6)
+        );
+
+        let mut editor = Editor::new();
+        editor.insert_text("foo.ba").unwrap();
+        let range = Range::new(Position::new(0, 4), Position::new(0, 6));
+        editor
+            .apply_completion(Some(range), "bar(${1:x})", true, &[])
+            .unwrap();
+        assert_eq!(editor.buffer().text(), "foo.bar(x)");
+        assert_eq!(editor.copy().unwrap(), "x");
+
+        // Edits elsewhere land in the same undo step
+        let mut editor = Editor::new();
+        editor.insert_text("fn f() {\n    Ha\n}\n").unwrap();
+        editor.goto_position(Position::new(1, 6)).unwrap();
+        let import = RangeReplacement {
+            range: Range::new(Position::zero(), Position::zero()),
+            text: "use std::collections::HashMap;\n".to_string(),
+        };
+        editor
+            .apply_completion(
+                Some(Range::new(Position::new(1, 4), Position::new(1, 6))),
+                "HashMap",
+                false,
+                &[import],
+            )
+            .unwrap();
+        assert_eq!(
+            editor.buffer().text(),
+            "use std::collections::HashMap;\nfn f() {\n    HashMap\n}\n"
+        );
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "fn f() {\n    Ha\n}\n");
+    }
+
+    #[test]
+    fn test_folding() {
+        let mut editor = Editor::new();
+        editor
+            .syntax_highlighter_mut()
+            .highlighter_mut()
+            .set_language("rust")
+            .unwrap();
+        editor
+            .insert_text("fn a() {\n    if x {\n        y();\n    }\n}\nfn b() {\n    z();\n}\n")
+            .unwrap();
+        let folded = |editor: &Editor| {
+            editor
+                .folded_ranges()
+                .iter()
+                .map(|range| (range.start_line, range.end_line))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(editor.fold_level(2).unwrap(), 1);
+        assert_eq!(folded(&editor), [(1, 2)]);
+        // Folding again folds the enclosing range
+        assert!(editor.fold(2).unwrap());
+        assert_eq!(folded(&editor), [(0, 3), (1, 2)]);
+        assert_eq!(editor.unfold(0, false).unwrap(), 1);
+        assert_eq!(folded(&editor), [(1, 2)]);
+        assert_eq!(editor.fold_all().unwrap(), 2);
+        assert_eq!(folded(&editor), [(0, 3), (1, 2), (5, 6)]);
+
+        // Vertical movement skips the hidden lines
+        editor.goto_position(Position::new(0, 2)).unwrap();
+        let mut stops = Vec::new();
+        for _ in 0..3 {
+            editor
+                .move_cursors(Direction::Down, MovementUnit::Line, false)
+                .unwrap();
+            stops.push(editor.cursor_manager().primary_cursor().position);
+        }
+        assert_eq!(
+            stops,
+            [
+                Position::new(4, 1),
+                Position::new(5, 2),
+                Position::new(7, 1)
+            ]
+        );
+
+        // Folds follow edits above them
+        editor.goto_position(Position::new(0, 0)).unwrap();
+        editor.insert_text("// top\n").unwrap();
+        assert_eq!(folded(&editor), [(1, 4), (2, 3), (6, 7)]);
+
+        assert_eq!(editor.unfold(3, true).unwrap(), 2);
+        assert_eq!(folded(&editor), [(6, 7)]);
+
+        // Going to a folded line reveals it
+        editor.goto_position(Position::new(7, 0)).unwrap();
+        assert!(editor.folded_ranges().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_ends_snippet() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+
+        let mut editor = Editor::new();
+        editor.insert_snippet("${1:a} ${2:b}").unwrap();
+        assert!(editor.snippet_status().is_some());
+
+        editor.load_file(&path).await.unwrap();
+        assert!(editor.snippet_status().is_none());
+        assert!(editor.state().snippet.is_none());
+        assert!(!editor.next_tabstop().unwrap());
+        assert_eq!(editor.buffer().text(), "fn main() {}\n");
+    }
+
+    #[tokio::test]
+    async fn test_load_drops_folds() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let first = temp_dir.path().join("first.rs");
+        let second = temp_dir.path().join("second.rs");
+        std::fs::write(&first, "fn a() {\n    x();\n}\n").unwrap();
+        std::fs::write(&second, "fn b() {\n    y();\n    z();\n}\n").unwrap();
+
+        let mut editor = Editor::new();
+        editor.load_file(&first).await.unwrap();
+        assert!(editor.fold(1).unwrap());
+        assert!(!editor.folded_ranges().is_empty());
+
+        editor.load_file(&second).await.unwrap();
+        assert!(editor.folded_ranges().is_empty());
+        assert!(editor.fold(1).unwrap());
+        assert_eq!(editor.folded_ranges().len(), 1);
+    }
+
+    #[test]
+    fn test_visual_line_movement_wraps_at_view_width() {
+        let mut editor = Editor::with_config(EditorConfig {
+            word_wrap: true,
+            ..EditorConfig::default()
+        });
+        editor.insert_text("aaaa bbbb cccc").unwrap();
+        editor.update_view_state(ViewState {
+            visible_columns: 5,
+            ..ViewState::default()
+        });
+
+        editor
+            .cursor_manager_mut()
+            .primary_cursor_mut()
+            .move_to(Position::new(0, 1));
+        editor
+            .move_cursors(Direction::Down, MovementUnit::VisualLine, false)
+            .unwrap();
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(0, 6)
+        );
+
+        // Without word wrap the line is a single row
+        editor
+            .set_config(EditorConfig {
+                word_wrap: false,
+                ..EditorConfig::default()
+            })
+            .unwrap();
+        editor
+            .move_cursors(Direction::Up, MovementUnit::VisualLine, false)
+            .unwrap();
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(0, 0)
+        );
+    }
+
+    #[test]
+    fn test_scroll_to_position() {
+        let mut editor = Editor::new();
+
+        // Create a document with many lines
+        let lines: Vec<String> = (0..100).map(|i| format!("Line {}", i)).collect();
+        editor.insert_text(&lines.join("\n")).unwrap();
+
+        // Scroll to line 50
+        editor.scroll_to_position(Position::new(50, 0));
+
+        let scroll_info = editor.get_scroll_info();
+        assert!(scroll_info.visible_range.start.line <= 50);
+        assert!(scroll_info.visible_range.end.line > 50);
+    }
+
+    #[test]
+    fn test_auto_indentation() {
+        let mut editor = Editor::with_config(EditorConfig {
+            auto_indent: true,
+            tab_size: 4,
+            use_tabs: false,
+            ..EditorConfig::default()
+        });
+
+        editor.insert_text("    fn main() {").unwrap();
+        editor.type_char('\n').unwrap();
+
+        let current_line = editor.current_line().unwrap();
+        assert!(current_line.starts_with("    ")); // Should maintain indentation
+    }
+
+    #[test]
+    fn test_syntax_indentation() {
+        let mut editor = Editor::new();
+        editor
+            .syntax_highlighter_mut()
+            .highlighter_mut()
+            .set_language("rust")
+            .unwrap();
+
+        // Enter between braces puts the cursor on an indented line inside
+        editor.insert_text("fn main() ").unwrap();
+        editor.type_char('{').unwrap();
+        editor.type_char('\n').unwrap();
+        editor.insert_text("if x ").unwrap();
+        editor.type_char('{').unwrap();
+        editor.type_char('\n').unwrap();
+        editor.insert_text("y();").unwrap();
+        assert_eq!(
+            editor.buffer().text(),
+            "fn main() {\n    if x {\n        y();\n    }\n}"
+        );
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(2, 12)
+        );
+
+        editor.select_all().unwrap();
+        editor.execute(EditorCommand::UnindentLines).unwrap();
+        editor.select_all().unwrap();
+        assert!(editor.reindent_selection().unwrap());
+        assert_eq!(
+            editor.buffer().text(),
+            "fn main() {\n    if x {\n        y();\n    }\n}"
+        );
+        assert!(!editor.reindent_selection().unwrap());
+
+        // A closing brace typed on its own line moves back a level
+        editor.select_all().unwrap();
+        editor.insert_text("fn main() {\n    x();\n    ").unwrap();
+        editor.type_char('}').unwrap();
+        assert_eq!(editor.buffer().text(), "fn main() {\n    x();\n}");
+    }
+
+    #[test]
+    fn test_auto_close_brackets() {
+        let mut editor = Editor::with_config(EditorConfig {
+            auto_close_brackets: true,
+            ..EditorConfig::default()
+        });
+
+        editor.type_char('(').unwrap();
+        assert_eq!(editor.buffer().text(), "()");
+
+        // Cursor should be between brackets
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(0, 1)
+        );
+    }
+
+    #[test]
+    fn test_regex_search() {
+        let mut editor = Editor::new();
+        editor.insert_text("test123\ntest456\nabc789").unwrap();
+
+        let options = SearchOptions {
+            query: r"test\d+".to_string(),
+            case_sensitive: true,
+            whole_word: false,
+            use_regex: true,
+            forward: true,
+            wrap_around: true,
+        };
+
+        let results = editor.search(options).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].text, "test123");
+        assert_eq!(results[1].text, "test456");
+    }
+
+    #[test]
+    fn test_undo_coalesces_typing_into_words() {
+        let mut editor = Editor::new();
+        for ch in "hello world".chars() {
+            editor.type_char(ch).unwrap();
+        }
+
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.buffer().text(), "hello ");
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(0, 6)
+        );
+
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.buffer().text(), "");
+        assert!(!editor.undo().unwrap());
+
+        assert!(editor.redo().unwrap());
+        assert!(editor.redo().unwrap());
+        assert_eq!(editor.buffer().text(), "hello world");
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(0, 11)
+        );
+    }
+
+    #[test]
+    fn test_undo_breaks_typing_after_cursor_move() {
+        let mut editor = Editor::new();
+        editor.type_char('a').unwrap();
+        editor.type_char('b').unwrap();
+        editor
+            .move_cursors(Direction::Left, MovementUnit::Character, false)
+            .unwrap();
+        editor.type_char('c').unwrap();
+        assert_eq!(editor.buffer().text(), "acb");
+
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "ab");
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(0, 1)
+        );
+    }
+
+    #[test]
+    fn test_undo_multi_cursor_replace_all() {
+        let mut editor = Editor::new();
+        editor.insert_text("foo bar foo\nfoo baz").unwrap();
+        editor.goto_position(Position::new(0, 4)).unwrap();
+        editor.cursor_manager_mut().add_cursor(Position::new(1, 4));
+        let text_before = editor.buffer().text();
+        let cursors_before = editor.cursor_manager().cursors().to_vec();
+
+        let options = SearchOptions {
+            query: "foo".to_string(),
+            case_sensitive: true,
+            whole_word: false,
+            use_regex: false,
+            forward: true,
+            wrap_around: true,
+        };
+        editor.search(options).unwrap();
+        assert_eq!(editor.replace_all("quux").unwrap(), 3);
+        let text_after = editor.buffer().text();
+        let cursors_after = editor.cursor_manager().cursors().to_vec();
+        assert_eq!(text_after, "quux bar quux\nquux baz");
+
+        // All replacements are undone in one step
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.buffer().text(), text_before);
+        assert_eq!(editor.cursor_manager().cursors(), cursors_before.as_slice());
+
+        assert!(editor.redo().unwrap());
+        assert_eq!(editor.buffer().text(), text_after);
+        assert_eq!(editor.cursor_manager().cursors(), cursors_after.as_slice());
+    }
+
+    #[test]
+    fn test_undo_multi_cursor_indent_lines() {
+        let mut editor = Editor::new();
+        editor.insert_text("one\ntwo\nthree").unwrap();
+        editor.goto_position(Position::new(0, 1)).unwrap();
+        editor.cursor_manager_mut().add_cursor(Position::new(1, 2));
+        editor.cursor_manager_mut().add_cursor(Position::new(2, 5));
+        let text_before = editor.buffer().text();
+        let cursors_before = editor.cursor_manager().cursors().to_vec();
+
+        editor.indent_lines().unwrap();
+        let text_after = editor.buffer().text();
+        let cursors_after = editor.cursor_manager().cursors().to_vec();
+        assert_eq!(text_after, "    one\n    two\n    three");
+
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.buffer().text(), text_before);
+        assert_eq!(editor.cursor_manager().cursors(), cursors_before.as_slice());
+
+        assert!(editor.redo().unwrap());
+        assert_eq!(editor.buffer().text(), text_after);
+        assert_eq!(editor.cursor_manager().cursors(), cursors_after.as_slice());
+    }
+
+    #[test]
+    fn test_occurrence_cursors() {
+        let mut editor = Editor::new();
+        editor
+            .insert_text("let foo = 1;\nfoo(foo_bar);\nFoo + foo\n")
+            .unwrap();
+        editor.goto_position(Position::new(0, 5)).unwrap();
+
+        let whole_word = SearchOptions {
+            query: String::new(),
+            case_sensitive: true,
+            whole_word: true,
+            use_regex: false,
+            forward: true,
+            wrap_around: true,
+        };
+        let selections = |editor: &Editor| editor.cursor_manager().selected_ranges();
+
+        // The first call selects the word, later calls add its next match
+        assert!(editor.add_next_occurrence(&whole_word).unwrap());
+        assert!(editor.add_next_occurrence(&whole_word).unwrap());
+        assert_eq!(
+            selections(&editor),
+            vec![
+                Range::new(Position::new(0, 4), Position::new(0, 7)),
+                Range::new(Position::new(1, 0), Position::new(1, 3)),
+            ]
+        );
+
+        assert!(editor.skip_occurrence(&whole_word).unwrap());
+        assert_eq!(
+            selections(&editor)[1],
+            Range::new(Position::new(2, 6), Position::new(2, 9))
+        );
+
+        assert!(editor.undo_last_cursor_add());
+        assert!(!editor.undo_last_cursor_add());
+        assert_eq!(editor.cursor_manager().cursor_count(), 1);
+
+        let any_case = SearchOptions {
+            case_sensitive: false,
+            whole_word: false,
+            ..whole_word
+        };
+        assert_eq!(editor.select_all_occurrences(&any_case).unwrap(), 5);
+        editor.insert_text("bar").unwrap();
+        assert_eq!(
+            editor.buffer().text(),
+            "let bar = 1;\nbar(bar_bar);\nbar + bar\n"
+        );
+    }
+
+    #[test]
+    fn test_occurrences_of_non_ascii_words() {
+        let mut editor = Editor::new();
+        editor.insert_text("café, cafés and café").unwrap();
+        editor.goto_position(Position::new(0, 1)).unwrap();
+
+        let options = SearchOptions {
+            query: String::new(),
+            case_sensitive: true,
+            whole_word: true,
+            use_regex: false,
+            forward: true,
+            wrap_around: true,
+        };
+        assert_eq!(editor.select_all_occurrences(&options).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_expand_and_shrink_selection() {
+        let mut editor = Editor::new();
+        editor
+            .syntax_highlighter_mut()
+            .highlighter_mut()
+            .set_language("rust")
+            .unwrap();
+        editor
+            .insert_text("fn f() {\n    a(b + 1);\n    c(d * 2);\n}\n")
+            .unwrap();
+        editor.goto_position(Position::new(1, 6)).unwrap();
+        editor.cursor_manager_mut().add_cursor(Position::new(2, 6));
+        let selected = |editor: &Editor| {
+            let buffer = editor.buffer();
+            editor
+                .cursor_manager()
+                .selected_ranges()
+                .iter()
+                .map(|range| buffer.text_in_range(range).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(editor.expand_selection().unwrap());
+        assert!(editor.expand_selection().unwrap());
+        assert_eq!(selected(&editor), ["b + 1", "d * 2"]);
+        assert!(editor.expand_selection().unwrap());
+        assert_eq!(selected(&editor), ["(b + 1)", "(d * 2)"]);
+
+        // Shrinking retraces the expansions
+        assert!(editor.shrink_selection().unwrap());
+        assert!(editor.shrink_selection().unwrap());
+        assert_eq!(selected(&editor), ["b", "d"]);
+        assert!(editor.shrink_selection().unwrap());
+        assert!(!editor.cursor_manager().has_selection());
+        assert!(!editor.shrink_selection().unwrap());
+    }
+
+    /// Editor whose buffer matches a file freshly loaded from disk
+    fn editor_with_saved_text(text: &str) -> Editor {
+        let mut editor = Editor::new();
+        editor.insert_text(text).unwrap();
+        editor.buffer_mut().set_saved_text(Some(text.to_string()));
+        editor.buffer_mut().set_dirty(false);
+        editor
+    }
 
-        let mut results = Vec::new();
-        let mut start_pos = 0;
+    #[test]
+    fn test_block_selection_edits_per_row() {
+        let mut editor = Editor::new();
+        editor.insert_text("one\ntwo\nthree").unwrap();
+        editor
+            .select_block(BlockSelection {
+                anchor: Position::new(0, 0),
+                head: Position::new(2, 0),
+            })
+            .unwrap();
 
-        while let Some(pos) = search_text[start_pos..].find(&query) {
-            let actual_pos = start_pos + pos;
-            let end_pos = actual_pos + query.len();
+        editor.insert_text("- ").unwrap();
+        assert_eq!(editor.buffer().text(), "- one\n- two\n- three");
 
-            // Convert byte positions to line/column positions
-            let start_position = self.byte_to_position(text, actual_pos)?;
-            let end_position = self.byte_to_position(text, end_pos)?;
+        editor
+            .select_block(BlockSelection {
+                anchor: Position::new(0, 2),
+                head: Position::new(2, 5),
+            })
+            .unwrap();
+        assert_eq!(editor.copy().unwrap(), "one\ntwo\nthr");
 
-            // Check whole word constraint
-            if options.whole_word && !self.is_whole_word_match(text, actual_pos, end_pos) {
-                start_pos = actual_pos + 1;
-                continue;
-            }
+        editor.paste("1\n2\n3\n").unwrap();
+        assert_eq!(editor.buffer().text(), "- 1\n- 2\n- 3ee");
+    }
 
-            results.push(SearchResult {
-                range: Range::new(start_position, end_position),
-                text: text[actual_pos..end_pos].to_string(),
-                match_index: results.len(),
-                total_matches: 0, // Will be updated after all matches are found
-            });
+    #[test]
+    fn test_reconcile_reloads_clean_buffer() {
+        let mut editor = editor_with_saved_text("use std::io;\nfn main() {\n    run();\n}\n");
+        editor.goto_position(Position::new(2, 6)).unwrap();
 
-            start_pos = actual_pos + 1;
-        }
+        let change = editor
+            .reconcile_with_disk("use std::fs;\nuse std::io;\nfn main() {\n    run();\n}\n")
+            .unwrap();
 
-        // Update total_matches count
-        for result in &mut results {
-            result.total_matches = results.len();
-        }
+        assert!(matches!(change, ExternalChange::Reloaded));
+        assert!(!editor.buffer().is_dirty());
+        // The cursor follows its line down past the inserted import
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(3, 6)
+        );
 
-        Ok(results)
+        // Our own save showing up as a change on disk is not a change
+        let disk = editor.buffer().saved_text().unwrap().to_string();
+        assert!(matches!(
+            editor.reconcile_with_disk(&disk).unwrap(),
+            ExternalChange::Unchanged
+        ));
     }
 
-    /// Search using regular expressions
-    fn search_regex(&self, text: &str, options: &SearchOptions) -> EditorResult<Vec<SearchResult>> {
-        let regex = if options.case_sensitive {
-            Regex::new(&options.query)
-        } else {
-            Regex::new(&format!("(?i){}", options.query))
+    #[test]
+    fn test_reconcile_merges_dirty_buffer() {
+        let mut editor = editor_with_saved_text("a\nb\nc\n");
+        editor.goto_position(Position::new(0, 1)).unwrap();
+        editor.insert_text("!").unwrap();
+
+        let change = editor.reconcile_with_disk("a\nb\nc?\n").unwrap();
+        assert!(matches!(change, ExternalChange::Merged { ref conflicts } if conflicts.is_empty()));
+        assert_eq!(editor.buffer().text(), "a!\nb\nc?\n");
+        assert!(editor.buffer().is_dirty());
+
+        let change = editor.reconcile_with_disk("a?\nb\nc?\n").unwrap();
+        match change {
+            ExternalChange::Merged { conflicts } => assert_eq!(conflicts.len(), 1),
+            other => panic!("Expected a conflict, got {:?}", other),
         }
-        .map_err(|e| EditorError::SearchError(format!("Invalid regex: {}", e)))?;
-
-        let mut results = Vec::new();
-
-        for mat in regex.find_iter(text) {
-            let start_pos = mat.start();
-            let end_pos = mat.end();
+        assert!(editor
+            .buffer()
+            .text()
+            .contains(crate::core::merge::CONFLICT_START));
+    }
 
-            let start_position = self.byte_to_position(text, start_pos)?;
-            let end_position = self.byte_to_position(text, end_pos)?;
+    #[test]
+    fn test_reconcile_keeps_unsaved_trailing_whitespace() {
+        let mut editor = editor_with_saved_text("a\nb\nc\n");
+        assert!(editor.buffer().config().trim_trailing_whitespace);
+        editor.goto_position(Position::new(0, 1)).unwrap();
+        editor.insert_text("  ").unwrap();
+
+        let change = editor.reconcile_with_disk("a\nb\nc?\n").unwrap();
+        assert!(matches!(change, ExternalChange::Merged { ref conflicts } if conflicts.is_empty()));
+        assert_eq!(editor.buffer().text(), "a  \nb\nc?\n");
+        assert!(editor.buffer().is_dirty());
+    }
 
-            results.push(SearchResult {
-                range: Range::new(start_position, end_position),
-                text: mat.as_str().to_string(),
-                match_index: results.len(),
-                total_matches: 0, // Will be updated after all matches are found
-            });
-        }
+    #[test]
+    fn test_apply_text_is_one_undo_step() {
+        let mut editor = Editor::new();
+        editor
+            .insert_text("fn a( ) {}\nfn b() {\n    run();\n}\nfn c( ) {}\n")
+            .unwrap();
+        editor.goto_position(Position::new(2, 6)).unwrap();
 
-        // Update total_matches count
-        for result in &mut results {
-            result.total_matches = results.len();
-        }
+        assert!(editor
+            .apply_text("fn a() {}\nfn b() {\n    run();\n}\nfn c() {}\n")
+            .unwrap());
+        assert_eq!(
+            editor.buffer().text(),
+            "fn a() {}\nfn b() {\n    run();\n}\nfn c() {}\n"
+        );
+        // The cursor's line was not reformatted, so it stays put
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(2, 6)
+        );
+        assert!(!editor
+            .apply_text("fn a() {}\nfn b() {\n    run();\n}\nfn c() {}\n")
+            .unwrap());
 
-        Ok(results)
+        editor.undo().unwrap();
+        assert_eq!(
+            editor.buffer().text(),
+            "fn a( ) {}\nfn b() {\n    run();\n}\nfn c( ) {}\n"
+        );
     }
 
-    /// Convert byte position to line/column position
-    fn byte_to_position(&self, text: &str, byte_pos: usize) -> EditorResult<Position> {
-        let mut line = 0;
-        let mut column = 0;
-        let mut current_byte = 0;
+    #[test]
+    fn test_apply_texts_combines_rewrites_of_parts() {
+        let mut editor = Editor::new();
+        editor.insert_text("a( )\nb( )\nc( )\n").unwrap();
 
-        for ch in text.chars() {
-            if current_byte >= byte_pos {
-                break;
-            }
+        // Each rewrite only touches its own line; the last repeats the first
+        assert!(editor
+            .apply_texts(&["a()\nb( )\nc( )\n", "a( )\nb( )\nc()\n", "x\nb( )\nc( )\n"])
+            .unwrap());
+        assert_eq!(editor.buffer().text(), "a()\nb( )\nc()\n");
 
-            if ch == '\n' {
-                line += 1;
-                column = 0;
-            } else {
-                column += 1;
-            }
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "a( )\nb( )\nc( )\n");
+    }
 
-            current_byte += ch.len_utf8();
-        }
+    #[test]
+    fn test_move_and_duplicate_lines() {
+        let mut editor = Editor::new();
+        editor.insert_text("a\nb\nc\nd\ne").unwrap();
+        editor.goto_position(Position::new(1, 1)).unwrap();
+        editor.cursor_manager_mut().add_cursor(Position::new(3, 0));
+        let positions = |editor: &Editor| editor.cursor_manager().cursor_positions();
 
-        Ok(Position::new(line, column))
-    }
+        assert!(editor.move_lines_up().unwrap());
+        assert_eq!(editor.buffer().text(), "b\na\nd\nc\ne");
+        assert_eq!(
+            positions(&editor),
+            vec![Position::new(0, 1), Position::new(2, 0)]
+        );
+        // The first block is at the top, so nothing moves
+        assert!(!editor.move_lines_up().unwrap());
 
-    /// Check if a match is a whole word
-    fn is_whole_word_match(&self, text: &str, start: usize, end: usize) -> bool {
-        let chars: Vec<char> = text.chars().collect();
+        assert!(editor.move_lines_down().unwrap());
+        assert_eq!(editor.buffer().text(), "a\nb\nc\nd\ne");
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "b\na\nd\nc\ne");
+        assert_eq!(
+            positions(&editor),
+            vec![Position::new(0, 1), Position::new(2, 0)]
+        );
 
-        // Check character before match
-        if start > 0 && utils::is_word_char(chars[start - 1]) {
-            return false;
-        }
+        // A selection ending at the start of a line leaves that line out
+        editor
+            .cursor_manager_mut()
+            .restore_cursors(vec![Cursor::with_selection(
+                0,
+                Position::new(1, 0),
+                Position::new(3, 0),
+            )]);
+        assert!(editor.move_lines_down().unwrap());
+        assert_eq!(editor.buffer().text(), "b\nc\na\nd\ne");
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().selection_range(),
+            Range::new(Position::new(2, 0), Position::new(4, 0))
+        );
 
-        // Check character after match
-        if end < chars.len() && utils::is_word_char(chars[end]) {
-            return false;
-        }
+        // Selecting by line takes the line the selection ends on as well
+        editor.set_selection_mode(SelectionMode::Line);
+        assert!(editor.duplicate_lines().unwrap());
+        assert_eq!(editor.buffer().text(), "b\nc\na\nd\ne\na\nd\ne");
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().selection_range(),
+            Range::new(Position::new(5, 0), Position::new(7, 0))
+        );
 
-        true
+        editor.set_selection_mode(SelectionMode::Normal);
+        editor
+            .cursor_manager_mut()
+            .restore_cursors(vec![Cursor::with_selection(
+                0,
+                Position::new(0, 0),
+                Position::new(1, 1),
+            )]);
+        assert!(editor.duplicate_lines().unwrap());
+        assert_eq!(editor.buffer().text(), "b\ncb\nc\na\nd\ne\na\nd\ne");
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().selection_range(),
+            Range::new(Position::new(1, 1), Position::new(2, 1))
+        );
     }
-}
 
-impl Default for Editor {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+    #[test]
+    fn test_join_lines() {
+        let mut editor = Editor::new();
+        editor
+            .insert_text("let x = foo(\n    a,\n    b,\n);\n\nnext")
+            .unwrap();
+        editor.goto_position(Position::new(0, 3)).unwrap();
+        editor.cursor_manager_mut().add_cursor(Position::new(4, 0));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert!(editor.join_lines().unwrap());
+        assert_eq!(editor.buffer().text(), "let x = foo( a,\n    b,\n);\nnext");
 
-    #[test]
-    fn test_editor_creation() {
-        let editor = Editor::new();
-        assert_eq!(editor.state().line_count, 1);
-        assert_eq!(editor.state().char_count, 0);
-        assert!(!editor.state().is_dirty);
-        assert!(!editor.state().is_readonly);
+        editor.select_all().unwrap();
+        assert!(editor.join_lines().unwrap());
+        assert_eq!(editor.buffer().text(), "let x = foo( a, b, ); next");
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "let x = foo( a,\n    b,\n);\nnext");
     }
 
     #[test]
-    fn test_text_insertion() {
+    fn test_transform_selections() {
         let mut editor = Editor::new();
-        editor.insert_text("Hello, World!").unwrap();
+        editor
+            .insert_text("let userName = parseHTTPRequest(raw);\nlet s = \"a\\\"b\";")
+            .unwrap();
+        // One cursor inside a word, one selecting a call and one inside the
+        // same word as the first
+        editor.goto_position(Position::new(0, 6)).unwrap();
+        editor
+            .cursor_manager_mut()
+            .add_cursor_with_selection(Position::new(0, 15), Position::new(0, 36));
+        editor.cursor_manager_mut().add_cursor(Position::new(0, 8));
 
-        assert_eq!(editor.buffer().text(), "Hello, World!");
-        assert!(editor.state().is_dirty);
-        assert_eq!(editor.state().char_count, 13);
+        assert!(editor
+            .transform_selections(TextTransform::SnakeCase)
+            .unwrap());
+        assert_eq!(
+            editor.buffer().text(),
+            "let user_name = parse_http_request(raw);\nlet s = \"a\\\"b\";"
+        );
+        let cursors = editor.cursor_manager().cursors();
+        assert_eq!(cursors[0].position, Position::new(0, 6));
+        assert_eq!(cursors[1].position, Position::new(0, 8));
+        assert_eq!(
+            cursors[2].selection_range(),
+            Range::new(Position::new(0, 16), Position::new(0, 39))
+        );
+        assert!(!editor
+            .transform_selections(TextTransform::SnakeCase)
+            .unwrap());
+
+        editor
+            .cursor_manager_mut()
+            .restore_cursors(vec![Cursor::with_selection(
+                0,
+                Position::new(1, 8),
+                Position::new(1, 14),
+            )]);
+        assert!(editor
+            .transform_selections(TextTransform::ToRawString)
+            .unwrap());
+        assert_eq!(
+            editor.buffer().text(),
+            "let user_name = parse_http_request(raw);\nlet s = r#\"a\"b\"#;"
+        );
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().selection_range(),
+            Range::new(Position::new(1, 8), Position::new(1, 16))
+        );
+
+        // Text that can't be unescaped is an error and changes nothing
+        editor.select_all().unwrap();
+        editor.insert_text("bad \\q").unwrap();
+        editor.select_all().unwrap();
+        assert!(editor
+            .transform_selections(TextTransform::UnescapeString)
+            .is_err());
+        assert_eq!(editor.buffer().text(), "bad \\q");
     }
 
     #[test]
-    fn test_cursor_movement() {
+    fn test_sort_reverse_and_delete_lines() {
         let mut editor = Editor::new();
-        editor.insert_text("Line 1\nLine 2\nLine 3").unwrap();
+        editor.insert_text("item10\nItem2\nitem2\nitem1\n").unwrap();
+
+        // Without a selection the whole buffer is sorted, keeping the last line
+        assert!(editor.sort_lines(SortOptions::default()).unwrap());
+        assert_eq!(editor.buffer().text(), "Item2\nitem1\nitem10\nitem2\n");
+        let natural = SortOptions {
+            natural: true,
+            case_insensitive: true,
+            unique: true,
+        };
+        assert!(editor.sort_lines(natural).unwrap());
+        assert_eq!(editor.buffer().text(), "item1\nItem2\nitem10\n");
 
-        // Move down
-        editor
-            .move_cursors(Direction::Down, MovementUnit::Line, false)
-            .unwrap();
+        assert!(editor.reverse_lines().unwrap());
+        assert_eq!(editor.buffer().text(), "item10\nItem2\nitem1\n");
+
+        editor.goto_position(Position::new(2, 2)).unwrap();
+        assert!(editor.transpose_lines().unwrap());
+        assert_eq!(editor.buffer().text(), "item10\nitem1\nItem2\n");
         assert_eq!(
             editor.cursor_manager().primary_cursor().position,
-            Position::new(2, 0)
+            Position::new(3, 0)
         );
 
-        // Test invalid line number
-        assert!(editor.goto_line(0).is_err());
-        assert!(editor.goto_line(100).is_err());
+        editor.goto_position(Position::new(0, 3)).unwrap();
+        editor.cursor_manager_mut().add_cursor(Position::new(2, 1));
+        assert!(editor.delete_lines().unwrap());
+        assert_eq!(editor.buffer().text(), "item1\n");
+        assert_eq!(
+            editor.cursor_manager().cursor_positions(),
+            vec![Position::new(0, 3), Position::new(1, 0)]
+        );
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "item10\nitem1\nItem2\n");
     }
 
     #[test]
-    fn test_replace_operations() {
+    fn test_search_spans_windows() {
         let mut editor = Editor::new();
-        editor.insert_text("Hello World\nHello Rust").unwrap();
+        let line = "needle haystack\n";
+        let count = SEARCH_WINDOW_BYTES / line.len() * 2;
+        editor.insert_text(&line.repeat(count)).unwrap();
 
         let options = SearchOptions {
-            query: "Hello".to_string(),
+            query: "haystack\nneedle".to_string(),
             case_sensitive: true,
             whole_word: false,
             use_regex: false,
             forward: true,
             wrap_around: true,
         };
+        let results = editor.search(options.clone()).unwrap();
+        assert_eq!(results.len(), count - 1);
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].range.start.line + 1 == pair[1].range.start.line));
+
+        let results = editor
+            .search(SearchOptions {
+                query: "NEEDLE".to_string(),
+                case_sensitive: false,
+                ..options
+            })
+            .unwrap();
+        assert_eq!(results.len(), count);
+        assert_eq!(results[count - 1].match_index, count - 1);
+    }
 
-        editor.search(options).unwrap();
-        let replaced = editor.replace_all("Hi").unwrap();
+    #[tokio::test]
+    async fn test_load_drops_syntax_of_previous_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let first = temp_dir.path().join("first.rs");
+        // No language is detected for the second file
+        let second = temp_dir.path().join("second");
+        std::fs::write(&first, "fn first() {}\n").unwrap();
+        std::fs::write(&second, "struct Second;\n").unwrap();
 
-        assert_eq!(replaced, 2);
-        assert_eq!(editor.buffer().text(), "Hi World\nHi Rust");
+        let mut editor = Editor::new();
+        editor.load_file(&first).await.unwrap();
+        assert!(editor
+            .get_all_tokens()
+            .unwrap()
+            .iter()
+            .any(|token| token.token.text == "first"));
+
+        editor.load_file(&second).await.unwrap();
+        assert!(editor
+            .get_all_tokens()
+            .unwrap()
+            .iter()
+            .any(|token| token.token.text == "Second"));
     }
 
-    #[test]
-    fn test_readonly_mode() {
-        let mut editor = Editor::new();
-        editor.set_readonly(true);
+    #[tokio::test]
+    async fn test_load_keeps_cursor_settings() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {}\n").unwrap();
 
-        let result = editor.insert_text("Hello");
-        assert!(result.is_err());
+        let mut editor = Editor::with_config(EditorConfig {
+            tab_size: 8,
+            word_wrap: true,
+            ..EditorConfig::default()
+        });
+        let layout = editor.cursor_manager().wrap_layout();
+        editor.load_file(&path).await.unwrap();
+
+        assert_eq!(editor.cursor_manager().wrap_layout(), layout);
     }
 
-    #[test]
-    fn test_performance_metrics() {
-        let mut editor = Editor::new();
+    #[tokio::test]
+    async fn test_large_file_mode_degrades_features() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("generated.rs");
+        std::fs::write(&path, "let a = 1;\nlet b = a;\nlet c = b;\n").unwrap();
 
-        // Perform some operations
-        editor.insert_text("Hello").unwrap();
-        editor.insert_text(" World").unwrap();
+        let mut editor = Editor::with_config(EditorConfig {
+            large_file: LargeFileConfig {
+                threshold_mb: 0,
+                max_highlight_mb: 0,
+                max_search_results: 2,
+            },
+            ..EditorConfig::default()
+        });
+        editor.load_file(&path).await.unwrap();
+
+        assert!(editor.state().large_file);
+        assert!(!editor.highlighting_enabled());
+        assert!(editor.get_all_tokens().unwrap().is_empty());
+
+        let results = editor
+            .search(SearchOptions {
+                query: "let".to_string(),
+                case_sensitive: true,
+                whole_word: true,
+                use_regex: false,
+                forward: true,
+                wrap_around: true,
+            })
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].range.start, Position::new(1, 0));
 
-        let metrics = editor.metrics();
-        assert!(metrics.operation_count > 0);
-        assert!(metrics.last_operation_time > Duration::ZERO);
+        // Clean large buffers reload, dirty ones are left alone
+        editor.goto_line(3).unwrap();
+        let change = editor
+            .reconcile_with_disk("let a = 1;\nlet b = a;\nlet c = b;\n")
+            .unwrap();
+        assert!(matches!(change, ExternalChange::Unchanged));
+
+        let version = editor.buffer().version();
+        let change = editor.reconcile_with_disk("let a = 2;\n").unwrap();
+        assert!(matches!(change, ExternalChange::Reloaded));
+        assert_eq!(editor.buffer().text(), "let a = 2;\n");
+        assert!(editor.buffer().version() > version);
+        assert!(!editor.buffer().can_undo());
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(1, 0)
+        );
+
+        editor.insert_text("// ").unwrap();
+        let change = editor.reconcile_with_disk("let a = 3;\n").unwrap();
+        assert!(matches!(change, ExternalChange::Diverged));
+        assert!(editor.buffer().is_dirty());
     }
 
     #[test]
-    fn test_view_state_management() {
+    fn test_macro_record_and_replay() {
         let mut editor = Editor::new();
+        editor.insert_text("a\nb\nc\nd\n").unwrap();
+        editor.goto_position(Position::zero()).unwrap();
 
-        let view_state = ViewState {
-            scroll_top: 10,
-            scroll_left: 50.0,
-            visible_lines: 30,
-            viewport_width: 1000.0,
-            viewport_height: 800.0,
-        };
+        editor.start_macro_recording("bullet");
+        for command in [
+            EditorCommand::InsertText {
+                text: "- ".to_string(),
+            },
+            EditorCommand::MoveCursors {
+                direction: Direction::Down,
+                unit: MovementUnit::Line,
+                extend_selection: false,
+            },
+            EditorCommand::MoveCursors {
+                direction: Direction::Left,
+                unit: MovementUnit::Word,
+                extend_selection: false,
+            },
+        ] {
+            editor.execute(command).unwrap();
+        }
+        let recorded = editor.stop_macro_recording().unwrap();
+        assert_eq!(recorded.commands.len(), 3);
+        assert!(!editor.is_recording_macro());
 
-        editor.update_view_state(view_state.clone());
-        assert_eq!(editor.view_state().scroll_top, 10);
-        assert_eq!(editor.view_state().visible_lines, 30);
+        // The serialized form is what gets persisted
+        let json = serde_json::to_string(&recorded).unwrap();
+        assert!(json.contains(r#""command":"insert_text""#));
+        assert_eq!(serde_json::from_str::<Macro>(&json).unwrap(), recorded);
+
+        editor.play_macro(&recorded, 2).unwrap();
+        assert_eq!(editor.buffer().text(), "- a\n- b\n- c\nd\n");
+
+        // All repetitions undo together
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "- a\nb\nc\nd\n");
     }
 
     #[test]
-    fn test_scroll_to_position() {
+    fn test_macro_leaves_out_undo_and_redo() {
         let mut editor = Editor::new();
+        editor.insert_text("text\n").unwrap();
 
-        // Create a document with many lines
-        let lines: Vec<String> = (0..100).map(|i| format!("Line {}", i)).collect();
-        editor.insert_text(&lines.join("\n")).unwrap();
+        editor.start_macro_recording("undo");
+        for command in [
+            EditorCommand::InsertText {
+                text: "a".to_string(),
+            },
+            EditorCommand::Undo,
+            EditorCommand::Redo,
+        ] {
+            editor.execute(command).unwrap();
+        }
+        let recorded = editor.stop_macro_recording().unwrap();
+        assert_eq!(
+            recorded.commands,
+            [EditorCommand::InsertText {
+                text: "a".to_string()
+            }]
+        );
 
-        // Scroll to line 50
-        editor.scroll_to_position(Position::new(50, 0));
+        // Macros saved elsewhere may still contain them
+        let mut saved = recorded.clone();
+        saved.commands.push(EditorCommand::Undo);
+        editor.play_macro(&saved, 1).unwrap();
+        assert_eq!(editor.buffer().text(), "text\naa");
 
-        let scroll_info = editor.get_scroll_info();
-        assert!(scroll_info.visible_range.start.line <= 50);
-        assert!(scroll_info.visible_range.end.line > 50);
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "text\na");
     }
 
     #[test]
-    fn test_auto_indentation() {
-        let mut editor = Editor::with_config(EditorConfig {
-            auto_indent: true,
-            tab_size: 4,
-            use_tabs: false,
-            ..EditorConfig::default()
-        });
+    fn test_macro_on_every_cursor() {
+        let mut editor = Editor::new();
+        editor.insert_text("one\ntwo\nthree\n").unwrap();
+        editor.restore_cursors(vec![
+            Cursor::new(0, Position::new(2, 0)),
+            Cursor::new(1, Position::new(0, 0)),
+        ]);
+
+        let heading = Macro {
+            name: "heading".to_string(),
+            commands: vec![EditorCommand::InsertText {
+                text: "# \n".to_string(),
+            }],
+        };
+        editor.play_macro_on_cursors(&heading).unwrap();
 
-        editor.insert_text("    fn main() {").unwrap();
-        editor.type_char('\n').unwrap();
+        // The second run starts where the first cursor's line moved to
+        assert_eq!(editor.buffer().text(), "# \none\ntwo\n# \nthree\n");
+        assert_eq!(
+            editor.cursor_manager().cursor_positions(),
+            [Position::new(1, 0), Position::new(4, 0)]
+        );
 
-        let current_line = editor.current_line().unwrap();
-        assert!(current_line.starts_with("    ")); // Should maintain indentation
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer().text(), "one\ntwo\nthree\n");
     }
 
     #[test]
-    fn test_auto_close_brackets() {
-        let mut editor = Editor::with_config(EditorConfig {
-            auto_close_brackets: true,
-            ..EditorConfig::default()
-        });
-
-        editor.type_char('(').unwrap();
-        assert_eq!(editor.buffer().text(), "()");
-
-        // Cursor should be between brackets
-        assert_eq!(
-            editor.cursor_manager().primary_cursor().position,
-            Position::new(0, 1)
-        );
+    fn test_split_views_share_the_buffer() {
+        let mut editor = Editor::new();
+        editor
+            .syntax_highlighter_mut()
+            .highlighter_mut()
+            .set_language("rust")
+            .unwrap();
+        let text = "fn a() {\n    run();\n}\n";
+        editor.insert_text(text).unwrap();
+        editor.goto_position(Position::new(1, 4)).unwrap();
+        let position = |editor: &Editor| editor.cursor_manager().primary_cursor().position;
+
+        let first = editor.active_view();
+        assert_eq!(first, editor.id());
+        let second = editor.split_view().unwrap();
+        assert_eq!(editor.view_ids(), [first, second]);
+        assert!(editor.activate_view(second));
+        assert_eq!(position(&editor), Position::new(1, 4));
+
+        // Each view has its own cursors and folds
+        assert!(editor.fold(0).unwrap());
+        editor.goto_position(Position::new(0, 0)).unwrap();
+        editor.insert_text("// a\n").unwrap();
+        assert!(editor.activate_view(first));
+        assert!(editor.folded_ranges().is_empty());
+        // Edits made in the other view move the cursors with the text
+        assert_eq!(position(&editor), Position::new(2, 4));
+        editor.insert_text("x").unwrap();
+
+        assert!(editor.activate_view(second));
+        assert_eq!(position(&editor), Position::new(1, 0));
+        assert_eq!(editor.buffer().text(), "// a\nfn a() {\n    xrun();\n}\n");
+        assert_eq!(editor.folded_ranges()[0].start_line, 1);
+
+        // Undo goes through the shared history and reaches every view
+        assert!(editor.undo().unwrap());
+        assert!(editor.undo().unwrap());
+        assert!(editor.activate_view(first));
+        assert_eq!(editor.buffer().text(), text);
+        assert_eq!(position(&editor), Position::new(1, 4));
+
+        assert!(editor.close_view(first));
+        assert_eq!(editor.view_ids(), [second]);
+        assert!(!editor.close_view(second));
+        assert!(!editor.activate_view(first));
     }
 
     #[test]
-    fn test_regex_search() {
+    fn test_split_views_move_with_each_edit() {
+        struct ViewListener(std::sync::Mutex<Vec<(Uuid, Vec<Position>)>>);
+        impl EditorEventListener for ViewListener {
+            fn on_view_moved(&self, view_id: Uuid, positions: &[Position]) {
+                self.0.lock().unwrap().push((view_id, positions.to_vec()));
+            }
+        }
+
+        let listener = Arc::new(ViewListener(std::sync::Mutex::new(Vec::new())));
         let mut editor = Editor::new();
-        editor.insert_text("test123\ntest456\nabc789").unwrap();
+        editor.add_event_listener(listener.clone());
+        editor.insert_text("one\ntwo\n").unwrap();
+        editor.goto_position(Position::new(1, 0)).unwrap();
+        let second = editor.split_view().unwrap();
 
-        let options = SearchOptions {
-            query: r"test\d+".to_string(),
-            case_sensitive: true,
-            whole_word: false,
-            use_regex: true,
-            forward: true,
-            wrap_around: true,
-        };
+        editor.goto_position(Position::new(0, 0)).unwrap();
+        editor.insert_text("zero\n").unwrap();
+        editor.insert_text("\n").unwrap();
 
-        let results = editor.search(options).unwrap();
-        assert_eq!(results.len(), 2);
-        assert_eq!(results[0].text, "test123");
-        assert_eq!(results[1].text, "test456");
+        // Both edits reached the other view before it was activated again
+        let moved = listener.0.lock().unwrap().clone();
+        assert_eq!(
+            moved,
+            [
+                (second, vec![Position::new(2, 0)]),
+                (second, vec![Position::new(3, 0)])
+            ]
+        );
+        assert!(editor.activate_view(second));
+        assert_eq!(
+            editor.cursor_manager().primary_cursor().position,
+            Position::new(3, 0)
+        );
     }
 }
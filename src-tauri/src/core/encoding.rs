@@ -0,0 +1,404 @@
+// src-tauri/src/core/encoding.rs
+use crate::core::{EditorError, EditorResult};
+use encoding_rs::DecoderResult;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{ErrorKind, Read};
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Number of leading bytes inspected when guessing an encoding without a BOM
+pub(crate) const SNIFF_LEN: usize = 4096;
+
+/// Size of the chunks files are decoded in by [`FileEncoding::decode_reader`]
+const DECODE_CHUNK_SIZE: usize = 1 << 20;
+
+/// Character encodings a file can be read and written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, mapping every byte to the code point of the same value
+    Latin1,
+    Windows1252,
+}
+
+impl TextEncoding {
+    /// Byte order mark written at the start of files in this encoding
+    pub fn bom(&self) -> Option<&'static [u8]> {
+        match self {
+            TextEncoding::Utf8 => Some(UTF8_BOM),
+            TextEncoding::Utf16Le => Some(UTF16LE_BOM),
+            TextEncoding::Utf16Be => Some(UTF16BE_BOM),
+            TextEncoding::Latin1 | TextEncoding::Windows1252 => None,
+        }
+    }
+
+    /// Streaming codec for this encoding, `None` for Latin-1 which maps bytes directly
+    fn codec(&self) -> Option<&'static encoding_rs::Encoding> {
+        match self {
+            TextEncoding::Utf8 => Some(encoding_rs::UTF_8),
+            TextEncoding::Utf16Le => Some(encoding_rs::UTF_16LE),
+            TextEncoding::Utf16Be => Some(encoding_rs::UTF_16BE),
+            TextEncoding::Latin1 => None,
+            TextEncoding::Windows1252 => Some(encoding_rs::WINDOWS_1252),
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16Le => "UTF-16 LE",
+            TextEncoding::Utf16Be => "UTF-16 BE",
+            TextEncoding::Latin1 => "ISO-8859-1",
+            TextEncoding::Windows1252 => "Windows-1252",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Encoding of a file on disk, including whether it starts with a BOM
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileEncoding {
+    pub encoding: TextEncoding,
+    pub bom: bool,
+}
+
+impl FileEncoding {
+    pub fn new(encoding: TextEncoding, bom: bool) -> Self {
+        Self {
+            encoding,
+            bom: bom && encoding.bom().is_some(),
+        }
+    }
+
+    /// Detect the encoding of file contents
+    ///
+    /// A BOM decides the encoding outright. Otherwise text with NUL bytes in
+    /// every other position is taken as BOM-less UTF-16, valid UTF-8 as UTF-8,
+    /// and anything else as a single-byte legacy encoding.
+    pub fn detect(bytes: &[u8]) -> Self {
+        Self::detect_impl(bytes, true)
+    }
+
+    /// Detect the encoding of a file from its first bytes
+    pub fn detect_prefix(bytes: &[u8]) -> Self {
+        Self::detect_impl(bytes, false)
+    }
+
+    fn detect_impl(bytes: &[u8], complete: bool) -> Self {
+        for encoding in [
+            TextEncoding::Utf8,
+            TextEncoding::Utf16Le,
+            TextEncoding::Utf16Be,
+        ] {
+            if encoding.bom().is_some_and(|bom| bytes.starts_with(bom)) {
+                return Self::new(encoding, true);
+            }
+        }
+
+        // UTF-16 encoded ASCII is also valid UTF-8, so it is checked first
+        if let Some(encoding) = sniff_utf16(bytes) {
+            return Self::new(encoding, false);
+        }
+
+        let is_utf8 = match std::str::from_utf8(bytes) {
+            Ok(_) => true,
+            // A prefix may end partway through a character
+            Err(e) => !complete && e.error_len().is_none(),
+        };
+        if is_utf8 {
+            return Self::new(TextEncoding::Utf8, false);
+        }
+
+        // Bytes 0x80-0x9F are control characters in Latin-1 but printable in Windows-1252
+        if bytes.iter().any(|&b| (0x80..=0x9F).contains(&b)) {
+            Self::new(TextEncoding::Windows1252, false)
+        } else {
+            Self::new(TextEncoding::Latin1, false)
+        }
+    }
+
+    /// Decode file contents, dropping a leading BOM of this encoding
+    pub fn decode(&self, bytes: &[u8]) -> EditorResult<String> {
+        let bytes = match self.encoding.bom() {
+            Some(bom) => bytes.strip_prefix(bom).unwrap_or(bytes),
+            None => bytes,
+        };
+
+        match self.encoding {
+            TextEncoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|e| {
+                self.error(format!(
+                    "invalid byte at offset {}",
+                    e.utf8_error().valid_up_to()
+                ))
+            }),
+            TextEncoding::Utf16Le => self.decode_utf16(bytes, u16::from_le_bytes),
+            TextEncoding::Utf16Be => self.decode_utf16(bytes, u16::from_be_bytes),
+            TextEncoding::Latin1 => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+            TextEncoding::Windows1252 => Ok(encoding_rs::WINDOWS_1252
+                .decode_without_bom_handling(bytes)
+                .0
+                .into_owned()),
+        }
+    }
+
+    /// Decode file contents from a reader in chunks, passing each decoded chunk to `sink`
+    ///
+    /// Never holds more than one chunk of the input and output in memory.
+    pub fn decode_reader<R: Read>(
+        &self,
+        mut reader: R,
+        mut sink: impl FnMut(&str),
+    ) -> EditorResult<()> {
+        let mut decoder = self
+            .encoding
+            .codec()
+            .map(|codec| codec.new_decoder_with_bom_removal());
+        let mut input = vec![0; DECODE_CHUNK_SIZE];
+        let mut output = String::new();
+        let mut offset = 0;
+
+        loop {
+            let read = match reader.read(&mut input) {
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let last = read == 0;
+            output.clear();
+
+            match &mut decoder {
+                Some(decoder) => {
+                    let mut chunk = &input[..read];
+                    loop {
+                        output.reserve(
+                            decoder
+                                .max_utf8_buffer_length_without_replacement(chunk.len())
+                                .unwrap_or(chunk.len() * 3 + 16),
+                        );
+                        let (result, consumed) =
+                            decoder.decode_to_string_without_replacement(chunk, &mut output, last);
+                        chunk = &chunk[consumed..];
+
+                        match result {
+                            DecoderResult::InputEmpty => break,
+                            DecoderResult::OutputFull => continue,
+                            DecoderResult::Malformed(..) => {
+                                let position = offset + read - chunk.len();
+                                return Err(
+                                    self.error(format!("invalid byte at offset {}", position))
+                                );
+                            }
+                        }
+                    }
+                }
+                None => output.extend(input[..read].iter().map(|&b| char::from(b))),
+            }
+
+            sink(&output);
+            if last {
+                return Ok(());
+            }
+            offset += read;
+        }
+    }
+
+    /// Encode text for writing to disk, with a BOM if this encoding has one
+    pub fn encode(&self, text: &str) -> EditorResult<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+        if self.bom {
+            bytes.extend_from_slice(self.encoding.bom().unwrap_or_default());
+        }
+
+        match self.encoding {
+            TextEncoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+            TextEncoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            TextEncoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+            TextEncoding::Latin1 => {
+                for ch in text.chars() {
+                    let byte = u8::try_from(ch).map_err(|_| self.unmappable(ch))?;
+                    bytes.push(byte);
+                }
+            }
+            TextEncoding::Windows1252 => {
+                let (encoded, _, had_unmappable) = encoding_rs::WINDOWS_1252.encode(text);
+                if had_unmappable {
+                    let ch = text
+                        .chars()
+                        .find(|&ch| {
+                            let mut buf = [0; 4];
+                            encoding_rs::WINDOWS_1252.encode(ch.encode_utf8(&mut buf)).2
+                        })
+                        .unwrap_or(char::REPLACEMENT_CHARACTER);
+                    return Err(self.unmappable(ch));
+                }
+                bytes.extend_from_slice(&encoded);
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn decode_utf16(&self, bytes: &[u8], read: fn([u8; 2]) -> u16) -> EditorResult<String> {
+        if !bytes.len().is_multiple_of(2) {
+            return Err(self.error("odd number of bytes".to_string()));
+        }
+
+        let units = bytes.chunks_exact(2).map(|pair| read([pair[0], pair[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|e| {
+                self.error(format!(
+                    "unpaired surrogate {:#06x}",
+                    e.unpaired_surrogate()
+                ))
+            })
+    }
+
+    fn unmappable(&self, ch: char) -> EditorError {
+        EditorError::EncodingError(format!(
+            "'{}' (U+{:04X}) can't be saved as {}",
+            ch, ch as u32, self.encoding
+        ))
+    }
+
+    fn error(&self, reason: String) -> EditorError {
+        EditorError::EncodingError(format!("Not valid {}: {}", self.encoding, reason))
+    }
+}
+
+impl fmt::Display for FileEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.bom {
+            write!(f, "{} with BOM", self.encoding)
+        } else {
+            write!(f, "{}", self.encoding)
+        }
+    }
+}
+
+/// Guess BOM-less UTF-16 from NUL bytes in every other position, as in mostly ASCII text
+fn sniff_utf16(bytes: &[u8]) -> Option<TextEncoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN) & !1];
+    let pairs = sample.len() / 2;
+    if pairs == 0 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let (mut even_nuls, mut odd_nuls) = (0, 0);
+    for pair in sample.chunks_exact(2) {
+        even_nuls += usize::from(pair[0] == 0);
+        odd_nuls += usize::from(pair[1] == 0);
+    }
+
+    let mostly = |count: usize| count * 10 >= pairs * 7;
+    let rarely = |count: usize| count * 10 <= pairs;
+
+    if mostly(odd_nuls) && rarely(even_nuls) {
+        Some(TextEncoding::Utf16Le)
+    } else if mostly(even_nuls) && rarely(odd_nuls) {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_bom() {
+        assert_eq!(
+            FileEncoding::detect(b"\xEF\xBB\xBFfn main() {}"),
+            FileEncoding::new(TextEncoding::Utf8, true)
+        );
+        assert_eq!(
+            FileEncoding::detect(b"\xFF\xFEh\0i\0"),
+            FileEncoding::new(TextEncoding::Utf16Le, true)
+        );
+        assert_eq!(
+            FileEncoding::detect(b"\xFE\xFF\0h\0i"),
+            FileEncoding::new(TextEncoding::Utf16Be, true)
+        );
+    }
+
+    #[test]
+    fn test_detect_without_bom() {
+        assert_eq!(
+            FileEncoding::detect("héllo\n".as_bytes()),
+            FileEncoding::default()
+        );
+        assert_eq!(
+            FileEncoding::detect(b"h\0e\0l\0l\0o\0\n\0"),
+            FileEncoding::new(TextEncoding::Utf16Le, false)
+        );
+        assert_eq!(
+            FileEncoding::detect(b"caf\xE9\n"),
+            FileEncoding::new(TextEncoding::Latin1, false)
+        );
+        assert_eq!(
+            FileEncoding::detect(b"\x93quoted\x94\n"),
+            FileEncoding::new(TextEncoding::Windows1252, false)
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "// café\r\nfn main() {}\n";
+        for encoding in [
+            FileEncoding::new(TextEncoding::Utf8, true),
+            FileEncoding::new(TextEncoding::Utf16Le, true),
+            FileEncoding::new(TextEncoding::Utf16Be, false),
+            FileEncoding::new(TextEncoding::Latin1, false),
+            FileEncoding::new(TextEncoding::Windows1252, false),
+        ] {
+            let bytes = encoding.encode(text).unwrap();
+            assert_eq!(encoding.decode(&bytes).unwrap(), text, "{}", encoding);
+        }
+    }
+
+    #[test]
+    fn test_decode_reader_matches_decode() {
+        let text = "fn main() {\n    println!(\"h\u{e9}llo \u{1f980}\");\n}\n".repeat(20_000);
+        for encoding in [
+            FileEncoding::new(TextEncoding::Utf8, true),
+            FileEncoding::new(TextEncoding::Utf16Be, true),
+        ] {
+            let bytes = encoding.encode(&text).unwrap();
+            assert_eq!(FileEncoding::detect_prefix(&bytes[..SNIFF_LEN]), encoding);
+
+            let mut decoded = String::new();
+            encoding
+                .decode_reader(bytes.as_slice(), |chunk| decoded.push_str(chunk))
+                .unwrap();
+            assert_eq!(decoded, text, "{}", encoding);
+        }
+
+        let result = FileEncoding::default().decode_reader(&b"ok\xFF"[..], |_| {});
+        assert!(matches!(result, Err(EditorError::EncodingError(_))));
+    }
+
+    #[test]
+    fn test_unmappable_characters() {
+        let latin1 = FileEncoding::new(TextEncoding::Latin1, false);
+        assert!(matches!(
+            latin1.encode("price: €5"),
+            Err(EditorError::EncodingError(_))
+        ));
+        assert_eq!(
+            FileEncoding::new(TextEncoding::Windows1252, false)
+                .encode("€5")
+                .unwrap(),
+            b"\x805"
+        );
+        assert!(FileEncoding::default().decode(b"caf\xE9").is_err());
+    }
+}
@@ -0,0 +1,332 @@
+// src-tauri/src/core/folding.rs
+//! Folding ranges
+//!
+//! Ranges come from the syntax tree (items, bracketed blocks, runs of imports
+//! and runs of comments) and from `region`/`endregion` marker comments. A
+//! range keeps its first line visible and hides the lines after it, leaving a
+//! closing bracket on its own line visible too.
+
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, Tree};
+
+/// What a folding range covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FoldKind {
+    /// A function, type, impl or other item
+    Item,
+    /// The inside of a bracketed block, list or argument list
+    Block,
+    /// Consecutive `use` or import statements
+    Imports,
+    /// Consecutive comment lines or a multi-line comment
+    Comment,
+    /// Lines between `region` and `endregion` marker comments
+    Region,
+}
+
+/// Lines that can be folded under a header line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FoldingRange {
+    /// Line that stays visible when folded
+    pub start_line: usize,
+    /// Last line hidden when folded
+    pub end_line: usize,
+    pub kind: FoldKind,
+}
+
+impl FoldingRange {
+    pub fn new(start_line: usize, end_line: usize, kind: FoldKind) -> Self {
+        Self {
+            start_line,
+            end_line,
+            kind,
+        }
+    }
+
+    /// Whether `line` is the header or one of the hidden lines
+    pub fn contains_line(&self, line: usize) -> bool {
+        self.start_line <= line && line <= self.end_line
+    }
+
+    /// Whether `other` lies entirely inside this range
+    pub fn contains(&self, other: &FoldingRange) -> bool {
+        self.start_line <= other.start_line && other.end_line <= self.end_line
+    }
+}
+
+/// All folding ranges of a document, sorted by start line
+///
+/// Only one range starts on each line; the largest one wins.
+pub fn folding_ranges(tree: Option<&Tree>, text: &str) -> Vec<FoldingRange> {
+    let mut ranges = region_folding_ranges(text);
+    if let Some(tree) = tree {
+        ranges.extend(syntax_folding_ranges(tree));
+    }
+
+    ranges.sort_by(|a, b| {
+        a.start_line
+            .cmp(&b.start_line)
+            .then(b.end_line.cmp(&a.end_line))
+    });
+    ranges.dedup_by_key(|range| range.start_line);
+    ranges
+}
+
+/// Nesting level of each range, starting at 1 for top-level ranges
+///
+/// `ranges` must be sorted by start line, as [`folding_ranges`] returns them.
+pub fn fold_levels(ranges: &[FoldingRange]) -> Vec<usize> {
+    let mut open: Vec<&FoldingRange> = Vec::new();
+    ranges
+        .iter()
+        .map(|range| {
+            while open.last().is_some_and(|outer| !outer.contains(range)) {
+                open.pop();
+            }
+            open.push(range);
+            open.len()
+        })
+        .collect()
+}
+
+/// Folding ranges from the syntax tree
+pub fn syntax_folding_ranges(tree: &Tree) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut stack = vec![tree.root_node()];
+
+    while let Some(node) = stack.pop() {
+        let mut walker = node.walk();
+        let children: Vec<Node> = node.named_children(&mut walker).collect();
+
+        // Runs of imports or comments on consecutive lines fold together
+        let mut i = 0;
+        while i < children.len() {
+            let Some(kind) = run_kind(&children[i]) else {
+                i += 1;
+                continue;
+            };
+
+            let mut end = i + 1;
+            while end < children.len()
+                && run_kind(&children[end]) == Some(kind)
+                && children[end].start_position().row <= last_row(&children[end - 1]) + 1
+            {
+                end += 1;
+            }
+
+            let start_line = children[i].start_position().row;
+            let end_line = last_row(&children[end - 1]);
+            if end_line > start_line {
+                ranges.push(FoldingRange::new(start_line, end_line, kind));
+            }
+            i = end;
+        }
+
+        for child in children.iter().rev() {
+            if let Some(range) = node_folding_range(child) {
+                ranges.push(range);
+            }
+            stack.push(*child);
+        }
+    }
+
+    ranges
+}
+
+/// Folding ranges between `region` and `endregion` marker comments
+///
+/// Markers may follow `//`, `#`, `--`, `;`, `/*` or `<!--`, as in
+/// `// region Setup`, `#region` or `// #endregion`. The end marker is hidden
+/// along with the region.
+pub fn region_folding_ranges(text: &str) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut open = Vec::new();
+
+    for (line, content) in text.lines().enumerate() {
+        match region_marker(content) {
+            Some(RegionMarker::Start) => open.push(line),
+            Some(RegionMarker::End) => {
+                if let Some(start_line) = open.pop() {
+                    ranges.push(FoldingRange::new(start_line, line, FoldKind::Region));
+                }
+            }
+            None => {}
+        }
+    }
+
+    ranges
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RegionMarker {
+    Start,
+    End,
+}
+
+fn region_marker(line: &str) -> Option<RegionMarker> {
+    let line = line.trim_start();
+    let rest = ["<!--", "//", "/*", "--", "#", ";"]
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))?
+        .trim_start();
+    let rest = rest.strip_prefix('#').unwrap_or(rest);
+
+    if starts_with_word(rest, "endregion") {
+        Some(RegionMarker::End)
+    } else if starts_with_word(rest, "region") {
+        Some(RegionMarker::Start)
+    } else {
+        None
+    }
+}
+
+fn starts_with_word(text: &str, word: &str) -> bool {
+    text.strip_prefix(word)
+        .is_some_and(|rest| !rest.starts_with(|ch: char| ch.is_alphanumeric() || ch == '_'))
+}
+
+/// Kind of run a node can be part of
+fn run_kind(node: &Node) -> Option<FoldKind> {
+    let kind = node.kind();
+    if kind.contains("comment") {
+        Some(FoldKind::Comment)
+    } else if is_import(kind) {
+        Some(FoldKind::Imports)
+    } else {
+        None
+    }
+}
+
+fn is_import(kind: &str) -> bool {
+    kind == "use_declaration" || kind.starts_with("import")
+}
+
+/// Folding range of a single item or bracketed node
+fn node_folding_range(node: &Node) -> Option<FoldingRange> {
+    let kind = node.kind();
+    if run_kind(node).is_some() {
+        return None;
+    }
+
+    let fold_kind = if kind.ends_with("_item")
+        || kind.ends_with("_definition")
+        || kind.ends_with("_declaration")
+    {
+        FoldKind::Item
+    } else if node
+        .child(0)
+        .is_some_and(|child| matches!(child.kind(), "{" | "(" | "["))
+    {
+        FoldKind::Block
+    } else {
+        return None;
+    };
+
+    let start_line = node.start_position().row;
+    let end_line = match closing_leaf(*node) {
+        // Keep a closing bracket on its own line visible
+        leaf if matches!(leaf.kind(), "}" | ")" | "]")
+            && leaf.start_position().row > start_line =>
+        {
+            leaf.start_position().row - 1
+        }
+        _ => last_row(node),
+    };
+
+    (end_line > start_line).then(|| FoldingRange::new(start_line, end_line, fold_kind))
+}
+
+/// Last leaf of a node, ignoring trailing semicolons and commas
+fn closing_leaf(mut node: Node) -> Node {
+    loop {
+        let mut walker = node.walk();
+        let last = node
+            .children(&mut walker)
+            .filter(|child| !matches!(child.kind(), ";" | ","))
+            .last();
+        match last {
+            Some(child) => node = child,
+            None => return node,
+        }
+    }
+}
+
+/// Last line a node has text on
+fn last_row(node: &Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::Parser;
+
+    const SOURCE: &str = "\
+use std::fs;
+use std::io::{
+    Read,
+};
+
+// Reads things
+// from disk
+fn main() {
+    let data = read(
+        \"a\",
+    );
+    if true {
+        run();
+    }
+}
+
+// region Helpers
+fn short() {}
+// endregion
+";
+
+    fn parse(text: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .unwrap();
+        parser.parse(text, None).unwrap()
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let tree = parse(SOURCE);
+        let ranges = folding_ranges(Some(&tree), SOURCE);
+
+        assert_eq!(
+            ranges,
+            [
+                FoldingRange::new(0, 3, FoldKind::Imports),
+                FoldingRange::new(1, 2, FoldKind::Block),
+                FoldingRange::new(5, 6, FoldKind::Comment),
+                FoldingRange::new(7, 13, FoldKind::Item),
+                FoldingRange::new(8, 9, FoldKind::Item),
+                FoldingRange::new(11, 12, FoldKind::Block),
+                FoldingRange::new(16, 18, FoldKind::Region),
+            ]
+        );
+        assert_eq!(fold_levels(&ranges), [1, 2, 1, 1, 2, 2, 1]);
+    }
+
+    #[test]
+    fn test_region_markers() {
+        let text = "#region outer\n# region inner\nx\n# endregion\n#endregion\n// endregion";
+        assert_eq!(
+            folding_ranges(None, text),
+            [
+                FoldingRange::new(0, 4, FoldKind::Region),
+                FoldingRange::new(1, 3, FoldKind::Region),
+            ]
+        );
+        assert!(region_folding_ranges("// regional\n// endregion").is_empty());
+    }
+}
@@ -0,0 +1,365 @@
+// src-tauri/src/core/formatter.rs
+//! Buffer formatting with rustfmt
+//!
+//! Buffers are fed to rustfmt over stdin with the edition and `rustfmt.toml`
+//! of the project the file belongs to, so unsaved text can be formatted
+//! without touching the rest of the project.
+
+use crate::core::diff::{diff, split_lines, DiffOp};
+use crate::core::traits::{DocumentFormatter, FormattingOptions};
+use crate::core::Range;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::debug;
+
+/// Names rustfmt looks for its config under, in order of preference
+const RUSTFMT_CONFIG_NAMES: [&str; 2] = ["rustfmt.toml", ".rustfmt.toml"];
+
+/// Formats Rust source by running rustfmt on it
+#[derive(Debug, Clone)]
+pub struct RustfmtFormatter {
+    /// rustfmt executable
+    rustfmt: PathBuf,
+    /// Project config, which takes precedence over `options`
+    config_path: Option<PathBuf>,
+    edition: Option<String>,
+    options: FormattingOptions,
+}
+
+impl RustfmtFormatter {
+    /// Formatter using rustfmt from `PATH` and its default settings
+    pub fn new() -> Self {
+        Self {
+            rustfmt: PathBuf::from("rustfmt"),
+            config_path: None,
+            edition: None,
+            options: FormattingOptions::default(),
+        }
+    }
+
+    /// Formatter using the `rustfmt.toml` and edition of the project a file is in
+    pub fn for_file(path: &Path) -> Self {
+        let dir = path.parent().unwrap_or(path);
+        Self {
+            config_path: find_rustfmt_config(dir),
+            edition: find_edition(dir),
+            ..Self::new()
+        }
+    }
+
+    /// Run a different rustfmt executable
+    pub fn with_rustfmt(mut self, rustfmt: impl Into<PathBuf>) -> Self {
+        self.rustfmt = rustfmt.into();
+        self
+    }
+
+    /// Project config passed to rustfmt, if one was found
+    pub fn config_path(&self) -> Option<&Path> {
+        self.config_path.as_deref()
+    }
+
+    /// Edition passed to rustfmt, if the project sets one
+    pub fn edition(&self) -> Option<&str> {
+        self.edition.as_deref()
+    }
+
+    /// Arguments rustfmt is run with
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["--quiet".to_string()];
+        if let Some(edition) = &self.edition {
+            args.extend(["--edition".to_string(), edition.clone()]);
+        }
+
+        match &self.config_path {
+            Some(config_path) => {
+                args.extend([
+                    "--config-path".to_string(),
+                    config_path.to_string_lossy().into_owned(),
+                ]);
+            }
+            None => {
+                let mut config = format!(
+                    "hard_tabs={},tab_spaces={}",
+                    !self.options.insert_spaces, self.options.tab_size
+                );
+                if let Some(max_width) = self.options.max_line_length {
+                    config.push_str(&format!(",max_width={}", max_width));
+                }
+                args.extend(["--config".to_string(), config]);
+            }
+        }
+
+        args
+    }
+}
+
+impl Default for RustfmtFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentFormatter for RustfmtFormatter {
+    fn format_document(&self, text: &str) -> Result<String, String> {
+        let args = self.args();
+        debug!("Running {} {}", self.rustfmt.display(), args.join(" "));
+
+        let mut child = Command::new(&self.rustfmt)
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run {}: {}", self.rustfmt.display(), e))?;
+
+        // rustfmt reads all of its input before writing, so this cannot block on a full pipe
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .map_err(|e| format!("Failed to send text to rustfmt: {}", e))?;
+        }
+
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("Failed to read rustfmt output: {}", e))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(match stderr.trim() {
+                "" => format!("rustfmt exited with {}", output.status),
+                message => message.to_string(),
+            });
+        }
+
+        String::from_utf8(output.stdout).map_err(|e| format!("rustfmt output is not UTF-8: {}", e))
+    }
+
+    /// rustfmt only formats whole files, so the file is formatted and then
+    /// only the changes to lines in `range` are kept
+    fn format_range(&self, text: &str, range: Range) -> Result<String, String> {
+        let formatted = self.format_document(text)?;
+        Ok(restrict_to_lines(text, &formatted, range_lines(&range)))
+    }
+
+    fn get_options(&self) -> FormattingOptions {
+        self.options.clone()
+    }
+
+    fn set_options(&mut self, options: FormattingOptions) {
+        self.options = options;
+    }
+}
+
+/// Lines a range covers, leaving out the line it ends at the start of
+pub fn range_lines(range: &Range) -> RangeInclusive<usize> {
+    let last = if range.end.column == 0 && range.end.line > range.start.line {
+        range.end.line - 1
+    } else {
+        range.end.line
+    };
+    range.start.line..=last
+}
+
+/// Keep only the changes from `text` to `formatted` that touch `lines`
+///
+/// Lines are counted in `text`. Lines are matched up by their text without
+/// whitespace, and each is kept or dropped as a whole. A group of lines that
+/// formatting joins or splits is kept if any of it is in `lines`.
+pub fn restrict_to_lines(text: &str, formatted: &str, lines: RangeInclusive<usize>) -> String {
+    let old_lines = split_lines(text);
+    let new_lines = split_lines(formatted);
+    let tokens = |line: &&str| line.split_whitespace().collect::<String>();
+    let old_tokens: Vec<String> = old_lines.iter().map(tokens).collect();
+    let new_tokens: Vec<String> = new_lines.iter().map(tokens).collect();
+
+    let mut result = String::with_capacity(text.len());
+    // Lines of the current run of changes, removed and added
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    // Old line the current run of changes ends before
+    let mut next_line = 0;
+
+    for op in diff(&old_tokens, &new_tokens)
+        .into_iter()
+        .map(Some)
+        .chain([None])
+    {
+        match op {
+            Some(DiffOp::Delete { old }) => {
+                removed.push(old);
+                next_line = old + 1;
+                continue;
+            }
+            Some(DiffOp::Insert { new }) => {
+                added.push(new);
+                continue;
+            }
+            Some(DiffOp::Equal { .. }) | None => {}
+        }
+
+        if removed.len() == added.len() {
+            for (&old, &new) in removed.iter().zip(&added) {
+                let line = if lines.contains(&old) {
+                    new_lines[new]
+                } else {
+                    old_lines[old]
+                };
+                result.push_str(line);
+            }
+        } else {
+            let touched = if removed.is_empty() {
+                lines.contains(&next_line)
+            } else {
+                removed.iter().any(|line| lines.contains(line))
+            };
+            if touched {
+                added
+                    .iter()
+                    .for_each(|&new| result.push_str(new_lines[new]));
+            } else {
+                removed
+                    .iter()
+                    .for_each(|&old| result.push_str(old_lines[old]));
+            }
+        }
+        removed.clear();
+        added.clear();
+
+        if let Some(DiffOp::Equal { old, new }) = op {
+            let line = if lines.contains(&old) {
+                new_lines[new]
+            } else {
+                old_lines[old]
+            };
+            result.push_str(line);
+            next_line = old + 1;
+        }
+    }
+
+    result
+}
+
+/// Find the rustfmt config that applies to files in a directory
+pub fn find_rustfmt_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().find_map(|ancestor| {
+        RUSTFMT_CONFIG_NAMES
+            .iter()
+            .map(|name| ancestor.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Find the edition of the crate that files in a directory belong to
+///
+/// Reads the nearest `Cargo.toml`, following `edition.workspace = true` up
+/// to the workspace manifest.
+pub fn find_edition(dir: &Path) -> Option<String> {
+    let mut inherits = false;
+
+    for ancestor in dir.ancestors() {
+        let Ok(content) = std::fs::read_to_string(ancestor.join("Cargo.toml")) else {
+            continue;
+        };
+        let Ok(manifest) = content.parse::<toml::Table>() else {
+            continue;
+        };
+
+        if !inherits {
+            match manifest
+                .get("package")
+                .and_then(|package| package.get("edition"))
+            {
+                Some(toml::Value::String(edition)) => return Some(edition.clone()),
+                Some(_) => inherits = true,
+                None if manifest.contains_key("package") => return None,
+                None => {}
+            }
+        }
+
+        if let Some(edition) = manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("package"))
+            .and_then(|package| package.get("edition"))
+            .and_then(toml::Value::as_str)
+        {
+            return Some(edition.to_string());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Position;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_restrict_to_lines() {
+        let text = "fn a( ) {}\nfn b( ) {}\nfn c( ) {}\n";
+        let formatted = "fn a() {}\nfn b() {}\nfn c() {}\n";
+
+        assert_eq!(
+            restrict_to_lines(text, formatted, 1..=1),
+            "fn a( ) {}\nfn b() {}\nfn c( ) {}\n"
+        );
+        assert_eq!(restrict_to_lines(text, formatted, 0..=2), formatted);
+
+        let range = Range::new(Position::new(0, 3), Position::new(2, 0));
+        assert_eq!(range_lines(&range), 0..=1);
+        assert_eq!(
+            restrict_to_lines(text, formatted, range_lines(&range)),
+            "fn a() {}\nfn b() {}\nfn c( ) {}\n"
+        );
+
+        // A line split in two is only taken when it is in range
+        let text = "fn a( ) {}\nfn b() { run( ); }\n";
+        let formatted = "fn a() {}\nfn b() {\n    run();\n}\n";
+        assert_eq!(
+            restrict_to_lines(text, formatted, 0..=0),
+            "fn a() {}\nfn b() { run( ); }\n"
+        );
+        assert_eq!(
+            restrict_to_lines(text, formatted, 1..=1),
+            "fn a( ) {}\nfn b() {\n    run();\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_project_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let src = root.join("crates/app/src");
+        std::fs::create_dir_all(&src).unwrap();
+
+        std::fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n\n[workspace.package]\nedition = \"2024\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("crates/app/Cargo.toml"),
+            "[package]\nname = \"app\"\nedition.workspace = true\n",
+        )
+        .unwrap();
+        std::fs::write(root.join(".rustfmt.toml"), "max_width = 80\n").unwrap();
+
+        let formatter = RustfmtFormatter::for_file(&src.join("main.rs"));
+        assert_eq!(formatter.edition(), Some("2024"));
+        assert_eq!(
+            formatter.config_path(),
+            Some(root.join(".rustfmt.toml").as_path())
+        );
+        assert!(formatter.args().contains(&"--config-path".to_string()));
+
+        std::fs::write(
+            root.join("crates/app/Cargo.toml"),
+            "[package]\nname = \"app\"\nedition = \"2018\"\n",
+        )
+        .unwrap();
+        assert_eq!(find_edition(&src).as_deref(), Some("2018"));
+    }
+}
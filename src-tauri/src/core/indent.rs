@@ -0,0 +1,285 @@
+// src-tauri/src/core/indent.rs
+//! Syntax-aware indentation
+//!
+//! Each language describes its indentation with a tree-sitter query, set in
+//! its `LanguageConfig`. Nodes are captured as:
+//! - `@indent`: lines inside the node after its first line go one level deeper
+//! - `@outdent`: a line starting with the node goes one level back
+//! - `@indent.after`: the line after one ending with the node goes one level
+//!   deeper, for code the parser cannot make sense of yet such as a `=>`
+//!   without an arm body
+//! - `@indent.ignore`: lines starting inside the node keep their indentation,
+//!   as in multi-line strings
+//!
+//! Nodes starting on the same line add a single level between them, so
+//! `foo(bar(` indents its arguments once.
+
+use std::collections::HashSet;
+use tree_sitter::{Language, Node, Query, QueryCursor, QueryError, StreamingIterator, Tree};
+
+/// Indent query for Rust
+pub const RUST_INDENT_QUERY: &str = r#"
+[
+  (block)
+  (declaration_list)
+  (field_declaration_list)
+  (enum_variant_list)
+  (match_block)
+  (match_arm)
+  (use_list)
+  (arguments)
+  (parameters)
+  (type_arguments)
+  (type_parameters)
+  (tuple_expression)
+  (array_expression)
+  (field_initializer_list)
+  (ordered_field_declaration_list)
+  (token_tree)
+  (where_clause)
+  (field_expression)
+  (binary_expression)
+  (let_declaration)
+  (assignment_expression)
+  (compound_assignment_expr)
+  (const_item)
+  (static_item)
+] @indent
+
+["}" "]" ")"] @outdent
+
+["=>" "="] @indent.after
+
+[
+  (string_literal)
+  (raw_string_literal)
+  (block_comment)
+] @indent.ignore
+"#;
+
+/// Indent query for JSON
+pub const JSON_INDENT_QUERY: &str = r#"
+[(object) (array)] @indent
+
+["}" "]"] @outdent
+"#;
+
+/// Indent query for TOML
+pub const TOML_INDENT_QUERY: &str = r#"
+[(array) (inline_table)] @indent
+
+["]" "}"] @outdent
+
+(string) @indent.ignore
+"#;
+
+/// A compiled indent query
+#[derive(Debug)]
+pub struct IndentQuery {
+    query: Query,
+    indent: Option<u32>,
+    outdent: Option<u32>,
+    indent_after: Option<u32>,
+    ignore: Option<u32>,
+}
+
+/// Nodes near a line captured by each kind of capture
+#[derive(Default)]
+struct Captured {
+    indent: HashSet<usize>,
+    outdent: HashSet<usize>,
+    indent_after: HashSet<usize>,
+    ignore: HashSet<usize>,
+}
+
+impl IndentQuery {
+    /// Compile an indent query for a language
+    pub fn new(language: &Language, source: &str) -> Result<Self, QueryError> {
+        let query = Query::new(language, source)?;
+        Ok(Self {
+            indent: query.capture_index_for_name("indent"),
+            outdent: query.capture_index_for_name("outdent"),
+            indent_after: query.capture_index_for_name("indent.after"),
+            ignore: query.capture_index_for_name("indent.ignore"),
+            query,
+        })
+    }
+
+    /// Indent level of a line whose text starts at byte `offset` of `source`
+    ///
+    /// `offset` is either the first non-blank character of an existing line
+    /// or the point a line break is about to be inserted at. Returns `None`
+    /// when the line's indentation is not the syntax's business, such as
+    /// inside a multi-line string.
+    pub fn indent_level(&self, tree: &Tree, source: &str, offset: usize) -> Option<usize> {
+        let offset = offset.min(source.len());
+        let root = tree.root_node();
+
+        // First character of the line's own text
+        let first = offset
+            + source[offset..]
+                .find(|ch| ch != ' ' && ch != '\t')
+                .unwrap_or(source.len() - offset);
+        // End of the text before the line
+        let last = source[..offset].trim_end().len();
+
+        let captured = self.captured(root, source, last.saturating_sub(1), first + 1);
+        let mut level: usize = 0;
+        let mut indented_rows = HashSet::new();
+
+        let mut node = root.descendant_for_byte_range(offset, offset);
+        while let Some(current) = node {
+            let inside = current.start_byte() < offset && offset < current.end_byte();
+            if inside && captured.ignore.contains(&current.id()) {
+                return None;
+            }
+            if inside
+                && captured.indent.contains(&current.id())
+                && indented_rows.insert(current.start_position().row)
+            {
+                level += 1;
+            }
+            node = current.parent();
+        }
+
+        if last > 0 {
+            let mut node = root.descendant_for_byte_range(last - 1, last);
+            while let Some(current) = node.filter(|node| node.end_byte() == last) {
+                if captured.indent_after.contains(&current.id()) {
+                    if indented_rows.insert(current.start_position().row) {
+                        level += 1;
+                    }
+                    break;
+                }
+                node = current.parent();
+            }
+        }
+
+        if first < source.len() && !source[first..].starts_with(['\n', '\r']) {
+            let mut node = root.descendant_for_byte_range(first, first + 1);
+            while let Some(current) = node.filter(|node| node.start_byte() == first) {
+                if captured.outdent.contains(&current.id()) {
+                    level = level.saturating_sub(1);
+                    break;
+                }
+                node = current.parent();
+            }
+        }
+
+        Some(level)
+    }
+
+    /// Collect the captured nodes overlapping a byte range
+    fn captured(&self, root: Node, source: &str, start: usize, end: usize) -> Captured {
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(start..end.min(source.len()).max(start));
+
+        let mut captured = Captured::default();
+        let mut matches = cursor.matches(&self.query, root, source.as_bytes());
+        while let Some(query_match) = matches.next() {
+            for capture in query_match.captures {
+                let set = match Some(capture.index) {
+                    index if index == self.indent => &mut captured.indent,
+                    index if index == self.outdent => &mut captured.outdent,
+                    index if index == self.indent_after => &mut captured.indent_after,
+                    index if index == self.ignore => &mut captured.ignore,
+                    _ => continue,
+                };
+                set.insert(capture.node.id());
+            }
+        }
+
+        captured
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::Parser;
+
+    fn rust_query() -> IndentQuery {
+        IndentQuery::new(&tree_sitter_rust::LANGUAGE.into(), RUST_INDENT_QUERY).unwrap()
+    }
+
+    fn parse(source: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .unwrap();
+        parser.parse(source, None).unwrap()
+    }
+
+    /// Indent levels of every line, as the query computes them
+    fn levels(source: &str) -> Vec<Option<usize>> {
+        let query = rust_query();
+        let tree = parse(source);
+        let mut offset = 0;
+        source
+            .split_inclusive('\n')
+            .map(|line| {
+                let blank = line.len() - line.trim_start_matches([' ', '\t']).len();
+                let level = query.indent_level(&tree, source, offset + blank);
+                offset += line.len();
+                level
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rust_indent_levels() {
+        let source = "\
+fn main() {
+let items = vec![
+1,
+2,
+];
+match items.len() {
+0 => {
+println!(\"none\");
+}
+n =>
+println!(\"{}\", n),
+}
+items
+.iter()
+.map(|item| item * 2)
+.count();
+}
+fn apply<T>(value: T)
+where
+T: Clone,
+{
+}
+";
+        let expected = [
+            0, 1, 2, 2, 1, 1, 2, 3, 2, 2, 3, 1, 1, 2, 2, 2, 0, 0, 0, 1, 0, 0,
+        ];
+        assert_eq!(
+            levels(source),
+            expected.into_iter().map(Some).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_new_line_indent() {
+        let query = rust_query();
+
+        // Between braces, the new line goes inside and the brace moves back
+        let source = "fn main() {}";
+        let tree = parse(source);
+        assert_eq!(query.indent_level(&tree, source, 11), Some(0));
+        let source = "fn main() {\n    run();}";
+        assert_eq!(query.indent_level(&parse(source), source, 22), Some(0));
+        let source = "fn main() {\n    run();\n}";
+        assert_eq!(query.indent_level(&parse(source), source, 22), Some(1));
+
+        // After an arm with no body yet
+        let source = "fn main() {\n    match x {\n        Some(y) =>\n    }\n}";
+        assert_eq!(query.indent_level(&parse(source), source, 44), Some(3));
+
+        // Inside a multi-line string the line is left alone
+        let source = "fn main() {\n    let s = \"a\nb\";\n}";
+        assert_eq!(query.indent_level(&parse(source), source, 27), None);
+    }
+}
@@ -0,0 +1,23 @@
+// src-tauri/src/core/macros.rs
+use crate::core::command::EditorCommand;
+use serde::{Deserialize, Serialize};
+
+/// A named sequence of recorded editor commands
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    pub commands: Vec<EditorCommand>,
+}
+
+impl Macro {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
@@ -0,0 +1,218 @@
+// src-tauri/src/core/merge.rs
+use crate::core::diff::{diff, split_lines, DiffOp};
+use serde::{Deserialize, Serialize};
+
+/// Marker opening the buffer side of a conflict
+pub const CONFLICT_START: &str = "<<<<<<< buffer";
+/// Marker separating the buffer side from the disk side of a conflict
+pub const CONFLICT_SEPARATOR: &str = "=======";
+/// Marker closing the disk side of a conflict
+pub const CONFLICT_END: &str = ">>>>>>> disk";
+
+/// A region changed differently in the buffer and on disk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeConflict {
+    /// First line of the conflict, including its markers (0-indexed)
+    pub start_line: usize,
+    /// Line after the closing marker
+    pub end_line: usize,
+    /// Text of the region in the common ancestor
+    pub base: String,
+    /// Text of the region in the buffer
+    pub ours: String,
+    /// Text of the region on disk
+    pub theirs: String,
+}
+
+/// Result of a three-way merge
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeResult {
+    /// Merged text, with conflict markers around conflicting regions
+    pub text: String,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergeResult {
+    /// Check if the merge completed without conflicts
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge two descendants of a common ancestor line by line (diff3)
+///
+/// Regions changed on only one side take that side's version. Regions changed
+/// identically on both sides are taken once. Anything else becomes a conflict.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    let base_lines = split_lines(base);
+    let our_lines = split_lines(ours);
+    let their_lines = split_lines(theirs);
+
+    let ours_match = matching_lines(&base_lines, &our_lines);
+    let theirs_match = matching_lines(&base_lines, &their_lines);
+
+    let line_ending = if ours.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut merged = MergeBuilder::new(line_ending);
+
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    while b < base_lines.len() || o < our_lines.len() || t < their_lines.len() {
+        // Lines unchanged on both sides are copied through
+        if b < base_lines.len() && ours_match[b] == Some(o) && theirs_match[b] == Some(t) {
+            merged.push(base_lines[b]);
+            b += 1;
+            o += 1;
+            t += 1;
+            continue;
+        }
+
+        // Otherwise take everything up to the next line both sides kept
+        let (b_end, o_end, t_end) = (b..base_lines.len())
+            .find_map(|k| Some((k, ours_match[k]?, theirs_match[k]?)))
+            .unwrap_or((base_lines.len(), our_lines.len(), their_lines.len()));
+
+        let base_chunk = &base_lines[b..b_end];
+        let our_chunk = &our_lines[o..o_end];
+        let their_chunk = &their_lines[t..t_end];
+
+        if our_chunk == base_chunk {
+            merged.extend(their_chunk);
+        } else if their_chunk == base_chunk || our_chunk == their_chunk {
+            merged.extend(our_chunk);
+        } else {
+            merged.conflict(base_chunk, our_chunk, their_chunk);
+        }
+
+        b = b_end;
+        o = o_end;
+        t = t_end;
+    }
+
+    merged.finish()
+}
+
+/// For each line of `base`, the index of the matching line in `other`
+fn matching_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    for op in diff(base, other) {
+        if let DiffOp::Equal { old, new } = op {
+            matches[old] = Some(new);
+        }
+    }
+    matches
+}
+
+/// Accumulates merged lines and conflicts
+struct MergeBuilder<'a> {
+    line_ending: &'a str,
+    text: String,
+    line_count: usize,
+    conflicts: Vec<MergeConflict>,
+}
+
+impl<'a> MergeBuilder<'a> {
+    fn new(line_ending: &'a str) -> Self {
+        Self {
+            line_ending,
+            text: String::new(),
+            line_count: 0,
+            conflicts: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, line: &str) {
+        self.text.push_str(line);
+        self.line_count += 1;
+    }
+
+    fn extend(&mut self, lines: &[&str]) {
+        for line in lines {
+            self.push(line);
+        }
+    }
+
+    /// Push lines that will be followed by more text, terminating the last one
+    fn extend_terminated(&mut self, lines: &[&str]) {
+        self.extend(lines);
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push_str(self.line_ending);
+        }
+    }
+
+    fn marker(&mut self, marker: &str) {
+        self.text.push_str(marker);
+        self.text.push_str(self.line_ending);
+        self.line_count += 1;
+    }
+
+    fn conflict(&mut self, base: &[&str], ours: &[&str], theirs: &[&str]) {
+        let start_line = self.line_count;
+
+        self.extend_terminated(&[]);
+        self.marker(CONFLICT_START);
+        self.extend_terminated(ours);
+        self.marker(CONFLICT_SEPARATOR);
+        self.extend_terminated(theirs);
+        self.marker(CONFLICT_END);
+
+        self.conflicts.push(MergeConflict {
+            start_line,
+            end_line: self.line_count,
+            base: base.concat(),
+            ours: ours.concat(),
+            theirs: theirs.concat(),
+        });
+    }
+
+    fn finish(self) -> MergeResult {
+        MergeResult {
+            text: self.text,
+            conflicts: self.conflicts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_non_overlapping_changes() {
+        let base = "fn a() {}\nfn b() {}\nfn c() {}\n";
+        let ours = "fn a() { one(); }\nfn b() {}\nfn c() {}\n";
+        let theirs = "fn a() {}\nfn b() {}\nfn c() { three(); }\n";
+
+        let result = merge3(base, ours, theirs);
+        assert!(result.is_clean());
+        assert_eq!(
+            result.text,
+            "fn a() { one(); }\nfn b() {}\nfn c() { three(); }\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_identical_changes() {
+        let result = merge3("a\nb\n", "a\nB\n", "a\nB\n");
+        assert!(result.is_clean());
+        assert_eq!(result.text, "a\nB\n");
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let result = merge3("a\nb\nc", "a\nours\nc", "a\ntheirs\nc");
+
+        assert_eq!(
+            result.text,
+            "a\n<<<<<<< buffer\nours\n=======\ntheirs\n>>>>>>> disk\nc"
+        );
+        assert_eq!(
+            result.conflicts,
+            vec![MergeConflict {
+                start_line: 1,
+                end_line: 6,
+                base: "b\n".to_string(),
+                ours: "ours\n".to_string(),
+                theirs: "theirs\n".to_string(),
+            }]
+        );
+    }
+}
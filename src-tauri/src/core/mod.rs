@@ -12,26 +12,60 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use thiserror::Error;
 
+pub mod anchor;
+pub mod atomic_save;
+pub mod command;
 pub mod cursor;
+pub mod diff;
 pub mod editor;
+pub mod encoding;
+pub mod folding;
+pub mod formatter;
+pub mod indent;
+pub mod macros;
+pub mod merge;
+pub mod snapshot;
+pub mod snippet;
 pub mod syntax;
+pub mod syntax_selection;
 pub mod text_buffer;
+pub mod text_transform;
 pub mod traits;
+pub mod undo_tree;
 pub mod utils;
+pub mod vim;
+pub mod wrap;
 
 // Re-export commonly used types
-pub use cursor::{Cursor, CursorManager, Direction, MovementUnit, SelectionMode};
+pub use anchor::{AnchorId, Gravity, RangeAnchor};
+pub use atomic_save::{BackupMode, SaveStage};
+pub use command::{CommandOutput, EditorCommand, RangeReplacement};
+pub use cursor::{BlockSelection, Cursor, CursorManager, Direction, MovementUnit, SelectionMode};
+pub use diff::{diff_lines, LineDiff, LineDiffKind};
 pub use editor::{
-    Editor, EditorConfig, EditorEvent, EditorMetrics, EditorState, SearchOptions, SearchResult,
-    ViewState,
+    Editor, EditorConfig, EditorEvent, EditorMetrics, EditorState, ExternalChange, SearchOptions,
+    SearchResult, SortOptions, ViewState,
 };
+pub use encoding::{FileEncoding, TextEncoding};
+pub use folding::{FoldKind, FoldingRange};
+pub use formatter::RustfmtFormatter;
+pub use indent::IndentQuery;
+pub use macros::Macro;
+pub use merge::{merge3, MergeConflict, MergeResult};
+pub use snapshot::BufferSnapshot;
+pub use snippet::{Snippet, SnippetSession, SnippetStatus, SnippetVariables};
 pub use syntax::{
     SyntaxHighlighter, SyntaxTheme, ThemedSyntaxHighlighter, ThemedToken, Token, TokenType,
 };
 pub use text_buffer::{
-    BufferChangeEvent, BufferConfig, LineEnding, Position, Range, TextBuffer, TextEdit,
+    BufferChangeEvent, BufferConfig, FileStamp, LargeFileConfig, LineEnding, Position, Range,
+    TextBuffer, TextEdit,
 };
+pub use text_transform::{TextTransform, TransformError};
 pub use traits::EditorEventListener;
+pub use undo_tree::{UndoBranch, UndoNodeId, UndoNodeInfo, UndoTree};
+pub use vim::{VimEffect, VimKey, VimMode, VimState, VimStatus};
+pub use wrap::WrapLayout;
 
 /// Errors that can occur in the core editing system
 #[derive(Debug, Error)]
@@ -63,6 +97,26 @@ pub enum EditorError {
     /// Search operation error
     #[error("Search error: {0}")]
     SearchError(String),
+
+    /// A Vim command couldn't be run
+    #[error("Vim error: {0}")]
+    VimError(String),
+
+    /// Selected text couldn't be transformed
+    #[error("Transform error: {0}")]
+    TransformError(#[from] TransformError),
+
+    /// Text can't be decoded from or encoded to a file's encoding
+    #[error("Encoding error: {0}")]
+    EncodingError(String),
+
+    /// Saving a file failed partway through; the original file is left intact
+    #[error("Failed to save {} while {stage}: {source}", .path.display())]
+    SaveFailed {
+        path: PathBuf,
+        stage: SaveStage,
+        source: std::io::Error,
+    },
 }
 
 pub type EditorResult<T> = Result<T, EditorError>;
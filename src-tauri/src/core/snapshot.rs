@@ -0,0 +1,225 @@
+// src-tauri/src/core/snapshot.rs
+use crate::core::text_buffer::{Position, Range};
+use anyhow::Result;
+use ropey::{Rope, RopeSlice};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Immutable view of a buffer at one version
+///
+/// Cloning the rope only bumps reference counts, so taking a snapshot is cheap
+/// regardless of buffer size. Snapshots can be sent to background tasks and
+/// read while the buffer keeps changing.
+#[derive(Clone)]
+pub struct BufferSnapshot {
+    rope: Rope,
+    version: u64,
+    file_path: Option<PathBuf>,
+}
+
+impl BufferSnapshot {
+    pub(crate) fn new(rope: Rope, version: u64, file_path: Option<PathBuf>) -> Self {
+        Self {
+            rope,
+            version,
+            file_path,
+        }
+    }
+
+    /// Version of the buffer the snapshot was taken at
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// File the buffer was associated with
+    pub fn file_path(&self) -> Option<&Path> {
+        self.file_path.as_deref()
+    }
+
+    pub fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Copy the whole text out of the snapshot
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    /// Get text of a specific line (0-indexed), including its line ending
+    pub fn line(&self, line: usize) -> Option<RopeSlice<'_>> {
+        self.rope.get_line(line)
+    }
+
+    /// Get the length of a line in characters, excluding its line ending
+    pub fn line_len(&self, line: usize) -> Result<usize> {
+        line_len(&self.rope, line)
+    }
+
+    /// Get the text within a range
+    pub fn text_in_range(&self, range: &Range) -> Result<String> {
+        let start = self.position_to_char_index(range.start)?;
+        let end = self.position_to_char_index(range.end)?;
+
+        if start > end {
+            return Err(anyhow::anyhow!("Invalid range: start > end"));
+        }
+
+        Ok(self.rope.slice(start..end).to_string())
+    }
+
+    pub fn position_to_char_index(&self, position: Position) -> Result<usize> {
+        position_to_char_index(&self.rope, position)
+    }
+
+    pub fn char_index_to_position(&self, char_index: usize) -> Result<Position> {
+        char_index_to_position(&self.rope, char_index)
+    }
+
+    /// Convert a position to a UTF-8 byte offset, as used by parsers
+    pub fn position_to_byte_offset(&self, position: Position) -> Result<usize> {
+        let char_index = self.position_to_char_index(position)?;
+        Ok(self.rope.char_to_byte(char_index))
+    }
+
+    /// Convert a UTF-8 byte offset to a position
+    ///
+    /// Offsets inside a multi-byte character resolve to the start of that character.
+    pub fn byte_offset_to_position(&self, byte_offset: usize) -> Result<Position> {
+        if byte_offset > self.rope.len_bytes() {
+            return Err(anyhow::anyhow!(
+                "Byte offset {} is out of bounds",
+                byte_offset
+            ));
+        }
+
+        self.char_index_to_position(self.rope.byte_to_char(byte_offset))
+    }
+}
+
+impl fmt::Debug for BufferSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferSnapshot")
+            .field("version", &self.version)
+            .field("file_path", &self.file_path)
+            .field("char_count", &self.rope.len_chars())
+            .field("line_count", &self.rope.len_lines())
+            .finish()
+    }
+}
+
+/// Length of a line in characters, excluding its line ending
+pub(crate) fn line_len(rope: &Rope, line: usize) -> Result<usize> {
+    let line_slice = rope
+        .get_line(line)
+        .ok_or_else(|| anyhow::anyhow!("Line {} is out of bounds", line))?;
+
+    let len = line_slice.len_chars();
+    let ending = match (
+        len.checked_sub(2).map(|i| line_slice.char(i)),
+        len.checked_sub(1).map(|i| line_slice.char(i)),
+    ) {
+        (Some('\r'), Some('\n')) => 2,
+        (_, Some('\n' | '\r')) => 1,
+        _ => 0,
+    };
+
+    Ok(len - ending)
+}
+
+pub(crate) fn position_to_char_index(rope: &Rope, position: Position) -> Result<usize> {
+    if position.line >= rope.len_lines() {
+        return Err(anyhow::anyhow!("Line {} is out of bounds", position.line));
+    }
+
+    let line_start_char = rope.line_to_char(position.line);
+    let line_len = line_len(rope, position.line)?;
+
+    if position.column > line_len {
+        return Err(anyhow::anyhow!(
+            "Column {} is out of bounds for line {}",
+            position.column,
+            position.line
+        ));
+    }
+
+    Ok(line_start_char + position.column)
+}
+
+pub(crate) fn char_index_to_position(rope: &Rope, char_index: usize) -> Result<Position> {
+    if char_index > rope.len_chars() {
+        return Err(anyhow::anyhow!(
+            "Character index {} is out of bounds",
+            char_index
+        ));
+    }
+
+    let line = rope.char_to_line(char_index);
+    let line_start_char = rope.line_to_char(line);
+
+    Ok(Position::new(line, char_index - line_start_char))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::text_buffer::{TextBuffer, TextEdit};
+
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+    #[test]
+    fn test_snapshot_is_isolated_from_later_edits() {
+        assert_send_sync::<BufferSnapshot>();
+
+        let mut buffer = TextBuffer::from_content("let a = 1;\nlet b = 2;\n", None).unwrap();
+        let snapshot = buffer.snapshot();
+
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 0), "// hi\n".to_string()))
+            .unwrap();
+
+        assert_eq!(snapshot.version() + 1, buffer.version());
+        assert_eq!(snapshot.text(), "let a = 1;\nlet b = 2;\n");
+        assert_eq!(snapshot.len_lines(), 3);
+        assert_eq!(buffer.snapshot().len_lines(), 4);
+    }
+
+    #[test]
+    fn test_position_conversions() {
+        let buffer = TextBuffer::from_content("é = 1;\r\nlet x;\n", None).unwrap();
+        let snapshot = buffer.snapshot();
+
+        assert_eq!(snapshot.line_len(0).unwrap(), 6);
+        assert_eq!(
+            snapshot
+                .position_to_byte_offset(Position::new(0, 1))
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            snapshot.byte_offset_to_position(9).unwrap(),
+            Position::new(1, 0)
+        );
+        assert_eq!(
+            snapshot
+                .text_in_range(&Range::new(Position::new(1, 0), Position::new(1, 3)))
+                .unwrap(),
+            "let"
+        );
+        assert!(snapshot
+            .position_to_char_index(Position::new(0, 7))
+            .is_err());
+        assert!(snapshot.byte_offset_to_position(100).is_err());
+    }
+}
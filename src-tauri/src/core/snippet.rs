@@ -0,0 +1,1205 @@
+// src-tauri/src/core/snippet.rs
+//! Snippet parsing, expansion and tabstop sessions
+//!
+//! Parses the LSP/TextMate snippet grammar: tabstops (`$1`, `${1}`),
+//! placeholders (`${1:default}`), choices (`${1|one,two|}`), variables
+//! (`$TM_FILENAME`, `${CURRENT_YEAR:default}`) and regex transforms
+//! (`${TM_FILENAME/(.*)\..*/$1/}`). Text that doesn't parse is inserted as
+//! it is written, as editors do for malformed snippets.
+
+use crate::core::anchor::{AnchorId, Gravity};
+use crate::core::text_buffer::{Range, TextBuffer, TextEdit};
+use anyhow::Result;
+use chrono::{DateTime, TimeZone};
+use regex::{Captures, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::ops;
+use uuid::Uuid;
+
+/// One piece of a parsed snippet
+#[derive(Debug, Clone)]
+pub enum SnippetElement {
+    Text(String),
+    /// `$1` or `${1}`, or `${1/regex/format/}` when transformed
+    Tabstop {
+        index: usize,
+        transform: Option<Transform>,
+    },
+    /// `${1:default}`
+    Placeholder {
+        index: usize,
+        children: Vec<SnippetElement>,
+    },
+    /// `${1|one,two|}`
+    Choice {
+        index: usize,
+        options: Vec<String>,
+    },
+    /// `$NAME`, `${NAME:default}` or `${NAME/regex/format/}`
+    Variable {
+        name: String,
+        default: Option<Vec<SnippetElement>>,
+        transform: Option<Transform>,
+    },
+}
+
+/// Regex replacement applied to a variable or tabstop
+#[derive(Debug, Clone)]
+pub struct Transform {
+    regex: Regex,
+    format: Vec<FormatItem>,
+    global: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FormatItem {
+    Text(String),
+    Group(usize),
+    Case(usize, CaseModifier),
+    /// `${1:+set}`, `${1:-unset}`, `${1:unset}` or `${1:?set:unset}`
+    Conditional {
+        group: usize,
+        set: String,
+        unset: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaseModifier {
+    Upcase,
+    Downcase,
+    Capitalize,
+    CamelCase,
+    PascalCase,
+}
+
+impl CaseModifier {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "upcase" => Some(Self::Upcase),
+            "downcase" => Some(Self::Downcase),
+            "capitalize" => Some(Self::Capitalize),
+            "camelcase" => Some(Self::CamelCase),
+            "pascalcase" => Some(Self::PascalCase),
+            _ => None,
+        }
+    }
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            Self::Upcase => text.to_uppercase(),
+            Self::Downcase => text.to_lowercase(),
+            Self::Capitalize => capitalize(text),
+            Self::PascalCase => text
+                .split(|ch: char| !ch.is_alphanumeric())
+                .map(capitalize)
+                .collect(),
+            Self::CamelCase => {
+                let pascal = Self::PascalCase.apply(text);
+                let mut chars = pascal.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_lowercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+impl Transform {
+    /// Replace the first match in `value`, or every match with the `g` option
+    pub fn apply(&self, value: &str) -> String {
+        if self.global {
+            self.regex
+                .replace_all(value, |captures: &Captures| self.format(captures))
+                .into_owned()
+        } else {
+            self.regex
+                .replace(value, |captures: &Captures| self.format(captures))
+                .into_owned()
+        }
+    }
+
+    fn format(&self, captures: &Captures) -> String {
+        let group = |index: usize| captures.get(index).map_or("", |found| found.as_str());
+        let mut text = String::new();
+
+        for item in &self.format {
+            match item {
+                FormatItem::Text(literal) => text.push_str(literal),
+                FormatItem::Group(index) => text.push_str(group(*index)),
+                FormatItem::Case(index, modifier) => text.push_str(&modifier.apply(group(*index))),
+                FormatItem::Conditional {
+                    group: index,
+                    set,
+                    unset,
+                } => text.push_str(if group(*index).is_empty() { unset } else { set }),
+            }
+        }
+
+        text
+    }
+}
+
+/// A parsed snippet
+#[derive(Debug, Clone, Default)]
+pub struct Snippet {
+    elements: Vec<SnippetElement>,
+}
+
+impl Snippet {
+    /// Parse snippet syntax, keeping anything malformed as literal text
+    pub fn parse(text: &str) -> Self {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+        };
+        Self {
+            elements: parser.elements(false),
+        }
+    }
+
+    pub fn elements(&self) -> &[SnippetElement] {
+        &self.elements
+    }
+
+    /// Resolve variables and lay out the text and tabstops
+    pub fn expand(&self, variables: &SnippetVariables) -> ExpandedSnippet {
+        let mut defaults = HashMap::new();
+        let mut max_index = 0;
+        collect_defaults(&self.elements, &mut defaults, &mut max_index);
+
+        let mut expander = Expander {
+            variables,
+            defaults,
+            text: String::new(),
+            len: 0,
+            tabstops: BTreeMap::new(),
+            next_index: max_index + 1,
+            expanding: Vec::new(),
+        };
+        expander.expand(&self.elements);
+        expander.finish()
+    }
+}
+
+/// First placeholder or choice given for each tabstop, which its mirrors copy
+fn collect_defaults<'a>(
+    elements: &'a [SnippetElement],
+    defaults: &mut HashMap<usize, &'a SnippetElement>,
+    max_index: &mut usize,
+) {
+    for element in elements {
+        match element {
+            SnippetElement::Text(_) => {}
+            SnippetElement::Tabstop { index, .. } => *max_index = (*max_index).max(*index),
+            SnippetElement::Placeholder { index, children } => {
+                *max_index = (*max_index).max(*index);
+                defaults.entry(*index).or_insert(element);
+                collect_defaults(children, defaults, max_index);
+            }
+            SnippetElement::Choice { index, .. } => {
+                *max_index = (*max_index).max(*index);
+                defaults.entry(*index).or_insert(element);
+            }
+            SnippetElement::Variable { default, .. } => {
+                if let Some(children) = default {
+                    collect_defaults(children, defaults, max_index);
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.chars.get(self.pos + 1).copied()
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        let matched = self.peek() == Some(ch);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    /// Parse elements up to the end, or up to an unescaped `}` when nested
+    fn elements(&mut self, nested: bool) -> Vec<SnippetElement> {
+        let mut elements = Vec::new();
+        let mut text = String::new();
+
+        while let Some(ch) = self.peek() {
+            if nested && ch == '}' {
+                break;
+            }
+
+            if ch == '\\' {
+                if let Some(escaped @ ('$' | '}' | '\\')) = self.peek_next() {
+                    text.push(escaped);
+                    self.pos += 2;
+                    continue;
+                }
+            }
+
+            if ch == '$' {
+                let start = self.pos;
+                if let Some(element) = self.dollar() {
+                    if !text.is_empty() {
+                        elements.push(SnippetElement::Text(std::mem::take(&mut text)));
+                    }
+                    elements.push(element);
+                    continue;
+                }
+                self.pos = start;
+            }
+
+            text.push(ch);
+            self.pos += 1;
+        }
+
+        if !text.is_empty() {
+            elements.push(SnippetElement::Text(text));
+        }
+        elements
+    }
+
+    /// Parse a tabstop, placeholder, choice or variable starting at `$`
+    fn dollar(&mut self) -> Option<SnippetElement> {
+        self.pos += 1;
+        if let Some(index) = self.number() {
+            return Some(SnippetElement::Tabstop {
+                index,
+                transform: None,
+            });
+        }
+        if let Some(name) = self.name() {
+            return Some(SnippetElement::Variable {
+                name,
+                default: None,
+                transform: None,
+            });
+        }
+        if !self.eat('{') {
+            return None;
+        }
+
+        if let Some(index) = self.number() {
+            return if self.eat('}') {
+                Some(SnippetElement::Tabstop {
+                    index,
+                    transform: None,
+                })
+            } else if self.eat(':') {
+                let children = self.elements(true);
+                self.eat('}')
+                    .then_some(SnippetElement::Placeholder { index, children })
+            } else if self.eat('|') {
+                let options = self.choices()?;
+                self.eat('}')
+                    .then_some(SnippetElement::Choice { index, options })
+            } else if self.eat('/') {
+                let transform = self.transform()?;
+                Some(SnippetElement::Tabstop {
+                    index,
+                    transform: Some(transform),
+                })
+            } else {
+                None
+            };
+        }
+
+        let name = self.name()?;
+        if self.eat('}') {
+            Some(SnippetElement::Variable {
+                name,
+                default: None,
+                transform: None,
+            })
+        } else if self.eat(':') {
+            let default = self.elements(true);
+            self.eat('}').then_some(SnippetElement::Variable {
+                name,
+                default: Some(default),
+                transform: None,
+            })
+        } else if self.eat('/') {
+            let transform = self.transform()?;
+            Some(SnippetElement::Variable {
+                name,
+                default: None,
+                transform: Some(transform),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.pos += 1;
+        }
+
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        let number = digits.parse().ok();
+        if number.is_none() {
+            self.pos = start;
+        }
+        number
+    }
+
+    fn name(&mut self) -> Option<String> {
+        if !self
+            .peek()
+            .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        {
+            return None;
+        }
+
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        {
+            self.pos += 1;
+        }
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Parse choice options up to and including the closing `|`
+    fn choices(&mut self) -> Option<Vec<String>> {
+        let mut options = Vec::new();
+        let mut option = String::new();
+
+        loop {
+            match self.peek()? {
+                '\\' if matches!(self.peek_next(), Some(',' | '|' | '\\')) => {
+                    option.extend(self.peek_next());
+                    self.pos += 2;
+                }
+                ',' => {
+                    options.push(std::mem::take(&mut option));
+                    self.pos += 1;
+                }
+                '|' => {
+                    options.push(option);
+                    self.pos += 1;
+                    return Some(options);
+                }
+                ch => {
+                    option.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Parse `regex/format/options}` after the first `/`
+    fn transform(&mut self) -> Option<Transform> {
+        let mut pattern = String::new();
+        loop {
+            match self.peek()? {
+                '\\' if self.peek_next() == Some('/') => {
+                    pattern.push('/');
+                    self.pos += 2;
+                }
+                '\\' => {
+                    pattern.push('\\');
+                    pattern.extend(self.peek_next());
+                    self.pos += 2;
+                }
+                '/' => {
+                    self.pos += 1;
+                    break;
+                }
+                ch => {
+                    pattern.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        let format = self.format()?;
+
+        let mut options = String::new();
+        loop {
+            match self.peek()? {
+                '}' => {
+                    self.pos += 1;
+                    break;
+                }
+                ch => {
+                    options.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(options.contains('i'))
+            .multi_line(options.contains('m'))
+            .build()
+            .ok()?;
+        Some(Transform {
+            regex,
+            format,
+            global: options.contains('g'),
+        })
+    }
+
+    /// Parse a transform's format string up to and including the closing `/`
+    fn format(&mut self) -> Option<Vec<FormatItem>> {
+        let mut items = Vec::new();
+        let mut text = String::new();
+
+        loop {
+            match self.peek()? {
+                '/' => {
+                    self.pos += 1;
+                    break;
+                }
+                '\\' if matches!(self.peek_next(), Some('/' | '$' | '\\')) => {
+                    text.extend(self.peek_next());
+                    self.pos += 2;
+                }
+                '$' => {
+                    let start = self.pos;
+                    if let Some(item) = self.format_item() {
+                        if !text.is_empty() {
+                            items.push(FormatItem::Text(std::mem::take(&mut text)));
+                        }
+                        items.push(item);
+                        continue;
+                    }
+                    self.pos = start + 1;
+                    text.push('$');
+                }
+                ch => {
+                    text.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            items.push(FormatItem::Text(text));
+        }
+        Some(items)
+    }
+
+    /// Parse `$1` or `${1...}` in a format string
+    fn format_item(&mut self) -> Option<FormatItem> {
+        self.pos += 1;
+        if let Some(group) = self.number() {
+            return Some(FormatItem::Group(group));
+        }
+        if !self.eat('{') {
+            return None;
+        }
+
+        let group = self.number()?;
+        if self.eat('}') {
+            return Some(FormatItem::Group(group));
+        }
+        if !self.eat(':') {
+            return None;
+        }
+
+        let item = if self.eat('/') {
+            let modifier = CaseModifier::from_name(&self.name()?)?;
+            FormatItem::Case(group, modifier)
+        } else if self.eat('+') {
+            FormatItem::Conditional {
+                group,
+                set: self.format_text(&['}'])?,
+                unset: String::new(),
+            }
+        } else if self.eat('?') {
+            let set = self.format_text(&[':'])?;
+            self.eat(':');
+            FormatItem::Conditional {
+                group,
+                set,
+                unset: self.format_text(&['}'])?,
+            }
+        } else {
+            self.eat('-');
+            FormatItem::Conditional {
+                group,
+                set: String::new(),
+                unset: self.format_text(&['}'])?,
+            }
+        };
+
+        self.eat('}').then_some(item)
+    }
+
+    /// Text up to one of `stops`, which is left unparsed
+    fn format_text(&mut self, stops: &[char]) -> Option<String> {
+        let mut text = String::new();
+        loop {
+            match self.peek()? {
+                ch if stops.contains(&ch) => return Some(text),
+                '\\' => {
+                    text.extend(self.peek_next());
+                    self.pos += 2;
+                }
+                ch => {
+                    text.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Values of snippet variables at one insertion point
+///
+/// Variables without a value expand to their default, or else to a
+/// placeholder holding the variable's name.
+#[derive(Debug, Clone, Default)]
+pub struct SnippetVariables {
+    values: HashMap<String, String>,
+}
+
+impl SnippetVariables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    /// Add the `CURRENT_*` date and time variables for `now`
+    pub fn with_time<Tz: TimeZone>(mut self, now: &DateTime<Tz>) -> Self
+    where
+        Tz::Offset: Display,
+    {
+        let formats = [
+            ("CURRENT_YEAR", "%Y"),
+            ("CURRENT_YEAR_SHORT", "%y"),
+            ("CURRENT_MONTH", "%m"),
+            ("CURRENT_MONTH_NAME", "%B"),
+            ("CURRENT_MONTH_NAME_SHORT", "%b"),
+            ("CURRENT_DATE", "%d"),
+            ("CURRENT_DAY_NAME", "%A"),
+            ("CURRENT_DAY_NAME_SHORT", "%a"),
+            ("CURRENT_HOUR", "%H"),
+            ("CURRENT_MINUTE", "%M"),
+            ("CURRENT_SECOND", "%S"),
+            ("CURRENT_SECONDS_UNIX", "%s"),
+            ("CURRENT_TIMEZONE_OFFSET", "%:z"),
+        ];
+        for (name, format) in formats {
+            self.set(name, now.format(format).to_string());
+        }
+        self
+    }
+
+    /// Add the `RANDOM`, `RANDOM_HEX` and `UUID` variables
+    pub fn with_random(mut self) -> Self {
+        let uuid = Uuid::new_v4();
+        let bytes = uuid.as_bytes();
+        let number = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+        self.set("RANDOM", format!("{:06}", number % 1_000_000));
+        self.set("RANDOM_HEX", format!("{:06x}", number & 0xff_ffff));
+        self.set("UUID", uuid.to_string());
+        self
+    }
+}
+
+/// Snippet text with the places its tabstops ended up
+#[derive(Debug, Clone)]
+pub struct ExpandedSnippet {
+    pub text: String,
+    /// Tabstops in the order they are visited, ending with `$0`
+    pub tabstops: Vec<ExpandedTabstop>,
+}
+
+/// Where one tabstop and its mirrors are in an expanded snippet
+#[derive(Debug, Clone)]
+pub struct ExpandedTabstop {
+    pub index: usize,
+    /// Char offsets into the text of every copy of the tabstop
+    pub ranges: Vec<ops::Range<usize>>,
+    pub choices: Option<Vec<String>>,
+    /// Copies showing a transform of the tabstop's text
+    pub transformed: Vec<(ops::Range<usize>, Transform)>,
+}
+
+impl ExpandedTabstop {
+    fn new(index: usize) -> Self {
+        Self {
+            index,
+            ranges: Vec::new(),
+            choices: None,
+            transformed: Vec::new(),
+        }
+    }
+}
+
+impl ExpandedSnippet {
+    /// Indent lines after the first by `indent` and expand tabs to `tab`
+    pub fn reindent(self, indent: &str, tab: &str) -> Self {
+        let mut text = String::with_capacity(self.text.len());
+        let mut offsets = Vec::new();
+        let mut len = 0;
+
+        for ch in self.text.chars() {
+            offsets.push(len);
+            match ch {
+                '\n' => {
+                    text.push('\n');
+                    text.push_str(indent);
+                    len += 1 + indent.chars().count();
+                }
+                '\t' => {
+                    text.push_str(tab);
+                    len += tab.chars().count();
+                }
+                _ => {
+                    text.push(ch);
+                    len += 1;
+                }
+            }
+        }
+        offsets.push(len);
+
+        let map = |range: &ops::Range<usize>| offsets[range.start]..offsets[range.end];
+        let tabstops = self
+            .tabstops
+            .into_iter()
+            .map(|tabstop| ExpandedTabstop {
+                ranges: tabstop.ranges.iter().map(map).collect(),
+                transformed: tabstop
+                    .transformed
+                    .into_iter()
+                    .map(|(range, transform)| (map(&range), transform))
+                    .collect(),
+                ..tabstop
+            })
+            .collect();
+
+        Self { text, tabstops }
+    }
+}
+
+struct Expander<'a> {
+    variables: &'a SnippetVariables,
+    defaults: HashMap<usize, &'a SnippetElement>,
+    text: String,
+    /// Length of `text` in chars
+    len: usize,
+    tabstops: BTreeMap<usize, ExpandedTabstop>,
+    /// Index given to the next unknown variable's placeholder
+    next_index: usize,
+    /// Tabstops being expanded, so placeholders that mirror themselves stop
+    expanding: Vec<usize>,
+}
+
+impl<'a> Expander<'a> {
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.len += text.chars().count();
+    }
+
+    fn tabstop(&mut self, index: usize) -> &mut ExpandedTabstop {
+        self.tabstops
+            .entry(index)
+            .or_insert_with(|| ExpandedTabstop::new(index))
+    }
+
+    fn expand(&mut self, elements: &'a [SnippetElement]) {
+        for element in elements {
+            let start = self.len;
+            match element {
+                SnippetElement::Text(text) => self.push(text),
+                SnippetElement::Tabstop {
+                    index,
+                    transform: None,
+                } => {
+                    if let Some(default) = self.defaults.get(index).copied() {
+                        self.expand_default(*index, default);
+                    }
+                    let range = start..self.len;
+                    self.tabstop(*index).ranges.push(range);
+                }
+                SnippetElement::Tabstop {
+                    index,
+                    transform: Some(transform),
+                } => {
+                    let value = self.default_text(*index);
+                    self.push(&transform.apply(&value));
+                    let range = start..self.len;
+                    self.tabstop(*index)
+                        .transformed
+                        .push((range, transform.clone()));
+                }
+                SnippetElement::Placeholder { index, children } => {
+                    if !self.expanding.contains(index) {
+                        self.expanding.push(*index);
+                        self.expand(children);
+                        self.expanding.pop();
+                    }
+                    let range = start..self.len;
+                    self.tabstop(*index).ranges.push(range);
+                }
+                SnippetElement::Choice { index, options } => {
+                    self.push(options.first().map_or("", String::as_str));
+                    let range = start..self.len;
+                    let tabstop = self.tabstop(*index);
+                    tabstop.ranges.push(range);
+                    tabstop.choices.get_or_insert_with(|| options.clone());
+                }
+                SnippetElement::Variable {
+                    name,
+                    default,
+                    transform,
+                } => match (self.variables.get(name), default) {
+                    (Some(value), _) => {
+                        let value = match transform {
+                            Some(transform) => transform.apply(value),
+                            None => value.to_string(),
+                        };
+                        self.push(&value);
+                    }
+                    (None, Some(children)) => self.expand(children),
+                    (None, None) => {
+                        // Unknown variables become placeholders holding their name
+                        let index = self.next_index;
+                        self.next_index += 1;
+                        self.push(name);
+                        let range = start..self.len;
+                        self.tabstop(index).ranges.push(range);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Expand the placeholder or choice a mirrored tabstop copies
+    fn expand_default(&mut self, index: usize, default: &'a SnippetElement) {
+        if self.expanding.contains(&index) {
+            return;
+        }
+
+        self.expanding.push(index);
+        match default {
+            SnippetElement::Placeholder { children, .. } => self.expand(children),
+            SnippetElement::Choice { options, .. } => {
+                self.push(options.first().map_or("", String::as_str));
+            }
+            _ => {}
+        }
+        self.expanding.pop();
+    }
+
+    /// Plain text of a tabstop's default, for transforms to work on
+    fn default_text(&self, index: usize) -> String {
+        let Some(default) = self.defaults.get(&index).copied() else {
+            return String::new();
+        };
+
+        let mut expander = Expander {
+            variables: self.variables,
+            defaults: self.defaults.clone(),
+            text: String::new(),
+            len: 0,
+            tabstops: BTreeMap::new(),
+            next_index: self.next_index,
+            expanding: self.expanding.clone(),
+        };
+        expander.expand_default(index, default);
+        expander.text
+    }
+
+    fn finish(mut self) -> ExpandedSnippet {
+        let last = self.tabstops.remove(&0).unwrap_or_else(|| {
+            let mut last = ExpandedTabstop::new(0);
+            last.ranges.push(self.len..self.len);
+            last
+        });
+
+        let mut tabstops: Vec<ExpandedTabstop> = self.tabstops.into_values().collect();
+        tabstops.push(last);
+
+        ExpandedSnippet {
+            text: self.text,
+            tabstops,
+        }
+    }
+}
+
+/// Where a snippet session is, for the frontend to show
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnippetStatus {
+    /// Number of the active tabstop, as written in the snippet
+    pub index: usize,
+    /// Position of the active tabstop in visiting order, from 0
+    pub position: usize,
+    /// Number of tabstops, including the final `$0`
+    pub count: usize,
+    /// Options to offer when the active tabstop is a choice
+    pub choices: Option<Vec<String>>,
+}
+
+/// Both ends of a tracked range
+#[derive(Debug, Clone, Copy)]
+struct AnchoredRange {
+    start: AnchorId,
+    end: AnchorId,
+}
+
+impl AnchoredRange {
+    fn new(buffer: &mut TextBuffer, range: Range, inclusive: bool) -> Result<Self> {
+        let (start_gravity, end_gravity) = if inclusive {
+            (Gravity::Left, Gravity::Right)
+        } else {
+            (Gravity::Right, Gravity::Left)
+        };
+
+        Ok(Self {
+            start: buffer.create_anchor(range.start, start_gravity)?,
+            end: buffer.create_anchor(range.end, end_gravity)?,
+        })
+    }
+
+    fn range(&self, buffer: &TextBuffer) -> Option<Range> {
+        let start = buffer.anchor_position(self.start)?;
+        let end = buffer.anchor_position(self.end)?;
+        Some(Range::new(start, end.max(start)))
+    }
+
+    fn remove(self, buffer: &mut TextBuffer) {
+        buffer.remove_anchor(self.start);
+        buffer.remove_anchor(self.end);
+    }
+
+    /// Track the same range with text typed at its edges inside or outside it
+    fn regrip(self, buffer: &mut TextBuffer, inclusive: bool) -> Result<Self> {
+        let range = self.range(buffer);
+        self.remove(buffer);
+        let range = range.ok_or_else(|| anyhow::anyhow!("Snippet range was lost"))?;
+        Self::new(buffer, range, inclusive)
+    }
+}
+
+#[derive(Debug)]
+struct SessionTabstop {
+    index: usize,
+    ranges: Vec<AnchoredRange>,
+    choices: Option<Vec<String>>,
+    transformed: Vec<(AnchoredRange, Transform)>,
+}
+
+/// Tabstops of inserted snippets, visited one after another
+///
+/// Every copy of a tabstop, across all insertions, gets a cursor. The
+/// active tabstop grows as text is typed at its edges; the others don't.
+#[derive(Debug)]
+pub struct SnippetSession {
+    tabstops: Vec<SessionTabstop>,
+    current: usize,
+}
+
+impl SnippetSession {
+    /// Track the tabstops of snippets inserted at the given char indices
+    pub fn start(
+        buffer: &mut TextBuffer,
+        insertions: Vec<(usize, ExpandedSnippet)>,
+    ) -> Result<Self> {
+        let mut by_index: BTreeMap<(bool, usize), SessionTabstop> = BTreeMap::new();
+
+        for (offset, expanded) in insertions {
+            for tabstop in expanded.tabstops {
+                let anchor = |range: &ops::Range<usize>, buffer: &mut TextBuffer, inclusive| {
+                    let start = buffer.char_index_to_position(offset + range.start)?;
+                    let end = buffer.char_index_to_position(offset + range.end)?;
+                    AnchoredRange::new(buffer, Range::new(start, end), inclusive)
+                };
+
+                let ranges = tabstop
+                    .ranges
+                    .iter()
+                    .map(|range| anchor(range, buffer, false))
+                    .collect::<Result<Vec<_>>>()?;
+                // Transformed copies are replaced whole, which collapses exclusive ranges
+                let transformed = tabstop
+                    .transformed
+                    .into_iter()
+                    .map(|(range, transform)| Ok((anchor(&range, buffer, true)?, transform)))
+                    .collect::<Result<Vec<_>>>()?;
+
+                // `$0` sorts after every other tabstop
+                let entry = by_index
+                    .entry((tabstop.index == 0, tabstop.index))
+                    .or_insert_with(|| SessionTabstop {
+                        index: tabstop.index,
+                        ranges: Vec::new(),
+                        choices: None,
+                        transformed: Vec::new(),
+                    });
+                entry.ranges.extend(ranges);
+                entry.transformed.extend(transformed);
+                if entry.choices.is_none() {
+                    entry.choices = tabstop.choices;
+                }
+            }
+        }
+
+        Ok(Self {
+            tabstops: by_index.into_values().collect(),
+            current: 0,
+        })
+    }
+
+    /// Position of the active tabstop in visiting order
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabstops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabstops.is_empty()
+    }
+
+    /// Whether `position` is the final tabstop, which ends the session
+    pub fn is_last(&self, position: usize) -> bool {
+        position + 1 >= self.tabstops.len()
+    }
+
+    pub fn status(&self) -> Option<SnippetStatus> {
+        let tabstop = self.tabstops.get(self.current)?;
+        Some(SnippetStatus {
+            index: tabstop.index,
+            position: self.current,
+            count: self.tabstops.len(),
+            choices: tabstop.choices.clone(),
+        })
+    }
+
+    /// Edits updating the transformed copies of the active tabstop
+    pub fn transform_edits(&self, buffer: &TextBuffer) -> Result<Vec<TextEdit>> {
+        let Some(tabstop) = self.tabstops.get(self.current) else {
+            return Ok(Vec::new());
+        };
+        let Some(source) = tabstop.ranges.first().and_then(|range| range.range(buffer)) else {
+            return Ok(Vec::new());
+        };
+
+        let value = buffer.text_in_range(&source)?;
+        Ok(tabstop
+            .transformed
+            .iter()
+            .filter_map(|(range, transform)| {
+                let range = range.range(buffer)?;
+                Some(TextEdit::replace(range, transform.apply(&value)))
+            })
+            .collect())
+    }
+
+    /// Make `position` the active tabstop, returning the ranges to select
+    pub fn select(&mut self, buffer: &mut TextBuffer, position: usize) -> Result<Vec<Range>> {
+        let position = position.min(self.tabstops.len().saturating_sub(1));
+
+        for (index, tabstop) in self.tabstops.iter_mut().enumerate() {
+            if index != self.current && index != position {
+                continue;
+            }
+            let inclusive = index == position;
+            tabstop.ranges = std::mem::take(&mut tabstop.ranges)
+                .into_iter()
+                .map(|range| range.regrip(buffer, inclusive))
+                .collect::<Result<Vec<_>>>()?;
+        }
+        self.current = position;
+
+        let mut ranges: Vec<Range> = self.tabstops[position]
+            .ranges
+            .iter()
+            .filter_map(|range| range.range(buffer))
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        ranges.dedup();
+        Ok(ranges)
+    }
+
+    /// Stop tracking the tabstops
+    pub fn end(self, buffer: &mut TextBuffer) {
+        for tabstop in self.tabstops {
+            for range in tabstop.ranges {
+                range.remove(buffer);
+            }
+            for (range, _) in tabstop.transformed {
+                range.remove(buffer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn expand(body: &str, variables: &SnippetVariables) -> (String, Vec<(usize, Vec<String>)>) {
+        let expanded = Snippet::parse(body).expand(variables);
+        let text = expanded.text.clone();
+        let slice = |range: &ops::Range<usize>| {
+            let start = text
+                .char_indices()
+                .nth(range.start)
+                .map_or(text.len(), |(i, _)| i);
+            let end = text
+                .char_indices()
+                .nth(range.end)
+                .map_or(text.len(), |(i, _)| i);
+            expanded.text[start..end].to_string()
+        };
+        let tabstops = expanded
+            .tabstops
+            .iter()
+            .map(|tabstop| (tabstop.index, tabstop.ranges.iter().map(slice).collect()))
+            .collect();
+        (text.clone(), tabstops)
+    }
+
+    fn stops(expected: Vec<(usize, Vec<&str>)>) -> Vec<(usize, Vec<String>)> {
+        expected
+            .into_iter()
+            .map(|(index, texts)| (index, texts.into_iter().map(String::from).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_tabstops_placeholders_and_choices() {
+        let variables = SnippetVariables::new();
+
+        let (text, tabstops) = expand(
+            "fn ${1:name}(${2:arg}: ${3|i32,u8|}) -> $3 {\n\t$0\n}\n// $1",
+            &variables,
+        );
+        assert_eq!(text, "fn name(arg: i32) -> i32 {\n\t\n}\n// name");
+        assert_eq!(
+            tabstops,
+            stops(vec![
+                (1, vec!["name", "name"]),
+                (2, vec!["arg"]),
+                (3, vec!["i32", "i32"]),
+                (0, vec![""]),
+            ])
+        );
+
+        // Nested placeholders, and `$0` defaulting to the end
+        let (text, tabstops) = expand("${1:outer ${2:inner}} done", &variables);
+        assert_eq!(text, "outer inner done");
+        assert_eq!(
+            tabstops,
+            stops(vec![
+                (1, vec!["outer inner"]),
+                (2, vec!["inner"]),
+                (0, vec![""]),
+            ])
+        );
+
+        // Malformed syntax and escapes stay literal
+        let (text, _) = expand(r"cost: \$5 $ ${1:open \} \\", &variables);
+        assert_eq!(text, r"cost: $5 $ ${1:open } \");
+
+        let choices = Snippet::parse("${1|a\\,b,c\\|d|}").expand(&variables);
+        assert_eq!(
+            choices.tabstops[0].choices,
+            Some(vec!["a,b".to_string(), "c|d".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_variables_and_transforms() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 9, 14, 5, 0).unwrap();
+        let variables = SnippetVariables::new()
+            .with_time(&now)
+            .with("TM_FILENAME", "parse_http_request.rs")
+            .with("TM_SELECTED_TEXT", "");
+
+        let (text, tabstops) = expand(
+            "// $CURRENT_YEAR-${CURRENT_MONTH}-$CURRENT_DATE ${CURRENT_MONTH_NAME_SHORT}\n\
+             struct ${TM_FILENAME/(.*)\\..+$/${1:/pascalcase}/}${TM_SELECTED_TEXT:;}\n\
+             ${UNKNOWN} ${MISSING:fallback $1}",
+            &variables,
+        );
+        assert_eq!(
+            text,
+            "// 2024-03-09 Mar\nstruct ParseHttpRequest\nUNKNOWN fallback "
+        );
+        // Unknown variables become placeholders after the numbered tabstops
+        assert_eq!(
+            tabstops,
+            stops(vec![(1, vec![""]), (2, vec!["UNKNOWN"]), (0, vec![""])])
+        );
+
+        let variables = SnippetVariables::new().with("NAME", "hello world");
+        let transformed = |body: &str| expand(body, &variables).0;
+        assert_eq!(transformed("${NAME/(\\w+)/${1:/upcase}/g}"), "HELLO WORLD");
+        assert_eq!(
+            transformed("${NAME/(\\w+)/${1:/capitalize}/}"),
+            "Hello world"
+        );
+        assert_eq!(transformed("${NAME/(x)?.*/${1:?yes:no}/}"), "no");
+        assert_eq!(transformed("${NAME/(h)?.*/${1:+has h}/}"), "has h");
+        assert_eq!(transformed("${NAME/ /_/g}"), "hello_world");
+        assert_eq!(transformed("${NAME/(.*)/${1:/camelcase}/}"), "helloWorld");
+
+        // A transformed tabstop shows its placeholder transformed
+        let expanded = Snippet::parse("${1:name} ${1/(.*)/${1:/upcase}/}").expand(&variables);
+        assert_eq!(expanded.text, "name NAME");
+        assert_eq!(expanded.tabstops[0].transformed.len(), 1);
+
+        let random = SnippetVariables::new().with_random();
+        assert_eq!(random.get("RANDOM").map(str::len), Some(6));
+        assert_eq!(random.get("RANDOM_HEX").map(str::len), Some(6));
+    }
+
+    #[test]
+    fn test_reindent() {
+        let expanded = Snippet::parse("if $1 {\n\t$0\n}")
+            .expand(&SnippetVariables::new())
+            .reindent("  ", "    ");
+        assert_eq!(expanded.text, "if  {\n      \n  }");
+        assert_eq!(expanded.tabstops[0].ranges.first(), Some(&(3..3)));
+        assert_eq!(expanded.tabstops[1].ranges.first(), Some(&(12..12)));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::core::indent::{IndentQuery, JSON_INDENT_QUERY, RUST_INDENT_QUERY, TOML_INDENT_QUERY};
 use crate::core::text_buffer::{BufferChangeEvent, Position, Range, TextBuffer};
 use anyhow::{Context, Result};
 use lru::LruCache;
@@ -199,6 +200,8 @@ pub struct LanguageConfig {
     pub highlight_query: &'static str,
     pub file_extensions: &'static [&'static str],
     pub comment_prefix: &'static str,
+    /// Query driving indentation, empty when lines just keep their indentation
+    pub indent_query: &'static str,
 }
 
 /// Get supported language configurations
@@ -213,6 +216,7 @@ pub fn get_language_configs() -> HashMap<&'static str, LanguageConfig> {
             language: unsafe { tree_sitter_rust() },
             file_extensions: &["rs"],
             comment_prefix: "//",
+            indent_query: RUST_INDENT_QUERY,
             highlight_query: r##"
             ; Keywords
             ["as" "async" "await" "break" "const" "continue" "crate" "dyn" "else" "enum" 
@@ -338,6 +342,7 @@ pub fn get_language_configs() -> HashMap<&'static str, LanguageConfig> {
             language: unsafe { tree_sitter_toml() },
             file_extensions: &["toml"],
             comment_prefix: "#",
+            indent_query: TOML_INDENT_QUERY,
             highlight_query: r#"
             (comment) @comment
             (string) @string
@@ -364,6 +369,7 @@ pub fn get_language_configs() -> HashMap<&'static str, LanguageConfig> {
             language: unsafe { tree_sitter_json() },
             file_extensions: &["json"],
             comment_prefix: "",
+            indent_query: JSON_INDENT_QUERY,
             highlight_query: r#"
             (string) @string
             (number) @number
@@ -397,12 +403,16 @@ pub struct SyntaxHighlighter {
     language_config: Option<LanguageConfig>,
     /// Tree-sitter query for highlighting
     highlight_query: Option<Query>,
+    /// Query for indenting lines, if the language has one
+    indent_query: Option<IndentQuery>,
     /// Query cursor for executing queries
     query_cursor: RwLock<QueryCursor>,
     /// Cache of highlighted tokens by buffer version
     cache: RwLock<LruCache<u64, HighlightCache>>,
     /// Current syntax tree
     current_tree: RwLock<Option<Tree>>,
+    /// Buffer version the current tree was parsed from
+    tree_version: RwLock<Option<u64>>,
     /// Performance metrics
     parse_times: RwLock<Vec<std::time::Duration>>,
 }
@@ -416,9 +426,11 @@ impl SyntaxHighlighter {
             parser: RwLock::new(Parser::new()),
             language_config: None,
             highlight_query: None,
+            indent_query: None,
             query_cursor: RwLock::new(QueryCursor::new()),
             cache: RwLock::new(LruCache::new(cache_size)),
             current_tree: RwLock::new(None),
+            tree_version: RwLock::new(None),
             parse_times: RwLock::new(Vec::new()),
         }
     }
@@ -441,9 +453,18 @@ impl SyntaxHighlighter {
 
         let query = Query::new(config.language, config.highlight_query)
             .map_err(|e| anyhow::anyhow!("Failed to create highlight query: {}", e))?;
+        let indent_query = match config.indent_query {
+            "" => None,
+            source => Some(
+                IndentQuery::new(&config.language, source)
+                    .map_err(|e| anyhow::anyhow!("Failed to create indent query: {}", e))?,
+            ),
+        };
 
         self.language_config = Some(config);
         self.highlight_query = Some(query);
+        self.indent_query = indent_query;
+        *self.tree_version.write() = None;
 
         debug!("Set syntax highlighting language to: {}", language_name);
         Ok(())
@@ -454,6 +475,11 @@ impl SyntaxHighlighter {
         self.language_config.as_ref().map(|config| config.name)
     }
 
+    /// Indent query of the current language
+    pub fn indent_query(&self) -> Option<&IndentQuery> {
+        self.indent_query.as_ref()
+    }
+
     /// Detect language from file extension
     pub fn detect_language_from_path(&self, path: &str) -> Option<&'static str> {
         let extension = std::path::Path::new(path).extension()?.to_str()?;
@@ -493,6 +519,25 @@ impl SyntaxHighlighter {
         Ok(())
     }
 
+    /// Get a syntax tree matching the buffer's current version
+    ///
+    /// Reparses from scratch when the buffer changed since the last tree was
+    /// built. Returns `None` when no language is set.
+    pub fn syntax_tree(&mut self, buffer: &TextBuffer) -> Result<Option<Tree>> {
+        if self.language_config.is_none() {
+            return Ok(None);
+        }
+
+        let version = buffer.version();
+        if *self.tree_version.read() != Some(version) || self.current_tree.read().is_none() {
+            *self.current_tree.write() = None;
+            self.parse(&buffer.text())?;
+            *self.tree_version.write() = Some(version);
+        }
+
+        Ok(self.current_tree.read().clone())
+    }
+
     /// Get syntax highlighting tokens for the entire buffer
     #[instrument(skip(self, buffer))]
     pub fn highlight_buffer(&mut self, buffer: &TextBuffer) -> Result<Vec<Token>> {
@@ -784,6 +829,7 @@ impl SyntaxHighlighter {
     pub fn clear_cache(&mut self) {
         self.cache.write().clear();
         *self.current_tree.write() = None;
+        *self.tree_version.write() = None;
         debug!("Cleared syntax highlighting cache");
     }
 }
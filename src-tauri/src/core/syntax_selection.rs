@@ -0,0 +1,161 @@
+// src-tauri/src/core/syntax_selection.rs
+//! Growing and shrinking selections along the syntax tree
+//!
+//! Each step moves to the next named node that strictly contains (or is
+//! strictly contained in) the current selection, so repeated steps walk
+//! through expressions, statements, blocks, items and finally the module.
+
+use crate::core::snapshot;
+use crate::core::text_buffer::{Position, Range};
+use anyhow::Result;
+use ropey::Rope;
+use tree_sitter::{Node, Tree};
+
+/// Range of the smallest syntax node strictly enclosing `range`
+///
+/// Returns `None` when the selection already covers the whole tree.
+pub fn expand_range(tree: &Tree, rope: &Rope, range: &Range) -> Result<Option<Range>> {
+    let (start, end) = byte_range(rope, range)?;
+    let Some(mut node) = tree.root_node().named_descendant_for_byte_range(start, end) else {
+        return Ok(None);
+    };
+
+    loop {
+        let encloses = node.start_byte() <= start && node.end_byte() >= end;
+        let larger = node.start_byte() < start || node.end_byte() > end;
+        if encloses && larger {
+            return node_range(rope, &node).map(Some);
+        }
+
+        match node.parent() {
+            Some(parent) => node = parent,
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Range of the largest syntax node strictly inside `range`
+///
+/// Prefers the child holding `focus`, usually the cursor position. Returns
+/// `None` when the selection is empty or has no smaller node inside it.
+pub fn shrink_range(
+    tree: &Tree,
+    rope: &Rope,
+    range: &Range,
+    focus: Position,
+) -> Result<Option<Range>> {
+    let (start, end) = byte_range(rope, range)?;
+    if start == end {
+        return Ok(None);
+    }
+
+    let focus = snapshot::position_to_char_index(rope, focus)
+        .map(|index| rope.char_to_byte(index))
+        .unwrap_or(start)
+        .clamp(start, end);
+    let Some(mut node) = tree.root_node().named_descendant_for_byte_range(start, end) else {
+        return Ok(None);
+    };
+
+    loop {
+        let mut walker = node.walk();
+        let children: Vec<Node> = node
+            .named_children(&mut walker)
+            .filter(|child| child.start_byte() >= start && child.end_byte() <= end)
+            .collect();
+
+        let child = children
+            .iter()
+            .find(|child| child.start_byte() <= focus && focus <= child.end_byte())
+            .or_else(|| children.first());
+
+        match child {
+            None => return Ok(None),
+            Some(child) if child.start_byte() == start && child.end_byte() == end => {
+                node = *child;
+            }
+            Some(child) => return node_range(rope, child).map(Some),
+        }
+    }
+}
+
+fn byte_range(rope: &Rope, range: &Range) -> Result<(usize, usize)> {
+    let start = snapshot::position_to_char_index(rope, range.start)?;
+    let end = snapshot::position_to_char_index(rope, range.end)?;
+    Ok((rope.char_to_byte(start), rope.char_to_byte(end)))
+}
+
+fn node_range(rope: &Rope, node: &Node) -> Result<Range> {
+    let start = snapshot::char_index_to_position(rope, rope.byte_to_char(node.start_byte()))?;
+    let end = snapshot::char_index_to_position(rope, rope.byte_to_char(node.end_byte()))?;
+    Ok(Range::new(start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::Parser;
+
+    const SOURCE: &str = "fn main() {\n    let total = add(1, 2) * 3;\n}\n";
+
+    fn parse(text: &str) -> (Tree, Rope) {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .unwrap();
+        (parser.parse(text, None).unwrap(), Rope::from_str(text))
+    }
+
+    fn text(rope: &Rope, range: &Range) -> String {
+        let start = snapshot::position_to_char_index(rope, range.start).unwrap();
+        let end = snapshot::position_to_char_index(rope, range.end).unwrap();
+        rope.slice(start..end).to_string()
+    }
+
+    #[test]
+    fn test_expand_walks_up_the_tree() {
+        let (tree, rope) = parse(SOURCE);
+        // Cursor inside `add`
+        let mut range = Range::single_point(Position::new(1, 17));
+        let mut steps = Vec::new();
+        while let Some(expanded) = expand_range(&tree, &rope, &range).unwrap() {
+            steps.push(text(&rope, &expanded));
+            range = expanded;
+        }
+
+        assert_eq!(
+            steps,
+            [
+                "add",
+                "add(1, 2)",
+                "add(1, 2) * 3",
+                "let total = add(1, 2) * 3;",
+                "{\n    let total = add(1, 2) * 3;\n}",
+                "fn main() {\n    let total = add(1, 2) * 3;\n}",
+                SOURCE,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shrink_follows_the_focus() {
+        let (tree, rope) = parse(SOURCE);
+        let statement = Range::new(Position::new(1, 4), Position::new(1, 30));
+
+        // Focus on `add(1, 2)` picks the value over the pattern
+        let shrunk = shrink_range(&tree, &rope, &statement, Position::new(1, 17))
+            .unwrap()
+            .unwrap();
+        assert_eq!(text(&rope, &shrunk), "add(1, 2) * 3");
+
+        let shrunk = shrink_range(&tree, &rope, &shrunk, Position::new(1, 17))
+            .unwrap()
+            .unwrap();
+        assert_eq!(text(&rope, &shrunk), "add(1, 2)");
+
+        let add = Range::new(Position::new(1, 16), Position::new(1, 19));
+        assert!(shrink_range(&tree, &rope, &add, Position::new(1, 17))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,10 +1,20 @@
 use anyhow::{Context, Result};
-use ropey::{Rope, RopeSlice};
+use ropey::{Rope, RopeBuilder, RopeSlice};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
 use std::fmt;
-use std::path::PathBuf;
-use tracing::{debug, instrument, warn};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, instrument, warn};
+
+use crate::core::anchor::{self, AnchorId, AnchorSet, Gravity, RangeAnchor};
+use crate::core::atomic_save::{write_atomic, BackupMode};
+use crate::core::cursor::Cursor;
+use crate::core::encoding::{FileEncoding, SNIFF_LEN};
+use crate::core::snapshot::{self, BufferSnapshot};
+use crate::core::undo_tree::{UndoNodeId, UndoTree};
+use crate::core::utils::is_word_char;
+use crate::core::{EditorError, EditorResult};
 
 /// Represents a position in the text buffer
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -88,36 +98,90 @@ impl TextEdit {
     pub fn replace(range: Range, text: String) -> Self {
         Self::new(range, text)
     }
+
+    /// Position just past the inserted text once this edit has been applied
+    pub fn inserted_end(&self) -> Position {
+        anchor::inserted_end(self.range.start, &self.new_text)
+    }
+}
+
+/// A change as it was applied to the rope, with enough information to invert it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedEdit {
+    /// Character offset the change was applied at
+    pub start: usize,
+    /// Text that was removed
+    pub removed: String,
+    /// Text that was inserted
+    pub inserted: String,
+}
+
+/// How an undo group may be merged with its neighbours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UndoGroupKind {
+    /// Always becomes its own undo step
+    Discrete,
+    /// Typed text that may be merged with the preceding typing step
+    Typing,
 }
 
 /// Undo/Redo entry containing multiple edits that should be treated as one operation
-#[debug(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UndoRedoEntry {
-    pub edits: Vec<TextEdit>,
-    pub cursor_before: Vec<Position>,
-    pub cursor_after: Vec<Position>,
-    pub timestamp: std::time::SystemTime,
+    /// Edits in the order they were applied
+    pub edits: Vec<RecordedEdit>,
+    pub cursor_before: Vec<Cursor>,
+    pub cursor_after: Vec<Cursor>,
+    pub kind: UndoGroupKind,
+    pub timestamp: SystemTime,
 }
 
 impl UndoRedoEntry {
     pub fn new(
-        edits: Vec<TextEdit>,
-        cursor_before: Vec<Position>,
-        cursor_after: Vec<Position>,
+        edits: Vec<RecordedEdit>,
+        cursor_before: Vec<Cursor>,
+        cursor_after: Vec<Cursor>,
     ) -> Self {
         Self {
             edits,
             cursor_before,
             cursor_after,
-            timestamp: std::time::SystemTime::now(),
+            kind: UndoGroupKind::Discrete,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    /// Whether a typing step starting with `next` begins a new word after this entry
+    fn starts_new_word(&self, next: &UndoRedoEntry) -> bool {
+        let previous_char = self
+            .edits
+            .last()
+            .and_then(|edit| edit.inserted.chars().last());
+        let next_char = next
+            .edits
+            .first()
+            .and_then(|edit| edit.inserted.chars().next());
+
+        match (previous_char, next_char) {
+            (Some(previous), Some(next)) => is_word_char(next) && !is_word_char(previous),
+            _ => true,
         }
     }
 }
 
+/// An undo group that is still collecting edits
+#[derive(Debug)]
+struct PendingUndoGroup {
+    kind: UndoGroupKind,
+    edits: Vec<RecordedEdit>,
+    cursor_before: Vec<Cursor>,
+}
+
 // Event fired when the buffer changes
 #[derive(Debug, Clone, Serialize)]
 pub struct BufferChangeEvent {
     pub version: u64,
+    /// Edits in the order they were applied, each relative to the text the previous one left
     pub edits: Vec<TextEdit>,
     pub full_text_length: usize,
     pub line_count: usize,
@@ -136,6 +200,12 @@ pub struct BufferConfig {
     pub trim_trailing_whitespace: bool,
     /// Whether to ensure file ends with newline
     pub insert_final_newline: bool,
+    /// Maximum pause between keystrokes that are merged into one undo step
+    pub undo_coalesce_timeout_ms: u64,
+    /// Backups of the previous file contents kept on save
+    pub backup_mode: BackupMode,
+    /// Thresholds for large-file mode
+    pub large_file: LargeFileConfig,
 }
 
 impl Default for BufferConfig {
@@ -146,8 +216,69 @@ impl Default for BufferConfig {
             line_ending: LineEnding::default(),
             trim_trailing_whitespace: true,
             insert_final_newline: true,
+            undo_coalesce_timeout_ms: 1000,
+            backup_mode: BackupMode::None,
+            large_file: LargeFileConfig::default(),
+        }
+    }
+}
+
+/// Size limits above which features are scaled back to keep huge files responsive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LargeFileConfig {
+    /// Files at least this large are loaded in chunks and open in large-file mode
+    pub threshold_mb: u64,
+    /// Buffers larger than this are not syntax highlighted
+    pub max_highlight_mb: u64,
+    /// Maximum number of matches a search collects in large-file mode
+    pub max_search_results: usize,
+}
+
+impl Default for LargeFileConfig {
+    fn default() -> Self {
+        Self {
+            threshold_mb: 50,
+            max_highlight_mb: 10,
+            max_search_results: 10_000,
+        }
+    }
+}
+
+impl LargeFileConfig {
+    const MB: u64 = 1024 * 1024;
+
+    /// Size in bytes at which large-file mode starts
+    pub fn threshold_bytes(&self) -> u64 {
+        self.threshold_mb.saturating_mul(Self::MB)
+    }
+
+    /// Size in bytes above which highlighting is disabled
+    pub fn max_highlight_bytes(&self) -> u64 {
+        self.max_highlight_mb.saturating_mul(Self::MB)
+    }
+}
+
+/// Size and modification time of a file, to tell it changed without reading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    /// Stamp of a file from its metadata
+    pub fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
         }
     }
+
+    /// Read the stamp of the file at `path`
+    pub async fn read(path: &Path) -> std::io::Result<Self> {
+        let metadata = tokio::fs::metadata(path).await?;
+        Ok(Self::from_metadata(&metadata))
+    }
 }
 
 /// Line ending styles
@@ -196,18 +327,14 @@ impl LineEnding {
                         cr_count += 1;
                     }
                 }
-                '\n' => {
-                    if i == 0 || chars[i + 1] != '\r' {
-                        lf_count += 1;
-                    }
-                }
+                '\n' if i == 0 || chars[i - 1] != '\r' => lf_count += 1,
                 _ => {}
             }
         }
 
-        if crlf_count >= lf_count && crlf_count >= cr_count {
+        if crlf_count > lf_count && crlf_count >= cr_count {
             LineEnding::Windows
-        } else if cr_count >= lf_count {
+        } else if cr_count > lf_count {
             LineEnding::Mac
         } else {
             LineEnding::Unix
@@ -225,14 +352,28 @@ pub struct TextBuffer {
     dirty: bool,
     /// File path (if associated with a file)
     file_path: Option<PathBuf>,
+    /// File contents as of the last load or save, the base for merging external changes
+    saved_text: Option<String>,
+    /// Size and modification time of the file as of the last load or save
+    disk_stamp: Option<FileStamp>,
     /// Line ending style for this buffer
     line_ending: LineEnding,
+    /// Encoding the file is read and written in
+    encoding: FileEncoding,
+    /// Whether the file was big enough to open in large-file mode
+    large_file: bool,
+    /// Positions kept up to date as the text changes
+    anchors: AnchorSet,
     /// Buffer configuration
     config: BufferConfig,
-    /// Undo stack
-    undo_stack: VecDeque<UndoRedoEntry>,
-    /// Redo stack
-    redo_stack: VecDeque<UndoRedoEntry>,
+    /// Undo history, keeping every branch
+    undo_tree: UndoTree,
+    /// Undo group currently collecting edits
+    pending_undo_group: Option<PendingUndoGroup>,
+    /// Nesting depth of open undo groups
+    undo_group_depth: usize,
+    /// Prevents the next typing step from merging with the previous one
+    coalesce_break: bool,
     /// Change event listeners
     change_listeners: Vec<Box<dyn Fn(&BufferChangeEvent) + Send + Sync>>,
 }
@@ -245,7 +386,7 @@ impl TextBuffer {
 
     /// Create a new text buffer with custom configuration
     pub fn with_config(config: BufferConfig) -> Self {
-        let rope: Rope::new();
+        let rope = Rope::new();
         debug!("Created new text buffer with {} lines", rope.len_lines());
 
         Self {
@@ -253,10 +394,17 @@ impl TextBuffer {
             version: 0,
             dirty: false,
             file_path: None,
+            saved_text: None,
+            disk_stamp: None,
             line_ending: config.line_ending,
+            encoding: FileEncoding::default(),
+            large_file: false,
+            anchors: AnchorSet::new(),
             config,
-            undo_stack: VecDeque::new(),
-            redo_stack: VecDeque::new(),
+            undo_tree: UndoTree::new(),
+            pending_undo_group: None,
+            undo_group_depth: 0,
+            coalesce_break: false,
             change_listeners: Vec::new(),
         }
     }
@@ -264,14 +412,27 @@ impl TextBuffer {
     /// Create a text buffer from file content
     #[instrument(skip(content))]
     pub fn from_content(content: &str, file_path: Option<PathBuf>) -> Result<Self> {
-        let config = BufferConfig::default();
+        Ok(Self::from_rope(
+            Rope::from_str(content),
+            content,
+            file_path,
+            BufferConfig::default(),
+        ))
+    }
+
+    /// Create a text buffer around a rope, detecting line endings from `sample`
+    fn from_rope(
+        rope: Rope,
+        sample: &str,
+        file_path: Option<PathBuf>,
+        config: BufferConfig,
+    ) -> Self {
         let line_ending = if config.auto_detect_line_endings {
-            LineEnding::detect(content)
+            LineEnding::detect(sample)
         } else {
             config.line_ending
         };
 
-        let rope = Rope::from_str(content);
         debug!(
             "Created text buffer from content: {} chars, {} lines, line ending: {:?}",
             rope.len_chars(),
@@ -279,27 +440,129 @@ impl TextBuffer {
             line_ending
         );
 
-        Ok(Self {
+        Self {
             rope,
             version: 0,
             dirty: false,
             file_path,
+            saved_text: None,
+            disk_stamp: None,
             line_ending,
+            encoding: FileEncoding::default(),
+            large_file: false,
+            anchors: AnchorSet::new(),
             config,
-            undo_stack: VecDeque::new(),
-            redo_stack: VecDeque::new(),
+            undo_tree: UndoTree::new(),
+            pending_undo_group: None,
+            undo_group_depth: 0,
+            coalesce_break: false,
             change_listeners: Vec::new(),
-        })
+        }
     }
 
-    /// Load text buffer from file
+    /// Load text buffer from file, detecting its encoding
     #[instrument]
     pub async fn from_file(path: PathBuf) -> Result<Self> {
-        let content = tokio::fs::read_to_string(&path)
+        Self::from_file_with_config(path, None, BufferConfig::default()).await
+    }
+
+    /// Load text buffer from file in a specific encoding
+    #[instrument]
+    pub async fn from_file_with_encoding(path: PathBuf, encoding: FileEncoding) -> Result<Self> {
+        Self::from_file_with_config(path, Some(encoding), BufferConfig::default()).await
+    }
+
+    /// Load text buffer from file with the given configuration
+    ///
+    /// Files above the large-file threshold are decoded in chunks straight into
+    /// the rope, without a copy of the whole text, and open in large-file mode.
+    #[instrument(skip(config))]
+    pub async fn from_file_with_config(
+        path: PathBuf,
+        encoding: Option<FileEncoding>,
+        config: BufferConfig,
+    ) -> Result<Self> {
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let stamp = FileStamp::from_metadata(&metadata);
+
+        if metadata.len() >= config.large_file.threshold_bytes() {
+            let mut buffer = Self::load_large(path, encoding, config).await?;
+            buffer.disk_stamp = Some(stamp);
+            return Ok(buffer);
+        }
+
+        let bytes = tokio::fs::read(&path)
             .await
             .with_context(|| format!("Failed to read file: {}", path.display()))?;
 
-        Self::from_content(&content, Some(path))
+        let encoding = resolve_encoding(encoding, FileEncoding::detect(&bytes));
+        let content = encoding.decode(&bytes)?;
+
+        debug!("Decoded {} as {}", path.display(), encoding);
+
+        let mut buffer = Self::from_rope(Rope::from_str(&content), &content, Some(path), config);
+        buffer.encoding = encoding;
+        buffer.saved_text = Some(content);
+        buffer.disk_stamp = Some(stamp);
+        Ok(buffer)
+    }
+
+    async fn load_large(
+        path: PathBuf,
+        encoding: Option<FileEncoding>,
+        config: BufferConfig,
+    ) -> Result<Self> {
+        let (rope, encoding) = Self::read_chunked(&path, encoding).await?;
+
+        // Line endings are detected from the start of the file only
+        let sample_end = rope.len_chars().min(SNIFF_LEN);
+        let sample = rope.slice(..sample_end).to_string();
+
+        let mut buffer = Self::from_rope(rope, &sample, Some(path), config);
+        buffer.encoding = encoding;
+        buffer.large_file = true;
+
+        info!(
+            "Opened {} in large-file mode ({} bytes)",
+            buffer
+                .file_path
+                .as_ref()
+                .map_or_else(String::new, |p| p.display().to_string()),
+            buffer.rope.len_bytes()
+        );
+        Ok(buffer)
+    }
+
+    /// Read a file in chunks straight into a rope, without a copy of the whole text
+    pub async fn read_rope(path: &Path, encoding: FileEncoding) -> Result<Rope> {
+        let (rope, _) = Self::read_chunked(path, Some(encoding)).await?;
+        Ok(rope)
+    }
+
+    async fn read_chunked(
+        path: &Path,
+        encoding: Option<FileEncoding>,
+    ) -> Result<(Rope, FileEncoding)> {
+        let read_path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || read_chunked(&read_path, encoding))
+            .await
+            .context("File loading task failed")?
+            .with_context(|| format!("Failed to read file: {}", path.display()))
+    }
+
+    /// Create a buffer holding new contents of this buffer's file
+    ///
+    /// The file settings carry over and the version keeps counting up, but the
+    /// undo history and anchors start over.
+    pub fn reloaded(&self, rope: Rope) -> Self {
+        let mut buffer = Self::from_rope(rope, "", self.file_path.clone(), self.config.clone());
+        buffer.version = self.version + 1;
+        buffer.line_ending = self.line_ending;
+        buffer.encoding = self.encoding;
+        buffer.large_file = self.large_file;
+        buffer
     }
 
     /// Get the current version of the buffer
@@ -322,6 +585,39 @@ impl TextBuffer {
         self.file_path = path;
     }
 
+    /// Check if the file was opened in large-file mode
+    ///
+    /// Large files keep no copy of their saved contents, so external changes
+    /// can't be merged, and are left out of swap files and the save timeline.
+    pub fn is_large_file(&self) -> bool {
+        self.large_file
+    }
+
+    /// Get the file contents as of the last load or save
+    pub fn saved_text(&self) -> Option<&str> {
+        self.saved_text.as_deref()
+    }
+
+    /// Record the contents the associated file now has on disk
+    pub fn set_saved_text(&mut self, text: Option<String>) {
+        self.saved_text = text;
+    }
+
+    /// Get the size and modification time of the file as of the last load or save
+    pub fn disk_stamp(&self) -> Option<FileStamp> {
+        self.disk_stamp
+    }
+
+    /// Record the size and modification time the associated file now has
+    pub fn set_disk_stamp(&mut self, stamp: Option<FileStamp>) {
+        self.disk_stamp = stamp;
+    }
+
+    /// Mark the buffer as having (or not having) unsaved changes
+    pub fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+    }
+
     /// Get the line ending style
     pub fn line_ending(&self) -> LineEnding {
         self.line_ending
@@ -333,6 +629,17 @@ impl TextBuffer {
         self.mark_dirty();
     }
 
+    /// Get the encoding the file is saved in
+    pub fn encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+
+    /// Set the encoding the file is saved in
+    pub fn set_encoding(&mut self, encoding: FileEncoding) {
+        self.encoding = encoding;
+        self.mark_dirty();
+    }
+
     /// Get buffer configuration
     pub fn config(&self) -> &BufferConfig {
         &self.config
@@ -387,57 +694,58 @@ impl TextBuffer {
 
     /// Get the length of a specific line (excluding line ending)
     pub fn line_len(&self, line: usize) -> Result<usize> {
-        if line >= self.len_lines() {
-            return Err(anyhow::anyhow!("Line {} is out of bounds", line));
-        }
-
-        let line_slice = self.rope.line(line);
-        let mut len = line_slice.len_chars();
-
-        let line_str = line_slice.to_string();
-        if line_str.ends_with("\r\n") {
-            len = len.saturating_sub(2);
-        } else if line_str.ends_with('\n') || line_str.ends_with('\r') {
-            len = len.saturating_sub(1);
-        }
-
-        Ok(len)
+        snapshot::line_len(&self.rope, line)
     }
 
     /// Convert a position to a character index
     pub fn position_to_char_index(&self, position: Position) -> Result<usize> {
-        if position.line >= self.len_lines() {
-            return Err(anyhow::anyhow!("Line {} is out of bounds", position.line));
-        }
+        snapshot::position_to_char_index(&self.rope, position)
+    }
 
-        let line_start_char = self.rope.line_to_char(position.line);
-        let line_len = self.line_len(position.line)?;
+    /// Convert a character index to a position
+    pub fn char_index_to_position(&self, char_index: usize) -> Result<Position> {
+        snapshot::char_index_to_position(&self.rope, char_index)
+    }
 
-        if position.column > line_len {
-            return Err(anyhow::anyhow!(
-                "Column {} is out of bounds for line {}",
-                position.column,
-                position.line
-            ));
-        }
+    /// Take an immutable snapshot of the current contents
+    ///
+    /// The snapshot shares the rope's storage, so this is cheap even for large
+    /// files, and it can be read from other threads while editing continues.
+    pub fn snapshot(&self) -> BufferSnapshot {
+        BufferSnapshot::new(self.rope.clone(), self.version, self.file_path.clone())
+    }
 
-        Ok(line_start_char + position.column)
+    /// Create an anchor that keeps pointing at the same text as the buffer changes
+    pub fn create_anchor(&mut self, position: Position, gravity: Gravity) -> Result<AnchorId> {
+        self.position_to_char_index(position)?;
+        Ok(self.anchors.insert(position, gravity))
     }
 
-    /// Convert a character index to a position
-    pub fn char_index_to_position(&self, char_index: usize) -> Result<Position> {
-        if char_index > self.len_chars() {
-            return Err(anyhow::anyhow!(
-                "Character index {} is out of bounds",
-                char_index
-            ));
-        }
+    /// Create an anchor that keeps covering the same text as the buffer changes
+    pub fn create_range_anchor(&mut self, range: Range) -> Result<RangeAnchor> {
+        self.position_to_char_index(range.start)?;
+        self.position_to_char_index(range.end)?;
+        Ok(self.anchors.insert_range(range))
+    }
+
+    /// Get the current position of an anchor
+    pub fn anchor_position(&self, id: AnchorId) -> Option<Position> {
+        self.anchors.get(id)
+    }
 
-        let line = self.rope.char_to_line(char_index);
-        let line_start_char = self.rope.line_to_char(line);
-        let column = char_index - line_start_char;
+    /// Get the current range of a range anchor
+    pub fn anchor_range(&self, range: RangeAnchor) -> Option<Range> {
+        self.anchors.get_range(range)
+    }
+
+    /// Stop tracking an anchor
+    pub fn remove_anchor(&mut self, id: AnchorId) -> bool {
+        self.anchors.remove(id)
+    }
 
-        Ok(Position::new(line, column))
+    /// Stop tracking a range anchor
+    pub fn remove_range_anchor(&mut self, range: RangeAnchor) -> bool {
+        self.anchors.remove_range(range)
     }
 
     /// Apply a single text edit to the buffer
@@ -453,29 +761,29 @@ impl TextBuffer {
             return Ok(());
         }
 
-        let mut sorted_edits = edits.clone();
+        let mut sorted_edits = edits;
         sorted_edits.sort_by(|a, b| b.range.start.cmp(&a.range.start));
 
         for edit in &sorted_edits {
             self.validate_edit(edit)?;
         }
 
+        let mut recorded = Vec::with_capacity(sorted_edits.len());
         for edit in &sorted_edits {
-            self.apply_single_edit(edit)?;
+            recorded.push(self.apply_single_edit(edit)?);
         }
 
         self.version += 1;
         self.mark_dirty();
 
-        self.redo_stack.clear();
-
-        self.fire_change_event(edits);
+        self.record_undo(recorded);
 
         debug!(
             "Applied {} edits, new version: {}",
             sorted_edits.len(),
             self.version
         );
+        self.fire_change_event(sorted_edits);
         Ok(())
     }
 
@@ -495,110 +803,331 @@ impl TextBuffer {
     }
 
     /// Apply a single edit to the rope
-    fn apply_single_edit(&mut self, edit: &TextEdit) -> Result<()> {
+    fn apply_single_edit(&mut self, edit: &TextEdit) -> Result<RecordedEdit> {
         let start_char = self.position_to_char_index(edit.range.start)?;
         let end_char = self.position_to_char_index(edit.range.end)?;
 
-        // Remove the old text
+        let removed = self.rope.slice(start_char..end_char).to_string();
+        self.replace_chars(start_char, end_char, &edit.new_text);
+
+        Ok(RecordedEdit {
+            start: start_char,
+            removed,
+            inserted: edit.new_text.clone(),
+        })
+    }
+
+    /// Replace a character range of the rope with new text
+    ///
+    /// Every change to the rope goes through here, so anchors are moved here too.
+    fn replace_chars(&mut self, start_char: usize, end_char: usize, text: &str) {
+        if !self.anchors.is_empty() {
+            if let (Ok(start), Ok(end)) = (
+                self.char_index_to_position(start_char),
+                self.char_index_to_position(end_char),
+            ) {
+                self.anchors.adjust(&Range::new(start, end), text);
+            }
+        }
+
         if start_char < end_char {
             self.rope.remove(start_char..end_char);
         }
 
-        // Insert the new text
-        if !edit.new_text.is_empty() {
-            self.rope.insert(start_char, &edit.new_text);
+        if !text.is_empty() {
+            self.rope.insert(start_char, text);
         }
+    }
 
-        Ok(())
+    /// Replay a recorded edit, either forwards (redo) or backwards (undo)
+    fn replay_edit(&mut self, edit: &RecordedEdit, forwards: bool) -> Result<TextEdit> {
+        let (remove, insert) = if forwards {
+            (&edit.removed, &edit.inserted)
+        } else {
+            (&edit.inserted, &edit.removed)
+        };
+        let end_char = edit.start + remove.chars().count();
+
+        let range = Range::new(
+            self.char_index_to_position(edit.start)?,
+            self.char_index_to_position(end_char)?,
+        );
+        self.replace_chars(edit.start, end_char, insert);
+
+        Ok(TextEdit::replace(range, insert.clone()))
+    }
+
+    /// Store applied edits in the open undo group, or as their own undo step
+    fn record_undo(&mut self, edits: Vec<RecordedEdit>) {
+        match self.pending_undo_group.as_mut() {
+            Some(group) => group.edits.extend(edits),
+            None => self.push_undo_entry(UndoRedoEntry::new(edits, Vec::new(), Vec::new())),
+        }
+    }
+
+    /// Add an entry to the undo tree, keeping the tree size manageable
+    fn push_undo_entry(&mut self, entry: UndoRedoEntry) {
+        self.undo_tree.push(entry);
+        self.undo_tree.prune(self.config.max_undo_entries);
     }
 
     /// Begin an undo group (for grouping multiple edits into one undo operation)
-    pub fn begin_undo_group(&mut self, cursor_positions: Vec<Position>) {
-        // This will be used when we implement the undo group functionality
-        // For now, we store the cursor positions for the next undo entry
+    ///
+    /// Groups may be nested; only the outermost group produces an undo step.
+    pub fn begin_undo_group(&mut self, cursor_before: Vec<Cursor>, kind: UndoGroupKind) {
+        self.undo_group_depth += 1;
+
+        if self.undo_group_depth == 1 {
+            self.pending_undo_group = Some(PendingUndoGroup {
+                kind,
+                edits: Vec::new(),
+                cursor_before,
+            });
+        }
     }
 
     /// End an undo group
-    pub fn end_undo_group(&mut self, edits: Vec<TextEdit>, final_cursor_positions: Vec<Position>) {
-        let entry = UndoRedoEntry::new(
-            edits,
-            vec![], // We'll implement proper cursor tracking later
-            final_cursor_positions,
-        );
+    pub fn end_undo_group(&mut self, cursor_after: Vec<Cursor>) {
+        if self.undo_group_depth == 0 {
+            warn!("end_undo_group called without a matching begin_undo_group");
+            return;
+        }
 
-        self.undo_stack.push_back(entry);
+        self.undo_group_depth -= 1;
+        if self.undo_group_depth > 0 {
+            return;
+        }
 
-        // Keep undo stack size manageable
-        while self.undo_stack.len() > self.config.max_undo_entries {
-            self.undo_stack.pop_front();
+        let Some(group) = self.pending_undo_group.take() else {
+            return;
+        };
+
+        if group.edits.is_empty() {
+            return;
         }
-    }
 
-    /// Undo the last operation
-    pub fn undo(&mut self) -> Result<Option<Vec<Position>>> {
-        if let Some(entry) = self.undo_stack.pop_back() {
-            let mut inverse_edits = Vec::new();
-            for edit in &entry.edits {
-                let old_text = self.text_in_range(&edit_range)?;
-                let inverse_edit =
-                    TextEdit::new(Range::new(edit.range.start, edit.range.start), old_text);
-                inverse_edits.push(inverse_edit);
+        let entry = UndoRedoEntry {
+            edits: group.edits,
+            cursor_before: group.cursor_before,
+            cursor_after,
+            kind: group.kind,
+            timestamp: SystemTime::now(),
+        };
+
+        if self.should_coalesce(&entry) {
+            if let Some(previous) = self.undo_tree.mergeable_entry_mut() {
+                previous.edits.extend(entry.edits);
+                previous.cursor_after = entry.cursor_after;
+                previous.timestamp = entry.timestamp;
             }
+        } else {
+            self.push_undo_entry(entry);
+        }
+
+        self.coalesce_break = false;
+    }
+
+    /// Check whether a finished typing step should merge into the previous one
+    fn should_coalesce(&self, entry: &UndoRedoEntry) -> bool {
+        if entry.kind != UndoGroupKind::Typing || self.coalesce_break {
+            return false;
+        }
+
+        let Some(previous) = self.undo_tree.mergeable_entry() else {
+            return false;
+        };
+
+        let timeout = Duration::from_millis(self.config.undo_coalesce_timeout_ms);
+        let within_timeout = entry
+            .timestamp
+            .duration_since(previous.timestamp)
+            .map(|elapsed| elapsed <= timeout)
+            .unwrap_or(false);
+
+        previous.kind == UndoGroupKind::Typing
+            && within_timeout
+            && previous.cursor_after == entry.cursor_before
+            && !previous.starts_new_word(entry)
+    }
+
+    /// Stop the next typing step from merging with the previous one
+    pub fn break_undo_coalescing(&mut self) {
+        self.coalesce_break = true;
+    }
+
+    /// Undo the last operation, returning the cursors from before it
+    pub fn undo(&mut self) -> Result<Option<Vec<Cursor>>> {
+        if !self.undo_tree.can_undo() {
+            return Ok(None);
+        }
+
+        let (changes, cursors) = self.replay_node(self.undo_tree.current(), false)?;
+        self.finish_history_step(changes);
+
+        debug!("Undid operation, version {}", self.version);
+        Ok(Some(cursors))
+    }
+
+    /// Redo the last undone operation, returning the cursors from after it
+    pub fn redo(&mut self) -> Result<Option<Vec<Cursor>>> {
+        let Some(target) = self.undo_tree.redo_target() else {
+            return Ok(None);
+        };
+
+        let (changes, cursors) = self.replay_node(target, true)?;
+        self.finish_history_step(changes);
 
-            self.apply_edit(inverse_edits.clone())?;
+        debug!("Redid operation, version {}", self.version);
+        Ok(Some(cursors))
+    }
 
-            let redo_entry = UndoRedoEntry::new(
-                inverse_edits,
-                entry.cursor_after.clone(),
-                entry.cursor_before.clone(),
-            );
-            self.redo_stack.push_back(redo_entry);
+    /// Move to any state in the undo tree, returning the cursors for that state
+    pub fn jump_to_undo_node(&mut self, target: UndoNodeId) -> Result<Option<Vec<Cursor>>> {
+        let (undo_path, redo_path) = self
+            .undo_tree
+            .path(self.undo_tree.current(), target)
+            .ok_or_else(|| anyhow::anyhow!("Undo node {} does not exist", target))?;
 
-            debug!("Undid operation, returning to version {}", self.version - 1);
-            return Ok(Some(entry.cursor_before));
+        if undo_path.is_empty() && redo_path.is_empty() {
+            return Ok(None);
         }
 
-        Ok(None)
+        let mut changes = Vec::new();
+        let mut cursors = Vec::new();
+
+        for node in undo_path {
+            let (node_changes, node_cursors) = self.replay_node(node, false)?;
+            changes.extend(node_changes);
+            cursors = node_cursors;
+        }
+
+        for node in redo_path {
+            let (node_changes, node_cursors) = self.replay_node(node, true)?;
+            changes.extend(node_changes);
+            cursors = node_cursors;
+        }
+
+        self.finish_history_step(changes);
+
+        debug!("Jumped to undo node {}, version {}", target, self.version);
+        Ok(Some(cursors))
     }
 
-    /// Redo the last undone operation
-    pub fn redo(&mut self) -> Result<Option<Vec<Position>>> {
-        if let Some(entry) = self.redo_stack.pop_back() {
-            self.apply_edits(entry.edits.clone())?;
+    /// Go back to the state the buffer was in the given time before the current one
+    pub fn undo_earlier(&mut self, duration: Duration) -> Result<Option<Vec<Cursor>>> {
+        let time = self
+            .undo_tree
+            .current_timestamp()
+            .checked_sub(duration)
+            .unwrap_or(UNIX_EPOCH);
 
-            self.undo_stack.push_back(entry.clone());
+        self.jump_to_undo_node(self.undo_tree.node_at_time(time))
+    }
+
+    /// Go forward to the state the buffer was in the given time after the current one
+    pub fn undo_later(&mut self, duration: Duration) -> Result<Option<Vec<Cursor>>> {
+        let time = self
+            .undo_tree
+            .current_timestamp()
+            .checked_add(duration)
+            .unwrap_or_else(SystemTime::now);
+
+        self.jump_to_undo_node(self.undo_tree.node_at_time(time))
+    }
 
-            debug!("Redid operation, version {}", self.version);
-            return Ok(Some(entry.cursor_after));
+    /// Apply or revert the edits of an undo node and move the tree to the resulting state
+    fn replay_node(
+        &mut self,
+        id: UndoNodeId,
+        forwards: bool,
+    ) -> Result<(Vec<TextEdit>, Vec<Cursor>)> {
+        let node = self
+            .undo_tree
+            .node(id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Undo node {} does not exist", id))?;
+
+        let mut changes = Vec::with_capacity(node.entry.edits.len());
+        if forwards {
+            for edit in &node.entry.edits {
+                changes.push(self.replay_edit(edit, true)?);
+            }
+            self.undo_tree.set_current(id);
+            Ok((changes, node.entry.cursor_after))
+        } else {
+            for edit in node.entry.edits.iter().rev() {
+                changes.push(self.replay_edit(edit, false)?);
+            }
+            if let Some(parent) = node.parent {
+                self.undo_tree.set_current(parent);
+            }
+            Ok((changes, node.entry.cursor_before))
         }
+    }
 
-        Ok(None)
+    /// Bookkeeping shared by all undo tree navigation
+    fn finish_history_step(&mut self, changes: Vec<TextEdit>) {
+        self.version += 1;
+        self.mark_dirty();
+        self.coalesce_break = true;
+        self.fire_change_event(changes);
     }
 
     /// Check if undo is available
     pub fn can_undo(&self) -> bool {
-        !self.undo_stack.is_empty()
+        self.undo_tree.can_undo()
     }
 
     /// Check if redo is available
     pub fn can_redo(&self) -> bool {
-        !self.redo_stack.is_empty()
+        self.undo_tree.can_redo()
+    }
+
+    /// Get the undo history tree
+    pub fn undo_tree(&self) -> &UndoTree {
+        &self.undo_tree
+    }
+
+    /// Replace the undo history, e.g. with one restored from a previous session
+    ///
+    /// The tree's current state must match the buffer's current text.
+    pub fn set_undo_tree(&mut self, undo_tree: UndoTree) {
+        self.undo_tree = undo_tree;
+        self.pending_undo_group = None;
+        self.undo_group_depth = 0;
+        self.coalesce_break = true;
     }
 
     /// Save the buffer to its associated file
     #[instrument(skip(self))]
-    pub async fn save(&mut self) -> Result<()> {
-        let file_path = self
-            .file_path
-            .clone()
-            .ok_or_else(|| anyhow::anyhow!("No file path associated with buffer"))?;
+    pub async fn save(&mut self) -> EditorResult<()> {
+        let file_path = self.file_path.clone().ok_or(EditorError::NoFile)?;
 
         self.save_to_file(file_path).await
     }
 
     /// Save the buffer to a specific file
+    ///
+    /// The file is replaced atomically, so a failed save leaves it untouched.
+    /// Text that can't be represented in the buffer's encoding fails the save.
     #[instrument(skip(self))]
-    pub async fn save_to_file(&mut self, path: PathBuf) -> Result<()> {
+    pub async fn save_to_file(&mut self, path: PathBuf) -> EditorResult<()> {
+        let content = self.content_for_save();
+        let bytes = self.encoding.encode(&content)?;
+
+        write_atomic(&path, bytes, self.config.backup_mode).await?;
+
+        self.file_path = Some(path.clone());
+        self.saved_text = (!self.large_file).then_some(content);
+        self.disk_stamp = FileStamp::read(&path).await.ok();
+        self.dirty = false;
+
+        debug!("Saved buffer to: {}", path.display());
+        Ok(())
+    }
+
+    /// Get the text as it is written to disk, with save-time cleanups applied
+    pub fn content_for_save(&self) -> String {
         let mut content = self.text();
 
         if self.config.trim_trailing_whitespace {
@@ -609,15 +1138,7 @@ impl TextBuffer {
             content.push_str(self.line_ending.as_str());
         }
 
-        tokio::fs::write(&path, content)
-            .await
-            .with_context(|| format!("Failed to write file: {}", path.display()))?;
-
-        self.file_path = Some(path.clone());
-        self.dirty = false;
-
-        debug!("Saved buffer to: {}", path.display());
-        Ok(())
+        content
     }
 
     /// Trim trailing whitespace from content
@@ -648,7 +1169,7 @@ impl TextBuffer {
     /// Fire change event to all listeners
     fn fire_change_event(&self, edits: Vec<TextEdit>) {
         let event = BufferChangeEvent {
-            verson: self.version,
+            version: self.version,
             edits,
             full_text_length: self.len_chars(),
             line_count: self.len_lines(),
@@ -670,6 +1191,32 @@ impl TextBuffer {
     }
 }
 
+/// Pick the encoding to load a file in from the requested and detected ones
+fn resolve_encoding(requested: Option<FileEncoding>, detected: FileEncoding) -> FileEncoding {
+    match requested {
+        // Keep the BOM the file actually has when the encoding matches
+        Some(encoding) if encoding.encoding == detected.encoding => detected,
+        Some(encoding) => encoding,
+        None => detected,
+    }
+}
+
+/// Decode a file into a rope chunk by chunk
+fn read_chunked(path: &Path, encoding: Option<FileEncoding>) -> EditorResult<(Rope, FileEncoding)> {
+    let mut file = std::fs::File::open(path)?;
+
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    file.by_ref()
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+    let encoding = resolve_encoding(encoding, FileEncoding::detect_prefix(&head));
+
+    let mut builder = RopeBuilder::new();
+    encoding.decode_reader(head.as_slice().chain(file), |chunk| builder.append(chunk))?;
+
+    Ok((builder.finish(), encoding))
+}
+
 impl Default for TextBuffer {
     fn default() -> Self {
         Self::new()
@@ -683,10 +1230,12 @@ impl fmt::Debug for TextBuffer {
             .field("dirty", &self.dirty)
             .field("file_path", &self.file_path)
             .field("line_ending", &self.line_ending)
+            .field("encoding", &self.encoding)
+            .field("large_file", &self.large_file)
+            .field("anchor_count", &self.anchors.len())
             .field("char_count", &self.len_chars())
             .field("line_count", &self.len_lines())
-            .field("undo_stack_size", &self.undo_stack.len())
-            .field("redo_stack_size", &self.redo_stack.len())
+            .field("undo_tree_size", &self.undo_tree.len())
             .finish()
     }
 }
@@ -833,4 +1382,192 @@ mod tests {
         assert_eq!(buffer.text(), "Hello, Rust");
         assert_eq!(buffer.version(), 1);
     }
+
+    #[test]
+    fn test_undo_redo_restores_text() {
+        let mut buffer = TextBuffer::from_content("Hello World", None).unwrap();
+        buffer
+            .apply_edit(TextEdit::replace(
+                Range::new(Position::new(0, 6), Position::new(0, 11)),
+                "Rust\nEditor".to_string(),
+            ))
+            .unwrap();
+
+        buffer.undo().unwrap();
+        assert_eq!(buffer.text(), "Hello World");
+        assert!(buffer.can_redo());
+
+        buffer.redo().unwrap();
+        assert_eq!(buffer.text(), "Hello Rust\nEditor");
+        assert!(!buffer.can_redo());
+    }
+
+    #[test]
+    fn test_undo_group_is_one_step() {
+        let mut buffer = TextBuffer::from_content("abc", None).unwrap();
+        buffer.begin_undo_group(Vec::new(), UndoGroupKind::Discrete);
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 3), "d".to_string()))
+            .unwrap();
+        buffer
+            .apply_edit(TextEdit::delete(Range::new(
+                Position::new(0, 0),
+                Position::new(0, 1),
+            )))
+            .unwrap();
+        buffer.end_undo_group(Vec::new());
+        assert_eq!(buffer.text(), "bcd");
+
+        buffer.undo().unwrap();
+        assert_eq!(buffer.text(), "abc");
+        assert!(!buffer.can_undo());
+    }
+
+    #[test]
+    fn test_typing_coalesces_until_word_boundary() {
+        let mut buffer = TextBuffer::new();
+        for (column, ch) in "ab cd".chars().enumerate() {
+            buffer.begin_undo_group(Vec::new(), UndoGroupKind::Typing);
+            buffer
+                .apply_edit(TextEdit::insert(Position::new(0, column), ch.to_string()))
+                .unwrap();
+            buffer.end_undo_group(Vec::new());
+        }
+
+        buffer.undo().unwrap();
+        assert_eq!(buffer.text(), "ab ");
+        buffer.undo().unwrap();
+        assert_eq!(buffer.text(), "");
+    }
+
+    #[test]
+    fn test_edit_after_undo_keeps_branch() {
+        let mut buffer = TextBuffer::from_content("base", None).unwrap();
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 4), " one".to_string()))
+            .unwrap();
+        let first_branch = buffer.undo_tree().current();
+
+        buffer.undo().unwrap();
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 4), " two".to_string()))
+            .unwrap();
+        assert_eq!(buffer.text(), "base two");
+        assert_eq!(buffer.undo_tree().branches().len(), 2);
+
+        buffer.jump_to_undo_node(first_branch).unwrap();
+        assert_eq!(buffer.text(), "base one");
+
+        buffer.undo().unwrap();
+        buffer.redo().unwrap();
+        assert_eq!(buffer.text(), "base one");
+    }
+
+    #[test]
+    fn test_undo_earlier_by_time() {
+        let mut buffer = TextBuffer::from_content("a", None).unwrap();
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 1), "b".to_string()))
+            .unwrap();
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 2), "c".to_string()))
+            .unwrap();
+
+        buffer.undo_earlier(Duration::from_secs(3600)).unwrap();
+        assert_eq!(buffer.text(), "a");
+
+        buffer.undo_later(Duration::from_secs(3600)).unwrap();
+        assert_eq!(buffer.text(), "abc");
+    }
+
+    #[test]
+    fn test_anchors_follow_edits_and_undo() {
+        let mut buffer = TextBuffer::from_content("fn main() {\n    todo!();\n}\n", None).unwrap();
+        let call = buffer
+            .create_range_anchor(Range::new(Position::new(1, 4), Position::new(1, 11)))
+            .unwrap();
+        let bookmark = buffer
+            .create_anchor(Position::new(2, 0), Gravity::Left)
+            .unwrap();
+
+        buffer
+            .apply_edits(vec![
+                TextEdit::insert(Position::new(0, 0), "// entry\n".to_string()),
+                TextEdit::insert(Position::new(1, 4), "let _ = ".to_string()),
+            ])
+            .unwrap();
+        assert_eq!(
+            buffer.anchor_range(call),
+            Some(Range::new(Position::new(2, 12), Position::new(2, 19)))
+        );
+        assert_eq!(buffer.anchor_position(bookmark), Some(Position::new(3, 0)));
+
+        buffer.undo().unwrap();
+        assert_eq!(
+            buffer.anchor_range(call),
+            Some(Range::new(Position::new(1, 4), Position::new(1, 11)))
+        );
+        assert_eq!(buffer.anchor_position(bookmark), Some(Position::new(2, 0)));
+
+        assert!(buffer.remove_anchor(bookmark));
+        assert_eq!(buffer.anchor_position(bookmark), None);
+        assert!(buffer
+            .create_anchor(Position::new(9, 0), Gravity::Right)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_encoding_round_trip() {
+        use crate::core::encoding::TextEncoding;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("fixture.txt");
+        let utf16 = FileEncoding::new(TextEncoding::Utf16Le, true);
+        std::fs::write(&path, utf16.encode("h\u{e9}llo\n").unwrap()).unwrap();
+
+        let mut buffer = TextBuffer::from_file(path.clone()).await.unwrap();
+        assert_eq!(buffer.text(), "h\u{e9}llo\n");
+        assert_eq!(buffer.encoding(), utf16);
+
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 5), "!".to_string()))
+            .unwrap();
+        buffer.save().await.unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            utf16.encode("h\u{e9}llo!\n").unwrap()
+        );
+
+        let latin1 = FileEncoding::new(TextEncoding::Latin1, false);
+        let buffer = TextBuffer::from_file_with_encoding(path, latin1)
+            .await
+            .unwrap();
+        assert_eq!(buffer.encoding(), latin1);
+        assert!(buffer.text().starts_with("\u{ff}\u{fe}h\0"));
+    }
+
+    #[tokio::test]
+    async fn test_large_file_loads_in_chunks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("build.log");
+        let content = "[info] compiling crate\r\n".repeat(100_000);
+        std::fs::write(&path, &content).unwrap();
+
+        let config = BufferConfig {
+            large_file: LargeFileConfig {
+                threshold_mb: 1,
+                ..LargeFileConfig::default()
+            },
+            ..BufferConfig::default()
+        };
+        let buffer = TextBuffer::from_file_with_config(path, None, config)
+            .await
+            .unwrap();
+
+        assert!(buffer.is_large_file());
+        assert!(buffer.saved_text().is_none());
+        assert_eq!(buffer.line_ending(), LineEnding::Windows);
+        assert_eq!(buffer.len_lines(), 100_001);
+        assert_eq!(buffer.text(), content);
+    }
 }
@@ -0,0 +1,408 @@
+// src-tauri/src/core/text_transform.rs
+//! Case conversions and string literal escaping
+//!
+//! Case conversions work on every name in the text and leave what lies
+//! between names, such as spaces and punctuation, alone. Names split into
+//! words the way Rust naming does: at `_` and `-`, before a capital that
+//! follows a lower-case letter or digit, and before the last capital of an
+//! acronym, so `HTTPServer2Config` is `HTTP`, `Server2` and `Config`.
+//! Acronyms become ordinary words, giving `HttpServer2Config` in PascalCase.
+//! Leading and trailing underscores and a `r#` prefix are kept.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A transformation applied to selected text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextTransform {
+    /// `snake_case`
+    SnakeCase,
+    /// `SCREAMING_SNAKE_CASE`
+    ScreamingSnakeCase,
+    /// `camelCase`
+    CamelCase,
+    /// `PascalCase`
+    PascalCase,
+    /// `kebab-case`
+    KebabCase,
+    /// `Title Case`
+    TitleCase,
+    /// Escape text for use inside a `"..."` string literal
+    EscapeString,
+    /// Turn the inside of a `"..."` string literal back into plain text
+    UnescapeString,
+    /// Turn a string literal, or plain text, into a raw string literal
+    ToRawString,
+    /// Turn a raw string literal, or plain text, into a `"..."` string literal
+    ToStringLiteral,
+}
+
+/// Errors from transforming text
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TransformError {
+    /// A backslash escape Rust string literals don't allow
+    #[error("Invalid escape sequence `{sequence}` at offset {offset}")]
+    InvalidEscape { sequence: String, offset: usize },
+}
+
+impl TextTransform {
+    /// Transform `text`
+    pub fn apply(self, text: &str) -> Result<String, TransformError> {
+        Ok(match self {
+            Self::SnakeCase => to_snake_case(text),
+            Self::ScreamingSnakeCase => to_screaming_snake_case(text),
+            Self::CamelCase => to_camel_case(text),
+            Self::PascalCase => to_pascal_case(text),
+            Self::KebabCase => to_kebab_case(text),
+            Self::TitleCase => to_title_case(text),
+            Self::EscapeString => escape_string(text),
+            Self::UnescapeString => unescape_string(text)?,
+            Self::ToRawString => to_raw_string(text)?,
+            Self::ToStringLiteral => to_string_literal(text),
+        })
+    }
+}
+
+/// Convert names to `snake_case`
+pub fn to_snake_case(text: &str) -> String {
+    convert_names(text, |words| join_words(words, "_", str::to_lowercase))
+}
+
+/// Convert names to `SCREAMING_SNAKE_CASE`
+pub fn to_screaming_snake_case(text: &str) -> String {
+    convert_names(text, |words| join_words(words, "_", str::to_uppercase))
+}
+
+/// Convert names to `camelCase`
+pub fn to_camel_case(text: &str) -> String {
+    convert_names(text, |words| match words.split_first() {
+        Some((first, rest)) => first.to_lowercase() + &join_words(rest, "", capitalize),
+        None => String::new(),
+    })
+}
+
+/// Convert names to `PascalCase`
+pub fn to_pascal_case(text: &str) -> String {
+    convert_names(text, |words| join_words(words, "", capitalize))
+}
+
+/// Convert names to `kebab-case`
+pub fn to_kebab_case(text: &str) -> String {
+    convert_names(text, |words| join_words(words, "-", str::to_lowercase))
+}
+
+/// Convert names to `Title Case`, with the words of a name spaced apart
+pub fn to_title_case(text: &str) -> String {
+    convert_names(text, |words| join_words(words, " ", capitalize))
+}
+
+/// Escape text for use inside a `"..."` string literal
+pub fn escape_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\0' => escaped.push_str("\\0"),
+            ch if ch.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Turn the inside of a `"..."` string literal back into plain text
+///
+/// A backslash at the end of a line skips the line break and the
+/// indentation after it, as in Rust.
+pub fn unescape_string(text: &str) -> Result<String, TransformError> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((offset, ch)) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+
+        let invalid = |end: usize| TransformError::InvalidEscape {
+            sequence: text[offset..end].to_string(),
+            offset,
+        };
+        let Some((_, escape)) = chars.next() else {
+            return Err(invalid(text.len()));
+        };
+        match escape {
+            '\\' => unescaped.push('\\'),
+            '"' => unescaped.push('"'),
+            '\'' => unescaped.push('\''),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            't' => unescaped.push('\t'),
+            '0' => unescaped.push('\0'),
+            '\n' | '\r' => {
+                // A line continuation, which skips the indentation after it
+                while chars.next_if(|(_, ch)| ch.is_whitespace()).is_some() {}
+            }
+            'x' => {
+                let end = text[offset..]
+                    .char_indices()
+                    .nth(4)
+                    .map_or(text.len(), |(i, _)| offset + i);
+                let code = text
+                    .get(offset + 2..end)
+                    .filter(|digits| digits.len() == 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .filter(u8::is_ascii)
+                    .ok_or_else(|| invalid(end))?;
+                unescaped.push(code as char);
+                chars.nth(1);
+            }
+            'u' => {
+                let close = text[offset..].find('}').map(|i| offset + i + 1);
+                let ch = close
+                    .and_then(|end| text[offset + 2..end - 1].strip_prefix('{'))
+                    .map(|digits| digits.replace('_', ""))
+                    .filter(|digits| (1..=6).contains(&digits.len()))
+                    .and_then(|digits| u32::from_str_radix(&digits, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| invalid(close.unwrap_or(offset + 2)))?;
+                unescaped.push(ch);
+                while chars.next_if(|(_, ch)| *ch != '}').is_some() {}
+                chars.next();
+            }
+            _ => return Err(invalid(offset + 1 + escape.len_utf8())),
+        }
+    }
+
+    Ok(unescaped)
+}
+
+/// Turn a string literal into a raw string literal with as few `#`s as work
+///
+/// Raw string literals are returned as they are, and text that isn't a
+/// string literal becomes the contents of one.
+pub fn to_raw_string(text: &str) -> Result<String, TransformError> {
+    let (prefix, contents) = match parse_literal(text) {
+        Some(literal) if literal.raw => return Ok(text.to_string()),
+        Some(literal) => (literal.prefix, unescape_string(literal.contents)?),
+        None => ("", text.to_string()),
+    };
+
+    let hashes = (0..)
+        .map(|count| "#".repeat(count))
+        .find(|hashes| !contents.contains(&format!("\"{}", hashes)))
+        .unwrap_or_default();
+    Ok(format!("{prefix}r{hashes}\"{contents}\"{hashes}"))
+}
+
+/// Turn a raw string literal into a `"..."` string literal
+///
+/// String literals that aren't raw are returned as they are, and text that
+/// isn't a string literal becomes the contents of one.
+pub fn to_string_literal(text: &str) -> String {
+    let (prefix, contents) = match parse_literal(text) {
+        Some(literal) if !literal.raw => return text.to_string(),
+        Some(literal) => (literal.prefix, literal.contents),
+        None => ("", text),
+    };
+    format!("{prefix}\"{}\"", escape_string(contents))
+}
+
+/// The parts of a string literal
+struct Literal<'a> {
+    /// `b` or `c` for byte and C strings
+    prefix: &'a str,
+    raw: bool,
+    contents: &'a str,
+}
+
+/// Split a string literal, such as `"a"`, `b"a"` or `r#"a"#`, into its parts
+fn parse_literal(text: &str) -> Option<Literal<'_>> {
+    let prefix_len = usize::from(text.starts_with(['b', 'c']));
+    let (prefix, rest) = text.split_at(prefix_len);
+
+    if let Some(raw) = rest.strip_prefix('r') {
+        let hashes = &raw[..raw.len() - raw.trim_start_matches('#').len()];
+        let contents = raw[hashes.len()..]
+            .strip_prefix('"')?
+            .strip_suffix(hashes)?
+            .strip_suffix('"')?;
+        return Some(Literal {
+            prefix,
+            raw: true,
+            contents,
+        });
+    }
+
+    let contents = rest.strip_prefix('"')?.strip_suffix('"')?;
+    Some(Literal {
+        prefix,
+        raw: false,
+        contents,
+    })
+}
+
+/// Rewrite every name in `text` from its words, keeping what is around names
+fn convert_names(text: &str, convert: impl Fn(&[&str]) -> String) -> String {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let is_name_char = |i: usize| {
+        chars.get(i).is_some_and(|&(_, ch)| {
+            ch.is_alphanumeric()
+                || ch == '_'
+                // Hyphens join kebab-case names but not `a - b`
+                || (ch == '-'
+                    && i > 0
+                    && chars[i - 1].1.is_alphanumeric()
+                    && chars.get(i + 1).is_some_and(|(_, ch)| ch.is_alphanumeric()))
+        })
+    };
+    let byte_at = |i: usize| chars.get(i).map_or(text.len(), |&(offset, _)| offset);
+
+    let mut converted = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_name_char(i) {
+            converted.push(chars[i].1);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while is_name_char(i) {
+            i += 1;
+        }
+        let name = &text[byte_at(start)..byte_at(i)];
+
+        // The `r` of a raw identifier such as `r#type`
+        let raw_prefix = name == "r" && chars.get(i).is_some_and(|(_, ch)| *ch == '#');
+        let core = name.trim_matches('_');
+        if raw_prefix || core.is_empty() {
+            converted.push_str(name);
+            continue;
+        }
+
+        let leading = &name[..name.len() - name.trim_start_matches('_').len()];
+        let trailing = &name[name.trim_end_matches('_').len()..];
+        converted.push_str(leading);
+        converted.push_str(&convert(&words(core)));
+        converted.push_str(trailing);
+    }
+    converted
+}
+
+/// Split a name into its words
+fn words(name: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+
+    for part in name.split(['_', '-']).filter(|part| !part.is_empty()) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (previous, current) = (chars[i - 1].1, chars[i].1);
+            let next = chars.get(i + 1).map(|&(_, ch)| ch);
+            let boundary = current.is_uppercase()
+                && (!previous.is_uppercase() || next.is_some_and(|next| next.is_lowercase()));
+            if boundary {
+                words.push(&part[start..chars[i].0]);
+                start = chars[i].0;
+            }
+        }
+        words.push(&part[start..]);
+    }
+
+    words
+}
+
+/// Join words, converting each with `case`
+fn join_words(words: &[&str], separator: &str, case: impl Fn(&str) -> String) -> String {
+    words
+        .iter()
+        .map(|word| case(word))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Upper-case the first letter of a word and lower-case the rest
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| {
+            first
+                .to_uppercase()
+                .chain(chars.as_str().to_lowercase().chars())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_conversions() {
+        assert_eq!(to_snake_case("parseHTTPRequest"), "parse_http_request");
+        assert_eq!(to_snake_case("IOError"), "io_error");
+        assert_eq!(to_snake_case("Vec2 v2Parser"), "vec2 v2_parser");
+        assert_eq!(to_screaming_snake_case("maxValue"), "MAX_VALUE");
+        assert_eq!(to_camel_case("HTTP_SERVER"), "httpServer");
+        assert_eq!(to_pascal_case("my-project"), "MyProject");
+        assert_eq!(to_pascal_case("XMLHttpRequest"), "XmlHttpRequest");
+        assert_eq!(to_kebab_case("MyProject"), "my-project");
+        assert_eq!(
+            to_title_case("user_name, itemCount"),
+            "User Name, Item Count"
+        );
+
+        // Only names change, with their underscores and raw prefixes kept
+        assert_eq!(
+            to_camel_case("let _unused_value = r#type.field_name - x;"),
+            "let _unusedValue = r#type.fieldName - x;"
+        );
+        assert_eq!(to_pascal_case("__"), "__");
+    }
+
+    #[test]
+    fn test_string_escaping() {
+        let text = "say \"hi\"\n\tC:\\path\u{7}";
+        let escaped = escape_string(text);
+        assert_eq!(escaped, "say \\\"hi\\\"\\n\\tC:\\\\path\\u{7}");
+        assert_eq!(unescape_string(&escaped).unwrap(), text);
+
+        assert_eq!(
+            unescape_string("\\x41\\u{1F600}\\'a\\\n    b").unwrap(),
+            "A\u{1F600}'ab"
+        );
+        assert_eq!(
+            unescape_string("ok \\q"),
+            Err(TransformError::InvalidEscape {
+                sequence: "\\q".to_string(),
+                offset: 3,
+            })
+        );
+        assert!(unescape_string("\\xFF").is_err());
+        assert!(unescape_string("\\u{110000}").is_err());
+        assert!(unescape_string("trailing\\").is_err());
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        assert_eq!(to_raw_string("\"a\\\\b\"").unwrap(), "r\"a\\b\"");
+        assert_eq!(
+            to_raw_string("b\"say \\\"hi\\\"\"").unwrap(),
+            "br#\"say \"hi\"\"#"
+        );
+        assert_eq!(to_raw_string("a\"#b").unwrap(), "r##\"a\"#b\"##");
+        assert_eq!(to_raw_string("r\"x\"").unwrap(), "r\"x\"");
+
+        assert_eq!(to_string_literal("r#\"say \"hi\"\"#"), "\"say \\\"hi\\\"\"");
+        assert_eq!(to_string_literal("\"x\""), "\"x\"");
+        assert_eq!(to_string_literal("plain\\text"), "\"plain\\\\text\"");
+    }
+}
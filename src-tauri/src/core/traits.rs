@@ -1,5 +1,6 @@
 use crate::core::{Position, Range};
 use std::path::Path;
+use uuid::Uuid;
 
 /// Trait for listening to editor events
 pub trait EditorEventListener: Send + Sync {
@@ -27,6 +28,16 @@ pub trait EditorEventListener: Send + Sync {
     fn on_text_changed(&self, version: u64) {
         let _ = version; // Default implementation does nothing
     }
+
+    /// Called when the editor gains or loses focus
+    fn on_focus_changed(&self, has_focus: bool) {
+        let _ = has_focus; // Default implementation does nothing
+    }
+
+    /// Called when the cursors of a view other than the active one move with an edit
+    fn on_view_moved(&self, view_id: Uuid, positions: &[Position]) {
+        let _ = (view_id, positions); // Default implementation does nothing
+    }
 }
 
 /// Trait for text processing and analysis
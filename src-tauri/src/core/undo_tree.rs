@@ -0,0 +1,422 @@
+// src-tauri/src/core/undo_tree.rs
+use crate::core::text_buffer::UndoRedoEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+use tracing::debug;
+
+/// Identifier of a node in the undo tree
+pub type UndoNodeId = usize;
+
+/// A state of the buffer, reached from its parent by applying `entry`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoNode {
+    pub id: UndoNodeId,
+    pub parent: Option<UndoNodeId>,
+    pub children: Vec<UndoNodeId>,
+    /// Child that redo follows (the most recently visited one)
+    pub active_child: Option<UndoNodeId>,
+    /// Edits leading from the parent state to this one
+    pub entry: UndoRedoEntry,
+}
+
+/// Summary of an undo node for the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoNodeInfo {
+    pub id: UndoNodeId,
+    pub parent: Option<UndoNodeId>,
+    pub children: Vec<UndoNodeId>,
+    pub timestamp: SystemTime,
+    pub edit_count: usize,
+    pub is_current: bool,
+}
+
+/// A path from the root of the tree to one of its leaves
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoBranch {
+    /// Leaf node at the end of the branch
+    pub tip: UndoNodeId,
+    /// Number of changes between the root and the tip
+    pub length: usize,
+    /// Time of the last change on the branch
+    pub timestamp: SystemTime,
+    /// Whether the current state lies on this branch
+    pub contains_current: bool,
+}
+
+/// Undo history that keeps every alternative branch instead of discarding redo states
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoTree {
+    nodes: HashMap<UndoNodeId, UndoNode>,
+    root: UndoNodeId,
+    current: UndoNodeId,
+    next_id: UndoNodeId,
+}
+
+impl UndoTree {
+    /// Create a tree containing only the initial state
+    pub fn new() -> Self {
+        let root = UndoNode {
+            id: 0,
+            parent: None,
+            children: Vec::new(),
+            active_child: None,
+            entry: UndoRedoEntry::new(Vec::new(), Vec::new(), Vec::new()),
+        };
+
+        Self {
+            nodes: HashMap::from([(0, root)]),
+            root: 0,
+            current: 0,
+            next_id: 1,
+        }
+    }
+
+    /// Get the root node id (the oldest reachable state)
+    pub fn root(&self) -> UndoNodeId {
+        self.root
+    }
+
+    /// Get the node id of the current state
+    pub fn current(&self) -> UndoNodeId {
+        self.current
+    }
+
+    /// Get a node by id
+    pub fn node(&self, id: UndoNodeId) -> Option<&UndoNode> {
+        self.nodes.get(&id)
+    }
+
+    /// Get the number of nodes in the tree
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Check if the tree only contains the initial state
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    /// Check if there is a state to undo to
+    pub fn can_undo(&self) -> bool {
+        self.current != self.root
+    }
+
+    /// Check if there is a state to redo to
+    pub fn can_redo(&self) -> bool {
+        self.redo_target().is_some()
+    }
+
+    /// Get the child that redo would move to
+    pub fn redo_target(&self) -> Option<UndoNodeId> {
+        self.nodes.get(&self.current)?.active_child
+    }
+
+    /// Add a new state as a child of the current one and make it current
+    pub fn push(&mut self, entry: UndoRedoEntry) -> UndoNodeId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.nodes.insert(
+            id,
+            UndoNode {
+                id,
+                parent: Some(self.current),
+                children: Vec::new(),
+                active_child: None,
+                entry,
+            },
+        );
+
+        if let Some(parent) = self.nodes.get_mut(&self.current) {
+            parent.children.push(id);
+            parent.active_child = Some(id);
+        }
+
+        self.current = id;
+        id
+    }
+
+    /// Get the entry of the current state if further edits may be merged into it
+    ///
+    /// A state that already has children cannot change, since they were built on it.
+    pub fn mergeable_entry(&self) -> Option<&UndoRedoEntry> {
+        if self.current == self.root {
+            return None;
+        }
+
+        self.nodes
+            .get(&self.current)
+            .filter(|node| node.children.is_empty())
+            .map(|node| &node.entry)
+    }
+
+    /// Mutable access to the entry returned by [`UndoTree::mergeable_entry`]
+    pub fn mergeable_entry_mut(&mut self) -> Option<&mut UndoRedoEntry> {
+        if self.current == self.root {
+            return None;
+        }
+
+        self.nodes
+            .get_mut(&self.current)
+            .filter(|node| node.children.is_empty())
+            .map(|node| &mut node.entry)
+    }
+
+    /// Make the given node current, pointing redo along the path to it
+    pub fn set_current(&mut self, id: UndoNodeId) {
+        if !self.nodes.contains_key(&id) {
+            return;
+        }
+
+        self.current = id;
+
+        let mut child = id;
+        while let Some(parent) = self.nodes.get(&child).and_then(|node| node.parent) {
+            if let Some(parent_node) = self.nodes.get_mut(&parent) {
+                parent_node.active_child = Some(child);
+            }
+            child = parent;
+        }
+    }
+
+    /// Nodes to undo and then redo, in order, to get from `from` to `to`
+    pub fn path(
+        &self,
+        from: UndoNodeId,
+        to: UndoNodeId,
+    ) -> Option<(Vec<UndoNodeId>, Vec<UndoNodeId>)> {
+        if !self.nodes.contains_key(&from) || !self.nodes.contains_key(&to) {
+            return None;
+        }
+
+        let target_ancestors = self.ancestors(to);
+        let target_set: HashSet<UndoNodeId> = target_ancestors.iter().copied().collect();
+
+        let mut undo = Vec::new();
+        let mut node = from;
+        while !target_set.contains(&node) {
+            undo.push(node);
+            node = self.nodes.get(&node)?.parent?;
+        }
+
+        let common_ancestor = node;
+        let mut redo: Vec<UndoNodeId> = target_ancestors
+            .into_iter()
+            .take_while(|&id| id != common_ancestor)
+            .collect();
+        redo.reverse();
+
+        Some((undo, redo))
+    }
+
+    /// The node and all its ancestors, starting with the node itself
+    fn ancestors(&self, id: UndoNodeId) -> Vec<UndoNodeId> {
+        let mut ancestors = vec![id];
+        let mut node = id;
+        while let Some(parent) = self.nodes.get(&node).and_then(|node| node.parent) {
+            ancestors.push(parent);
+            node = parent;
+        }
+        ancestors
+    }
+
+    /// Find the most recent state that existed at the given time
+    pub fn node_at_time(&self, time: SystemTime) -> UndoNodeId {
+        self.nodes
+            .values()
+            .filter(|node| node.id != self.root && node.entry.timestamp <= time)
+            .map(|node| node.id)
+            .max()
+            .unwrap_or(self.root)
+    }
+
+    /// Time of the change that produced the current state
+    pub fn current_timestamp(&self) -> SystemTime {
+        self.nodes
+            .get(&self.current)
+            .map(|node| node.entry.timestamp)
+            .unwrap_or_else(SystemTime::now)
+    }
+
+    /// List every branch of the tree, oldest first
+    pub fn branches(&self) -> Vec<UndoBranch> {
+        let mut leaves: Vec<&UndoNode> = self
+            .nodes
+            .values()
+            .filter(|node| node.children.is_empty())
+            .collect();
+        leaves.sort_by_key(|node| node.id);
+
+        leaves
+            .into_iter()
+            .map(|leaf| {
+                let path = self.ancestors(leaf.id);
+                UndoBranch {
+                    tip: leaf.id,
+                    length: path.len() - 1,
+                    timestamp: leaf.entry.timestamp,
+                    contains_current: path.contains(&self.current),
+                }
+            })
+            .collect()
+    }
+
+    /// Summaries of all nodes, in creation order
+    pub fn nodes_info(&self) -> Vec<UndoNodeInfo> {
+        let mut nodes: Vec<UndoNodeInfo> = self
+            .nodes
+            .values()
+            .map(|node| UndoNodeInfo {
+                id: node.id,
+                parent: node.parent,
+                children: node.children.clone(),
+                timestamp: node.entry.timestamp,
+                edit_count: node.entry.edits.len(),
+                is_current: node.id == self.current,
+            })
+            .collect();
+        nodes.sort_by_key(|node| node.id);
+        nodes
+    }
+
+    /// Drop the oldest history until at most `max_nodes` changes remain
+    ///
+    /// The root moves forward along the path to the current state, discarding
+    /// branches that fork off before it.
+    pub fn prune(&mut self, max_nodes: usize) {
+        while self.nodes.len() > max_nodes + 1 && self.current != self.root {
+            let Some(new_root) = self
+                .ancestors(self.current)
+                .into_iter()
+                .find(|&id| self.nodes.get(&id).and_then(|node| node.parent) == Some(self.root))
+            else {
+                break;
+            };
+
+            let old_root = self.root;
+            let siblings: Vec<UndoNodeId> = self
+                .nodes
+                .get(&old_root)
+                .map(|node| node.children.clone())
+                .unwrap_or_default();
+
+            for sibling in siblings.into_iter().filter(|&id| id != new_root) {
+                self.remove_subtree(sibling);
+            }
+            self.nodes.remove(&old_root);
+
+            if let Some(node) = self.nodes.get_mut(&new_root) {
+                node.parent = None;
+                node.entry.edits.clear();
+            }
+            self.root = new_root;
+        }
+
+        debug!("Undo tree holds {} nodes", self.nodes.len());
+    }
+
+    /// Remove a node and all of its descendants
+    fn remove_subtree(&mut self, id: UndoNodeId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.remove(&id) {
+                stack.extend(node.children);
+            }
+        }
+    }
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::text_buffer::RecordedEdit;
+    use std::time::Duration;
+
+    fn entry(text: &str, timestamp: SystemTime) -> UndoRedoEntry {
+        let mut entry = UndoRedoEntry::new(
+            vec![RecordedEdit {
+                start: 0,
+                removed: String::new(),
+                inserted: text.to_string(),
+            }],
+            Vec::new(),
+            Vec::new(),
+        );
+        entry.timestamp = timestamp;
+        entry
+    }
+
+    #[test]
+    fn test_push_keeps_branches() {
+        let now = SystemTime::now();
+        let mut tree = UndoTree::new();
+        let a = tree.push(entry("a", now));
+        let b = tree.push(entry("b", now));
+
+        tree.set_current(a);
+        let c = tree.push(entry("c", now));
+
+        assert_eq!(tree.node(a).unwrap().children, vec![b, c]);
+        assert_eq!(tree.branches().len(), 2);
+        assert!(tree
+            .branches()
+            .iter()
+            .any(|b| b.tip == c && b.contains_current));
+
+        tree.set_current(a);
+        assert_eq!(tree.redo_target(), Some(c));
+    }
+
+    #[test]
+    fn test_path_between_branches() {
+        let now = SystemTime::now();
+        let mut tree = UndoTree::new();
+        let a = tree.push(entry("a", now));
+        let b = tree.push(entry("b", now));
+        tree.set_current(a);
+        let c = tree.push(entry("c", now));
+        let d = tree.push(entry("d", now));
+
+        assert_eq!(tree.path(d, b), Some((vec![d, c], vec![b])));
+        assert_eq!(tree.path(b, b), Some((vec![], vec![])));
+        assert_eq!(tree.path(tree.root(), d), Some((vec![], vec![a, c, d])));
+    }
+
+    #[test]
+    fn test_node_at_time() {
+        let start = SystemTime::now();
+        let mut tree = UndoTree::new();
+        let a = tree.push(entry("a", start + Duration::from_secs(10)));
+        let b = tree.push(entry("b", start + Duration::from_secs(40)));
+
+        assert_eq!(tree.node_at_time(start), tree.root());
+        assert_eq!(tree.node_at_time(start + Duration::from_secs(30)), a);
+        assert_eq!(tree.node_at_time(start + Duration::from_secs(60)), b);
+    }
+
+    #[test]
+    fn test_prune_moves_root_forward() {
+        let now = SystemTime::now();
+        let mut tree = UndoTree::new();
+        let a = tree.push(entry("a", now));
+        tree.set_current(tree.root());
+        tree.push(entry("x", now));
+        tree.set_current(a);
+        tree.push(entry("b", now));
+        let c = tree.push(entry("c", now));
+
+        tree.prune(2);
+
+        assert_eq!(tree.root(), a);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.current(), c);
+        assert!(tree.node(a).unwrap().parent.is_none());
+    }
+}
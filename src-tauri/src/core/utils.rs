@@ -1,5 +1,9 @@
 use crate::core::{Position, Range};
+use std::cmp::Ordering;
+use std::iter::Peekable;
 use std::path::Path;
+use std::str::Chars;
+use unicode_width::UnicodeWidthChar;
 
 /// Create indentation string
 pub fn create_indentation(level: usize, use_tabs: bool, tab_size: usize) -> String {
@@ -197,6 +201,47 @@ pub fn common_suffix(a: &str, b: &str) -> String {
     }
 }
 
+/// Compare strings with runs of digits ordered by value, so `file2` < `file10`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_digits(&mut a), take_digits(&mut b));
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Consume the run of ASCII digits at the start of `chars`
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(ch) = chars.next_if(char::is_ascii_digit) {
+        digits.push(ch);
+    }
+    digits
+}
+
 /// Normalize whitespace in a string
 pub fn normalize_whitespace(text: &str) -> String {
     text.split_whitespace().collect::<Vec<_>>().join(" ")
@@ -260,53 +305,46 @@ pub fn format_file_size(size: u64) -> String {
     }
 }
 
-/// Get the visual width of a string (accounting for tabs)
+/// Get the visual width of a string (accounting for tabs and wide characters)
 pub fn visual_width(text: &str, tab_size: usize) -> usize {
-    let mut width = 0;
-    for ch in text.chars() {
-        if ch == '\t' {
-            width = (width / tab_size + 1) * tab_size;
-        } else {
-            width += 1;
-        }
-    }
-    width
+    text.chars()
+        .fold(0, |width, ch| advance_visual_column(width, ch, tab_size))
 }
 
-/// Convert column position accounting for tabs
+/// Convert column position accounting for tabs and wide characters
 pub fn column_to_visual_column(text: &str, column: usize, tab_size: usize) -> usize {
-    let chars: Vec<char> = text.chars().collect();
-    let mut visual_col = 0;
-    
-    for i in 0..column.min(chars.len()) {
-        if chars[i] == '\t' {
-            visual_col = (visual_col / tab_size + 1) * tab_size;
-        } else {
-            visual_col += 1;
-        }
-    }
-    
-    visual_col
+    text.chars()
+        .take(column)
+        .fold(0, |width, ch| advance_visual_column(width, ch, tab_size))
 }
 
 /// Convert visual column position back to actual column
+///
+/// A visual column inside a tab or wide character maps to the column after it.
 pub fn visual_column_to_column(text: &str, visual_column: usize, tab_size: usize) -> usize {
-    let chars: Vec<char> = text.chars().collect();
     let mut visual_col = 0;
     let mut column = 0;
-    
-    while column < chars.len() && visual_col < visual_column {
-        if chars[column] == '\t' {
-            visual_col = (visual_col / tab_size + 1) * tab_size;
-        } else {
-            visual_col += 1;
+
+    for ch in text.chars() {
+        if visual_col >= visual_column {
+            break;
         }
+        visual_col = advance_visual_column(visual_col, ch, tab_size);
         column += 1;
     }
-    
+
     column
 }
 
+/// Visual column after drawing `ch` at `visual_col`
+pub(crate) fn advance_visual_column(visual_col: usize, ch: char, tab_size: usize) -> usize {
+    if ch == '\t' {
+        (visual_col / tab_size + 1) * tab_size
+    } else {
+        visual_col + UnicodeWidthChar::width(ch).unwrap_or(1)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -437,6 +475,16 @@ mod tests {
         assert_eq!(common_suffix("testing", "ing"), "ing");
     }
 
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file10", "file10"), Ordering::Equal);
+        assert_eq!(natural_cmp("a10b", "a10a"), Ordering::Greater);
+        assert_eq!(natural_cmp("v1.9", "v1.10"), Ordering::Less);
+        assert_eq!(natural_cmp("x01", "x1"), Ordering::Greater);
+        assert_eq!(natural_cmp("abc", "ab"), Ordering::Greater);
+    }
+
     #[test]
     fn test_normalize_whitespace() {
         assert_eq!(normalize_whitespace("  hello    world  "), "hello world");
@@ -505,5 +553,11 @@ mod tests {
         assert_eq!(visual_column_to_column(text, 0, 4), 0); // 'a'
         assert_eq!(visual_column_to_column(text, 4, 4), 2); // 'b'
         assert_eq!(visual_column_to_column(text, 8, 4), 4); // 'c'
+
+        // Wide characters take up two columns
+        let text = "中文x";
+        assert_eq!(column_to_visual_column(text, 2, 4), 4);
+        assert_eq!(visual_column_to_column(text, 4, 4), 2);
+        assert_eq!(visual_column_to_column(text, 3, 4), 2);
     }
 }
\ No newline at end of file
@@ -0,0 +1,2602 @@
+// src-tauri/src/core/vim.rs
+//! Vim-style modal editing layered on top of the editor
+//!
+//! Keys are fed one at a time to [`VimState::handle_key`]. In normal and
+//! visual mode they collect in a pending buffer until they form a complete
+//! command, so counts, registers, operators, motions and text objects combine
+//! the way they do in Vim. All edits go through the editor, so undo, events
+//! and dirty tracking keep working.
+
+use crate::core::cursor::{BlockSelection, Cursor, SelectionMode};
+use crate::core::editor::Editor;
+use crate::core::snapshot;
+use crate::core::text_buffer::{Position, Range, TextEdit, UndoGroupKind};
+use crate::core::utils::{column_to_visual_column, is_word_char};
+use crate::core::{EditorError, EditorResult};
+use regex::RegexBuilder;
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// Vim editing mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum VimMode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+    VisualBlock,
+}
+
+impl VimMode {
+    /// Selection mode the editor uses while in this mode
+    pub fn selection_mode(self) -> SelectionMode {
+        match self {
+            Self::VisualLine => SelectionMode::Line,
+            Self::VisualBlock => SelectionMode::Block,
+            _ => SelectionMode::Normal,
+        }
+    }
+
+    pub fn is_visual(self) -> bool {
+        matches!(self, Self::Visual | Self::VisualLine | Self::VisualBlock)
+    }
+}
+
+/// A key press as the Vim layer sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimKey {
+    Char(char),
+    Ctrl(char),
+    Escape,
+    Enter,
+    Backspace,
+    Tab,
+}
+
+impl VimKey {
+    /// Parse keys written in Vim notation, such as `d2w` or `cwfoo<Esc>`
+    ///
+    /// Supports `<Esc>`, `<CR>`, `<Enter>`, `<BS>`, `<Tab>`, `<lt>` and `<C-x>`.
+    /// A `<` that doesn't start one of these is taken literally.
+    pub fn parse_keys(input: &str) -> Vec<VimKey> {
+        let mut keys = Vec::new();
+        let mut rest = input;
+
+        while let Some(ch) = rest.chars().next() {
+            if ch == '<' {
+                if let Some((key, len)) = rest
+                    .find('>')
+                    .and_then(|end| Self::from_notation(&rest[1..end]).map(|key| (key, end + 1)))
+                {
+                    keys.push(key);
+                    rest = &rest[len..];
+                    continue;
+                }
+            }
+
+            keys.push(VimKey::Char(ch));
+            rest = &rest[ch.len_utf8()..];
+        }
+
+        keys
+    }
+
+    fn from_notation(name: &str) -> Option<VimKey> {
+        let key = match name.to_ascii_lowercase().as_str() {
+            "esc" => VimKey::Escape,
+            "cr" | "enter" | "return" => VimKey::Enter,
+            "bs" => VimKey::Backspace,
+            "tab" => VimKey::Tab,
+            "lt" => VimKey::Char('<'),
+            lower => {
+                let ch = lower.strip_prefix("c-")?;
+                let mut chars = ch.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => VimKey::Ctrl(ch),
+                    _ => return None,
+                }
+            }
+        };
+        Some(key)
+    }
+}
+
+impl fmt::Display for VimKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VimKey::Char('<') => write!(f, "<lt>"),
+            VimKey::Char(ch) => write!(f, "{}", ch),
+            VimKey::Ctrl(ch) => write!(f, "<C-{}>", ch),
+            VimKey::Escape => write!(f, "<Esc>"),
+            VimKey::Enter => write!(f, "<CR>"),
+            VimKey::Backspace => write!(f, "<BS>"),
+            VimKey::Tab => write!(f, "<Tab>"),
+        }
+    }
+}
+
+/// Vim state shown in the status bar
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VimStatus {
+    pub mode: VimMode,
+    /// Keys of a command that isn't complete yet, such as `2d`
+    pub pending: String,
+    /// Text typed after `:` while an ex command is being entered
+    pub command_line: Option<String>,
+}
+
+/// Work the caller has to do after a key, as it can't happen inside the editor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum VimEffect {
+    None,
+    /// Save the file, to `path` when given, and close it afterwards if `quit` is set
+    Write {
+        path: Option<PathBuf>,
+        quit: bool,
+    },
+    /// Close the file, discarding unsaved changes if `force` is set
+    Quit {
+        force: bool,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Register {
+    text: String,
+    linewise: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+    Lowercase,
+    Uppercase,
+    ToggleCase,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FindChar {
+    ch: char,
+    forward: bool,
+    till: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart { big: bool },
+    WordEnd { big: bool },
+    WordBack { big: bool },
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    Find(FindChar),
+    RepeatFind { reverse: bool },
+    ParagraphForward,
+    ParagraphBack,
+    MatchingBracket,
+}
+
+/// How much of the text between cursor and target a motion covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MotionKind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextObject {
+    Word {
+        big: bool,
+        around: bool,
+    },
+    Pair {
+        open: char,
+        close: char,
+        around: bool,
+    },
+    Quote {
+        quote: char,
+        around: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    /// Whole lines, from a doubled operator such as `dd`
+    Lines,
+    /// The visual selection
+    Selection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertAt {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Insert(InsertAt),
+    Put { before: bool },
+    Undo,
+    Redo,
+    Replace(char),
+    Join,
+    Repeat,
+    ToggleCaseChar,
+    Visual(VimMode),
+    SwapVisualEnds,
+    CommandLine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Move(Motion),
+    Operate(Operator, Target),
+    SelectObject(TextObject),
+    Action(Action),
+}
+
+/// A complete normal or visual mode command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Parsed {
+    register: Option<char>,
+    count: Option<usize>,
+    command: Command,
+}
+
+/// Outcome of parsing the keys typed so far
+enum Parse<T> {
+    Incomplete,
+    Invalid,
+    Done(T, usize),
+}
+
+/// A change that `.` repeats: the command and the keys typed in insert mode after it
+#[derive(Debug, Clone)]
+struct Change {
+    command: Parsed,
+    inserted: Vec<VimKey>,
+}
+
+/// Text an operator acts on
+///
+/// Linewise regions cover the lines from `start.line` to `end.line`. Other
+/// regions run from `start` up to, but not including, `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    start: Position,
+    end: Position,
+    linewise: bool,
+}
+
+impl Region {
+    fn chars(start: Position, end: Position) -> Self {
+        Self {
+            start,
+            end,
+            linewise: false,
+        }
+    }
+
+    fn lines(first: usize, last: usize) -> Self {
+        Self {
+            start: Position::new(first, 0),
+            end: Position::new(last, 0),
+            linewise: true,
+        }
+    }
+}
+
+/// Modal editing state for one editor
+#[derive(Debug)]
+pub struct VimState {
+    mode: VimMode,
+    pending: Vec<VimKey>,
+    command_line: Option<String>,
+    registers: HashMap<char, Register>,
+    /// Where the visual selection started
+    visual_anchor: Position,
+    /// End of the visual selection that motions move
+    visual_head: Position,
+    /// Ends of the last visual selection, for the `'<` and `'>` marks
+    last_visual: Option<(Position, Position)>,
+    /// Column vertical motions aim for
+    preferred_column: Option<usize>,
+    last_find: Option<FindChar>,
+    last_change: Option<Change>,
+    /// Change being recorded while in insert mode
+    recording: Option<Change>,
+    /// Whether an undo group is open for the current insert session
+    insert_group_open: bool,
+}
+
+impl VimState {
+    pub fn new() -> Self {
+        Self {
+            mode: VimMode::Normal,
+            pending: Vec::new(),
+            command_line: None,
+            registers: HashMap::new(),
+            visual_anchor: Position::zero(),
+            visual_head: Position::zero(),
+            last_visual: None,
+            preferred_column: None,
+            last_find: None,
+            last_change: None,
+            recording: None,
+            insert_group_open: false,
+        }
+    }
+
+    pub fn mode(&self) -> VimMode {
+        self.mode
+    }
+
+    pub fn status(&self) -> VimStatus {
+        VimStatus {
+            mode: self.mode,
+            pending: self.pending.iter().map(ToString::to_string).collect(),
+            command_line: self.command_line.clone(),
+        }
+    }
+
+    /// Get the text held by a register
+    pub fn register(&self, name: char) -> Option<&str> {
+        self.registers
+            .get(&name.to_ascii_lowercase())
+            .map(|register| register.text.as_str())
+    }
+
+    /// Start modal editing in normal mode
+    pub(crate) fn enter(&mut self, editor: &mut Editor) -> EditorResult<()> {
+        self.mode = VimMode::Normal;
+        editor.set_selection_mode(SelectionMode::Normal);
+        let cursor = self.cursor(editor);
+        self.move_to(editor, cursor)
+    }
+
+    /// Stop modal editing, closing any open insert session
+    pub(crate) fn leave(&mut self, editor: &mut Editor) {
+        if self.insert_group_open {
+            editor.end_undo_group();
+            self.insert_group_open = false;
+        }
+        editor.set_selection_mode(SelectionMode::Normal);
+    }
+
+    /// Handle a single key press
+    pub fn handle_key(&mut self, editor: &mut Editor, key: VimKey) -> EditorResult<VimEffect> {
+        if self.command_line.is_some() {
+            return self.command_line_key(editor, key);
+        }
+
+        if self.mode == VimMode::Insert {
+            self.insert_key(editor, key)?;
+            return Ok(VimEffect::None);
+        }
+
+        if key == VimKey::Escape {
+            self.pending.clear();
+            if self.mode.is_visual() {
+                self.exit_visual(editor)?;
+            }
+            return Ok(VimEffect::None);
+        }
+
+        self.pending.push(key);
+        let parsed = match parse_command(&self.pending, self.mode.is_visual()) {
+            Parse::Incomplete => return Ok(VimEffect::None),
+            Parse::Invalid => {
+                debug!("Discarding invalid vim command {:?}", self.pending);
+                self.pending.clear();
+                return Ok(VimEffect::None);
+            }
+            Parse::Done(parsed, _) => parsed,
+        };
+        self.pending.clear();
+
+        let result = self.execute(editor, parsed);
+        if self.mode != VimMode::Insert {
+            if let Some(change) = self.recording.take() {
+                self.last_change = Some(change);
+            }
+        }
+        result.map(|_| VimEffect::None)
+    }
+
+    fn execute(&mut self, editor: &mut Editor, parsed: Parsed) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let parsed = Parsed {
+            count: parsed.count.map(|count| clamp_count(&rope, count)),
+            ..parsed
+        };
+        if self.mode.is_visual() {
+            return self.execute_visual(editor, parsed);
+        }
+
+        let cursor = self.cursor(editor);
+
+        match parsed.command {
+            Command::Move(motion) => {
+                if let Some((target, _)) =
+                    self.motion_target(&rope, cursor, motion, parsed.count, false)
+                {
+                    self.move_to(editor, target)?;
+                }
+            }
+            Command::Operate(operator, target) => {
+                let Some(region) =
+                    self.target_region(&rope, cursor, operator, target, parsed.count)
+                else {
+                    return Ok(());
+                };
+
+                if operator != Operator::Yank {
+                    self.record(parsed);
+                }
+                self.operate(editor, operator, region, parsed.register)?;
+            }
+            Command::SelectObject(_) => {}
+            Command::Action(action) => self.action(editor, action, parsed, cursor)?,
+        }
+
+        Ok(())
+    }
+
+    fn action(
+        &mut self,
+        editor: &mut Editor,
+        action: Action,
+        parsed: Parsed,
+        cursor: Position,
+    ) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let count = parsed.count.unwrap_or(1);
+
+        match action {
+            Action::Insert(at) => {
+                self.record(parsed);
+                self.begin_insert(editor);
+                let len = line_len(&rope, cursor.line);
+                match at {
+                    InsertAt::Before => {}
+                    InsertAt::After => editor
+                        .goto_position(Position::new(cursor.line, (cursor.column + 1).min(len)))?,
+                    InsertAt::LineStart => editor.goto_position(Position::new(
+                        cursor.line,
+                        first_non_blank(&rope, cursor.line),
+                    ))?,
+                    InsertAt::LineEnd => editor.goto_position(Position::new(cursor.line, len))?,
+                    InsertAt::LineBelow => self.open_line(editor, true)?,
+                    InsertAt::LineAbove => self.open_line(editor, false)?,
+                }
+                self.mode = VimMode::Insert;
+            }
+            Action::Put { before } => {
+                self.record(parsed);
+                let register = self.registers.get(&parsed.register.unwrap_or('"')).cloned();
+                if let Some(register) = register {
+                    self.put(editor, &register, count, before)?;
+                }
+            }
+            Action::Undo | Action::Redo => {
+                for _ in 0..count {
+                    let changed = if action == Action::Undo {
+                        editor.undo()?
+                    } else {
+                        editor.redo()?
+                    };
+                    if !changed {
+                        break;
+                    }
+                }
+                let cursor = self.cursor(editor);
+                self.move_to(editor, cursor)?;
+            }
+            Action::Replace(ch) => {
+                let len = line_len(&rope, cursor.line);
+                let end = cursor.column.saturating_add(count);
+                if end > len {
+                    return Ok(());
+                }
+
+                self.record(parsed);
+                let range = Range::new(cursor, Position::new(cursor.line, end));
+                if ch == '\n' {
+                    editor.apply_edits(vec![TextEdit::replace(range, newline(editor))])?;
+                    self.move_to(editor, Position::new(cursor.line + 1, 0))?;
+                } else {
+                    let text = ch.to_string().repeat(count);
+                    editor.apply_edits(vec![TextEdit::replace(range, text)])?;
+                    self.move_to(editor, Position::new(cursor.line, end - 1))?;
+                }
+            }
+            Action::Join => {
+                self.record(parsed);
+                self.join_lines(editor, cursor.line, count.max(2))?;
+            }
+            Action::ToggleCaseChar => {
+                let len = line_len(&rope, cursor.line);
+                if len == 0 {
+                    return Ok(());
+                }
+
+                self.record(parsed);
+                let end = Position::new(cursor.line, cursor.column.saturating_add(count).min(len));
+                let region = Region::chars(cursor, end);
+                self.transform_case(editor, Operator::ToggleCase, region)?;
+                self.move_to(editor, end)?;
+            }
+            Action::Repeat => {
+                let Some(change) = self.last_change.clone() else {
+                    return Ok(());
+                };
+
+                let mut command = change.command;
+                if parsed.count.is_some() {
+                    command.count = parsed.count;
+                }
+                self.execute(editor, command)?;
+                if self.mode == VimMode::Insert {
+                    for key in &change.inserted {
+                        self.insert_key(editor, *key)?;
+                    }
+                    self.insert_key(editor, VimKey::Escape)?;
+                }
+            }
+            Action::Visual(mode) => {
+                self.visual_anchor = cursor;
+                self.visual_head = cursor;
+                self.mode = mode;
+                self.show_visual(editor)?;
+            }
+            Action::SwapVisualEnds => {}
+            Action::CommandLine => self.command_line = Some(String::new()),
+        }
+
+        Ok(())
+    }
+
+    fn insert_key(&mut self, editor: &mut Editor, key: VimKey) -> EditorResult<()> {
+        match key {
+            VimKey::Escape => return self.leave_insert(editor),
+            VimKey::Char(ch) => editor.type_char(ch)?,
+            VimKey::Enter => editor.type_char('\n')?,
+            VimKey::Tab => editor.type_char('\t')?,
+            VimKey::Backspace => editor.backspace()?,
+            VimKey::Ctrl(_) => return Ok(()),
+        }
+
+        if let Some(change) = &mut self.recording {
+            change.inserted.push(key);
+        }
+        Ok(())
+    }
+
+    fn leave_insert(&mut self, editor: &mut Editor) -> EditorResult<()> {
+        let change = self.recording.take();
+
+        // A count on an insert command repeats the typed text
+        if let Some(Change {
+            command:
+                Parsed {
+                    count: Some(count),
+                    command: Command::Action(Action::Insert(at)),
+                    ..
+                },
+            inserted,
+        }) = &change
+        {
+            for _ in 1..*count {
+                if matches!(at, InsertAt::LineBelow | InsertAt::LineAbove) {
+                    self.open_line(editor, true)?;
+                }
+                for key in inserted {
+                    self.insert_key(editor, *key)?;
+                }
+            }
+        }
+
+        if change.is_some() {
+            self.last_change = change;
+        }
+        if self.insert_group_open {
+            editor.end_undo_group();
+            self.insert_group_open = false;
+        }
+
+        self.mode = VimMode::Normal;
+        let cursor = editor.cursor_manager().primary_cursor().position;
+        let cursor = Position::new(cursor.line, cursor.column.saturating_sub(1));
+        let cursor = self.clamp(editor.buffer().rope(), cursor);
+        self.move_to(editor, cursor)
+    }
+
+    fn begin_insert(&mut self, editor: &mut Editor) {
+        if !self.insert_group_open {
+            editor.begin_undo_group(UndoGroupKind::Discrete);
+            self.insert_group_open = true;
+        }
+    }
+
+    fn record(&mut self, command: Parsed) {
+        self.recording = Some(Change {
+            command,
+            inserted: Vec::new(),
+        });
+    }
+
+    fn command_line_key(&mut self, editor: &mut Editor, key: VimKey) -> EditorResult<VimEffect> {
+        let Some(line) = self.command_line.as_mut() else {
+            return Ok(VimEffect::None);
+        };
+
+        match key {
+            VimKey::Escape => self.command_line = None,
+            VimKey::Backspace => {
+                if line.pop().is_none() {
+                    self.command_line = None;
+                }
+            }
+            VimKey::Enter => {
+                let line = self.command_line.take().unwrap_or_default();
+                return self.run_ex(editor, &line);
+            }
+            VimKey::Char(ch) => line.push(ch),
+            VimKey::Tab => line.push('\t'),
+            VimKey::Ctrl(_) => {}
+        }
+
+        Ok(VimEffect::None)
+    }
+
+    /// Run an ex command such as `w`, `q!`, `12` or `%s/foo/bar/g`
+    fn run_ex(&mut self, editor: &mut Editor, input: &str) -> EditorResult<VimEffect> {
+        let rope = editor.buffer().rope().clone();
+        let cursor = self.cursor(editor);
+        let (range, command) = self.parse_ex_range(&rope, cursor, input.trim())?;
+        let command = command.trim();
+
+        let effect = match command {
+            "" => {
+                if let Some((_, line)) = range {
+                    self.move_to(editor, Position::new(line, first_non_blank(&rope, line)))?;
+                }
+                VimEffect::None
+            }
+            "w" | "write" => VimEffect::Write {
+                path: None,
+                quit: false,
+            },
+            "wq" | "x" | "xit" => VimEffect::Write {
+                path: None,
+                quit: true,
+            },
+            "q" | "quit" => {
+                if editor.state().is_dirty {
+                    return Err(EditorError::VimError(
+                        "No write since last change (add ! to override)".to_string(),
+                    ));
+                }
+                VimEffect::Quit { force: false }
+            }
+            "q!" | "quit!" => VimEffect::Quit { force: true },
+            "d" | "delete" => {
+                let (first, last) = range.unwrap_or((cursor.line, cursor.line));
+                self.operate(editor, Operator::Delete, Region::lines(first, last), None)?;
+                VimEffect::None
+            }
+            _ => {
+                if let Some(path) = command.strip_prefix("w ") {
+                    VimEffect::Write {
+                        path: Some(PathBuf::from(path.trim())),
+                        quit: false,
+                    }
+                } else if let Some(args) = command
+                    .strip_prefix('s')
+                    .filter(|args| args.starts_with(|ch: char| ch.is_ascii_punctuation()))
+                {
+                    let lines = range.unwrap_or((cursor.line, cursor.line));
+                    self.substitute(editor, lines, args)?;
+                    VimEffect::None
+                } else {
+                    return Err(EditorError::VimError(format!(
+                        "Not an editor command: {}",
+                        input
+                    )));
+                }
+            }
+        };
+
+        Ok(effect)
+    }
+
+    fn parse_ex_range<'a>(
+        &self,
+        rope: &Rope,
+        cursor: Position,
+        input: &'a str,
+    ) -> EditorResult<(Option<(usize, usize)>, &'a str)> {
+        if let Some(rest) = input.strip_prefix('%') {
+            return Ok((Some((0, last_line(rope))), rest));
+        }
+
+        let Some((first, rest)) = self.parse_address(rope, cursor, input)? else {
+            return Ok((None, input));
+        };
+        let Some(rest) = rest.strip_prefix(',') else {
+            return Ok((Some((first, first)), rest));
+        };
+
+        let (last, rest) = self
+            .parse_address(rope, cursor, rest)?
+            .ok_or_else(|| EditorError::VimError("Invalid range".to_string()))?;
+        Ok((Some((first.min(last), first.max(last))), rest))
+    }
+
+    fn parse_address<'a>(
+        &self,
+        rope: &Rope,
+        cursor: Position,
+        input: &'a str,
+    ) -> EditorResult<Option<(usize, &'a str)>> {
+        let last = last_line(rope);
+        if let Some(rest) = input.strip_prefix('.') {
+            return Ok(Some((cursor.line, rest)));
+        }
+        if let Some(rest) = input.strip_prefix('$') {
+            return Ok(Some((last, rest)));
+        }
+
+        for (mark, first) in [("'<", true), ("'>", false)] {
+            if let Some(rest) = input.strip_prefix(mark) {
+                let (anchor, head) = self
+                    .last_visual
+                    .ok_or_else(|| EditorError::VimError("Mark not set".to_string()))?;
+                let line = if first {
+                    anchor.line.min(head.line)
+                } else {
+                    anchor.line.max(head.line)
+                };
+                return Ok(Some((line.min(last), rest)));
+            }
+        }
+
+        let digits = input.len()
+            - input
+                .trim_start_matches(|ch: char| ch.is_ascii_digit())
+                .len();
+        if digits == 0 {
+            return Ok(None);
+        }
+        let number: usize = input[..digits]
+            .parse()
+            .map_err(|_| EditorError::VimError("Invalid line number".to_string()))?;
+        Ok(Some((number.saturating_sub(1).min(last), &input[digits..])))
+    }
+
+    /// Run `:s` over a range of lines, with `args` such as `/foo/bar/g`
+    ///
+    /// Patterns use Rust regex syntax. In the replacement `&` and `\0` stand
+    /// for the whole match and `\1` to `\9` for groups.
+    fn substitute(
+        &mut self,
+        editor: &mut Editor,
+        (first, last): (usize, usize),
+        args: &str,
+    ) -> EditorResult<()> {
+        let Some(delimiter) = args.chars().next() else {
+            return Ok(());
+        };
+        let parts = split_unescaped(&args[delimiter.len_utf8()..], delimiter);
+        let pattern = parts.first().map(String::as_str).unwrap_or_default();
+        let replacement = vim_replacement(parts.get(1).map(String::as_str).unwrap_or_default());
+        let flags = parts.get(2).map(String::as_str).unwrap_or_default();
+
+        if pattern.is_empty() {
+            return Err(EditorError::VimError(
+                "No previous regular expression".to_string(),
+            ));
+        }
+
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(flags.contains('i'))
+            .build()
+            .map_err(|e| EditorError::VimError(e.to_string()))?;
+
+        let rope = editor.buffer().rope().clone();
+        let mut edits = Vec::new();
+        let mut last_changed = None;
+        for line in first..=last.min(last_line(&rope)) {
+            let text = line_text(&rope, line);
+            let replaced = if flags.contains('g') {
+                regex.replace_all(&text, replacement.as_str())
+            } else {
+                regex.replacen(&text, 1, replacement.as_str())
+            };
+
+            if replaced != text {
+                let range = Range::new(
+                    Position::new(line, 0),
+                    Position::new(line, line_len(&rope, line)),
+                );
+                edits.push(TextEdit::replace(range, replaced.into_owned()));
+                last_changed = Some(line);
+            }
+        }
+
+        let Some(line) = last_changed else {
+            return Err(EditorError::VimError(format!(
+                "Pattern not found: {}",
+                pattern
+            )));
+        };
+
+        editor.apply_edits(edits)?;
+        let rope = editor.buffer().rope().clone();
+        let line = line.min(last_line(&rope));
+        self.move_to(editor, Position::new(line, first_non_blank(&rope, line)))
+    }
+
+    fn execute_visual(&mut self, editor: &mut Editor, parsed: Parsed) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let count = parsed.count.unwrap_or(1);
+
+        match parsed.command {
+            Command::Move(motion) => {
+                if let Some((target, _)) =
+                    self.motion_target(&rope, self.visual_head, motion, parsed.count, false)
+                {
+                    self.visual_head = target;
+                    self.show_visual(editor)?;
+                }
+            }
+            Command::SelectObject(object) => {
+                if let Some(region) = object_region(&rope, self.visual_head, object, count) {
+                    self.visual_anchor = region.start;
+                    self.visual_head = char_before(&rope, region.end);
+                    self.show_visual(editor)?;
+                }
+            }
+            Command::Operate(operator, _) => {
+                self.visual_operate(editor, operator, parsed.register)?;
+            }
+            Command::Action(Action::Visual(mode)) => {
+                if mode == self.mode {
+                    self.exit_visual(editor)?;
+                } else {
+                    self.mode = mode;
+                    self.show_visual(editor)?;
+                }
+            }
+            Command::Action(Action::SwapVisualEnds) => {
+                std::mem::swap(&mut self.visual_anchor, &mut self.visual_head);
+                self.show_visual(editor)?;
+            }
+            Command::Action(Action::Join) => {
+                let (first, last) = self.visual_lines();
+                self.exit_visual(editor)?;
+                self.join_lines(editor, first, (last - first + 1).max(2))?;
+            }
+            Command::Action(Action::Put { .. }) => {
+                let register = self.registers.get(&parsed.register.unwrap_or('"')).cloned();
+                let region = self.visual_region(&rope);
+                self.exit_visual(editor)?;
+                self.operate(editor, Operator::Delete, region, Some('_'))?;
+                if let Some(register) = register {
+                    self.put(editor, &register, count, true)?;
+                }
+            }
+            Command::Action(Action::Insert(at)) => self.visual_insert(editor, at)?,
+            Command::Action(Action::CommandLine) => {
+                self.exit_visual(editor)?;
+                self.command_line = Some("'<,'>".to_string());
+            }
+            Command::Action(_) => {}
+        }
+
+        Ok(())
+    }
+
+    fn visual_operate(
+        &mut self,
+        editor: &mut Editor,
+        operator: Operator,
+        register: Option<char>,
+    ) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        if self.mode == VimMode::VisualBlock {
+            return self.block_operate(editor, operator, register);
+        }
+
+        let region = self.visual_region(&rope);
+        self.last_visual = Some((self.visual_anchor, self.visual_head));
+        self.mode = VimMode::Normal;
+        editor.set_selection_mode(SelectionMode::Normal);
+        self.operate(editor, operator, region, register)
+    }
+
+    /// Apply an operator to every row of a block selection
+    fn block_operate(
+        &mut self,
+        editor: &mut Editor,
+        operator: Operator,
+        register: Option<char>,
+    ) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let rows: Vec<Range> = editor
+            .cursor_manager()
+            .cursors()
+            .iter()
+            .map(Cursor::selection_range)
+            .collect();
+        let (first, last) = self.visual_lines();
+        let top_left = rows.first().map_or(self.visual_anchor, |row| row.start);
+
+        self.last_visual = Some((self.visual_anchor, self.visual_head));
+        self.mode = VimMode::Normal;
+        editor.set_selection_mode(SelectionMode::Normal);
+
+        match operator {
+            Operator::Delete | Operator::Change | Operator::Yank => {
+                let text = rows
+                    .iter()
+                    .map(|row| slice_text(&rope, row.start, row.end))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.store(register, text, false, operator != Operator::Yank);
+                if operator == Operator::Yank {
+                    return self.move_to(editor, top_left);
+                }
+
+                if operator == Operator::Change {
+                    self.begin_insert(editor);
+                }
+                let edits = rows
+                    .iter()
+                    .filter(|row| !row.is_empty())
+                    .map(|row| TextEdit::delete(row.clone()))
+                    .collect();
+                editor.apply_edits(edits)?;
+
+                if operator == Operator::Change {
+                    self.insert_at_rows(editor, rows.iter().map(|row| row.start));
+                    Ok(())
+                } else {
+                    let cursor = self.clamp(editor.buffer().rope(), top_left);
+                    self.move_to(editor, cursor)
+                }
+            }
+            Operator::Indent | Operator::Outdent => {
+                self.operate(editor, operator, Region::lines(first, last), register)
+            }
+            Operator::Lowercase | Operator::Uppercase | Operator::ToggleCase => {
+                let edits = rows
+                    .iter()
+                    .map(|row| {
+                        let text = slice_text(&rope, row.start, row.end);
+                        TextEdit::replace(row.clone(), change_case(operator, &text))
+                    })
+                    .collect();
+                editor.apply_edits(edits)?;
+                self.move_to(editor, top_left)
+            }
+        }
+    }
+
+    /// `I` and `A` in visual mode, which insert on every row of a block
+    fn visual_insert(&mut self, editor: &mut Editor, at: InsertAt) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let append = at == InsertAt::LineEnd;
+
+        if self.mode == VimMode::VisualBlock {
+            let rows: Vec<Position> = editor
+                .cursor_manager()
+                .cursors()
+                .iter()
+                .map(|cursor| {
+                    let range = cursor.selection_range();
+                    if append {
+                        range.end
+                    } else {
+                        range.start
+                    }
+                })
+                .collect();
+            self.last_visual = Some((self.visual_anchor, self.visual_head));
+            self.mode = VimMode::Normal;
+            editor.set_selection_mode(SelectionMode::Normal);
+            self.begin_insert(editor);
+            self.insert_at_rows(editor, rows.into_iter());
+            return Ok(());
+        }
+
+        let region = self.visual_region(&rope);
+        self.exit_visual(editor)?;
+        self.begin_insert(editor);
+        let position = match (append, region.linewise) {
+            (false, false) => region.start,
+            (false, true) => Position::new(region.start.line, 0),
+            (true, false) => region.end,
+            (true, true) => Position::new(region.end.line, line_len(&rope, region.end.line)),
+        };
+        editor.goto_position(position)?;
+        self.mode = VimMode::Insert;
+        Ok(())
+    }
+
+    /// Switch to insert mode with one cursor per row
+    fn insert_at_rows(&mut self, editor: &mut Editor, rows: impl Iterator<Item = Position>) {
+        let cursors = rows
+            .enumerate()
+            .map(|(id, position)| Cursor::new(id, position))
+            .collect();
+        editor.restore_cursors(cursors);
+        self.mode = VimMode::Insert;
+    }
+
+    fn exit_visual(&mut self, editor: &mut Editor) -> EditorResult<()> {
+        self.last_visual = Some((self.visual_anchor, self.visual_head));
+        self.mode = VimMode::Normal;
+        editor.set_selection_mode(SelectionMode::Normal);
+        let head = self.clamp(editor.buffer().rope(), self.visual_head);
+        self.move_to(editor, head)
+    }
+
+    /// Mirror the visual selection in the editor's cursors
+    fn show_visual(&mut self, editor: &mut Editor) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let (anchor, head) = (self.visual_anchor, self.visual_head);
+        editor.set_selection_mode(self.mode.selection_mode());
+
+        if self.mode == VimMode::VisualBlock {
+            let tab_size = editor.config().tab_size;
+            let left = |position: Position| visual_column(&rope, position, tab_size);
+            let right = |position: Position| {
+                let after = Position::new(
+                    position.line,
+                    (position.column + 1)
+                        .min(line_len(&rope, position.line).max(position.column + 1)),
+                );
+                visual_column(&rope, after, tab_size).max(left(position) + 1)
+            };
+
+            let (anchor_column, head_column) = if left(anchor) <= left(head) {
+                (left(anchor), right(head).max(right(anchor)))
+            } else {
+                (right(anchor).max(right(head)), left(head))
+            };
+            editor.select_block(BlockSelection {
+                anchor: Position::new(anchor.line, anchor_column),
+                head: Position::new(head.line, head_column),
+            })?;
+            return Ok(());
+        }
+
+        let region = self.visual_region(&rope);
+        let (start, end) = if region.linewise {
+            (
+                Position::new(region.start.line, 0),
+                line_span(&rope, region.start.line, region.end.line).end,
+            )
+        } else {
+            (region.start, region.end)
+        };
+
+        let cursor = if head >= anchor {
+            Cursor::with_selection(0, start, end)
+        } else {
+            Cursor::with_selection(0, end, start)
+        };
+        editor.restore_cursors(vec![cursor]);
+        editor.scroll_to_position(head);
+        Ok(())
+    }
+
+    fn visual_lines(&self) -> (usize, usize) {
+        let (anchor, head) = (self.visual_anchor, self.visual_head);
+        (anchor.line.min(head.line), anchor.line.max(head.line))
+    }
+
+    /// Region covered by a character or line visual selection
+    fn visual_region(&self, rope: &Rope) -> Region {
+        let (first, last) = self.visual_lines();
+        if self.mode == VimMode::VisualLine {
+            return Region::lines(first, last);
+        }
+
+        let start = self.visual_anchor.min(self.visual_head);
+        let end = self.visual_anchor.max(self.visual_head);
+        let end = if end.column < line_len(rope, end.line) {
+            Position::new(end.line, end.column + 1)
+        } else if end.line < last_line(rope) {
+            // Selecting the end of a line takes its line break too
+            Position::new(end.line + 1, 0)
+        } else {
+            end
+        };
+        Region::chars(start, end)
+    }
+
+    fn target_region(
+        &mut self,
+        rope: &Rope,
+        cursor: Position,
+        operator: Operator,
+        target: Target,
+        count: Option<usize>,
+    ) -> Option<Region> {
+        let n = count.unwrap_or(1);
+        match target {
+            Target::Lines => {
+                let last = (cursor.line.saturating_add(n) - 1).min(last_line(rope));
+                Some(Region::lines(cursor.line, last))
+            }
+            Target::Object(object) => object_region(rope, cursor, object, n),
+            Target::Motion(motion) => self.motion_region(rope, cursor, operator, motion, count),
+            Target::Selection => None,
+        }
+    }
+
+    fn motion_region(
+        &mut self,
+        rope: &Rope,
+        cursor: Position,
+        operator: Operator,
+        motion: Motion,
+        count: Option<usize>,
+    ) -> Option<Region> {
+        // `cw` changes to the end of the word, leaving the space after it
+        if let (Operator::Change, Motion::WordStart { big }) = (operator, motion) {
+            let index = char_index(rope, cursor);
+            if rope.get_char(index).is_some_and(|ch| !ch.is_whitespace()) {
+                let mut end = current_word_end(rope, index, big);
+                for _ in 1..count.unwrap_or(1) {
+                    end = next_word_end(rope, end, big);
+                }
+                return Some(Region::chars(cursor, position_at(rope, end + 1)));
+            }
+        }
+
+        let (target, kind) = self.motion_target(rope, cursor, motion, count, true)?;
+        let (start, end) = (cursor.min(target), cursor.max(target));
+
+        let region = match kind {
+            MotionKind::Linewise => Region::lines(start.line, end.line),
+            MotionKind::Inclusive => Region::chars(start, char_after(rope, end)),
+            MotionKind::Exclusive if end.line > start.line => {
+                if let Motion::WordStart { .. } = motion {
+                    // A word motion that crosses lines stops after the last word it passed
+                    let start_index = char_index(rope, start);
+                    let mut index = char_index(rope, end);
+                    while index > start_index && rope.char(index - 1).is_whitespace() {
+                        index -= 1;
+                    }
+                    Region::chars(start, position_at(rope, index))
+                } else if end.column == 0 && start.column <= first_non_blank(rope, start.line) {
+                    // Vim treats this as a linewise motion over the lines before `end`
+                    Region::lines(start.line, end.line - 1)
+                } else if end.column == 0 {
+                    let line = end.line - 1;
+                    Region::chars(start, Position::new(line, line_len(rope, line)))
+                } else {
+                    Region::chars(start, end)
+                }
+            }
+            MotionKind::Exclusive => Region::chars(start, end),
+        };
+        Some(region)
+    }
+
+    /// Where a motion lands, or `None` when it can't move
+    ///
+    /// With `operator` set, horizontal motions may land just past the end of
+    /// the line, so the last character can be operated on.
+    fn motion_target(
+        &mut self,
+        rope: &Rope,
+        from: Position,
+        motion: Motion,
+        count: Option<usize>,
+        operator: bool,
+    ) -> Option<(Position, MotionKind)> {
+        let n = count.unwrap_or(1);
+        let last = last_line(rope);
+        let len = line_len(rope, from.line);
+        let max_column = if operator { len } else { len.saturating_sub(1) };
+        if !matches!(motion, Motion::Up | Motion::Down) {
+            self.preferred_column = None;
+        }
+
+        let target = match motion {
+            Motion::Left => {
+                if from.column == 0 {
+                    return None;
+                }
+                (
+                    Position::new(from.line, from.column.saturating_sub(n)),
+                    MotionKind::Exclusive,
+                )
+            }
+            Motion::Right => {
+                if from.column >= max_column {
+                    return None;
+                }
+                (
+                    Position::new(from.line, from.column.saturating_add(n).min(max_column)),
+                    MotionKind::Exclusive,
+                )
+            }
+            Motion::Up | Motion::Down => {
+                let line = if motion == Motion::Up {
+                    if from.line == 0 {
+                        return None;
+                    }
+                    from.line.saturating_sub(n)
+                } else {
+                    if from.line >= last {
+                        return None;
+                    }
+                    from.line.saturating_add(n).min(last)
+                };
+                let column = *self.preferred_column.get_or_insert(from.column);
+                let column = column.min(line_len(rope, line).saturating_sub(1));
+                (Position::new(line, column), MotionKind::Linewise)
+            }
+            Motion::WordStart { big } => {
+                let mut index = char_index(rope, from);
+                for _ in 0..n {
+                    index = next_word_start(rope, index, big);
+                }
+                if !operator && index >= rope.len_chars() {
+                    index = last_char_index(rope);
+                }
+                (position_at(rope, index), MotionKind::Exclusive)
+            }
+            Motion::WordEnd { big } => {
+                let mut index = char_index(rope, from);
+                for _ in 0..n {
+                    index = next_word_end(rope, index, big);
+                }
+                (position_at(rope, index), MotionKind::Inclusive)
+            }
+            Motion::WordBack { big } => {
+                let mut index = char_index(rope, from);
+                for _ in 0..n {
+                    index = prev_word_start(rope, index, big);
+                }
+                (position_at(rope, index), MotionKind::Exclusive)
+            }
+            Motion::LineStart => (Position::new(from.line, 0), MotionKind::Exclusive),
+            Motion::FirstNonBlank => (
+                Position::new(from.line, first_non_blank(rope, from.line)),
+                MotionKind::Exclusive,
+            ),
+            Motion::LineEnd => {
+                let line = (from.line.saturating_add(n) - 1).min(last);
+                self.preferred_column = Some(usize::MAX);
+                (
+                    Position::new(line, line_len(rope, line).saturating_sub(1)),
+                    MotionKind::Inclusive,
+                )
+            }
+            Motion::FirstLine | Motion::LastLine => {
+                let default = if motion == Motion::FirstLine { 0 } else { last };
+                let line = count.map_or(default, |count| count - 1).min(last);
+                (
+                    Position::new(line, first_non_blank(rope, line)),
+                    MotionKind::Linewise,
+                )
+            }
+            Motion::Find(find) => {
+                self.last_find = Some(find);
+                (find_in_line(rope, from, find, n, false)?, find.kind())
+            }
+            Motion::RepeatFind { reverse } => {
+                let mut find = self.last_find?;
+                find.forward ^= reverse;
+                (find_in_line(rope, from, find, n, true)?, find.kind())
+            }
+            Motion::ParagraphForward | Motion::ParagraphBack => {
+                // Skip blank lines, then the paragraph, landing on the blank line after it
+                let forward = motion == Motion::ParagraphForward;
+                let blank = |line: usize| line_len(rope, line) == 0;
+                let mut line = from.line;
+                for _ in 0..n {
+                    if forward {
+                        while line < last && blank(line) {
+                            line += 1;
+                        }
+                        while line < last && !blank(line) {
+                            line += 1;
+                        }
+                    } else {
+                        while line > 0 && blank(line) {
+                            line -= 1;
+                        }
+                        while line > 0 && !blank(line) {
+                            line -= 1;
+                        }
+                    }
+                }
+
+                // Without a blank line the motion ends on the last character
+                let column = match (forward && !blank(line), operator) {
+                    (false, _) => 0,
+                    (true, true) => line_len(rope, line),
+                    (true, false) => line_len(rope, line) - 1,
+                };
+                (Position::new(line, column), MotionKind::Exclusive)
+            }
+            Motion::MatchingBracket => (matching_bracket(rope, from)?, MotionKind::Inclusive),
+        };
+
+        Some(target)
+    }
+
+    fn operate(
+        &mut self,
+        editor: &mut Editor,
+        operator: Operator,
+        region: Region,
+        register: Option<char>,
+    ) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let (first, last) = (region.start.line, region.end.line);
+
+        match operator {
+            Operator::Yank => {
+                self.store(
+                    register,
+                    region_text(&rope, &region),
+                    region.linewise,
+                    false,
+                );
+                let cursor = self.cursor(editor);
+                let target = if !region.linewise {
+                    region.start
+                } else if cursor.line == first {
+                    cursor
+                } else {
+                    Position::new(first, first_non_blank(&rope, first))
+                };
+                self.move_to(editor, target)
+            }
+            Operator::Delete | Operator::Change => {
+                self.store(register, region_text(&rope, &region), region.linewise, true);
+                if operator == Operator::Change {
+                    self.begin_insert(editor);
+                }
+
+                if region.linewise && operator == Operator::Change {
+                    // Changing lines keeps the first line's indentation
+                    let indent: String = line_text(&rope, first)
+                        .chars()
+                        .take_while(|ch| ch.is_whitespace())
+                        .collect();
+                    let column = indent.chars().count();
+                    let range = Range::new(
+                        Position::new(first, 0),
+                        Position::new(last, line_len(&rope, last)),
+                    );
+                    editor.apply_edits(vec![TextEdit::replace(range, indent)])?;
+                    editor.goto_position(Position::new(first, column))?;
+                    self.mode = VimMode::Insert;
+                    return Ok(());
+                }
+
+                let range = if region.linewise {
+                    line_span(&rope, first, last)
+                } else {
+                    Range::new(region.start, region.end)
+                };
+                if !range.is_empty() {
+                    editor.apply_edits(vec![TextEdit::delete(range)])?;
+                }
+
+                let rope = editor.buffer().rope().clone();
+                if operator == Operator::Change {
+                    editor.goto_position(region.start)?;
+                    self.mode = VimMode::Insert;
+                    Ok(())
+                } else if region.linewise {
+                    let line = first.min(last_line(&rope));
+                    self.move_to(editor, Position::new(line, first_non_blank(&rope, line)))
+                } else {
+                    let cursor = self.clamp(&rope, region.start);
+                    self.move_to(editor, cursor)
+                }
+            }
+            Operator::Indent | Operator::Outdent => {
+                editor.restore_cursors(vec![Cursor::with_selection(
+                    0,
+                    Position::new(first, 0),
+                    Position::new(last, 0),
+                )]);
+                if operator == Operator::Indent {
+                    editor.indent_lines()?;
+                } else {
+                    editor.unindent_lines()?;
+                }
+
+                let rope = editor.buffer().rope().clone();
+                self.move_to(editor, Position::new(first, first_non_blank(&rope, first)))
+            }
+            Operator::Lowercase | Operator::Uppercase | Operator::ToggleCase => {
+                self.transform_case(editor, operator, region)?;
+                let rope = editor.buffer().rope().clone();
+                let cursor = self.clamp(&rope, region.start);
+                self.move_to(editor, cursor)
+            }
+        }
+    }
+
+    fn transform_case(
+        &mut self,
+        editor: &mut Editor,
+        operator: Operator,
+        region: Region,
+    ) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let range = if region.linewise {
+            Range::new(
+                Position::new(region.start.line, 0),
+                Position::new(region.end.line, line_len(&rope, region.end.line)),
+            )
+        } else {
+            Range::new(region.start, region.end)
+        };
+
+        let text = slice_text(&rope, range.start, range.end);
+        let changed = change_case(operator, &text);
+        if changed != text {
+            editor.apply_edits(vec![TextEdit::replace(range, changed)])?;
+        }
+        Ok(())
+    }
+
+    /// Save text into a register, following Vim's register rules
+    ///
+    /// Yanks also go to `"0`. Deletes of a line or more shift through `"1` to
+    /// `"9`, smaller ones go to `"-`. Uppercase names append to the register.
+    fn store(&mut self, name: Option<char>, text: String, linewise: bool, deleted: bool) {
+        let register = Register { text, linewise };
+        match name {
+            Some('_') => return,
+            Some(name @ 'A'..='Z') => {
+                let entry = self.registers.entry(name.to_ascii_lowercase()).or_default();
+                entry.text.push_str(&register.text);
+                entry.linewise |= register.linewise;
+                let appended = entry.clone();
+                self.registers.insert('"', appended);
+                return;
+            }
+            Some(name) if name != '"' => {
+                self.registers.insert(name, register.clone());
+            }
+            _ if !deleted => {
+                self.registers.insert('0', register.clone());
+            }
+            _ if linewise || register.text.contains('\n') => {
+                for n in (1..9).rev() {
+                    let from = char::from_digit(n, 10).unwrap_or('1');
+                    let to = char::from_digit(n + 1, 10).unwrap_or('9');
+                    if let Some(shifted) = self.registers.remove(&from) {
+                        self.registers.insert(to, shifted);
+                    }
+                }
+                self.registers.insert('1', register.clone());
+            }
+            _ => {
+                self.registers.insert('-', register.clone());
+            }
+        }
+        self.registers.insert('"', register);
+    }
+
+    fn put(
+        &mut self,
+        editor: &mut Editor,
+        register: &Register,
+        count: usize,
+        before: bool,
+    ) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let cursor = self.cursor(editor);
+        let newline = newline(editor);
+        let text = register.text.replace("\r\n", "\n").replace('\n', &newline);
+        if text.len().checked_mul(count).is_none() {
+            return Err(EditorError::VimError("Count too large".to_string()));
+        }
+        let text = text.repeat(count);
+
+        if register.linewise {
+            let line = if before { cursor.line } else { cursor.line + 1 };
+            if line < rope.len_lines() {
+                editor.apply_edits(vec![TextEdit::insert(Position::new(line, 0), text)])?;
+            } else {
+                // After a last line with no line break of its own
+                let end = Position::new(cursor.line, line_len(&rope, cursor.line));
+                let text = format!(
+                    "{}{}",
+                    newline,
+                    text.strip_suffix(&newline).unwrap_or(&text)
+                );
+                editor.apply_edits(vec![TextEdit::insert(end, text)])?;
+            }
+
+            let rope = editor.buffer().rope().clone();
+            return self.move_to(editor, Position::new(line, first_non_blank(&rope, line)));
+        }
+
+        let len = line_len(&rope, cursor.line);
+        let at = if before || len == 0 {
+            cursor
+        } else {
+            Position::new(cursor.line, (cursor.column + 1).min(len))
+        };
+        let inserted = text.chars().count();
+        editor.apply_edits(vec![TextEdit::insert(at, text)])?;
+
+        let rope = editor.buffer().rope().clone();
+        let end = position_at(&rope, (char_index(&rope, at) + inserted).saturating_sub(1));
+        let end = self.clamp(&rope, end);
+        self.move_to(editor, end)
+    }
+
+    /// Join `count` lines starting at `first` into one
+    fn join_lines(&mut self, editor: &mut Editor, first: usize, count: usize) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let last = (first.saturating_add(count) - 1).min(last_line(&rope));
+        if last <= first {
+            return Ok(());
+        }
+
+        let mut text = line_text(&rope, first);
+        let mut column = 0;
+        for line in first + 1..=last {
+            let next = line_text(&rope, line);
+            let next = next.trim_start();
+            column = text.chars().count();
+            let needs_space = !text.is_empty()
+                && !next.is_empty()
+                && !text.ends_with(char::is_whitespace)
+                && !next.starts_with(')');
+            if needs_space {
+                text.push(' ');
+            }
+            text.push_str(next);
+        }
+
+        let range = Range::new(
+            Position::new(first, 0),
+            Position::new(last, line_len(&rope, last)),
+        );
+        editor.apply_edits(vec![TextEdit::replace(range, text)])?;
+        let rope = editor.buffer().rope().clone();
+        let cursor = self.clamp(&rope, Position::new(first, column));
+        self.move_to(editor, cursor)
+    }
+
+    /// Open a new line below or above the cursor, with the cursor's indentation
+    fn open_line(&mut self, editor: &mut Editor, below: bool) -> EditorResult<()> {
+        let rope = editor.buffer().rope().clone();
+        let cursor = editor.cursor_manager().primary_cursor().position;
+        let indent: String = if editor.config().auto_indent {
+            line_text(&rope, cursor.line)
+                .chars()
+                .take_while(|ch| ch.is_whitespace())
+                .collect()
+        } else {
+            String::new()
+        };
+        let column = indent.chars().count();
+        let newline = newline(editor);
+
+        if below {
+            let end = Position::new(cursor.line, line_len(&rope, cursor.line));
+            editor.apply_edits(vec![TextEdit::insert(
+                end,
+                format!("{}{}", newline, indent),
+            )])?;
+            editor.goto_position(Position::new(cursor.line + 1, column))
+        } else {
+            let start = Position::new(cursor.line, 0);
+            editor.apply_edits(vec![TextEdit::insert(
+                start,
+                format!("{}{}", indent, newline),
+            )])?;
+            editor.goto_position(Position::new(cursor.line, column))
+        }
+    }
+
+    /// Normal mode cursor, which always sits on a character
+    fn cursor(&self, editor: &Editor) -> Position {
+        let position = editor.cursor_manager().primary_cursor().position;
+        self.clamp(editor.buffer().rope(), position)
+    }
+
+    fn clamp(&self, rope: &Rope, position: Position) -> Position {
+        let line = position.line.min(last_line(rope));
+        let column = position.column.min(line_len(rope, line).saturating_sub(1));
+        Position::new(line, column)
+    }
+
+    fn move_to(&mut self, editor: &mut Editor, position: Position) -> EditorResult<()> {
+        editor.goto_position(position)
+    }
+}
+
+impl Default for VimState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FindChar {
+    fn kind(self) -> MotionKind {
+        if self.forward {
+            MotionKind::Inclusive
+        } else {
+            MotionKind::Exclusive
+        }
+    }
+}
+
+fn parse_command(keys: &[VimKey], visual: bool) -> Parse<Parsed> {
+    use VimKey::{Char, Ctrl};
+
+    let mut i = 0;
+    let mut register = None;
+    if keys.first() == Some(&Char('"')) {
+        match keys.get(1) {
+            None => return Parse::Incomplete,
+            Some(&Char(name)) if is_register(name) => register = Some(name),
+            Some(_) => return Parse::Invalid,
+        }
+        i = 2;
+    }
+
+    let (count, used) = parse_count(&keys[i..]);
+    i += used;
+    let Some(&key) = keys.get(i) else {
+        return Parse::Incomplete;
+    };
+    let done = |command, count, len| {
+        Parse::Done(
+            Parsed {
+                register,
+                count,
+                command,
+            },
+            len,
+        )
+    };
+
+    if visual {
+        let command = match key {
+            Char('d' | 'x') => Command::Operate(Operator::Delete, Target::Selection),
+            Char('c' | 's') => Command::Operate(Operator::Change, Target::Selection),
+            Char('y') => Command::Operate(Operator::Yank, Target::Selection),
+            Char('>') => Command::Operate(Operator::Indent, Target::Selection),
+            Char('<') => Command::Operate(Operator::Outdent, Target::Selection),
+            Char('u') => Command::Operate(Operator::Lowercase, Target::Selection),
+            Char('U') => Command::Operate(Operator::Uppercase, Target::Selection),
+            Char('~') => Command::Operate(Operator::ToggleCase, Target::Selection),
+            Char('o') => Command::Action(Action::SwapVisualEnds),
+            Char('J') => Command::Action(Action::Join),
+            Char('p' | 'P') => Command::Action(Action::Put { before: true }),
+            Char('I') => Command::Action(Action::Insert(InsertAt::LineStart)),
+            Char('A') => Command::Action(Action::Insert(InsertAt::LineEnd)),
+            Char(':') => Command::Action(Action::CommandLine),
+            Char('v') => Command::Action(Action::Visual(VimMode::Visual)),
+            Char('V') => Command::Action(Action::Visual(VimMode::VisualLine)),
+            Ctrl('v') => Command::Action(Action::Visual(VimMode::VisualBlock)),
+            Char('i' | 'a') => {
+                return match parse_object(&keys[i..]) {
+                    Parse::Done(object, len) => done(Command::SelectObject(object), count, i + len),
+                    Parse::Incomplete => Parse::Incomplete,
+                    Parse::Invalid => Parse::Invalid,
+                };
+            }
+            _ => {
+                return match parse_motion(&keys[i..]) {
+                    Parse::Done(motion, len) => done(Command::Move(motion), count, i + len),
+                    Parse::Incomplete => Parse::Incomplete,
+                    Parse::Invalid => Parse::Invalid,
+                };
+            }
+        };
+        return done(command, count, i + 1);
+    }
+
+    let operator = match (key, keys.get(i + 1)) {
+        (Char('g'), None) => return Parse::Incomplete,
+        (Char('g'), Some(Char('~'))) => Some((Operator::ToggleCase, 2)),
+        (Char('g'), Some(Char('u'))) => Some((Operator::Lowercase, 2)),
+        (Char('g'), Some(Char('U'))) => Some((Operator::Uppercase, 2)),
+        (Char('d'), _) => Some((Operator::Delete, 1)),
+        (Char('c'), _) => Some((Operator::Change, 1)),
+        (Char('y'), _) => Some((Operator::Yank, 1)),
+        (Char('>'), _) => Some((Operator::Indent, 1)),
+        (Char('<'), _) => Some((Operator::Outdent, 1)),
+        _ => None,
+    };
+
+    if let Some((operator, used)) = operator {
+        let operator_keys = &keys[i..i + used];
+        let start = i + used;
+        let (motion_count, counted) = parse_count(&keys[start..]);
+        let rest = start + counted;
+        let count = match (count, motion_count) {
+            (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+            (a, b) => a.or(b),
+        };
+
+        let tail = &keys[rest..];
+        if tail.is_empty() {
+            return Parse::Incomplete;
+        }
+
+        // A doubled operator, such as `dd` or `g~~`, acts on whole lines
+        let target = Command::Operate(operator, Target::Lines);
+        if tail.starts_with(operator_keys) {
+            return done(target, count, rest + used);
+        }
+        if used == 2 && tail.first() == operator_keys.get(1) {
+            return done(target, count, rest + 1);
+        }
+        if used == 2 && operator_keys.starts_with(tail) {
+            return Parse::Incomplete;
+        }
+
+        if let Some(Char('i' | 'a')) = tail.first() {
+            return match parse_object(tail) {
+                Parse::Done(object, len) => done(
+                    Command::Operate(operator, Target::Object(object)),
+                    count,
+                    rest + len,
+                ),
+                Parse::Incomplete => Parse::Incomplete,
+                Parse::Invalid => Parse::Invalid,
+            };
+        }
+
+        return match parse_motion(tail) {
+            Parse::Done(motion, len) => done(
+                Command::Operate(operator, Target::Motion(motion)),
+                count,
+                rest + len,
+            ),
+            Parse::Incomplete => Parse::Incomplete,
+            Parse::Invalid => Parse::Invalid,
+        };
+    }
+
+    let command = match key {
+        Char('x') => Command::Operate(Operator::Delete, Target::Motion(Motion::Right)),
+        Char('X') => Command::Operate(Operator::Delete, Target::Motion(Motion::Left)),
+        Char('D') => Command::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        Char('C') => Command::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        Char('s') => Command::Operate(Operator::Change, Target::Motion(Motion::Right)),
+        Char('S') => Command::Operate(Operator::Change, Target::Lines),
+        Char('Y') => Command::Operate(Operator::Yank, Target::Lines),
+        Char('i') => Command::Action(Action::Insert(InsertAt::Before)),
+        Char('a') => Command::Action(Action::Insert(InsertAt::After)),
+        Char('I') => Command::Action(Action::Insert(InsertAt::LineStart)),
+        Char('A') => Command::Action(Action::Insert(InsertAt::LineEnd)),
+        Char('o') => Command::Action(Action::Insert(InsertAt::LineBelow)),
+        Char('O') => Command::Action(Action::Insert(InsertAt::LineAbove)),
+        Char('p') => Command::Action(Action::Put { before: false }),
+        Char('P') => Command::Action(Action::Put { before: true }),
+        Char('u') => Command::Action(Action::Undo),
+        Ctrl('r') => Command::Action(Action::Redo),
+        Char('J') => Command::Action(Action::Join),
+        Char('.') => Command::Action(Action::Repeat),
+        Char('~') => Command::Action(Action::ToggleCaseChar),
+        Char('v') => Command::Action(Action::Visual(VimMode::Visual)),
+        Char('V') => Command::Action(Action::Visual(VimMode::VisualLine)),
+        Ctrl('v') => Command::Action(Action::Visual(VimMode::VisualBlock)),
+        Char(':') => Command::Action(Action::CommandLine),
+        Char('r') => {
+            return match keys.get(i + 1) {
+                None => Parse::Incomplete,
+                Some(&Char(ch)) => done(Command::Action(Action::Replace(ch)), count, i + 2),
+                Some(VimKey::Enter) => done(Command::Action(Action::Replace('\n')), count, i + 2),
+                Some(_) => Parse::Invalid,
+            };
+        }
+        _ => {
+            return match parse_motion(&keys[i..]) {
+                Parse::Done(motion, len) => done(Command::Move(motion), count, i + len),
+                Parse::Incomplete => Parse::Incomplete,
+                Parse::Invalid => Parse::Invalid,
+            };
+        }
+    };
+
+    done(command, count, i + 1)
+}
+
+/// Largest count a command takes in a buffer shorter than it
+const MIN_COUNT_LIMIT: usize = 1000;
+
+/// Clamp a count to what can matter in a buffer
+///
+/// No motion or operator goes further than the buffer is long, and capping
+/// the count keeps the text repeated inserts and puts write bounded.
+fn clamp_count(rope: &Rope, count: usize) -> usize {
+    count.min(rope.len_chars().max(MIN_COUNT_LIMIT))
+}
+
+/// Parse a count, which can't start with `0` as that is a motion
+fn parse_count(keys: &[VimKey]) -> (Option<usize>, usize) {
+    let mut count: Option<usize> = None;
+    let mut used = 0;
+    for key in keys {
+        let digit = match (key, count) {
+            (VimKey::Char(ch @ '1'..='9'), _) | (VimKey::Char(ch @ '0'), Some(_)) => {
+                ch.to_digit(10).unwrap_or(0) as usize
+            }
+            _ => break,
+        };
+        count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        used += 1;
+    }
+    (count, used)
+}
+
+fn parse_motion(keys: &[VimKey]) -> Parse<Motion> {
+    use VimKey::Char;
+
+    let Some(&key) = keys.first() else {
+        return Parse::Incomplete;
+    };
+    let motion = match key {
+        Char('h') | VimKey::Backspace => Motion::Left,
+        Char('l' | ' ') => Motion::Right,
+        Char('j') => Motion::Down,
+        Char('k') => Motion::Up,
+        Char('w') => Motion::WordStart { big: false },
+        Char('W') => Motion::WordStart { big: true },
+        Char('e') => Motion::WordEnd { big: false },
+        Char('E') => Motion::WordEnd { big: true },
+        Char('b') => Motion::WordBack { big: false },
+        Char('B') => Motion::WordBack { big: true },
+        Char('0') => Motion::LineStart,
+        Char('^') => Motion::FirstNonBlank,
+        Char('$') => Motion::LineEnd,
+        Char('G') => Motion::LastLine,
+        Char(';') => Motion::RepeatFind { reverse: false },
+        Char(',') => Motion::RepeatFind { reverse: true },
+        Char('}') => Motion::ParagraphForward,
+        Char('{') => Motion::ParagraphBack,
+        Char('%') => Motion::MatchingBracket,
+        Char('g') => {
+            return match keys.get(1) {
+                None => Parse::Incomplete,
+                Some(Char('g')) => Parse::Done(Motion::FirstLine, 2),
+                Some(_) => Parse::Invalid,
+            };
+        }
+        Char(kind @ ('f' | 'F' | 't' | 'T')) => {
+            return match keys.get(1) {
+                None => Parse::Incomplete,
+                Some(&Char(ch)) => Parse::Done(
+                    Motion::Find(FindChar {
+                        ch,
+                        forward: kind == 'f' || kind == 't',
+                        till: kind == 't' || kind == 'T',
+                    }),
+                    2,
+                ),
+                Some(_) => Parse::Invalid,
+            };
+        }
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(motion, 1)
+}
+
+/// Parse a text object such as `iw` or `a(`
+fn parse_object(keys: &[VimKey]) -> Parse<TextObject> {
+    use VimKey::Char;
+
+    let around = keys.first() == Some(&Char('a'));
+    let Some(&key) = keys.get(1) else {
+        return Parse::Incomplete;
+    };
+    let pair = |open, close| TextObject::Pair {
+        open,
+        close,
+        around,
+    };
+    let object = match key {
+        Char('w') => TextObject::Word { big: false, around },
+        Char('W') => TextObject::Word { big: true, around },
+        Char('(' | ')' | 'b') => pair('(', ')'),
+        Char('{' | '}' | 'B') => pair('{', '}'),
+        Char('[' | ']') => pair('[', ']'),
+        Char('<' | '>') => pair('<', '>'),
+        Char(quote @ ('"' | '\'' | '`')) => TextObject::Quote { quote, around },
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(object, 2)
+}
+
+fn is_register(name: char) -> bool {
+    name.is_ascii_alphanumeric() || matches!(name, '"' | '-' | '_' | '+' | '*')
+}
+
+/// Character classes that decide where words start and end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn char_class(ch: char, big: bool) -> CharClass {
+    if ch.is_whitespace() {
+        CharClass::Blank
+    } else if big || is_word_char(ch) {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// Start of the next word, stopping at empty lines as Vim does
+fn next_word_start(rope: &Rope, mut index: usize, big: bool) -> usize {
+    let len = rope.len_chars();
+    if index >= len {
+        return len;
+    }
+
+    let class = char_class(rope.char(index), big);
+    if class != CharClass::Blank {
+        while index < len && char_class(rope.char(index), big) == class {
+            index += 1;
+        }
+    }
+
+    while index < len && rope.char(index).is_whitespace() {
+        if rope.char(index) == '\n' && matches!(rope.get_char(index + 1), Some('\n' | '\r')) {
+            return index + 1;
+        }
+        index += 1;
+    }
+    index
+}
+
+fn next_word_end(rope: &Rope, index: usize, big: bool) -> usize {
+    let len = rope.len_chars();
+    let mut index = index + 1;
+    while index < len && rope.char(index).is_whitespace() {
+        index += 1;
+    }
+    if index >= len {
+        return last_char_index(rope);
+    }
+    current_word_end(rope, index, big)
+}
+
+/// Last character of the run of same-class characters at `index`
+fn current_word_end(rope: &Rope, mut index: usize, big: bool) -> usize {
+    let class = char_class(rope.char(index), big);
+    while rope
+        .get_char(index + 1)
+        .is_some_and(|ch| char_class(ch, big) == class && ch != '\n')
+    {
+        index += 1;
+    }
+    index
+}
+
+fn prev_word_start(rope: &Rope, index: usize, big: bool) -> usize {
+    if index == 0 {
+        return 0;
+    }
+
+    let mut index = index - 1;
+    while index > 0 && rope.char(index).is_whitespace() {
+        if rope.char(index) == '\n' && rope.char(index - 1) == '\n' {
+            return index;
+        }
+        index -= 1;
+    }
+
+    let class = char_class(rope.char(index), big);
+    while index > 0 && char_class(rope.char(index - 1), big) == class {
+        index -= 1;
+    }
+    index
+}
+
+/// Find the `count`th occurrence of a character on the cursor's line
+///
+/// A repeated `t` or `T` starts one character further along, or it would find
+/// the character it stopped in front of again.
+fn find_in_line(
+    rope: &Rope,
+    from: Position,
+    find: FindChar,
+    count: usize,
+    repeat: bool,
+) -> Option<Position> {
+    let text: Vec<char> = line_text(rope, from.line).chars().collect();
+    let mut column = from.column;
+    for step in 0..count {
+        let skip = usize::from(find.till && repeat && step == 0);
+        column = if find.forward {
+            (column + 1 + skip..text.len()).find(|&c| text[c] == find.ch)?
+        } else {
+            (0..column.checked_sub(skip)?)
+                .rev()
+                .find(|&c| text[c] == find.ch)?
+        };
+    }
+
+    let column = match (find.till, find.forward) {
+        (true, true) => column - 1,
+        (true, false) => column + 1,
+        _ => column,
+    };
+    Some(Position::new(from.line, column))
+}
+
+/// Bracket matching the first bracket at or after the cursor on its line
+fn matching_bracket(rope: &Rope, from: Position) -> Option<Position> {
+    let text: Vec<char> = line_text(rope, from.line).chars().collect();
+    let column = (from.column..text.len()).find(|&c| "()[]{}".contains(text[c]))?;
+    let (open, close, forward) = match text[column] {
+        '(' => ('(', ')', true),
+        ')' => ('(', ')', false),
+        '[' => ('[', ']', true),
+        ']' => ('[', ']', false),
+        '{' => ('{', '}', true),
+        _ => ('{', '}', false),
+    };
+
+    let start = char_index(rope, Position::new(from.line, column));
+    let (inner, outer) = if forward {
+        (open, close)
+    } else {
+        (close, open)
+    };
+    let mut depth = 0usize;
+    let mut visit = |index: usize| {
+        let ch = rope.char(index);
+        if ch == inner {
+            depth += 1;
+        } else if ch == outer {
+            depth = depth.saturating_sub(1);
+        }
+        (depth == 0).then(|| position_at(rope, index))
+    };
+
+    if forward {
+        (start..rope.len_chars()).find_map(&mut visit)
+    } else {
+        (0..=start).rev().find_map(&mut visit)
+    }
+}
+
+fn object_region(rope: &Rope, at: Position, object: TextObject, count: usize) -> Option<Region> {
+    match object {
+        TextObject::Word { big, around } => word_object(rope, at, big, around),
+        TextObject::Pair {
+            open,
+            close,
+            around,
+        } => pair_object(rope, at, open, close, around, count),
+        TextObject::Quote { quote, around } => quote_object(rope, at, quote, around),
+    }
+}
+
+fn word_object(rope: &Rope, at: Position, big: bool, around: bool) -> Option<Region> {
+    let text: Vec<char> = line_text(rope, at.line).chars().collect();
+    if text.is_empty() {
+        return None;
+    }
+
+    let column = at.column.min(text.len() - 1);
+    let class = char_class(text[column], big);
+    let mut start = column;
+    while start > 0 && char_class(text[start - 1], big) == class {
+        start -= 1;
+    }
+    let mut end = column + 1;
+    while end < text.len() && char_class(text[end], big) == class {
+        end += 1;
+    }
+
+    if around {
+        if class == CharClass::Blank {
+            // Blanks and the word after them
+            if let Some(&ch) = text.get(end) {
+                let next = char_class(ch, big);
+                while end < text.len() && char_class(text[end], big) == next {
+                    end += 1;
+                }
+            }
+        } else {
+            // The word and the blanks after it, or before it at the end of a line
+            let mut trailing = end;
+            while trailing < text.len() && text[trailing].is_whitespace() {
+                trailing += 1;
+            }
+            if trailing > end {
+                end = trailing;
+            } else {
+                while start > 0 && text[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+            }
+        }
+    }
+
+    Some(Region::chars(
+        Position::new(at.line, start),
+        Position::new(at.line, end),
+    ))
+}
+
+/// Text between a pair of brackets around the cursor, `count` levels out
+///
+/// As in Vim, the inner part of a block spanning several lines leaves out the
+/// line break after the opening bracket and the indentation before the closing one.
+fn pair_object(
+    rope: &Rope,
+    at: Position,
+    open: char,
+    close: char,
+    around: bool,
+    count: usize,
+) -> Option<Region> {
+    let index = char_index(rope, at);
+    let len = rope.len_chars();
+
+    let mut depth = 0;
+    let mut levels = count;
+    let mut open_index = (index + 1).min(len);
+    loop {
+        open_index = open_index.checked_sub(1)?;
+        let ch = rope.char(open_index);
+        if ch == close && open_index != index {
+            depth += 1;
+        } else if ch == open {
+            if depth == 0 {
+                levels -= 1;
+                if levels == 0 {
+                    break;
+                }
+            } else {
+                depth -= 1;
+            }
+        }
+    }
+
+    let mut depth = 0;
+    let mut close_index = open_index + 1;
+    loop {
+        let ch = rope.get_char(close_index)?;
+        if ch == open {
+            depth += 1;
+        } else if ch == close {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        }
+        close_index += 1;
+    }
+
+    if around {
+        return Some(Region::chars(
+            position_at(rope, open_index),
+            position_at(rope, close_index + 1),
+        ));
+    }
+
+    let mut start = open_index + 1;
+    if rope.get_char(start) == Some('\r') && rope.get_char(start + 1) == Some('\n') {
+        start += 2;
+    } else if rope.get_char(start) == Some('\n') {
+        start += 1;
+    }
+
+    let mut end = close_index;
+    let close_position = position_at(rope, close_index);
+    let line_start = char_index(rope, Position::new(close_position.line, 0));
+    if start <= line_start
+        && rope
+            .slice(line_start..close_index)
+            .chars()
+            .all(char::is_whitespace)
+    {
+        end = line_start;
+    }
+
+    Some(Region::chars(
+        position_at(rope, start.min(end)),
+        position_at(rope, end),
+    ))
+}
+
+fn quote_object(rope: &Rope, at: Position, quote: char, around: bool) -> Option<Region> {
+    let text: Vec<char> = line_text(rope, at.line).chars().collect();
+    let quotes: Vec<usize> = (0..text.len())
+        .filter(|&c| text[c] == quote && (c == 0 || text[c - 1] != '\\'))
+        .collect();
+
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(open, close)| open <= at.column && at.column <= close)
+        .or_else(|| {
+            quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|&(open, _)| open > at.column)
+        })?;
+
+    let (start, end) = if around {
+        (open, close + 1)
+    } else {
+        (open + 1, close)
+    };
+    Some(Region::chars(
+        Position::new(at.line, start),
+        Position::new(at.line, end),
+    ))
+}
+
+/// Apply a case operator to text
+fn change_case(operator: Operator, text: &str) -> String {
+    match operator {
+        Operator::Lowercase => text.to_lowercase(),
+        Operator::Uppercase => text.to_uppercase(),
+        _ => text
+            .chars()
+            .flat_map(|ch| {
+                if ch.is_uppercase() {
+                    ch.to_lowercase().collect::<Vec<_>>()
+                } else {
+                    ch.to_uppercase().collect::<Vec<_>>()
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Split `:s` arguments on unescaped delimiters, unescaping the delimiter
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        let part = parts.last_mut().expect("parts is never empty");
+        match ch {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => part.push('\\'),
+            },
+            ch if ch == delimiter => parts.push(String::new()),
+            ch => part.push(ch),
+        }
+    }
+    parts
+}
+
+/// Convert a Vim replacement string to the regex crate's syntax
+fn vim_replacement(text: &str) -> String {
+    let mut replacement = String::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    replacement.push_str("${");
+                    replacement.push(digit);
+                    replacement.push('}');
+                }
+                Some('n' | 'r') => replacement.push('\n'),
+                Some('t') => replacement.push('\t'),
+                Some('$') => replacement.push_str("$$"),
+                Some(other) => replacement.push(other),
+                None => replacement.push('\\'),
+            },
+            '&' => replacement.push_str("${0}"),
+            '$' => replacement.push_str("$$"),
+            ch => replacement.push(ch),
+        }
+    }
+    replacement
+}
+
+fn newline(editor: &Editor) -> String {
+    editor.buffer().line_ending().as_str().to_string()
+}
+
+fn line_len(rope: &Rope, line: usize) -> usize {
+    snapshot::line_len(rope, line).unwrap_or(0)
+}
+
+/// Text of a line without its line ending
+fn line_text(rope: &Rope, line: usize) -> String {
+    match rope.get_line(line) {
+        Some(slice) => slice.slice(..line_len(rope, line)).to_string(),
+        None => String::new(),
+    }
+}
+
+/// Last line as Vim counts them, ignoring the empty line after a final line break
+fn last_line(rope: &Rope) -> usize {
+    let lines = rope.len_lines();
+    if lines > 1 && line_len(rope, lines - 1) == 0 {
+        lines - 2
+    } else {
+        lines - 1
+    }
+}
+
+fn first_non_blank(rope: &Rope, line: usize) -> usize {
+    line_text(rope, line)
+        .chars()
+        .take_while(|ch| ch.is_whitespace())
+        .count()
+}
+
+fn char_index(rope: &Rope, position: Position) -> usize {
+    snapshot::position_to_char_index(rope, position).unwrap_or_else(|_| rope.len_chars())
+}
+
+fn position_at(rope: &Rope, index: usize) -> Position {
+    snapshot::char_index_to_position(rope, index.min(rope.len_chars()))
+        .unwrap_or_else(|_| Position::zero())
+}
+
+/// Index of the last character that isn't a line break
+fn last_char_index(rope: &Rope) -> usize {
+    let mut index = rope.len_chars();
+    while index > 0 && matches!(rope.char(index - 1), '\n' | '\r') {
+        index -= 1;
+    }
+    index.saturating_sub(1)
+}
+
+/// Position just after the character at `position`, staying on its line
+fn char_after(rope: &Rope, position: Position) -> Position {
+    let len = line_len(rope, position.line);
+    Position::new(
+        position.line,
+        (position.column + 1).min(len.max(position.column)),
+    )
+}
+
+/// Position of the character before the exclusive end of a region
+fn char_before(rope: &Rope, end: Position) -> Position {
+    position_at(rope, char_index(rope, end).saturating_sub(1))
+}
+
+fn slice_text(rope: &Rope, start: Position, end: Position) -> String {
+    let start = char_index(rope, start);
+    let end = char_index(rope, end).max(start);
+    rope.slice(start..end).to_string()
+}
+
+/// Text an operator acts on; linewise text always ends with a line break
+fn region_text(rope: &Rope, region: &Region) -> String {
+    if !region.linewise {
+        return slice_text(rope, region.start, region.end);
+    }
+
+    let mut text = String::new();
+    for line in region.start.line..=region.end.line {
+        text.push_str(&line_text(rope, line));
+        text.push('\n');
+    }
+    text
+}
+
+/// Range covering whole lines, including the line break that separates them
+/// from the rest of the buffer
+fn line_span(rope: &Rope, first: usize, last: usize) -> Range {
+    if last + 1 < rope.len_lines() {
+        Range::new(Position::new(first, 0), Position::new(last + 1, 0))
+    } else if first > 0 {
+        Range::new(
+            Position::new(first - 1, line_len(rope, first - 1)),
+            Position::new(last, line_len(rope, last)),
+        )
+    } else {
+        Range::new(
+            Position::new(0, 0),
+            Position::new(last, line_len(rope, last)),
+        )
+    }
+}
+
+fn visual_column(rope: &Rope, position: Position, tab_size: usize) -> usize {
+    column_to_visual_column(&line_text(rope, position.line), position.column, tab_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor_with(text: &str) -> Editor {
+        let mut editor = Editor::new();
+        editor.insert_text(text).unwrap();
+        editor.goto_position(Position::zero()).unwrap();
+        editor.set_vim_enabled(true).unwrap();
+        editor
+    }
+
+    fn feed(editor: &mut Editor, keys: &str) {
+        for key in VimKey::parse_keys(keys) {
+            editor.handle_vim_key(key).unwrap();
+        }
+    }
+
+    fn cursor(editor: &Editor) -> Position {
+        editor.cursor_manager().primary_cursor().position
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(
+            VimKey::parse_keys("a<lt><Esc><C-r><x"),
+            [
+                VimKey::Char('a'),
+                VimKey::Char('<'),
+                VimKey::Escape,
+                VimKey::Ctrl('r'),
+                VimKey::Char('<'),
+                VimKey::Char('x'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operators_motions_and_counts() {
+        let mut editor = editor_with("one two three four\nsecond line\nthird line\n");
+
+        feed(&mut editor, "d2w");
+        assert_eq!(editor.buffer().line_text(0).unwrap(), "three four\n");
+
+        feed(&mut editor, "3x");
+        assert_eq!(editor.buffer().line_text(0).unwrap(), "ee four\n");
+
+        feed(&mut editor, "cwfive<Esc>");
+        assert_eq!(editor.buffer().line_text(0).unwrap(), "five four\n");
+        assert_eq!(cursor(&editor), Position::new(0, 3));
+
+        feed(&mut editor, "jdd");
+        assert_eq!(editor.buffer().text(), "five four\nthird line\n");
+        assert_eq!(editor.state().vim.as_ref().unwrap().mode, VimMode::Normal);
+
+        // The whole change is undone in one step
+        feed(&mut editor, "uu");
+        assert_eq!(editor.buffer().line_text(0).unwrap(), "ee four\n");
+
+        feed(&mut editor, "2d");
+        assert_eq!(editor.state().vim.as_ref().unwrap().pending, "2d");
+        feed(&mut editor, "<Esc>");
+        assert_eq!(editor.state().vim.as_ref().unwrap().pending, "");
+    }
+
+    #[test]
+    fn test_huge_counts_are_clamped() {
+        let huge = "99999999999999999999999";
+        let mut editor = editor_with("one\ntwo\nthree\n");
+
+        feed(&mut editor, &format!("{huge}rx{huge}l{huge}~"));
+        assert_eq!(editor.buffer().text(), "onE\ntwo\nthree\n");
+
+        feed(&mut editor, &format!("{huge}J"));
+        assert_eq!(editor.buffer().text(), "onE two three\n");
+
+        feed(&mut editor, &format!("0yl{huge}p"));
+        let line = editor.buffer().line_text(0).unwrap();
+        assert_eq!(line.len(), "onE two three\n".len() + MIN_COUNT_LIMIT);
+    }
+
+    #[test]
+    fn test_text_objects_registers_and_repeat() {
+        let mut editor = editor_with("call(first, second) word\nx\n");
+
+        feed(&mut editor, "fsci(done<Esc>");
+        assert_eq!(editor.buffer().line_text(0).unwrap(), "call(done) word\n");
+
+        feed(&mut editor, "$\"ayiwj\"ap");
+        assert_eq!(editor.buffer().line_text(1).unwrap(), "xword\n");
+
+        feed(&mut editor, "\"Ayiw");
+        assert_eq!(editor.vim().unwrap().register('a'), Some("wordxword"));
+
+        let mut editor = editor_with("a b c d e\n");
+        feed(&mut editor, "dw..");
+        assert_eq!(editor.buffer().text(), "d e\n");
+
+        feed(&mut editor, "A!<Esc>0.");
+        assert_eq!(editor.buffer().text(), "d e!!\n");
+    }
+
+    #[test]
+    fn test_visual_modes_and_ex_commands() {
+        let mut editor = editor_with("foo 1\nfoo 2\nfoo 3\nfoo 4\n");
+
+        feed(&mut editor, "jVj");
+        assert_eq!(
+            editor.state().vim.as_ref().unwrap().mode,
+            VimMode::VisualLine
+        );
+        feed(&mut editor, ":s/foo/bar/<CR>");
+        assert_eq!(editor.buffer().text(), "foo 1\nbar 2\nbar 3\nfoo 4\n");
+
+        feed(&mut editor, ":%s/(\\w+) (\\d)/\\2&/g<CR>");
+        assert_eq!(editor.buffer().text(), "1foo 1\n2bar 2\n3bar 3\n4foo 4\n");
+
+        feed(&mut editor, ":1<CR>");
+        assert_eq!(cursor(&editor).line, 0);
+        assert!(editor.handle_vim_key(VimKey::Char(':')).is_ok());
+        feed(&mut editor, "s/nothing/x/");
+        assert!(editor.handle_vim_key(VimKey::Enter).is_err());
+
+        assert_eq!(
+            editor.handle_vim_key(VimKey::Char(':')).unwrap(),
+            VimEffect::None
+        );
+        feed(&mut editor, "w");
+        assert_eq!(
+            editor.handle_vim_key(VimKey::Enter).unwrap(),
+            VimEffect::Write {
+                path: None,
+                quit: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_visual_block_insert() {
+        let mut editor = editor_with("abc\ndef\nghi\n");
+
+        feed(&mut editor, "l<C-v>jj");
+        assert_eq!(editor.cursor_manager().cursors().len(), 3);
+        feed(&mut editor, "I-<Esc>");
+        assert_eq!(editor.buffer().text(), "a-bc\nd-ef\ng-hi\n");
+        assert_eq!(editor.cursor_manager().cursors().len(), 1);
+
+        feed(&mut editor, "u");
+        assert_eq!(editor.buffer().text(), "abc\ndef\nghi\n");
+    }
+}
@@ -0,0 +1,183 @@
+// src-tauri/src/core/wrap.rs
+//! Soft-wrap layout
+//!
+//! Splits a line into the visual rows it is drawn on, so cursor movement can
+//! follow what is on screen when word wrap is on.
+
+use crate::core::utils::{advance_visual_column, column_to_visual_column};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Breaks lines into rows of a fixed visual width
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrapLayout {
+    /// Row width in visual columns
+    width: usize,
+    /// Tab width in visual columns
+    tab_size: usize,
+}
+
+impl WrapLayout {
+    /// Create a layout wrapping at `width` visual columns
+    pub fn new(width: usize, tab_size: usize) -> Self {
+        Self {
+            width: width.max(1),
+            tab_size: tab_size.max(1),
+        }
+    }
+
+    /// Layout that never wraps, giving one row per line
+    pub fn unwrapped(tab_size: usize) -> Self {
+        Self::new(usize::MAX, tab_size)
+    }
+
+    /// Row width in visual columns
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Columns at which each visual row of a line starts
+    ///
+    /// The first row always starts at 0. Rows break after the last whitespace
+    /// that fits, or mid-word when a word is wider than a row. Whitespace may
+    /// hang past the edge and grapheme clusters are never split.
+    pub fn row_starts(&self, text: &str) -> Vec<usize> {
+        let mut starts = vec![0];
+        let mut row_visual = 0;
+        // Column and visual column just past the last whitespace in the row
+        let mut break_after: Option<(usize, usize)> = None;
+        let mut visual = 0;
+        let mut column = 0;
+
+        for grapheme in text.graphemes(true) {
+            let next_visual = self.advance(visual, grapheme);
+            let is_whitespace = grapheme.chars().all(char::is_whitespace);
+            let row_start = starts[starts.len() - 1];
+
+            if !is_whitespace && next_visual - row_visual > self.width && column > row_start {
+                let (start, start_visual) = break_after.take().unwrap_or((column, visual));
+                starts.push(start);
+                row_visual = start_visual;
+
+                // The word carried down can still be too wide with this grapheme
+                if next_visual - row_visual > self.width && column > start {
+                    starts.push(column);
+                    row_visual = visual;
+                }
+            }
+
+            if is_whitespace {
+                break_after = Some((column + grapheme.chars().count(), next_visual));
+            }
+            visual = next_visual;
+            column += grapheme.chars().count();
+        }
+
+        starts
+    }
+
+    /// Number of visual rows a line takes
+    pub fn row_count(&self, text: &str) -> usize {
+        self.row_starts(text).len()
+    }
+
+    /// Row a column is drawn on and its visual offset from the row start
+    ///
+    /// A column at a row break belongs to the row it starts.
+    pub fn locate(&self, text: &str, column: usize) -> (usize, usize) {
+        let starts = self.row_starts(text);
+        let row = starts.partition_point(|&start| start <= column) - 1;
+        let offset = column_to_visual_column(text, column, self.tab_size)
+            - column_to_visual_column(text, starts[row], self.tab_size);
+        (row, offset)
+    }
+
+    /// Column in `row` closest to `offset` visual columns from the row start
+    ///
+    /// Lands on the last grapheme boundary at or before the offset, and never
+    /// on the break that starts the next row. Returns `None` past the last row.
+    pub fn column_at(&self, text: &str, row: usize, offset: usize) -> Option<usize> {
+        let starts = self.row_starts(text);
+        let start = *starts.get(row)?;
+        let end = starts.get(row + 1).copied();
+
+        let mut row_visual = None;
+        let mut visual = 0;
+        let mut column = 0;
+        let mut best = start;
+
+        for grapheme in text.graphemes(true) {
+            if end.is_some_and(|end| column >= end) {
+                return Some(best);
+            }
+            if column >= start {
+                let base = *row_visual.get_or_insert(visual);
+                if visual - base > offset {
+                    return Some(best);
+                }
+                best = column;
+            }
+            visual = self.advance(visual, grapheme);
+            column += grapheme.chars().count();
+        }
+
+        // The end of the line is a valid stop on the last row
+        if row_visual.is_none_or(|base| visual - base <= offset) {
+            best = column;
+        }
+        Some(best)
+    }
+
+    fn advance(&self, visual: usize, grapheme: &str) -> usize {
+        grapheme.chars().fold(visual, |visual, ch| {
+            advance_visual_column(visual, ch, self.tab_size)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_starts() {
+        let layout = WrapLayout::new(8, 4);
+        assert_eq!(layout.row_starts(""), [0]);
+        assert_eq!(layout.row_starts("hello"), [0]);
+        assert_eq!(layout.row_starts("hello world foo"), [0, 6, 12]);
+        // Trailing whitespace hangs instead of starting a row
+        assert_eq!(layout.row_starts("hello world     "), [0, 6]);
+
+        // Words wider than a row break mid-word
+        assert_eq!(WrapLayout::new(4, 4).row_starts("abcdefghij"), [0, 4, 8]);
+        assert_eq!(WrapLayout::new(4, 4).row_starts("ab cdefghij"), [0, 3, 7]);
+
+        // Combining marks stay with their base character
+        assert_eq!(WrapLayout::new(2, 4).row_starts("ae\u{301}bc"), [0, 3]);
+        // Wide characters take two columns
+        assert_eq!(WrapLayout::new(3, 4).row_starts("日本語"), [0, 1, 2]);
+
+        assert_eq!(WrapLayout::unwrapped(4).row_count("hello world foo"), 1);
+    }
+
+    #[test]
+    fn test_locate_and_column_at() {
+        let layout = WrapLayout::new(8, 4);
+        let text = "hello world foo";
+
+        assert_eq!(layout.locate(text, 0), (0, 0));
+        assert_eq!(layout.locate(text, 5), (0, 5));
+        assert_eq!(layout.locate(text, 6), (1, 0));
+        assert_eq!(layout.locate(text, 9), (1, 3));
+        assert_eq!(layout.locate(text, 15), (2, 3));
+
+        assert_eq!(layout.column_at(text, 1, 3), Some(9));
+        // Offsets past the row end stop before the next row
+        assert_eq!(layout.column_at(text, 0, 20), Some(5));
+        assert_eq!(layout.column_at(text, 2, 20), Some(15));
+        assert_eq!(layout.column_at(text, 3, 0), None);
+
+        // Offsets inside a wide character land before it
+        assert_eq!(WrapLayout::new(4, 4).column_at("日本語", 0, 3), Some(1));
+        assert_eq!(layout.column_at("", 0, 4), Some(0));
+    }
+}
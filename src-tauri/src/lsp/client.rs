@@ -1,7 +1,10 @@
+use crate::core::BufferSnapshot;
 use crate::lsp::{
-    utils, EnhancedDiagnostic, LspCapabilities, LspDocument, LspError, LspEvent, LspRequest,
-    LspResponse, LspResult, LspServerInfo, LspServerStatus,
+    position, utils, EnhancedDiagnostic, LspCapabilities, LspDocument, LspError, LspEvent,
+    LspRequest, LspResponse, LspResult, LspServerInfo, LspServerStatus, PositionEncoding,
+    RustAnalyzerRequests,
 };
+use ropey::Rope;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -38,6 +41,8 @@ pub struct LspClient {
 pub struct LspServer {
     pub info: LspServerInfo,
     pub capabilities: Option<ServerCapabilities>,
+    /// Unit the server counts columns in
+    pub position_encoding: PositionEncoding,
     process: Arc<Mutex<Option<Child>>>,
     stdin: Arc<Mutex<Option<tokio::process::ChildStdin>>>,
     request_sender: mpsc::UnboundedSender<LspServerMessage>,
@@ -115,6 +120,7 @@ impl LspClient {
         let server = Arc::new(LspServer {
             info: server_info.clone(),
             capabilities: None,
+            position_encoding: PositionEncoding::default(),
             process: Arc::new(Mutex::new(Some(process))),
             stdin: Arc::new(Mutex::new(Some(stdin))),
             request_sender,
@@ -556,7 +562,12 @@ impl LspClient {
                             "ul".to_string(),
                         ]),
                     }),
-                    position_encodings: Some(vec![PositionEncodingKind::UTF16]),
+                    position_encodings: Some(
+                        PositionEncoding::SUPPORTED
+                            .iter()
+                            .map(|encoding| encoding.kind())
+                            .collect(),
+                    ),
                     stale_request_support: None,
                 }),
                 experimental: None,
@@ -587,6 +598,12 @@ impl LspClient {
             if let Some(server) = servers.get_mut(server_id) {
                 let server_mut = Arc::get_mut(server).unwrap();
                 server_mut.capabilities = Some(initialize_result.capabilities.clone());
+                server_mut.position_encoding =
+                    PositionEncoding::negotiated(&initialize_result.capabilities);
+                debug!(
+                    "Negotiated {:?} positions with {}",
+                    server_mut.position_encoding, server_id
+                );
                 server_mut.info.capabilities = initialize_result.capabilities.clone();
                 server_mut.info.status = LspServerStatus::Running;
             }
@@ -773,6 +790,17 @@ impl LspClient {
         version: i32,
         changes: Vec<TextDocumentContentChangeEvent>,
     ) -> LspResult<()> {
+        let language_id = {
+            let documents = self.documents.read().await;
+            documents
+                .get(&uri)
+                .map(|doc| doc.language_id.clone())
+                .ok_or_else(|| LspError::DocumentNotFound {
+                    uri: uri.to_string(),
+                })?
+        };
+        let encoding = self.position_encoding_for_language(&language_id).await;
+
         // Update document with proper rope-based text editing
         let updated_content = {
             let mut documents = self.documents.write().await;
@@ -802,11 +830,13 @@ impl LspClient {
             for change in sorted_changes {
                 if let Some(range) = change.range {
                     // Range-based change - more complex but accurate
-                    let start_offset = self.position_to_offset(&rope, &range.start)?;
-                    let end_offset = self.position_to_offset(&rope, &range.end)?;
+                    let start_offset =
+                        position::lsp_position_to_char(&rope, &range.start, encoding);
+                    let end_offset =
+                        position::lsp_position_to_char(&rope, &range.end, encoding);
                     
                     // Validate offsets
-                    if start_offset <= end_offset && end_offset <= rope.len() {
+                    if start_offset <= end_offset {
                         // Remove the old text
                         rope.remove(start_offset..end_offset);
                         
@@ -829,15 +859,6 @@ impl LspClient {
         };
         
         // Find server for this document
-        let language_id = {
-            let documents = self.documents.read().await;
-            documents.get(&uri)
-                .map(|doc| doc.language_id.clone())
-                .ok_or_else(|| LspError::DocumentNotFound {
-                    uri: uri.to_string(),
-                })?
-        };
-        
         let server_id = self.find_server_for_language(&language_id).await?;
         
         // Send didChange notification with the processed changes
@@ -894,6 +915,143 @@ impl LspClient {
         Ok(())
     }
 
+    /// Format an open document with its language server, returning the new text
+    ///
+    /// The snapshot is sent to the server first when it differs from what the
+    /// server has. `range` limits formatting to part of the document.
+    pub async fn format_document(
+        &self,
+        uri: Url,
+        snapshot: &BufferSnapshot,
+        options: FormattingOptions,
+        range: Option<crate::core::Range>,
+    ) -> LspResult<String> {
+        let (server_id, capabilities, encoding) = self.sync_document(&uri, snapshot).await?;
+        let capabilities = LspCapabilities::from(&capabilities);
+        let (method, supported) = match range {
+            Some(_) => ("textDocument/rangeFormatting", capabilities.range_formatting),
+            None => ("textDocument/formatting", capabilities.formatting),
+        };
+        if !supported {
+            return Err(LspError::UnsupportedCapability {
+                capability: method.to_string(),
+            });
+        }
+
+        let rope = snapshot.rope();
+        let text_document = TextDocumentIdentifier { uri };
+        let params = match range {
+            Some(range) => serde_json::to_value(DocumentRangeFormattingParams {
+                text_document,
+                range: utils::editor_range_to_lsp(rope, &range, encoding)?,
+                options,
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })?,
+            None => serde_json::to_value(DocumentFormattingParams {
+                text_document,
+                options,
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })?,
+        };
+
+        let response = self.send_request(&server_id, method, params).await?;
+        let edits: Option<Vec<TextEdit>> = serde_json::from_value(response)?;
+
+        debug!(
+            "{} returned {} edits",
+            method,
+            edits.as_ref().map_or(0, Vec::len)
+        );
+        Ok(utils::apply_text_edits(
+            rope,
+            &edits.unwrap_or_default(),
+            encoding,
+        ))
+    }
+
+    /// Join lines of an open document with rust-analyzer, returning the new text
+    ///
+    /// Each range joins the lines it covers, or its line with the next when
+    /// empty. The snapshot is sent to the server first when it differs from
+    /// what the server has.
+    pub async fn join_lines(
+        &self,
+        uri: Url,
+        snapshot: &BufferSnapshot,
+        ranges: &[crate::core::Range],
+    ) -> LspResult<String> {
+        const METHOD: &str = "experimental/joinLines";
+
+        let (server_id, capabilities, encoding) = self.sync_document(&uri, snapshot).await?;
+        let supported = capabilities
+            .experimental
+            .as_ref()
+            .and_then(|experimental| experimental.get("joinLines"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if !supported {
+            return Err(LspError::UnsupportedCapability {
+                capability: METHOD.to_string(),
+            });
+        }
+
+        let rope = snapshot.rope();
+        let ranges = ranges
+            .iter()
+            .map(|range| utils::editor_range_to_lsp(rope, range, encoding))
+            .collect::<LspResult<Vec<_>>>()?;
+        let params = RustAnalyzerRequests::join_lines(TextDocumentIdentifier { uri }, ranges);
+
+        let response = self.send_request(&server_id, METHOD, params).await?;
+        let edits: Vec<TextEdit> = serde_json::from_value(response)?;
+
+        debug!("{} returned {} edits", METHOD, edits.len());
+        Ok(utils::apply_text_edits(rope, &edits, encoding))
+    }
+
+    /// Server of an open document, after sending it the snapshot if it changed
+    ///
+    /// The snapshot is compared with the server's copy chunk by chunk, so the
+    /// text is only copied out when it has to be sent.
+    async fn sync_document(
+        &self,
+        uri: &Url,
+        snapshot: &BufferSnapshot,
+    ) -> LspResult<(String, ServerCapabilities, PositionEncoding)> {
+        let document = self
+            .get_document(uri)
+            .await
+            .ok_or_else(|| LspError::DocumentNotFound {
+                uri: uri.to_string(),
+            })?;
+        let server_id = self.find_server_for_language(&document.language_id).await?;
+
+        let (capabilities, encoding) = {
+            let servers = self.servers.read().await;
+            let server = servers
+                .get(&server_id)
+                .ok_or_else(|| LspError::ServerNotFound {
+                    server_name: server_id.clone(),
+                })?;
+            (server.info.capabilities.clone(), server.position_encoding)
+        };
+
+        if *snapshot.rope() != document.content.as_str() {
+            self.did_change_document(
+                uri.clone(),
+                document.version + 1,
+                vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: snapshot.text(),
+                }],
+            )
+            .await?;
+        }
+
+        Ok((server_id, capabilities, encoding))
+    }
+
     /// Find the appropriate server for a language
     async fn find_server_for_language(&self, language_id: &str) -> LspResult<String> {
         let servers = self.servers.read().await;
@@ -911,6 +1069,22 @@ impl LspClient {
         })
     }
 
+    /// Position encoding of the server handling a language
+    ///
+    /// Falls back to UTF-16, the protocol default, when no server is running.
+    async fn position_encoding_for_language(&self, language_id: &str) -> PositionEncoding {
+        let Ok(server_id) = self.find_server_for_language(language_id).await else {
+            return PositionEncoding::default();
+        };
+
+        self.servers
+            .read()
+            .await
+            .get(&server_id)
+            .map(|server| server.position_encoding)
+            .unwrap_or_default()
+    }
+
     /// Handle server communication
     async fn run_server_communication(
         server: Arc<LspServer>,
@@ -6,10 +6,12 @@ use uuid::Uuid;
 
 pub mod client;
 pub mod ownership;
+pub mod position;
 pub mod rust_analyzer;
 
 pub use client::*;
 pub use ownership::*;
+pub use position::PositionEncoding;
 pub use rust_analyzer::*;
 /// Result type for LSP operations
 pub type LspResult<T> = Result<T, LspError>;
@@ -40,6 +42,9 @@ pub enum LspError {
 
     #[error("Capability not supported: {capability}")]
     UnsupportedCapability { capability: String },
+
+    #[error("Invalid position: {message}")]
+    InvalidPosition { message: String },
 }
 
 impl From<tower_lsp::jsonrpc::Error> for LspError {
@@ -388,8 +393,13 @@ impl Default for LspConfig {
 /// Utility functions for LSP operations
 pub mod utils {
     use super::*;
+    use ropey::Rope;
     use std::path::Path;
 
+    pub use super::position::{
+        editor_position_to_lsp, editor_range_to_lsp, lsp_position_to_editor, lsp_range_to_editor,
+    };
+
     /// Convert file path to LSP URI
     pub fn path_to_uri(path: &Path) -> LspResult<Url> {
         Url::from_file_path(path)
@@ -406,38 +416,6 @@ pub mod utils {
             })
     }
 
-    /// Convert position from editor coords to LSP positions
-    pub fn editor_position_to_lsp(line: u32, column: u32) -> Position {
-        Position {
-            line,
-            character: column,
-        }
-    }
-
-    /// Convert LSP position to editor coords
-    pub fn lsp_position_to_editor(position: &Position) -> (u32, u32) {
-        (position.line, position.character)
-    }
-
-    /// Convert range from editor coords to LSP range
-    pub fn editor_range_to_lsp(
-        start_line: u32,
-        start_column: u32,
-        end_line: u32,
-        end_column: u32,
-    ) -> Range {
-        Range {
-            start: Position {
-                line: start_line,
-                character: start_column,
-            },
-            end: Position {
-                line: end_line,
-                character: end_column,
-            },
-        }
-    }
-
     /// Check if a position is within a range
     pub fn position_in_range(position: &Position, range: &Range) -> bool {
         if position.line < range.start.line || position.line > range.end.line {
@@ -456,48 +434,108 @@ pub mod utils {
     }
 
     /// Get text in range from document content
-    pub fn get_text_in_range(content: &str, range: &Range) -> Option<String> {
-        let lines: Vec<&str> = content.lines().collect();
-
-        if range.start.line as usize >= lines.len() || range.end.line as usize >= lines.len() {
+    pub fn get_text_in_range(
+        content: &str,
+        range: &Range,
+        encoding: PositionEncoding,
+    ) -> Option<String> {
+        let rope = Rope::from_str(content);
+        if range.start.line as usize >= rope.len_lines()
+            || range.end.line as usize >= rope.len_lines()
+        {
             return None;
         }
 
-        if range.start.line == range.end.line {
-            let line = lines[range.start.line as usize];
-            let start = range.start.character as usize;
-            let end = range.end.character as usize;
+        let start = super::position::lsp_position_to_char(&rope, &range.start, encoding);
+        let end = super::position::lsp_position_to_char(&rope, &range.end, encoding);
+        (start <= end).then(|| rope.slice(start..end).to_string())
+    }
 
-            if start <= line.len() && end <= line.len() && start <= end {
-                return Some(line[start..end].to_string());
-            }
-        } else {
-            let mut result = String::new();
-
-            for line_idx in range.start.line..=range.end.line {
-                let line = lines[line_idx as usize];
-
-                if line_idx == range.start.line {
-                    let start = range.start.character as usize;
-                    if start <= line.len() {
-                        result.push_str(&line[start..]);
-                        result.push('\n');
-                    }
-                } else if line_idx == range.end.line {
-                    let end = range.end.character as usize;
-                    if end <= line.len() {
-                        result.push_str(&line[..end]);
-                    }
-                } else {
-                    result.push_str(line);
-                    result.push('\n');
-                }
+    /// Edit made by accepting a completion item
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct CompletionEdit {
+        /// Range to replace, or `None` to insert at the cursor
+        pub range: Option<crate::core::Range>,
+        pub text: String,
+        /// Whether `text` is snippet syntax
+        pub is_snippet: bool,
+        /// Edits elsewhere in the document, such as imports the item needs
+        pub additional_edits: Vec<crate::core::RangeReplacement>,
+    }
+
+    /// Get the edit a completion item makes
+    ///
+    /// Uses the item's text edit when it has one, replacing only the insert
+    /// range of an insert/replace edit. Otherwise the insert text, or else the
+    /// label, is inserted at the cursor. The item's additional text edits come
+    /// along with it.
+    pub fn completion_edit(
+        rope: &Rope,
+        item: &CompletionItem,
+        encoding: PositionEncoding,
+    ) -> CompletionEdit {
+        let (range, text) = match &item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => (Some(edit.range), edit.new_text.clone()),
+            Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+                (Some(edit.insert), edit.new_text.clone())
             }
+            None => (
+                None,
+                item.insert_text.clone().unwrap_or_else(|| item.label.clone()),
+            ),
+        };
+
+        let additional_edits = item
+            .additional_text_edits
+            .iter()
+            .flatten()
+            .map(|edit| crate::core::RangeReplacement {
+                range: lsp_range_to_editor(rope, &edit.range, encoding),
+                text: edit.new_text.clone(),
+            })
+            .collect();
 
-            return Some(result);
+        CompletionEdit {
+            range: range.map(|range| lsp_range_to_editor(rope, &range, encoding)),
+            text,
+            is_snippet: item.insert_text_format == Some(InsertTextFormat::SNIPPET),
+            additional_edits,
         }
+    }
 
-        None 
+    /// Apply text edits from a server to a document, returning the new text
+    ///
+    /// Edit ranges all refer to the original document, as the protocol
+    /// requires. Insertions at the same position keep their order.
+    pub fn apply_text_edits(rope: &Rope, edits: &[TextEdit], encoding: PositionEncoding) -> String {
+        let mut offsets: Vec<(usize, usize, &str)> = edits
+            .iter()
+            .map(|edit| {
+                let start = super::position::lsp_position_to_char(rope, &edit.range.start, encoding);
+                let end = super::position::lsp_position_to_char(rope, &edit.range.end, encoding);
+                (start, end.max(start), edit.new_text.as_str())
+            })
+            .collect();
+        // Stable, so reversing puts later edits at the same start first
+        offsets.sort_by_key(|&(start, _, _)| start);
+
+        let mut rope = rope.clone();
+        for (start, end, text) in offsets.into_iter().rev() {
+            rope.remove(start..end);
+            rope.insert(start, text);
+        }
+        rope.to_string()
+    }
+
+    /// Protocol formatting options matching the editor's
+    pub fn formatting_options(options: &crate::core::traits::FormattingOptions) -> FormattingOptions {
+        FormattingOptions {
+            tab_size: options.tab_size as u32,
+            insert_spaces: options.insert_spaces,
+            trim_trailing_whitespace: Some(options.trim_trailing_whitespace),
+            insert_final_newline: Some(options.insert_final_newline),
+            ..FormattingOptions::default()
+        }
     }
 }
 
@@ -505,25 +543,40 @@ pub mod utils {
 mod tests {
     use super::*;
     use super::utils::*;
+    use ropey::Rope;
 
     #[test]
     fn test_position_conversion() {
-        let pos = editor_position_to_lsp(10, 5);
-        assert_eq!(pos.line, 10);
-        assert_eq!(pos.character, 5);
-        
-        let (line, col) = lsp_position_to_editor(&pos);
-        assert_eq!(line, 10);
-        assert_eq!(col, 5);
+        let rope = Rope::from_str("fn main() {\n    let é = \"🦀\";\n}\n");
+        let editor_pos = crate::core::Position::new(1, 15);
+
+        let pos = editor_position_to_lsp(&rope, editor_pos, PositionEncoding::Utf16).unwrap();
+        assert_eq!(pos.line, 1);
+        assert_eq!(pos.character, 16);
+
+        assert_eq!(
+            lsp_position_to_editor(&rope, &pos, PositionEncoding::Utf16),
+            editor_pos
+        );
     }
 
     #[test]
     fn test_range_conversion() {
-        let range = editor_range_to_lsp(1, 2, 3, 4);
+        let rope = Rope::from_str("a\n中文 text\n");
+        let editor_range = crate::core::Range::new(
+            crate::core::Position::new(1, 1),
+            crate::core::Position::new(1, 4),
+        );
+
+        let range = editor_range_to_lsp(&rope, &editor_range, PositionEncoding::Utf8).unwrap();
         assert_eq!(range.start.line, 1);
-        assert_eq!(range.start.character, 2);
-        assert_eq!(range.end.line, 3);
-        assert_eq!(range.end.character, 4);
+        assert_eq!(range.start.character, 3);
+        assert_eq!(range.end.line, 1);
+        assert_eq!(range.end.character, 8);
+        assert_eq!(
+            lsp_range_to_editor(&rope, &range, PositionEncoding::Utf8),
+            editor_range
+        );
     }
 
     #[test]
@@ -563,14 +616,106 @@ mod tests {
             start: Position { line: 1, character: 2 },
             end: Position { line: 1, character: 6 },
         };
-        assert_eq!(get_text_in_range(content, &range1), Some("ne 1".to_string()));
+        assert_eq!(
+            get_text_in_range(content, &range1, PositionEncoding::Utf16),
+            Some("ne 1".to_string())
+        );
         
         // Multi-line range
         let range2 = Range {
             start: Position { line: 1, character: 2 },
             end: Position { line: 2, character: 4 },
         };
-        assert_eq!(get_text_in_range(content, &range2), Some("ne 1\nline".to_string()));
+        assert_eq!(
+            get_text_in_range(content, &range2, PositionEncoding::Utf16),
+            Some("ne 1\nline".to_string())
+        );
+
+        // Columns after a surrogate pair
+        let range3 = Range {
+            start: Position { line: 0, character: 3 },
+            end: Position { line: 0, character: 6 },
+        };
+        assert_eq!(
+            get_text_in_range("a🦀bcd", &range3, PositionEncoding::Utf16),
+            Some("bcd".to_string())
+        );
+    }
+
+    #[test]
+    fn test_completion_edit() {
+        let rope = Rope::from_str("let x = vec.pu");
+        let item = CompletionItem {
+            label: "push".to_string(),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            text_edit: Some(CompletionTextEdit::InsertAndReplace(InsertReplaceEdit {
+                new_text: "push(${1:value})".to_string(),
+                insert: Range::new(Position::new(0, 12), Position::new(0, 14)),
+                replace: Range::new(Position::new(0, 12), Position::new(0, 14)),
+            })),
+            ..CompletionItem::default()
+        };
+
+        let edit = completion_edit(&rope, &item, PositionEncoding::Utf16);
+        assert_eq!(
+            edit.range,
+            Some(crate::core::Range::new(
+                crate::core::Position::new(0, 12),
+                crate::core::Position::new(0, 14),
+            ))
+        );
+        assert_eq!(edit.text, "push(${1:value})");
+        assert!(edit.is_snippet);
+        assert!(edit.additional_edits.is_empty());
+
+        let import = CompletionItem {
+            label: "HashMap".to_string(),
+            additional_text_edits: Some(vec![TextEdit {
+                range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                new_text: "use std::collections::HashMap;\n".to_string(),
+            }]),
+            ..CompletionItem::default()
+        };
+        let edit = completion_edit(&rope, &import, PositionEncoding::Utf16);
+        assert_eq!(
+            edit.additional_edits,
+            [crate::core::RangeReplacement {
+                range: crate::core::Range::new(
+                    crate::core::Position::new(0, 0),
+                    crate::core::Position::new(0, 0),
+                ),
+                text: "use std::collections::HashMap;\n".to_string(),
+            }]
+        );
+
+        let plain = CompletionItem::new_simple("len".to_string(), String::new());
+        let edit = completion_edit(&rope, &plain, PositionEncoding::Utf16);
+        assert_eq!(edit.range, None);
+        assert_eq!(edit.text, "len");
+        assert!(!edit.is_snippet);
+    }
+
+    #[test]
+    fn test_apply_text_edits() {
+        let rope = Rope::from_str("fn main(){\nlet é=\"🦀\";}\n");
+        let edit = |start: (u32, u32), end: (u32, u32), text: &str| TextEdit {
+            range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            new_text: text.to_string(),
+        };
+
+        // Edits arrive in any order and all refer to the original text
+        let edits = [
+            edit((1, 11), (1, 11), "\n"),
+            edit((0, 9), (0, 9), " "),
+            edit((1, 0), (1, 0), "    "),
+            edit((1, 5), (1, 6), " = "),
+            edit((1, 11), (1, 11), "    "),
+            edit((0, 10), (0, 10), ""),
+        ];
+        assert_eq!(
+            apply_text_edits(&rope, &edits, PositionEncoding::Utf16),
+            "fn main() {\n    let é = \"🦀\";\n    }\n"
+        );
     }
 
     #[test]
@@ -0,0 +1,279 @@
+//! Conversions between editor positions and LSP positions
+//!
+//! Editor columns count chars, while LSP columns count code units of the
+//! encoding negotiated with the server, which is UTF-16 unless agreed otherwise.
+
+use crate::core::{self, snapshot};
+use crate::lsp::{LspError, LspResult};
+use ropey::{Rope, RopeSlice};
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range, ServerCapabilities};
+
+/// Unit LSP columns are counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Encodings offered to servers, most preferred first
+    pub const SUPPORTED: [PositionEncoding; 3] = [Self::Utf32, Self::Utf8, Self::Utf16];
+
+    pub fn from_kind(kind: &PositionEncodingKind) -> Option<Self> {
+        match kind.as_str() {
+            "utf-8" => Some(Self::Utf8),
+            "utf-16" => Some(Self::Utf16),
+            "utf-32" => Some(Self::Utf32),
+            _ => None,
+        }
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+            Self::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// Encoding the server picked in its initialize response
+    ///
+    /// Servers that don't pick one use UTF-16, as the protocol requires.
+    pub fn negotiated(capabilities: &ServerCapabilities) -> Self {
+        capabilities
+            .position_encoding
+            .as_ref()
+            .and_then(Self::from_kind)
+            .unwrap_or_default()
+    }
+
+    fn len_of(self, text: RopeSlice<'_>) -> usize {
+        match self {
+            Self::Utf8 => text.len_bytes(),
+            Self::Utf16 => text.len_utf16_cu(),
+            Self::Utf32 => text.len_chars(),
+        }
+    }
+}
+
+/// Convert a char index in the rope to an LSP position
+pub fn char_to_lsp_position(
+    rope: &Rope,
+    char_index: usize,
+    encoding: PositionEncoding,
+) -> LspResult<Position> {
+    if char_index > rope.len_chars() {
+        return Err(LspError::InvalidPosition {
+            message: format!("character index {} is out of bounds", char_index),
+        });
+    }
+
+    let line = rope.char_to_line(char_index);
+    let line_start = rope.line_to_char(line);
+    let character = encoding.len_of(rope.slice(line_start..char_index));
+
+    Ok(Position::new(line as u32, character as u32))
+}
+
+/// Convert an LSP position to a char index in the rope
+///
+/// As the protocol asks, columns past the end of a line resolve to the end of
+/// that line. Lines past the end resolve to the end of the document, and a
+/// column inside a multi-unit character resolves to the start of the character.
+pub fn lsp_position_to_char(rope: &Rope, position: &Position, encoding: PositionEncoding) -> usize {
+    let line = position.line as usize;
+    if line >= rope.len_lines() {
+        return rope.len_chars();
+    }
+
+    let line_start = rope.line_to_char(line);
+    let line_len = snapshot::line_len(rope, line).unwrap_or(0);
+    let text = rope.line(line).slice(..line_len);
+    let character = position.character as usize;
+
+    let column = match encoding {
+        PositionEncoding::Utf8 => text.byte_to_char(character.min(text.len_bytes())),
+        PositionEncoding::Utf16 => text.utf16_cu_to_char(character.min(text.len_utf16_cu())),
+        PositionEncoding::Utf32 => character.min(line_len),
+    };
+
+    line_start + column
+}
+
+/// Convert an editor position to an LSP position
+pub fn editor_position_to_lsp(
+    rope: &Rope,
+    position: core::Position,
+    encoding: PositionEncoding,
+) -> LspResult<Position> {
+    let char_index = snapshot::position_to_char_index(rope, position).map_err(|e| {
+        LspError::InvalidPosition {
+            message: e.to_string(),
+        }
+    })?;
+    char_to_lsp_position(rope, char_index, encoding)
+}
+
+/// Convert an LSP position to an editor position
+pub fn lsp_position_to_editor(
+    rope: &Rope,
+    position: &Position,
+    encoding: PositionEncoding,
+) -> core::Position {
+    let char_index = lsp_position_to_char(rope, position, encoding);
+    let line = rope.char_to_line(char_index);
+    core::Position::new(line, char_index - rope.line_to_char(line))
+}
+
+/// Convert an editor range to an LSP range
+pub fn editor_range_to_lsp(
+    rope: &Rope,
+    range: &core::Range,
+    encoding: PositionEncoding,
+) -> LspResult<Range> {
+    Ok(Range::new(
+        editor_position_to_lsp(rope, range.start, encoding)?,
+        editor_position_to_lsp(rope, range.end, encoding)?,
+    ))
+}
+
+/// Convert an LSP range to an editor range
+pub fn lsp_range_to_editor(rope: &Rope, range: &Range, encoding: PositionEncoding) -> core::Range {
+    core::Range::new(
+        lsp_position_to_editor(rope, &range.start, encoding),
+        lsp_position_to_editor(rope, &range.end, encoding),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const ENCODINGS: [PositionEncoding; 3] = [
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+        PositionEncoding::Utf32,
+    ];
+
+    fn units(text: &str, encoding: PositionEncoding) -> usize {
+        match encoding {
+            PositionEncoding::Utf8 => text.len(),
+            PositionEncoding::Utf16 => text.encode_utf16().count(),
+            PositionEncoding::Utf32 => text.chars().count(),
+        }
+    }
+
+    fn mixed_text() -> impl Strategy<Value = String> {
+        prop::collection::vec(
+            prop::sample::select(vec![
+                "a", "Z", " ", "é", "ß", "中", "文", "😀", "👍🏽", "e\u{301}", "\n", "\r\n", "\t",
+            ]),
+            0..64,
+        )
+        .prop_map(|parts| parts.concat())
+    }
+
+    #[test]
+    fn test_emoji_columns() {
+        let rope = Rope::from_str("let s = \"😀\"; x\n");
+        let x = core::Position::new(0, 13);
+
+        let utf16 = editor_position_to_lsp(&rope, x, PositionEncoding::Utf16).unwrap();
+        assert_eq!(utf16, Position::new(0, 14));
+        let utf8 = editor_position_to_lsp(&rope, x, PositionEncoding::Utf8).unwrap();
+        assert_eq!(utf8, Position::new(0, 16));
+
+        assert_eq!(
+            lsp_position_to_editor(&rope, &utf16, PositionEncoding::Utf16),
+            x
+        );
+        // Halfway through the surrogate pair resolves to the emoji itself
+        assert_eq!(
+            lsp_position_to_editor(&rope, &Position::new(0, 10), PositionEncoding::Utf16),
+            core::Position::new(0, 9)
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_positions_are_clamped() {
+        let rope = Rope::from_str("中文\r\nab");
+
+        assert_eq!(
+            lsp_position_to_editor(&rope, &Position::new(0, 40), PositionEncoding::Utf16),
+            core::Position::new(0, 2)
+        );
+        assert_eq!(
+            lsp_position_to_editor(&rope, &Position::new(9, 0), PositionEncoding::Utf16),
+            core::Position::new(1, 2)
+        );
+        assert!(
+            editor_position_to_lsp(&rope, core::Position::new(0, 3), PositionEncoding::Utf16)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_negotiated_encoding() {
+        let mut capabilities = ServerCapabilities::default();
+        assert_eq!(
+            PositionEncoding::negotiated(&capabilities),
+            PositionEncoding::Utf16
+        );
+
+        capabilities.position_encoding = Some(PositionEncodingKind::UTF8);
+        assert_eq!(
+            PositionEncoding::negotiated(&capabilities),
+            PositionEncoding::Utf8
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_columns_count_code_units(text in mixed_text()) {
+            let rope = Rope::from_str(&text);
+            for char_index in 0..=rope.len_chars() {
+                let line = rope.char_to_line(char_index);
+                let prefix = rope.slice(rope.line_to_char(line)..char_index).to_string();
+
+                for encoding in ENCODINGS {
+                    let position = char_to_lsp_position(&rope, char_index, encoding).unwrap();
+                    prop_assert_eq!(position.line as usize, line);
+                    prop_assert_eq!(position.character as usize, units(&prefix, encoding));
+                }
+            }
+        }
+
+        #[test]
+        fn prop_round_trips_editor_positions(text in mixed_text()) {
+            let rope = Rope::from_str(&text);
+            for line in 0..rope.len_lines() {
+                let line_len = snapshot::line_len(&rope, line).unwrap();
+                for column in 0..=line_len {
+                    let position = core::Position::new(line, column);
+                    for encoding in ENCODINGS {
+                        let lsp = editor_position_to_lsp(&rope, position, encoding).unwrap();
+                        prop_assert_eq!(lsp_position_to_editor(&rope, &lsp, encoding), position);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn prop_arbitrary_lsp_positions_land_in_bounds(
+            text in mixed_text(),
+            line in 0u32..8,
+            character in 0u32..80,
+        ) {
+            let rope = Rope::from_str(&text);
+            for encoding in ENCODINGS {
+                let position = lsp_position_to_editor(&rope, &Position::new(line, character), encoding);
+                let lsp = editor_position_to_lsp(&rope, position, encoding).unwrap();
+                prop_assert!(lsp.line < line || (lsp.line == line && lsp.character <= character));
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 // src-tauri/src/project/templates.rs
 //! Project templates for creating new Rust projects
 
+use crate::core::text_transform::{to_kebab_case, to_pascal_case, to_snake_case};
 use crate::project::{ProjectError, ProjectResult, TemplateConfig};
 use crate::utils::paths::PathUtils;
 use anyhow::{Context, Result};
@@ -274,9 +275,11 @@ impl TemplateEngine {
 
         // Built-in variables
         context.insert("project_name".to_string(), project_name.to_string());
-        context.insert("project_name_snake".to_string(), self.to_snake_case(project_name));
-        context.insert("project_name_kebab".to_string(), self.to_kebab_case(project_name));
-        context.insert("project_name_pascal".to_string(), self.to_pascal_case(project_name));
+        // Spaces would stay in the converted names, which have to be identifiers
+        let name_words = project_name.split_whitespace().collect::<Vec<_>>().join("-");
+        context.insert("project_name_snake".to_string(), to_snake_case(&name_words));
+        context.insert("project_name_kebab".to_string(), to_kebab_case(&name_words));
+        context.insert("project_name_pascal".to_string(), to_pascal_case(&name_words));
         
         // Add author from config
         if let Some(author) = &self.config.default_author {
@@ -474,40 +477,6 @@ Cargo.lock
         Ok(())
     }
 
-    /// Convert string to snake_case
-    fn to_snake_case(&self, s: &str) -> String {
-        s.chars()
-            .enumerate()
-            .map(|(i, c)| {
-                if c.is_uppercase() && i > 0 {
-                    format!("_{}", c.to_lowercase())
-                } else {
-                    c.to_lowercase().to_string()
-                }
-            })
-            .collect::<String>()
-            .replace('-', "_")
-            .replace(' ', "_")
-    }
-
-    /// Convert string to kebab-case
-    fn to_kebab_case(&self, s: &str) -> String {
-        self.to_snake_case(s).replace('_', "-")
-    }
-
-    /// Convert string to PascalCase
-    fn to_pascal_case(&self, s: &str) -> String {
-        s.split(&['-', '_', ' '][..])
-            .map(|word| {
-                let mut chars = word.chars();
-                match chars.next() {
-                    None => String::new(),
-                    Some(first) => first.to_uppercase().chain(chars.as_str().to_lowercase().chars()).collect(),
-                }
-            })
-            .collect()
-    }
-
     /// Get available templates
     pub fn available_templates(&self) -> Vec<(TemplateType, &ProjectTemplate)> {
         self.builtin_templates
@@ -1145,12 +1114,10 @@ mod tests {
 
     #[test]
     fn test_case_conversions() {
-        let engine = TemplateEngine::new(TemplateConfig::default());
-        
-        assert_eq!(engine.to_snake_case("MyProject"), "my_project");
-        assert_eq!(engine.to_kebab_case("MyProject"), "my-project");
-        assert_eq!(engine.to_pascal_case("my-project"), "MyProject");
-        assert_eq!(engine.to_pascal_case("my_project"), "MyProject");
+        assert_eq!(to_snake_case("MyProject"), "my_project");
+        assert_eq!(to_kebab_case("MyProject"), "my-project");
+        assert_eq!(to_pascal_case("my-project"), "MyProject");
+        assert_eq!(to_pascal_case("my_project"), "MyProject");
     }
 
     #[test]
@@ -1288,6 +1255,21 @@ mod tests {
         assert_eq!(context.get("license"), Some(&"MIT".to_string()));
     }
 
+    #[test]
+    fn test_template_context_with_spaced_name() {
+        let engine = TemplateEngine::new(TemplateConfig::default());
+        let template = engine.create_binary_template();
+
+        let context = engine
+            .build_template_context("my project", &template, HashMap::new())
+            .unwrap();
+
+        assert_eq!(context.get("project_name"), Some(&"my project".to_string()));
+        assert_eq!(context.get("project_name_snake"), Some(&"my_project".to_string()));
+        assert_eq!(context.get("project_name_kebab"), Some(&"my-project".to_string()));
+        assert_eq!(context.get("project_name_pascal"), Some(&"MyProject".to_string()));
+    }
+
     #[test]
     fn test_template_serialization() {
         let template = ProjectTemplate {
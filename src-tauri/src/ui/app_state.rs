@@ -1,13 +1,15 @@
-use crate::commands::project;
+use crate::commands::{project, EditorMap};
 use crate::core::Editor;
 use crate::project::{Project, ProjectManager};
 use crate::ui::{get_theme_by_name, Theme, UiError, UiEvent, UiPreferences, UiResult};
+use crate::utils::config::get_config_manager;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tauri::{AppHandle, Manager};
 use tokio::sync::{mpsc, RwLock};
+use tracing::warn;
 use uuid::Uuid;
 
 /// Global application state manager
@@ -19,8 +21,11 @@ pub struct AppState {
     /// Project manager for handling multiple projects
     pub project_manager: Arc<ProjectManager>,
 
-    /// Open editor instances keyed by file path
-    pub editors: HashMap<PathBuf, Arc<RwLock<Editor>>>,
+    /// Open editors and their views, shared with the editor commands
+    pub editors: EditorMap,
+
+    /// Editor of each open file, one per file however many views show it
+    pub documents: HashMap<PathBuf, Uuid>,
 
     /// Currently active editor (focused tab)
     pub active_editor: Option<PathBuf>,
@@ -111,6 +116,7 @@ impl AppState {
     /// Create a new application state
     pub fn new(
         project_manager: Arc<ProjectManager>,
+        editors: EditorMap,
     ) -> UiResult<(Self, mpsc::UnboundedReceiver<UiEvent>)> {
         let (event_sender, event_receiver) = mpsc::unbounded_channel();
 
@@ -120,7 +126,8 @@ impl AppState {
         let state = Self {
             project: None,
             project_manager,
-            editors: HashMap::new(),
+            editors,
+            documents: HashMap::new(),
             active_editor: None,
             preferences,
             current_theme,
@@ -196,12 +203,13 @@ impl AppState {
 
     /// Open a file in the editor
     pub async fn open_file(&mut self, file_path: PathBuf) -> UiResult<()> {
-        if !self.editors.contains_key(&file_path) {
+        if !self.documents.contains_key(&file_path) {
             let editor = Editor::new();
 
             // TODO: Integrate
-            let editor = Arc::new(RwLock::new(editor));
-            self.editors.insert(file_path.clone(), editor);
+            let editor_id = editor.id();
+            self.editors.write().await.insert(editor_id, editor);
+            self.documents.insert(file_path.clone(), editor_id);
         }
 
         self.active_editor = Some(file_path.clone());
@@ -215,10 +223,12 @@ impl AppState {
 
     /// Close a file editor
     pub async fn close_file(&mut self, file_path: &PathBuf) -> UiResult<()> {
-        self.editors.remove(file_path);
+        if let Some(editor_id) = self.documents.remove(file_path) {
+            self.editors.write().await.remove(&editor_id);
+        }
 
         if self.active_editor.as_ref() == Some(file_path) {
-            self.active_editor = self.editors.keys().next().cloned();
+            self.active_editor = self.documents.keys().next().cloned();
         }
 
         self.emit_state_updated()?;
@@ -228,23 +238,26 @@ impl AppState {
 
     /// Close all editors
     pub async fn close_all_editors(&mut self) -> UiResult<()> {
-        self.editors.clear();
+        let mut editors = self.editors.write().await;
+        for (_, editor_id) in self.documents.drain() {
+            editors.remove(&editor_id);
+        }
+        drop(editors);
         self.active_editor = None;
         self.emit_state_updated()?;
         Ok(())
     }
 
-    /// Get the active editor
-    pub fn get_active_editor(&self) -> Option<Arc<RwLock<Editor>>> {
+    /// Get the id of the active editor, for looking it up in [`AppState::editors`]
+    pub fn get_active_editor(&self) -> Option<Uuid> {
         self.active_editor
             .as_ref()
-            .and_then(|path| self.editors.get(path))
-            .cloned()
+            .and_then(|path| self.get_editor(path))
     }
 
-    /// Get the editor for a specific file
-    pub fn get_editor(&self, file_path: &PathBuf) -> Option<Arc<RwLock<Editor>>> {
-        self.editors.get(file_path).cloned()
+    /// Get the id of the editor for a specific file
+    pub fn get_editor(&self, file_path: &PathBuf) -> Option<Uuid> {
+        self.documents.get(file_path).copied()
     }
 
     /// Update UI preferences
@@ -256,6 +269,12 @@ impl AppState {
             }
         }
 
+        if preferences.auto_save != self.preferences.auto_save
+            || preferences.auto_save_delay != self.preferences.auto_save_delay
+        {
+            sync_auto_save(&preferences).await;
+        }
+
         self.preferences = preferences;
         self.emit_state_updated()?;
 
@@ -354,7 +373,7 @@ impl AppState {
     pub fn create_snapshot(&self) -> AppStateSnapshot {
         AppStateSnapshot {
             project_path: self.project.as_ref().map(|p| p.root_path().clone()),
-            open_files: self.editors.keys().cloned().collect(),
+            open_files: self.documents.keys().cloned().collect(),
             active_file: self.active_editor.clone(),
             preferences: self.preferences.clone(),
             theme: self.current_theme.name.clone(),
@@ -390,7 +409,7 @@ impl AppState {
         }
 
         if let Some(active_file) = snapshot.active_file {
-            if self.editors.contains_key(&active_file) {
+            if self.documents.contains_key(&active_file) {
                 self.active_editor = Some(active_file);
             }
         }
@@ -425,14 +444,33 @@ impl AppState {
     }
 }
 
+/// Carry the auto-save preferences over to the editor settings auto-save follows
+async fn sync_auto_save(preferences: &UiPreferences) {
+    let Ok(manager) = get_config_manager() else {
+        return;
+    };
+
+    let (enabled, delay) = (preferences.auto_save, preferences.auto_save_delay);
+    let result = manager
+        .update_editor_settings(|settings| {
+            settings.auto_save = enabled;
+            settings.auto_save_delay = delay;
+        })
+        .await;
+    if let Err(e) = result {
+        warn!("Failed to save auto-save preferences: {}", e);
+    }
+}
+
 /// Application state manager that can be shared across Tauri commands
 pub type SharedAppState = Arc<RwLock<AppState>>;
 
-/// Initialize the app state
+/// Initialize the app state, sharing the editors the editor commands use
 pub async fn initialize_app_state(
     project_manager: Arc<ProjectManager>,
+    editors: EditorMap,
 ) -> UiResult<(SharedAppState, mpsc::UnboundedReceiver<UiEvent>)> {
-    let (state, event_receiver) = AppState::new(project_manager)?;
+    let (state, event_receiver) = AppState::new(project_manager, editors)?;
     let shared_state = Arc::new(RwLock::new(state));
     Ok((shared_state, event_receiver))
 }
@@ -460,7 +498,7 @@ mod tests {
     async fn create_test_state() -> (AppState, mpsc::UnboundedReceiver<UiEvent>) {
         let temp_dir = tempdir().unwrap();
         let project_manager = Arc::new(ProjectManager::new(temp_dir.path().to_path_buf()));
-        AppState::new(project_manager).unwrap()
+        AppState::new(project_manager, EditorMap::default()).unwrap()
     }
 
     #[tokio::test]
@@ -468,7 +506,7 @@ mod tests {
         let (state, _) = create_test_state().await;
 
         assert!(state.project.is_none());
-        assert!(state.editors.is_empty());
+        assert!(state.documents.is_empty());
         assert!(state.active_editor.is_none());
         assert_eq!(state.preferences.theme, "dark");
         assert!(!state.is_loading);
@@ -481,13 +519,15 @@ mod tests {
 
         // Test opening file
         state.open_file(file_path.clone()).await.unwrap();
-        assert!(state.editors.contains_key(&file_path));
+        assert!(state.documents.contains_key(&file_path));
+        assert_eq!(state.editors.read().await.len(), 1);
         assert_eq!(state.active_editor, Some(file_path.clone()));
         assert_eq!(state.recent_files[0], file_path);
 
         // Test closing file
         state.close_file(&file_path).await.unwrap();
-        assert!(!state.editors.contains_key(&file_path));
+        assert!(!state.documents.contains_key(&file_path));
+        assert!(state.editors.read().await.is_empty());
         assert!(state.active_editor.is_none());
     }
 
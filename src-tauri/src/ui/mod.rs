@@ -296,7 +296,7 @@ impl Default for UiPreferences {
             show_line_numbers: true,
             show_minimap: true,
             word_wrap: false,
-            auto_save: true,
+            auto_save: false,
             auto_save_delay: 1000,
             panel_layout: None,
         }
@@ -390,5 +390,6 @@ mod tests {
         assert_eq!(prefs.theme, "dark");
         assert_eq!(prefs.font_size, 14);
         assert!(prefs.show_line_numbers);
+        assert!(!prefs.auto_save);
     }
 }
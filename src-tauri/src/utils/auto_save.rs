@@ -0,0 +1,96 @@
+use crate::utils::{DebounceConfig, Debouncer};
+use parking_lot::Mutex;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+use uuid::Uuid;
+
+/// Delays saves of edited files until typing pauses
+///
+/// The delay comes from the settings on every change, so the debouncer is
+/// replaced when it changes. Saves already waiting keep their old delay.
+pub struct AutoSaver {
+    debouncer: Mutex<(Duration, Arc<Debouncer>)>,
+}
+
+impl AutoSaver {
+    pub fn new() -> Self {
+        Self {
+            debouncer: Mutex::new((Duration::ZERO, Arc::new(Debouncer::new(Duration::ZERO)))),
+        }
+    }
+
+    /// Run `save` once an editor has gone `delay` without changes
+    pub async fn schedule<F, Fut>(&self, editor_id: Uuid, delay: Duration, save: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let debouncer = {
+            let mut current = self.debouncer.lock();
+            if current.0 != delay {
+                debug!("Auto-save delay is now {:?}", delay);
+                *current = (
+                    delay,
+                    Arc::new(Debouncer::with_config(DebounceConfig {
+                        delay,
+                        ..DebounceConfig::default()
+                    })),
+                );
+            }
+            current.1.clone()
+        };
+
+        debouncer.debounce(editor_id.to_string(), save).await;
+    }
+
+    /// Drop the waiting save of an editor, if any
+    pub async fn cancel(&self, editor_id: Uuid) {
+        let debouncer = self.debouncer.lock().1.clone();
+        debouncer.cancel(&editor_id.to_string()).await;
+    }
+}
+
+impl Default for AutoSaver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn test_saves_once_changes_settle() {
+        let saver = AutoSaver::new();
+        let editor_id = Uuid::new_v4();
+        let saves = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..5 {
+            let saves = saves.clone();
+            saver
+                .schedule(editor_id, Duration::from_millis(50), move || async move {
+                    saves.fetch_add(1, Ordering::SeqCst);
+                })
+                .await;
+            sleep(Duration::from_millis(10)).await;
+        }
+        sleep(Duration::from_millis(150)).await;
+        assert_eq!(saves.load(Ordering::SeqCst), 1);
+
+        // Cancelled saves never run
+        let cancelled = saves.clone();
+        saver
+            .schedule(editor_id, Duration::from_millis(50), move || async move {
+                cancelled.fetch_add(1, Ordering::SeqCst);
+            })
+            .await;
+        saver.cancel(editor_id).await;
+        sleep(Duration::from_millis(150)).await;
+        assert_eq!(saves.load(Ordering::SeqCst), 1);
+    }
+}
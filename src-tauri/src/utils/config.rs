@@ -1,5 +1,6 @@
 use crate::utils::{get_app_config_dir, UtilError, UtilResult};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -39,6 +40,12 @@ pub struct EditorSettings {
     pub use_tabs: bool,
     pub auto_save: bool,
     pub auto_save_delay: u64, // milliseconds
+    /// When files are saved while `auto_save` is on
+    #[serde(default)]
+    pub auto_save_mode: AutoSaveMode,
+    /// Formatting settings by language id
+    #[serde(default)]
+    pub formatting: HashMap<String, FormatSettings>,
     pub word_wrap: bool,
     pub show_line_numbers: bool,
     pub show_minimap: bool,
@@ -52,8 +59,10 @@ impl Default for EditorSettings {
         Self {
             tab_size: 4,
             use_tabs: false,
-            auto_save: true,
+            auto_save: false,
             auto_save_delay: 2000,
+            auto_save_mode: AutoSaveMode::default(),
+            formatting: HashMap::new(),
             word_wrap: false,
             show_line_numbers: true,
             show_minimap: true,
@@ -64,6 +73,53 @@ impl Default for EditorSettings {
     }
 }
 
+/// When open files are saved automatically
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoSaveMode {
+    /// Once typing pauses for `auto_save_delay`
+    #[default]
+    AfterDelay,
+    /// When an editor loses focus
+    OnFocusChange,
+    /// When the application window loses focus
+    OnWindowChange,
+}
+
+impl EditorSettings {
+    /// Formatting settings for a language, or the defaults if it has none
+    pub fn format_settings(&self, language: Option<&str>) -> FormatSettings {
+        language
+            .and_then(|language| self.formatting.get(language))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// How and when buffers of a language are formatted
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormatSettings {
+    pub formatter: FormatterKind,
+    /// Format the whole file when it is saved by hand
+    pub on_save: bool,
+    /// Format the lines of pasted text
+    pub on_paste: bool,
+}
+
+/// Which tool formats a language
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatterKind {
+    /// rustfmt for Rust, the language server for anything else
+    #[default]
+    Auto,
+    Rustfmt,
+    LanguageServer,
+    /// Never format
+    Disabled,
+}
+
 /// UI-specific settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UiSettings {
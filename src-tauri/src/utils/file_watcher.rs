@@ -1,4 +1,5 @@
 use crate::utils::{debounce, UtilError, UtilResult};
+use notify::event::ModifyKind;
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -124,7 +125,7 @@ impl FileWatcher {
 
         if !path.exists() {
             return Err(UtilError::Watch(WatchError::PathNotFound {
-                path: path.display(),
+                path: path.display().to_string(),
             }));
         }
 
@@ -136,14 +137,15 @@ impl FileWatcher {
 
         let debounce_watcher_id = watcher_id;
         tokio::spawn(async move {
-            let mut debouncer = crate::utils::debounce::Debouncer::new(debounce_delay);
+            let debouncer = crate::utils::debounce::Debouncer::new(debounce_delay);
 
             while let Some(event) = rx.recv().await {
                 let event_key = format!("{:?}", event);
+                let event_sender = event_sender.clone();
 
                 debouncer
-                    .debounce(event_key, move || {
-                        let _ = event_sender.send((debounce_watcher_id, event.clone()));
+                    .debounce(event_key, move || async move {
+                        let _ = event_sender.send((debounce_watcher_id, event));
                     })
                     .await;
             }
@@ -267,6 +269,20 @@ fn convert_notify_event(event: Event, ignore_patterns: &[String]) -> Option<File
         return None;
     }
 
+    // Renames report both paths; what matters is the file that now exists
+    if let (EventKind::Modify(ModifyKind::Name(_)), [old_path, new_path]) =
+        (&event.kind, event.paths.as_slice())
+    {
+        if should_ignore_path(new_path, ignore_patterns) {
+            return None;
+        }
+
+        return Some(FileEvent::Renamed {
+            old_path: old_path.clone(),
+            new_path: new_path.clone(),
+        });
+    }
+
     let path = &event.paths[0];
 
     if should_ignore_path(path, ignore_patterns) {
@@ -337,6 +353,11 @@ pub fn get_file_watcher() -> &'static FileWatcher {
     FILE_WATCHER.get().expect("File watcher not initialized")
 }
 
+/// Get global file watcher if it has been initialized
+pub fn try_get_file_watcher() -> Option<&'static FileWatcher> {
+    FILE_WATCHER.get()
+}
+
 /// Shutdown all watchers
 pub async fn shutdown_watchers() -> UtilResult<()> {
     if let Some(watcher) = FILE_WATCHER.get() {
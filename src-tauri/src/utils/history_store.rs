@@ -0,0 +1,344 @@
+use crate::core::{TextBuffer, UndoTree};
+use crate::utils::{get_app_data_dir, UtilError, UtilResult};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, instrument};
+
+/// Number of saved versions kept per file
+const MAX_SAVES_PER_FILE: usize = 200;
+
+/// A saved version of a file, without its content
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedVersion {
+    pub id: i64,
+    pub path: PathBuf,
+    /// Unix timestamp in milliseconds
+    pub saved_at: i64,
+    pub size: usize,
+}
+
+/// Local store for per-file undo history and the timeline of saves
+pub struct HistoryStore {
+    conn: Mutex<Connection>,
+}
+
+impl HistoryStore {
+    /// Open (or create) the history database at the given path
+    #[instrument]
+    pub fn open(db_path: &Path) -> UtilResult<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(db_path)?;
+        Self::with_connection(conn)
+    }
+
+    /// Open a temporary in-memory store
+    pub fn open_in_memory() -> UtilResult<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> UtilResult<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS undo_history (
+                 path TEXT PRIMARY KEY,
+                 content_hash TEXT NOT NULL,
+                 tree TEXT NOT NULL,
+                 updated_at INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS save_timeline (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 path TEXT NOT NULL,
+                 saved_at INTEGER NOT NULL,
+                 content TEXT NOT NULL,
+                 content_hash TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_save_timeline_path
+                 ON save_timeline (path, saved_at);",
+        )?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Store the undo history of a file, tied to the content written to it
+    pub fn save_undo_history(&self, path: &Path, text: &str, tree: &UndoTree) -> UtilResult<()> {
+        let tree_json = serde_json::to_string(tree)?;
+
+        self.conn.lock().execute(
+            "INSERT INTO undo_history (path, content_hash, tree, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (path) DO UPDATE SET
+                 content_hash = excluded.content_hash,
+                 tree = excluded.tree,
+                 updated_at = excluded.updated_at",
+            params![
+                path_key(path),
+                content_hash(text),
+                tree_json,
+                chrono::Utc::now().timestamp_millis()
+            ],
+        )?;
+
+        debug!("Stored undo history for {}", path.display());
+        Ok(())
+    }
+
+    /// Store the undo history of a buffer holding exactly what its file does
+    ///
+    /// The tree's edits refer to the buffer's text, so it is only stored when
+    /// that text is on disk as it is: with nothing unsaved and nothing that
+    /// saving cleans up, such as trailing whitespace. Returns whether it was
+    /// stored.
+    pub fn save_buffer_history(&self, path: &Path, buffer: &TextBuffer) -> UtilResult<bool> {
+        let text = buffer.text();
+        if buffer.is_dirty() || text != buffer.content_for_save() {
+            debug!(
+                "Not storing undo history for {}: not on disk",
+                path.display()
+            );
+            return Ok(false);
+        }
+
+        self.save_undo_history(path, &text, buffer.undo_tree())?;
+        Ok(true)
+    }
+
+    /// Load the undo history of a file
+    ///
+    /// Returns `None` when nothing is stored or the file changed since the
+    /// history was written, since its edits would no longer line up.
+    pub fn load_undo_history(&self, path: &Path, text: &str) -> UtilResult<Option<UndoTree>> {
+        let row: Option<(String, String)> = self
+            .conn
+            .lock()
+            .query_row(
+                "SELECT content_hash, tree FROM undo_history WHERE path = ?1",
+                params![path_key(path)],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        match row {
+            Some((hash, tree_json)) if hash == content_hash(text) => {
+                Ok(Some(serde_json::from_str(&tree_json)?))
+            }
+            Some(_) => {
+                debug!(
+                    "Discarding stale undo history for {}: file changed",
+                    path.display()
+                );
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Record a save of a file, returning the new version id
+    ///
+    /// Nothing is recorded if the content matches the latest saved version.
+    pub fn record_save(&self, path: &Path, content: &str) -> UtilResult<Option<i64>> {
+        let key = path_key(path);
+        let hash = content_hash(content);
+        let conn = self.conn.lock();
+
+        let latest_hash: Option<String> = conn
+            .query_row(
+                "SELECT content_hash FROM save_timeline
+                 WHERE path = ?1 ORDER BY saved_at DESC, id DESC LIMIT 1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        if latest_hash.as_deref() == Some(hash.as_str()) {
+            return Ok(None);
+        }
+
+        conn.execute(
+            "INSERT INTO save_timeline (path, saved_at, content, content_hash)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![key, chrono::Utc::now().timestamp_millis(), content, hash],
+        )?;
+        let id = conn.last_insert_rowid();
+
+        // Keep the timeline of each file bounded
+        conn.execute(
+            "DELETE FROM save_timeline WHERE path = ?1 AND id NOT IN (
+                 SELECT id FROM save_timeline WHERE path = ?1
+                 ORDER BY saved_at DESC, id DESC LIMIT ?2
+             )",
+            params![key, MAX_SAVES_PER_FILE as i64],
+        )?;
+
+        debug!("Recorded save {} of {}", id, path.display());
+        Ok(Some(id))
+    }
+
+    /// List the saved versions of a file, newest first
+    pub fn list_saves(&self, path: &Path) -> UtilResult<Vec<SavedVersion>> {
+        let conn = self.conn.lock();
+        let mut statement = conn.prepare(
+            "SELECT id, path, saved_at, length(CAST(content AS BLOB)) FROM save_timeline
+             WHERE path = ?1 ORDER BY saved_at DESC, id DESC",
+        )?;
+
+        let versions = statement
+            .query_map(params![path_key(path)], |row| {
+                Ok(SavedVersion {
+                    id: row.get(0)?,
+                    path: PathBuf::from(row.get::<_, String>(1)?),
+                    saved_at: row.get(2)?,
+                    size: row.get::<_, i64>(3)? as usize,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(versions)
+    }
+
+    /// Get the content of a saved version
+    pub fn saved_content(&self, id: i64) -> UtilResult<String> {
+        self.conn
+            .lock()
+            .query_row(
+                "SELECT content FROM save_timeline WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| UtilError::NotFound {
+                item: format!("saved version {}", id),
+            })
+    }
+}
+
+/// Key under which a file is stored
+fn path_key(path: &Path) -> String {
+    crate::utils::normalize_path(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Stable FNV-1a hash of file content
+fn content_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}:{}", hash, text.len())
+}
+
+// Global history store instance
+static HISTORY_STORE: once_cell::sync::OnceCell<HistoryStore> = once_cell::sync::OnceCell::new();
+
+/// Initialize the global history store in the app data directory
+pub fn init_history_store(app_handle: &tauri::AppHandle) -> UtilResult<()> {
+    let db_path = get_app_data_dir(app_handle)?.join("history.db");
+    let store = HistoryStore::open(&db_path)?;
+
+    HISTORY_STORE.set(store).map_err(|_| UtilError::Config {
+        message: "History store already initialized".to_string(),
+    })?;
+
+    info!("History store opened at: {}", db_path.display());
+    Ok(())
+}
+
+/// Get the global history store
+pub fn get_history_store() -> UtilResult<&'static HistoryStore> {
+    HISTORY_STORE.get().ok_or_else(|| UtilError::Config {
+        message: "History store not initialized".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Position, Range, TextEdit};
+
+    #[test]
+    fn test_undo_history_round_trip() {
+        let store = HistoryStore::open_in_memory().unwrap();
+        let path = Path::new("/project/src/main.rs");
+
+        let mut buffer = TextBuffer::from_content("fn main() {}", None).unwrap();
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 11), " ".to_string()))
+            .unwrap();
+        store
+            .save_undo_history(path, &buffer.text(), buffer.undo_tree())
+            .unwrap();
+
+        // A new session loads the same text and can undo into the old one
+        let mut reopened = TextBuffer::from_content(&buffer.text(), None).unwrap();
+        let tree = store
+            .load_undo_history(path, &reopened.text())
+            .unwrap()
+            .unwrap();
+        reopened.set_undo_tree(tree);
+        reopened.undo().unwrap();
+        assert_eq!(reopened.text(), "fn main() {}");
+
+        // History is dropped once the file no longer matches
+        assert!(store
+            .load_undo_history(path, "fn main() { changed }")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_buffer_history_only_stored_as_on_disk() {
+        let store = HistoryStore::open_in_memory().unwrap();
+        let path = Path::new("/project/src/main.rs");
+
+        let mut buffer = TextBuffer::from_content("a\n", None).unwrap();
+        assert!(buffer.config().trim_trailing_whitespace);
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 1), " ".to_string()))
+            .unwrap();
+        buffer.set_dirty(false);
+
+        // Saving writes "a\n", which undoing the space would cut short
+        assert!(!store.save_buffer_history(path, &buffer).unwrap());
+        assert!(store.load_undo_history(path, "a\n").unwrap().is_none());
+
+        buffer
+            .apply_edit(TextEdit::insert(Position::new(0, 1), "b".to_string()))
+            .unwrap();
+        assert!(!store.save_buffer_history(path, &buffer).unwrap());
+
+        buffer
+            .apply_edit(TextEdit::delete(Range::new(
+                Position::new(0, 2),
+                Position::new(0, 3),
+            )))
+            .unwrap();
+        buffer.set_dirty(false);
+        assert_eq!(buffer.text(), "ab\n");
+        assert!(store.save_buffer_history(path, &buffer).unwrap());
+        assert!(store.load_undo_history(path, "ab\n").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_save_timeline() {
+        let store = HistoryStore::open_in_memory().unwrap();
+        let path = Path::new("/project/src/lib.rs");
+
+        let first = store.record_save(path, "one").unwrap().unwrap();
+        assert!(store.record_save(path, "one").unwrap().is_none());
+        let second = store.record_save(path, "two").unwrap().unwrap();
+
+        let saves = store.list_saves(path).unwrap();
+        assert_eq!(
+            saves.iter().map(|save| save.id).collect::<Vec<_>>(),
+            vec![second, first]
+        );
+        assert_eq!(store.saved_content(first).unwrap(), "one");
+        assert!(store.saved_content(first + 100).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use crate::core::Macro;
+use crate::utils::{get_app_config_dir, UtilError, UtilResult};
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, instrument, warn};
+
+/// Named macros saved as JSON in the config directory
+pub struct MacroStore {
+    path: PathBuf,
+    macros: Mutex<BTreeMap<String, Macro>>,
+}
+
+impl MacroStore {
+    /// Open the store at the given path, loading any macros saved there
+    ///
+    /// A file that can't be parsed, such as one written by a newer version,
+    /// is moved aside to `macros.json.bak` and the store starts out empty, so
+    /// the app still starts and saving a macro doesn't overwrite the others.
+    #[instrument]
+    pub fn open(path: &Path) -> UtilResult<Self> {
+        let macros = match std::fs::read(path) {
+            Ok(json) => match serde_json::from_slice::<Vec<Macro>>(&json) {
+                Ok(macros) => macros
+                    .into_iter()
+                    .map(|recorded| (recorded.name.clone(), recorded))
+                    .collect(),
+                Err(e) => {
+                    let backup = path.with_extension("json.bak");
+                    warn!(
+                        "Moving unreadable macros in {} to {}: {}",
+                        path.display(),
+                        backup.display(),
+                        e
+                    );
+                    std::fs::rename(path, &backup)?;
+                    BTreeMap::new()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            macros: Mutex::new(macros),
+        })
+    }
+
+    /// Get a macro by name
+    pub fn get(&self, name: &str) -> Option<Macro> {
+        self.macros.lock().get(name).cloned()
+    }
+
+    /// All macros, sorted by name
+    pub fn list(&self) -> Vec<Macro> {
+        self.macros.lock().values().cloned().collect()
+    }
+
+    /// Save a macro, replacing any macro with the same name
+    pub fn save(&self, recorded: Macro) -> UtilResult<()> {
+        if recorded.name.trim().is_empty() {
+            return Err(UtilError::InvalidArgument {
+                argument: "Macro name cannot be empty".to_string(),
+            });
+        }
+
+        let mut macros = self.macros.lock();
+        debug!("Saving macro {}", recorded.name);
+        macros.insert(recorded.name.clone(), recorded);
+        self.write(&macros)
+    }
+
+    /// Delete a macro, returning whether it existed
+    pub fn remove(&self, name: &str) -> UtilResult<bool> {
+        let mut macros = self.macros.lock();
+        if macros.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        self.write(&macros)?;
+        Ok(true)
+    }
+
+    fn write(&self, macros: &BTreeMap<String, Macro>) -> UtilResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_vec_pretty(&macros.values().collect::<Vec<_>>())?;
+        let temp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&temp_path, json)?;
+        std::fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+// Global macro store instance
+static MACRO_STORE: once_cell::sync::OnceCell<MacroStore> = once_cell::sync::OnceCell::new();
+
+/// Initialize the global macro store in the app config directory
+pub fn init_macro_store(app_handle: &tauri::AppHandle) -> UtilResult<()> {
+    let path = get_app_config_dir(app_handle)?.join("macros.json");
+    let store = MacroStore::open(&path)?;
+
+    MACRO_STORE.set(store).map_err(|_| UtilError::Config {
+        message: "Macro store already initialized".to_string(),
+    })?;
+
+    info!("Macros stored in: {}", path.display());
+    Ok(())
+}
+
+/// Get the global macro store
+pub fn get_macro_store() -> UtilResult<&'static MacroStore> {
+    MACRO_STORE.get().ok_or_else(|| UtilError::Config {
+        message: "Macro store not initialized".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::EditorCommand;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_macros_persist_across_opens() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config").join("macros.json");
+
+        let store = MacroStore::open(&path).unwrap();
+        assert!(store.list().is_empty());
+
+        let recorded = Macro {
+            name: "semicolon".to_string(),
+            commands: vec![
+                EditorCommand::TypeChar { ch: ';' },
+                EditorCommand::Backspace,
+            ],
+        };
+        store.save(recorded.clone()).unwrap();
+        store.save(Macro::new("empty")).unwrap();
+        assert!(store.save(Macro::new(" ")).is_err());
+
+        let reopened = MacroStore::open(&path).unwrap();
+        assert_eq!(reopened.get("semicolon"), Some(recorded));
+        assert_eq!(
+            reopened
+                .list()
+                .into_iter()
+                .map(|recorded| recorded.name)
+                .collect::<Vec<_>>(),
+            ["empty", "semicolon"]
+        );
+
+        assert!(reopened.remove("empty").unwrap());
+        assert!(!reopened.remove("empty").unwrap());
+        assert_eq!(MacroStore::open(&path).unwrap().list().len(), 1);
+    }
+
+    #[test]
+    fn test_unreadable_file_is_moved_aside() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("macros.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        let store = MacroStore::open(&path).unwrap();
+        assert!(store.list().is_empty());
+        store.save(Macro::new("fresh")).unwrap();
+        assert!(MacroStore::open(&path).unwrap().get("fresh").is_some());
+
+        // The damaged file is kept as it was for the user to repair
+        let backup = temp_dir.path().join("macros.json.bak");
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), "not json");
+    }
+}
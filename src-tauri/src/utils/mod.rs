@@ -12,20 +12,30 @@ use std::path::{Path, PathBuf};
 use tracing::{error, warn};
 
 pub mod async_utils;
+pub mod auto_save;
 pub mod config;
 pub mod debounce;
 pub mod file_watcher;
+pub mod history_store;
 pub mod logging;
+pub mod macro_store;
 pub mod paths;
 pub mod processes;
+pub mod snippet_store;
+pub mod swap;
 
 // Re-export commonly used utilities
 pub use async_utils::{retry_async, timeout_future, CancellationToken};
-pub use config::{AppConfig, ConfigManager};
+pub use auto_save::AutoSaver;
+pub use config::{AppConfig, AutoSaveMode, ConfigManager, FormatSettings, FormatterKind};
 pub use debounce::{DebounceConfig, Debouncer};
 pub use file_watcher::{FileEvent, FileWatcher, WatchError};
+pub use history_store::{HistoryStore, SavedVersion};
+pub use macro_store::MacroStore;
 pub use paths::{ensure_directory, get_relative_path, normalize_path, PathExt};
 pub use processes::{CommandOutput, ProcessError, ProcessManager};
+pub use snippet_store::{SnippetStore, UserSnippet};
+pub use swap::{SwapFile, SwapManager, SwapRecovery};
 
 /// Common result type for utility functions
 pub type UtilResult<T> = Result<T, UtilError>;
@@ -59,6 +69,12 @@ pub enum UtilError {
 
     #[error("Not found: {item}")]
     NotFound { item: String },
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
 }
 
 /// Initialize all utility subsystems
@@ -69,6 +85,18 @@ pub async fn init_utils(app_handle: tauri::AppHandle) -> UtilResult<()> {
     // Initialize configuration
     config::init_config(&app_handle).await?;
 
+    // Open the local undo history and save timeline
+    history_store::init_history_store(&app_handle)?;
+
+    // Prepare the swap directory used for crash recovery
+    swap::init_swap_manager(&app_handle)?;
+
+    // Load recorded macros
+    macro_store::init_macro_store(&app_handle)?;
+
+    // Find user snippet files
+    snippet_store::init_snippet_store(&app_handle)?;
+
     tracing::info!("Utility subsystems initialized successfully");
     Ok(())
 }
@@ -0,0 +1,161 @@
+use crate::utils::{get_app_config_dir, UtilError, UtilResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, instrument};
+
+/// A snippet from a user snippet file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserSnippet {
+    pub name: String,
+    /// Words that offer the snippet as a completion
+    pub prefixes: Vec<String>,
+    /// Snippet syntax to insert
+    pub body: String,
+    pub description: Option<String>,
+}
+
+/// A snippet as written in a snippet file, in the same format VS Code uses
+#[derive(Debug, Deserialize)]
+struct SnippetEntry {
+    #[serde(default)]
+    prefix: Option<OneOrMany>,
+    body: OneOrMany,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// A string, or a list of strings such as the lines of a body
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::One(text) => vec![text],
+            Self::Many(texts) => texts,
+        }
+    }
+}
+
+/// User snippets kept as one `<language>.json` file per language
+pub struct SnippetStore {
+    dir: PathBuf,
+}
+
+impl SnippetStore {
+    /// Use snippet files in the given directory
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Directory the snippet files are read from
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Snippets for a language, sorted by name
+    ///
+    /// Files are read on every call so edits apply without a restart. A
+    /// language without a file has no snippets.
+    #[instrument(skip(self))]
+    pub fn snippets(&self, language: &str) -> UtilResult<Vec<UserSnippet>> {
+        if language.is_empty()
+            || !language
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || "-_+".contains(ch))
+        {
+            return Err(UtilError::InvalidArgument {
+                argument: format!("Invalid snippet language: {}", language),
+            });
+        }
+
+        let path = self.dir.join(format!("{}.json", language));
+        let entries: BTreeMap<String, SnippetEntry> = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        debug!("Loaded {} snippets from {}", entries.len(), path.display());
+        Ok(entries
+            .into_iter()
+            .map(|(name, entry)| UserSnippet {
+                prefixes: entry.prefix.map(OneOrMany::into_vec).unwrap_or_default(),
+                body: entry.body.into_vec().join("\n"),
+                description: entry.description,
+                name,
+            })
+            .collect())
+    }
+}
+
+// Global snippet store instance
+static SNIPPET_STORE: once_cell::sync::OnceCell<SnippetStore> = once_cell::sync::OnceCell::new();
+
+/// Initialize the global snippet store in the app config directory
+pub fn init_snippet_store(app_handle: &tauri::AppHandle) -> UtilResult<()> {
+    let dir = get_app_config_dir(app_handle)?.join("snippets");
+    std::fs::create_dir_all(&dir)?;
+
+    SNIPPET_STORE
+        .set(SnippetStore::new(&dir))
+        .map_err(|_| UtilError::Config {
+            message: "Snippet store already initialized".to_string(),
+        })?;
+
+    info!("User snippets read from: {}", dir.display());
+    Ok(())
+}
+
+/// Get the global snippet store
+pub fn get_snippet_store() -> UtilResult<&'static SnippetStore> {
+    SNIPPET_STORE.get().ok_or_else(|| UtilError::Config {
+        message: "Snippet store not initialized".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snippet_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnippetStore::new(temp_dir.path());
+        assert!(store.snippets("rust").unwrap().is_empty());
+
+        std::fs::write(
+            temp_dir.path().join("rust.json"),
+            r##"{
+                "Test function": {
+                    "prefix": ["test", "tfn"],
+                    "body": ["#[test]", "fn ${1:name}() {", "\t$0", "}"],
+                    "description": "A unit test"
+                },
+                "Print": {
+                    "prefix": "pr",
+                    "body": "println!(\"$1\");"
+                }
+            }"##,
+        )
+        .unwrap();
+
+        let snippets = store.snippets("rust").unwrap();
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets[0].name, "Print");
+        assert_eq!(snippets[0].prefixes, ["pr"]);
+        assert_eq!(snippets[0].description, None);
+        assert_eq!(snippets[1].prefixes, ["test", "tfn"]);
+        assert_eq!(snippets[1].body, "#[test]\nfn ${1:name}() {\n\t$0\n}");
+
+        assert!(store.snippets("../rust").is_err());
+    }
+}
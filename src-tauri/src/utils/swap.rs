@@ -0,0 +1,452 @@
+use crate::core::diff::{diff_lines, LineDiff};
+use crate::core::{Cursor, FileEncoding, ViewState};
+use crate::utils::{get_app_data_dir, DebounceConfig, Debouncer, UtilError, UtilResult};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, TryLockError};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+/// Delay after the last change before a swap file is written
+const SWAP_WRITE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest time continuous typing can hold back a swap file write
+const SWAP_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Extension of swap files in the swap directory
+const SWAP_EXTENSION: &str = "swp";
+
+/// Extension of the lock files that show which sessions are still running
+const LOCK_EXTENSION: &str = "lock";
+
+/// Snapshot of an editor written to disk so it can be recovered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapFile {
+    /// Editor the snapshot was taken from
+    pub editor_id: Uuid,
+    /// File being edited, `None` for untitled buffers
+    pub file_path: Option<PathBuf>,
+    /// Unsaved contents, `None` when the buffer matches the file on disk
+    pub content: Option<String>,
+    /// Encoding the file is read in, detected from the file when not recorded
+    #[serde(default)]
+    pub encoding: Option<FileEncoding>,
+    pub cursors: Vec<Cursor>,
+    pub view_state: ViewState,
+    /// Process that wrote the swap file
+    pub pid: u32,
+    /// Session that wrote the swap file, filled in when it is written
+    ///
+    /// The session holds a lock file for as long as it runs, which tells its
+    /// swap files apart from those left by a crash.
+    #[serde(default)]
+    pub session: Option<Uuid>,
+    /// Unix timestamp in milliseconds
+    pub saved_at: i64,
+    /// Whether the swap file was written on a clean exit rather than while editing
+    pub hot_exit: bool,
+}
+
+/// A swap file left behind by a previous session, for the recovery prompt
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapRecovery {
+    pub editor_id: Uuid,
+    pub file_path: Option<PathBuf>,
+    /// Unix timestamp in milliseconds
+    pub saved_at: i64,
+    pub hot_exit: bool,
+    /// Whether the buffer had unsaved changes
+    pub has_changes: bool,
+    /// Whether the file still exists on disk
+    pub file_exists: bool,
+    /// Changes recovery would make to the file on disk
+    pub diff: Vec<LineDiff>,
+}
+
+/// Writes and recovers swap files for buffers with unsaved changes
+pub struct SwapManager {
+    swap_dir: PathBuf,
+    debouncer: Debouncer,
+    session: Uuid,
+    /// Lock file held for the life of the session, released when the process exits
+    _session_lock: File,
+}
+
+impl SwapManager {
+    /// Create a swap manager storing swap files in the given directory
+    pub fn new(swap_dir: PathBuf) -> UtilResult<Self> {
+        Self::with_delay(swap_dir, SWAP_WRITE_DELAY, SWAP_MAX_DELAY)
+    }
+
+    /// Create a swap manager with custom write delays
+    pub fn with_delay(swap_dir: PathBuf, delay: Duration, max_delay: Duration) -> UtilResult<Self> {
+        std::fs::create_dir_all(&swap_dir)?;
+
+        let session = Uuid::new_v4();
+        let session_lock = File::create(lock_path(&swap_dir, session))?;
+        session_lock.lock()?;
+
+        Ok(Self {
+            swap_dir,
+            debouncer: Debouncer::with_config(DebounceConfig {
+                delay,
+                max_delay: Some(max_delay),
+                ..DebounceConfig::default()
+            }),
+            session,
+            _session_lock: session_lock,
+        })
+    }
+
+    /// Get the directory holding swap files
+    pub fn swap_dir(&self) -> &Path {
+        &self.swap_dir
+    }
+
+    /// Session the swap files written by this manager belong to
+    pub fn session(&self) -> Uuid {
+        self.session
+    }
+
+    /// Path of the swap file for an editor
+    pub fn swap_path(&self, editor_id: Uuid) -> PathBuf {
+        self.swap_dir
+            .join(format!("{}.{}", editor_id, SWAP_EXTENSION))
+    }
+
+    /// Schedule a swap file write for an editor once its changes settle
+    ///
+    /// `snapshot` runs when the write fires and returns `None` if the editor
+    /// no longer has unsaved changes, in which case its swap file is removed.
+    pub async fn schedule_write<F, Fut>(&self, editor_id: Uuid, snapshot: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Option<SwapFile>> + Send + 'static,
+    {
+        let path = self.swap_path(editor_id);
+        let session = self.session;
+
+        self.debouncer
+            .debounce(editor_id.to_string(), move || async move {
+                let result = match snapshot().await {
+                    Some(swap) => {
+                        let swap = SwapFile {
+                            session: Some(session),
+                            ..swap
+                        };
+                        write_swap_file(&path, &swap).await
+                    }
+                    None => remove_swap_file(&path).await,
+                };
+
+                if let Err(e) = result {
+                    warn!("Failed to update swap file {}: {}", path.display(), e);
+                }
+            })
+            .await;
+    }
+
+    /// Write a swap file immediately, replacing any pending write
+    #[instrument(skip(self, swap), fields(editor_id = %swap.editor_id))]
+    pub async fn write_now(&self, swap: &SwapFile) -> UtilResult<()> {
+        self.debouncer.cancel(&swap.editor_id.to_string()).await;
+        let swap = SwapFile {
+            session: Some(self.session),
+            ..swap.clone()
+        };
+        write_swap_file(&self.swap_path(swap.editor_id), &swap).await
+    }
+
+    /// Remove the swap file of an editor and cancel any pending write
+    pub async fn remove(&self, editor_id: Uuid) -> UtilResult<()> {
+        self.debouncer.cancel(&editor_id.to_string()).await;
+        remove_swap_file(&self.swap_path(editor_id)).await
+    }
+
+    /// Read the swap file of an editor
+    pub async fn read(&self, editor_id: Uuid) -> UtilResult<SwapFile> {
+        let path = self.swap_path(editor_id);
+        if !path.exists() {
+            return Err(UtilError::NotFound {
+                item: format!("swap file for editor {}", editor_id),
+            });
+        }
+
+        read_swap_file(&path).await
+    }
+
+    /// Whether a swap file was left behind by a session that is no longer running
+    ///
+    /// Swap files of other running instances are not orphans, however old.
+    pub fn is_orphan(&self, swap: &SwapFile) -> bool {
+        match swap.session {
+            Some(session) if session == self.session => false,
+            Some(session) => !lock_held(&lock_path(&self.swap_dir, session)),
+            // Written before sessions were recorded
+            None => swap.pid != std::process::id(),
+        }
+    }
+
+    /// Find swap files left behind by sessions that ended, oldest first
+    ///
+    /// Lock files of those sessions are removed along the way.
+    #[instrument(skip(self))]
+    pub async fn find_orphans(&self) -> UtilResult<Vec<SwapFile>> {
+        let mut orphans = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.swap_dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some(SWAP_EXTENSION) => {}
+                Some(LOCK_EXTENSION) => {
+                    if path != lock_path(&self.swap_dir, self.session) && !lock_held(&path) {
+                        let _ = tokio::fs::remove_file(&path).await;
+                    }
+                    continue;
+                }
+                _ => continue,
+            }
+
+            match read_swap_file(&path).await {
+                Ok(swap) if self.is_orphan(&swap) => orphans.push(swap),
+                Ok(_) => {}
+                Err(e) => warn!("Skipping unreadable swap file {}: {}", path.display(), e),
+            }
+        }
+
+        orphans.sort_by_key(|swap| swap.saved_at);
+        debug!("Found {} orphaned swap files", orphans.len());
+        Ok(orphans)
+    }
+
+    /// Compare a swap file against the file on disk
+    pub async fn recovery(swap: &SwapFile) -> SwapRecovery {
+        let disk_content = match &swap.file_path {
+            Some(path) => tokio::fs::read(path).await.ok().map(|bytes| {
+                let encoding = swap
+                    .encoding
+                    .unwrap_or_else(|| FileEncoding::detect(&bytes));
+                encoding
+                    .decode(&bytes)
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&bytes).into_owned())
+            }),
+            None => None,
+        };
+
+        let diff = match &swap.content {
+            Some(content) => diff_lines(disk_content.as_deref().unwrap_or(""), content),
+            None => Vec::new(),
+        };
+
+        SwapRecovery {
+            editor_id: swap.editor_id,
+            file_path: swap.file_path.clone(),
+            saved_at: swap.saved_at,
+            hot_exit: swap.hot_exit,
+            has_changes: swap.content.is_some(),
+            file_exists: disk_content.is_some(),
+            diff,
+        }
+    }
+}
+
+/// Path of the lock file a session holds while it runs
+fn lock_path(swap_dir: &Path, session: Uuid) -> PathBuf {
+    swap_dir.join(format!("{}.{}", session, LOCK_EXTENSION))
+}
+
+/// Whether a session lock file is held by a running process
+///
+/// A missing lock file means its session is gone. When locks can't be
+/// checked at all, the session is taken to be running, so that its swap
+/// files are never removed from under it.
+fn lock_held(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+
+    match file.try_lock() {
+        Ok(()) => false,
+        Err(TryLockError::WouldBlock) => true,
+        Err(TryLockError::Error(e)) => {
+            warn!("Failed to check session lock {}: {}", path.display(), e);
+            true
+        }
+    }
+}
+
+/// Write a swap file through a temporary file so a crash never leaves it half-written
+async fn write_swap_file(path: &Path, swap: &SwapFile) -> UtilResult<()> {
+    let json = serde_json::to_vec(swap)?;
+    let temp_path = path.with_extension(format!("{}.tmp", SWAP_EXTENSION));
+
+    tokio::fs::write(&temp_path, json).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+
+    debug!("Wrote swap file {}", path.display());
+    Ok(())
+}
+
+async fn read_swap_file(path: &Path) -> UtilResult<SwapFile> {
+    let json = tokio::fs::read(path).await?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+async fn remove_swap_file(path: &Path) -> UtilResult<()> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => {
+            debug!("Removed swap file {}", path.display());
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Global swap manager instance
+static SWAP_MANAGER: once_cell::sync::OnceCell<SwapManager> = once_cell::sync::OnceCell::new();
+
+/// Initialize the global swap manager in the app data directory
+pub fn init_swap_manager(app_handle: &tauri::AppHandle) -> UtilResult<()> {
+    let swap_dir = get_app_data_dir(app_handle)?.join("swap");
+    let manager = SwapManager::new(swap_dir.clone())?;
+
+    SWAP_MANAGER.set(manager).map_err(|_| UtilError::Config {
+        message: "Swap manager already initialized".to_string(),
+    })?;
+
+    info!("Swap files stored in: {}", swap_dir.display());
+    Ok(())
+}
+
+/// Get the global swap manager
+pub fn get_swap_manager() -> UtilResult<&'static SwapManager> {
+    SWAP_MANAGER.get().ok_or_else(|| UtilError::Config {
+        message: "Swap manager not initialized".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Position, TextEncoding};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    fn swap_file(editor_id: Uuid, content: &str, pid: u32) -> SwapFile {
+        SwapFile {
+            editor_id,
+            file_path: None,
+            content: Some(content.to_string()),
+            encoding: None,
+            cursors: vec![Cursor::new(0, Position::new(0, 2))],
+            view_state: ViewState::default(),
+            pid,
+            session: None,
+            saved_at: chrono::Utc::now().timestamp_millis(),
+            hot_exit: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_debounced_write_and_remove() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = SwapManager::with_delay(
+            temp_dir.path().to_path_buf(),
+            Duration::from_millis(20),
+            Duration::from_millis(200),
+        )
+        .unwrap();
+        let editor_id = Uuid::new_v4();
+        let buffer = Arc::new(Mutex::new(String::new()));
+
+        // Snapshots are taken when the write fires, like the editor listener does
+        for text in ["a", "ab", "abc"] {
+            *buffer.lock().unwrap() = text.to_string();
+            let buffer = buffer.clone();
+            manager
+                .schedule_write(editor_id, move || async move {
+                    let text = buffer.lock().unwrap().clone();
+                    Some(swap_file(editor_id, &text, std::process::id()))
+                })
+                .await;
+        }
+        assert!(!manager.swap_path(editor_id).exists());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let swap = manager.read(editor_id).await.unwrap();
+        assert_eq!(swap.content.as_deref(), Some("abc"));
+        assert_eq!(swap.cursors[0].position, Position::new(0, 2));
+
+        // A clean buffer removes its swap file
+        manager.schedule_write(editor_id, || async { None }).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!manager.swap_path(editor_id).exists());
+    }
+
+    #[tokio::test]
+    async fn test_find_orphans_and_recovery() {
+        let temp_dir = TempDir::new().unwrap();
+        let swap_dir = temp_dir.path().join("swap");
+        let manager = SwapManager::new(swap_dir.clone()).unwrap();
+
+        let file_path = temp_dir.path().join("main.rs");
+        std::fs::write(&file_path, "fn main() {\n}\n").unwrap();
+
+        // Another instance sharing the swap directory, with the same pid
+        let other = SwapManager::new(swap_dir.clone()).unwrap();
+        let mut orphan = swap_file(Uuid::new_v4(), "fn main() {\n    run();\n}\n", 0);
+        orphan.file_path = Some(file_path);
+        other.write_now(&orphan).await.unwrap();
+
+        let own = swap_file(Uuid::new_v4(), "mine", 0);
+        manager.write_now(&own).await.unwrap();
+
+        // Its swap files belong to it for as long as it runs
+        assert!(manager.find_orphans().await.unwrap().is_empty());
+        let other_lock = lock_path(&swap_dir, other.session());
+        drop(other);
+
+        let orphans = manager.find_orphans().await.unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].editor_id, orphan.editor_id);
+        assert!(!other_lock.exists());
+        assert!(lock_path(&swap_dir, manager.session()).exists());
+
+        let recovery = SwapManager::recovery(&orphans[0]).await;
+        assert!(recovery.file_exists && recovery.has_changes);
+        assert_eq!(
+            recovery
+                .diff
+                .iter()
+                .filter(|line| line.kind != crate::core::diff::LineDiffKind::Equal)
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["    run();"]
+        );
+
+        manager.remove(orphan.editor_id).await.unwrap();
+        assert!(manager.find_orphans().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recovery_decodes_file_encoding() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("notes.txt");
+        std::fs::write(&file_path, b"caf\xe9\n").unwrap();
+
+        let mut swap = swap_file(Uuid::new_v4(), "caf\u{e9}\n", 0);
+        swap.file_path = Some(file_path);
+        swap.encoding = Some(FileEncoding::new(TextEncoding::Latin1, false));
+
+        let recovery = SwapManager::recovery(&swap).await;
+        assert!(recovery.file_exists);
+        assert!(recovery
+            .diff
+            .iter()
+            .all(|line| line.kind == crate::core::diff::LineDiffKind::Equal));
+    }
+}